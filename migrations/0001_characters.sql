@@ -0,0 +1,42 @@
+CREATE TABLE IF NOT EXISTS characters (
+    obj_id INT UNSIGNED NOT NULL,
+    account_name VARCHAR(45) NOT NULL,
+    char_name VARCHAR(35) NOT NULL,
+    level TINYINT UNSIGNED NOT NULL DEFAULT 1,
+    max_hp DOUBLE NOT NULL,
+    cur_hp DOUBLE NOT NULL,
+    max_mp DOUBLE NOT NULL,
+    cur_mp DOUBLE NOT NULL,
+    face TINYINT UNSIGNED NOT NULL DEFAULT 0,
+    hair_style TINYINT UNSIGNED NOT NULL DEFAULT 0,
+    hair_color TINYINT UNSIGNED NOT NULL DEFAULT 0,
+    sex TINYINT UNSIGNED NOT NULL DEFAULT 0,
+    heading INT NOT NULL DEFAULT 0,
+    x INT NOT NULL,
+    y INT NOT NULL,
+    z INT NOT NULL,
+    exp BIGINT UNSIGNED NOT NULL DEFAULT 0,
+    sp INT UNSIGNED NOT NULL DEFAULT 0,
+    karma INT UNSIGNED NOT NULL DEFAULT 0,
+    clan_id INT UNSIGNED NOT NULL DEFAULT 0,
+    race TINYINT UNSIGNED NOT NULL,
+    class_id TINYINT UNSIGNED NOT NULL,
+    base_class TINYINT UNSIGNED NOT NULL,
+    delete_time BIGINT NOT NULL DEFAULT 0,
+    last_access BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (obj_id),
+    UNIQUE KEY char_name (char_name),
+    KEY account_name (account_name)
+);
+
+CREATE TABLE IF NOT EXISTS items (
+    object_id INT UNSIGNED NOT NULL,
+    owner_id INT UNSIGNED NOT NULL,
+    item_id INT UNSIGNED NOT NULL,
+    count BIGINT UNSIGNED NOT NULL DEFAULT 1,
+    enchant_level INT UNSIGNED NOT NULL DEFAULT 0,
+    loc VARCHAR(10) NOT NULL,
+    loc_data INT NOT NULL DEFAULT 0,
+    PRIMARY KEY (object_id),
+    KEY owner_id (owner_id)
+);
@@ -0,0 +1,9 @@
+ALTER TABLE characters
+    ADD COLUMN max_cp DOUBLE NOT NULL DEFAULT 0 AFTER cur_mp,
+    ADD COLUMN cur_cp DOUBLE NOT NULL DEFAULT 0 AFTER max_cp,
+    ADD COLUMN pvp_kills INT UNSIGNED NOT NULL DEFAULT 0 AFTER karma,
+    ADD COLUMN pk_kills INT UNSIGNED NOT NULL DEFAULT 0 AFTER pvp_kills,
+    ADD COLUMN title VARCHAR(16) NOT NULL DEFAULT '' AFTER pk_kills,
+    ADD COLUMN access_level INT NOT NULL DEFAULT 0 AFTER title,
+    ADD COLUMN online TINYINT UNSIGNED NOT NULL DEFAULT 0 AFTER last_access,
+    ADD COLUMN online_time BIGINT UNSIGNED NOT NULL DEFAULT 0 AFTER online;
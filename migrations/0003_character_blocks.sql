@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS character_blocks (
+    obj_id INT UNSIGNED NOT NULL,
+    blocked_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (obj_id, blocked_id)
+);
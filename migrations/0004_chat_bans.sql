@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS chat_bans (
+    obj_id INT UNSIGNED NOT NULL,
+    expires BIGINT NOT NULL DEFAULT 0,
+    reason VARCHAR(255) NOT NULL DEFAULT '',
+    banned_by VARCHAR(35) NOT NULL DEFAULT '',
+    PRIMARY KEY (obj_id)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE items
+    ADD KEY owner_loc (owner_id, loc);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS clans (
+    clan_id INT UNSIGNED NOT NULL,
+    clan_name VARCHAR(16) NOT NULL,
+    clan_level TINYINT UNSIGNED NOT NULL DEFAULT 0,
+    leader_id INT UNSIGNED NOT NULL,
+    PRIMARY KEY (clan_id),
+    UNIQUE KEY clan_name (clan_name)
+);
+
+ALTER TABLE characters
+    ADD COLUMN clan_privs INT UNSIGNED NOT NULL DEFAULT 0 AFTER clan_id;
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS private_stores (
+    obj_id INT UNSIGNED NOT NULL,
+    store_type TINYINT UNSIGNED NOT NULL,
+    message VARCHAR(29) NOT NULL DEFAULT '',
+    PRIMARY KEY (obj_id)
+);
+
+CREATE TABLE IF NOT EXISTS private_store_items (
+    obj_id INT UNSIGNED NOT NULL,
+    item INT UNSIGNED NOT NULL,
+    count BIGINT UNSIGNED NOT NULL,
+    price BIGINT UNSIGNED NOT NULL,
+    KEY obj_id (obj_id)
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS skill_reuse (
+    obj_id INT UNSIGNED NOT NULL,
+    skill_id INT UNSIGNED NOT NULL,
+    expires BIGINT NOT NULL,
+    PRIMARY KEY (obj_id, skill_id)
+);
@@ -20,6 +20,249 @@ pub struct GameServer {
     pub external_ip: String,
     pub port: u32,
     pub database: Database,
+    #[serde(default)]
+    pub options: Options,
+    #[serde(default)]
+    pub characters: Characters,
+    /// Directory the datapack (class templates and other static game data) is loaded from.
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
+    #[serde(default)]
+    pub chat: Chat,
+    #[serde(default)]
+    pub warehouse: Warehouse,
+    #[serde(default)]
+    pub stores: Stores,
+    #[serde(default)]
+    pub rates: Rates,
+    #[serde(default)]
+    pub death: Death,
+    #[serde(default)]
+    pub karma: Karma,
+}
+
+impl GameServer {
+    /// Checks what the types alone don't: rates are numbers no lower than 0, someone can log in, and the death
+    /// penalty is a percent.
+    pub fn validate(&self) -> Result<(), String> {
+        let rates = [
+            ("exp", self.rates.exp),
+            ("sp", self.rates.sp),
+            ("adena", self.rates.adena),
+            ("drop", self.rates.drop),
+            ("spoil", self.rates.spoil),
+            ("quest", self.rates.quest),
+        ];
+        if let Some((name, rate)) = rates.iter().find(|(_, rate)| !rate.is_finite() || *rate < 0.0) {
+            return Err(format!("rates.{} must be 0 or more, not {}", name, rate));
+        }
+        if self.options.max_players == 0 {
+            return Err("options.max_players must be at least 1".to_string());
+        }
+        if !(0.0..=100.0).contains(&self.death.exp_loss) {
+            return Err(format!("death.exp_loss must be a percent, not {}", self.death.exp_loss));
+        }
+        if self.karma.exp_per_point == 0 {
+            return Err("karma.exp_per_point must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn default_data_dir() -> String {
+    "./data".to_string()
+}
+
+/// How the server presents itself and who it lets in.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Options {
+    /// Players in the world at once, further logins are refused.
+    pub max_players: u32,
+    /// Marks a test server, only shown in the log until the login server lists game servers.
+    pub testing: bool,
+    /// Puts the loot of a monster straight in the inventory of the player it drops for, what doesn't fit still
+    /// falls to the ground.
+    pub auto_loot: bool,
+    /// Development only. Lets clients in with the account name they send. The login server doesn't hand the
+    /// session keys over yet, so nothing checks the account is theirs.
+    pub accept_unverified_logins: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { max_players: 1000, testing: false, auto_loot: false, accept_unverified_logins: false }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Characters {
+    /// Days a character stays in the "pending deletion" state before it is removed, 0 deletes immediately.
+    pub delete_days: u32,
+}
+
+impl Default for Characters {
+    fn default() -> Characters {
+        Characters { delete_days: 7 }
+    }
+}
+
+/// Who hears a chat channel that is not limited to the players around.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRange {
+    Off,
+    /// Players in the same 32768x32768 map tile.
+    Region,
+    Global,
+}
+
+#[derive(Deserialize)]
+pub struct Chat {
+    pub shout: ChatRange,
+    pub trade: ChatRange,
+    #[serde(default)]
+    pub filter: ChatFilter,
+    #[serde(default)]
+    pub flood: Flood,
+}
+
+impl Default for Chat {
+    fn default() -> Chat {
+        Chat { shout: ChatRange::Region, trade: ChatRange::Region, filter: ChatFilter::default(), flood: Flood::default() }
+    }
+}
+
+/// What happens to a message containing a filtered word.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    /// The word is replaced and the message goes through.
+    Replace,
+    /// The message is not sent at all.
+    Block,
+}
+
+#[derive(Deserialize)]
+pub struct ChatFilter {
+    /// Matched anywhere in a message, whatever the case.
+    pub words: Vec<String>,
+    pub action: FilterAction,
+    /// Put in place of a filtered word when replacing.
+    pub replacement: String,
+}
+
+impl Default for ChatFilter {
+    fn default() -> ChatFilter {
+        ChatFilter { words: Vec::new(), action: FilterAction::Replace, replacement: "***".to_string() }
+    }
+}
+
+/// Shortest time between two messages of a player on a channel, in milliseconds, 0 for no limit. GMs are not
+/// limited.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Flood {
+    pub all: u64,
+    pub shout: u64,
+    pub tell: u64,
+    pub trade: u64,
+    pub clan: u64,
+    pub hero_voice: u64,
+}
+
+impl Default for Flood {
+    fn default() -> Flood {
+        Flood { all: 300, shout: 5000, tell: 300, trade: 5000, clan: 300, hero_voice: 10000 }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Warehouse {
+    pub slots: usize,
+    /// Dwarves get a larger private warehouse.
+    pub dwarf_slots: usize,
+    pub clan_slots: usize,
+    /// Adena paid for each item deposited.
+    pub deposit_fee: u64,
+}
+
+impl Default for Warehouse {
+    fn default() -> Warehouse {
+        Warehouse { slots: 100, dwarf_slots: 120, clan_slots: 200, deposit_fee: 30 }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Stores {
+    /// Different items a private store can list.
+    pub slots: usize,
+    pub dwarf_slots: usize,
+    /// Keeps a character with an open store in the world after its client disconnects, until the store is
+    /// empty or the character logs in again. Such stores are opened again when the server restarts.
+    pub offline: bool,
+}
+
+impl Default for Stores {
+    fn default() -> Stores {
+        Stores { slots: 3, dwarf_slots: 4, offline: false }
+    }
+}
+
+/// Multipliers of what monsters give.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Rates {
+    pub exp: f64,
+    pub sp: f64,
+    /// Applies to the amount of adena dropped rather than to its chance.
+    pub adena: f64,
+    pub drop: f64,
+    pub spoil: f64,
+    /// Rewards of quests, once there are quests.
+    pub quest: f64,
+}
+
+impl Default for Rates {
+    fn default() -> Rates {
+        Rates { exp: 1.0, sp: 1.0, adena: 1.0, drop: 1.0, spoil: 1.0, quest: 1.0 }
+    }
+}
+
+/// What players lose when a monster kills them.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Death {
+    /// Percent of the experience of their level, 0 for no loss.
+    pub exp_loss: f64,
+    /// Characters below this level lose nothing.
+    pub min_level: u8,
+    /// Whether the loss can take a character down a level.
+    pub delevel: bool,
+}
+
+impl Default for Death {
+    fn default() -> Death {
+        Death { exp_loss: 4.0, min_level: 10, delevel: true }
+    }
+}
+
+/// What players get for killing players without karma.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Karma {
+    /// Karma given for each such kill.
+    pub per_kill: u32,
+    /// Experience a player earns to work off one point of karma.
+    pub exp_per_point: u64,
+}
+
+impl Default for Karma {
+    fn default() -> Karma {
+        Karma { per_kill: 240, exp_per_point: 20 }
+    }
 }
 
 #[derive(Deserialize)]
@@ -48,8 +291,11 @@ pub fn new_config() -> Result<Config, String> {
             match file.read_to_string(&mut contents) {
                 Ok(_) => {
                     // Parse the string of data into serde_json::Value.
-                    match toml::from_str(&contents) {
-                        Ok(config) => Ok(config),
+                    match toml::from_str::<Config>(&contents) {
+                        Ok(config) => match config.gameserver.validate() {
+                            Ok(()) => Ok(config),
+                            Err(e) => Err(format!("Invalid gameserver settings: {}", e)),
+                        },
                         Err(e) => panic!("Error parsing config.toml: {}", e),
                     }
                 }
@@ -58,4 +304,20 @@ pub fn new_config() -> Result<Config, String> {
         }
         Err(e) => Err(format!("Error reading config.toml: {}", e)),
     } 
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation_catches_bad_settings() {
+        let mut config: Config = toml::from_str(include_str!("../../config/network.toml")).unwrap();
+        assert!(config.gameserver.validate().is_ok());
+        config.gameserver.rates.drop = -1.0;
+        assert!(config.gameserver.validate().is_err());
+        config.gameserver.rates.drop = 2.0;
+        config.gameserver.death.exp_loss = 150.0;
+        assert!(config.gameserver.validate().is_err());
+    }
 }
\ No newline at end of file
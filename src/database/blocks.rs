@@ -0,0 +1,24 @@
+use super::connection::Database;
+
+/// Characters a character ignores, with their names.
+pub async fn load(db: &Database, obj_id: u32) -> Result<Vec<(u32, String)>, String> {
+    let query = "SELECT b.blocked_id, c.char_name FROM character_blocks b JOIN characters c ON c.obj_id = b.blocked_id WHERE b.obj_id = ?";
+    match sqlx::query_as::<_, (u32, String)>(query).bind(obj_id).fetch_all(&db.pool).await {
+        Ok(blocks) => Ok(blocks),
+        Err(e) => Err(format!("Error loading block list of {}: {}", obj_id, e)),
+    }
+}
+
+pub async fn add(db: &Database, obj_id: u32, blocked_id: u32) -> Result<(), String> {
+    match sqlx::query("INSERT IGNORE INTO character_blocks (obj_id, blocked_id) VALUES (?, ?)").bind(obj_id).bind(blocked_id).execute(&db.pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error blocking {} for {}: {}", blocked_id, obj_id, e)),
+    }
+}
+
+pub async fn remove(db: &Database, obj_id: u32, blocked_id: u32) -> Result<(), String> {
+    match sqlx::query("DELETE FROM character_blocks WHERE obj_id = ? AND blocked_id = ?").bind(obj_id).bind(blocked_id).execute(&db.pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error unblocking {} for {}: {}", blocked_id, obj_id, e)),
+    }
+}
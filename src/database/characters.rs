@@ -0,0 +1,314 @@
+use sqlx::{FromRow, MySqlConnection};
+
+use super::connection::Database;
+use super::items;
+
+#[derive(FromRow, Clone)]
+pub struct Character {
+    pub obj_id: u32,
+    pub account_name: String,
+    pub char_name: String,
+    pub level: u8,
+    pub max_hp: f64,
+    pub cur_hp: f64,
+    pub max_mp: f64,
+    pub cur_mp: f64,
+    pub max_cp: f64,
+    pub cur_cp: f64,
+    pub face: u8,
+    pub hair_style: u8,
+    pub hair_color: u8,
+    pub sex: u8,
+    pub heading: i32,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub exp: u64,
+    pub sp: u32,
+    pub karma: u32,
+    pub pvp_kills: u32,
+    pub pk_kills: u32,
+    pub title: String,
+    /// Negative values mark a banned character, positive ones grant GM rights.
+    pub access_level: i32,
+    pub clan_id: u32,
+    pub race: u8,
+    pub class_id: u8,
+    pub base_class: u8,
+    /// Unix time in milliseconds at which a pending deletion happens, 0 when not pending.
+    pub delete_time: i64,
+    /// Unix time in milliseconds of the last time the character entered or left the world.
+    pub last_access: i64,
+    pub online: u8,
+    /// Seconds spent in game.
+    pub online_time: u64,
+}
+
+const COLUMNS: &str = "obj_id, account_name, char_name, level, max_hp, cur_hp, max_mp, cur_mp, max_cp, cur_cp, face, \
+    hair_style, hair_color, sex, heading, x, y, z, exp, sp, karma, pvp_kills, pk_kills, title, access_level, clan_id, \
+    race, class_id, base_class, delete_time, last_access, online, online_time";
+
+/// Columns written by `update`, everything but the keys and the fields the lobby manages.
+const UPDATE: &str = "UPDATE characters SET level = ?, max_hp = ?, cur_hp = ?, max_mp = ?, cur_mp = ?, max_cp = ?, \
+    cur_cp = ?, face = ?, hair_style = ?, hair_color = ?, sex = ?, heading = ?, x = ?, y = ?, z = ?, exp = ?, sp = ?, \
+    karma = ?, pvp_kills = ?, pk_kills = ?, title = ?, clan_id = ?, class_id = ?, last_access = ?, online = ?, \
+    online_time = ? WHERE obj_id = ?";
+
+pub async fn load(db: &Database, obj_id: u32) -> Result<Option<Character>, String> {
+    let query = format!("SELECT {} FROM characters WHERE obj_id = ?", COLUMNS);
+    match sqlx::query_as::<_, Character>(&query).bind(obj_id).fetch_optional(&db.pool).await {
+        Ok(character) => Ok(character),
+        Err(e) => Err(format!("Error loading character {}: {}", obj_id, e)),
+    }
+}
+
+/// Loads the characters of an account in the order they are shown on the selection screen.
+pub async fn load_by_account(db: &Database, account_name: &str) -> Result<Vec<Character>, String> {
+    let query = format!("SELECT {} FROM characters WHERE account_name = ? ORDER BY obj_id", COLUMNS);
+    match sqlx::query_as::<_, Character>(&query).bind(account_name).fetch_all(&db.pool).await {
+        Ok(characters) => Ok(characters),
+        Err(e) => Err(format!("Error loading characters of account {}: {}", account_name, e)),
+    }
+}
+
+pub async fn name_exists(db: &Database, char_name: &str) -> Result<bool, String> {
+    match sqlx::query("SELECT obj_id FROM characters WHERE char_name = ?").bind(char_name).fetch_optional(&db.pool).await {
+        Ok(row) => Ok(row.is_some()),
+        Err(e) => Err(format!("Error checking character name {}: {}", char_name, e)),
+    }
+}
+
+/// Object id and exact name of the character with this name, whatever its case.
+pub async fn find_by_name(db: &Database, char_name: &str) -> Result<Option<(u32, String)>, String> {
+    let query = "SELECT obj_id, char_name FROM characters WHERE char_name = ?";
+    match sqlx::query_as::<_, (u32, String)>(query).bind(char_name).fetch_optional(&db.pool).await {
+        Ok(found) => Ok(found),
+        Err(e) => Err(format!("Error looking up character {}: {}", char_name, e)),
+    }
+}
+
+pub async fn object_ids(db: &Database) -> Result<Vec<u32>, String> {
+    match sqlx::query_scalar::<_, u32>("SELECT obj_id FROM characters").fetch_all(&db.pool).await {
+        Ok(ids) => Ok(ids),
+        Err(e) => Err(format!("Error reading character object ids: {}", e)),
+    }
+}
+
+/// Nobody can be online while the server starts, clears the flag left behind by a crash.
+pub async fn reset_online(db: &Database) -> Result<(), String> {
+    match sqlx::query("UPDATE characters SET online = 0 WHERE online = 1").execute(&db.pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error resetting online characters: {}", e)),
+    }
+}
+
+async fn insert(conn: &mut MySqlConnection, character: &Character) -> Result<(), sqlx::Error> {
+    let placeholders = vec!["?"; COLUMNS.split(',').count()].join(", ");
+    let query = format!("INSERT INTO characters ({}) VALUES ({})", COLUMNS, placeholders);
+    sqlx::query(&query)
+        .bind(character.obj_id)
+        .bind(&character.account_name)
+        .bind(&character.char_name)
+        .bind(character.level)
+        .bind(character.max_hp)
+        .bind(character.cur_hp)
+        .bind(character.max_mp)
+        .bind(character.cur_mp)
+        .bind(character.max_cp)
+        .bind(character.cur_cp)
+        .bind(character.face)
+        .bind(character.hair_style)
+        .bind(character.hair_color)
+        .bind(character.sex)
+        .bind(character.heading)
+        .bind(character.x)
+        .bind(character.y)
+        .bind(character.z)
+        .bind(character.exp)
+        .bind(character.sp)
+        .bind(character.karma)
+        .bind(character.pvp_kills)
+        .bind(character.pk_kills)
+        .bind(&character.title)
+        .bind(character.access_level)
+        .bind(character.clan_id)
+        .bind(character.race)
+        .bind(character.class_id)
+        .bind(character.base_class)
+        .bind(character.delete_time)
+        .bind(character.last_access)
+        .bind(character.online)
+        .bind(character.online_time)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Writes the in game state of a character. Takes a connection so callers can save other data owned by the
+/// character in the same transaction.
+pub async fn update(conn: &mut MySqlConnection, character: &Character) -> Result<(), sqlx::Error> {
+    sqlx::query(UPDATE)
+        .bind(character.level)
+        .bind(character.max_hp)
+        .bind(character.cur_hp)
+        .bind(character.max_mp)
+        .bind(character.cur_mp)
+        .bind(character.max_cp)
+        .bind(character.cur_cp)
+        .bind(character.face)
+        .bind(character.hair_style)
+        .bind(character.hair_color)
+        .bind(character.sex)
+        .bind(character.heading)
+        .bind(character.x)
+        .bind(character.y)
+        .bind(character.z)
+        .bind(character.exp)
+        .bind(character.sp)
+        .bind(character.karma)
+        .bind(character.pvp_kills)
+        .bind(character.pk_kills)
+        .bind(&character.title)
+        .bind(character.clan_id)
+        .bind(character.class_id)
+        .bind(character.last_access)
+        .bind(character.online)
+        .bind(character.online_time)
+        .bind(character.obj_id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Stores a new character together with its starting items, either everything is written or nothing.
+pub async fn create(db: &Database, character: &Character, starting_items: &[items::Item]) -> Result<(), String> {
+    let mut tx = match db.pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return Err(format!("Error starting character creation: {}", e)),
+    };
+
+    if let Err(e) = insert(&mut tx, character).await {
+        return Err(format!("Error creating character {}: {}", character.char_name, e));
+    }
+    for item in starting_items {
+        if let Err(e) = items::insert(&mut tx, item).await {
+            return Err(format!("Error giving item {} to {}: {}", item.item_id, character.char_name, e));
+        }
+    }
+
+    match tx.commit().await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error creating character {}: {}", character.char_name, e)),
+    }
+}
+
+/// Saves a character in its own transaction.
+pub async fn save(db: &Database, character: &Character) -> Result<(), String> {
+    let mut tx = match db.pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return Err(format!("Error starting save of {}: {}", character.char_name, e)),
+    };
+
+    if let Err(e) = update(&mut tx, character).await {
+        return Err(format!("Error saving {}: {}", character.char_name, e));
+    }
+
+    match tx.commit().await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error saving {}: {}", character.char_name, e)),
+    }
+}
+
+/// Marks a character as in game, `last_access` is where the online time of the session is counted from.
+pub async fn update_on_login(db: &Database, character: &mut Character, now: i64) -> Result<(), String> {
+    character.online = 1;
+    character.last_access = now;
+    save(db, character).await
+}
+
+/// Final save of a session: adds the time spent in game and clears the online flag.
+pub async fn update_on_logout(db: &Database, character: &mut Character, now: i64) -> Result<(), String> {
+    log_out(character, now);
+    save(db, character).await
+}
+
+/// Adds the seconds since the character logged in to its online time and marks it offline.
+fn log_out(character: &mut Character, now: i64) {
+    if character.online == 1 && now > character.last_access {
+        character.online_time += ((now - character.last_access) / 1000) as u64;
+    }
+    character.online = 0;
+    character.last_access = now;
+}
+
+pub async fn set_delete_time(db: &Database, obj_id: u32, delete_time: i64) -> Result<(), String> {
+    match sqlx::query("UPDATE characters SET delete_time = ? WHERE obj_id = ?").bind(delete_time).bind(obj_id).execute(&db.pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error updating delete time of character {}: {}", obj_id, e)),
+    }
+}
+
+/// Removes a character and everything it owns, returning every object id that became free.
+pub async fn delete(db: &Database, obj_id: u32) -> Result<Vec<u32>, String> {
+    let mut tx = match db.pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return Err(format!("Error starting deletion of character {}: {}", obj_id, e)),
+    };
+
+    let mut freed = match sqlx::query_scalar::<_, u32>("SELECT object_id FROM items WHERE owner_id = ?").bind(obj_id).fetch_all(&mut *tx).await {
+        Ok(ids) => ids,
+        Err(e) => return Err(format!("Error reading items of character {}: {}", obj_id, e)),
+    };
+    freed.push(obj_id);
+
+    if let Err(e) = sqlx::query("DELETE FROM items WHERE owner_id = ?").bind(obj_id).execute(&mut *tx).await {
+        return Err(format!("Error deleting items of character {}: {}", obj_id, e));
+    }
+    if let Err(e) = sqlx::query("DELETE FROM character_blocks WHERE obj_id = ? OR blocked_id = ?").bind(obj_id).bind(obj_id).execute(&mut *tx).await {
+        return Err(format!("Error deleting block lists of character {}: {}", obj_id, e));
+    }
+    if let Err(e) = sqlx::query("DELETE FROM chat_bans WHERE obj_id = ?").bind(obj_id).execute(&mut *tx).await {
+        return Err(format!("Error deleting chat ban of character {}: {}", obj_id, e));
+    }
+    if let Err(e) = sqlx::query("DELETE FROM skill_reuse WHERE obj_id = ?").bind(obj_id).execute(&mut *tx).await {
+        return Err(format!("Error deleting skill reuse of character {}: {}", obj_id, e));
+    }
+    if let Err(e) = sqlx::query("DELETE FROM private_store_items WHERE obj_id = ?").bind(obj_id).execute(&mut *tx).await {
+        return Err(format!("Error deleting store items of character {}: {}", obj_id, e));
+    }
+    if let Err(e) = sqlx::query("DELETE FROM private_stores WHERE obj_id = ?").bind(obj_id).execute(&mut *tx).await {
+        return Err(format!("Error deleting store of character {}: {}", obj_id, e));
+    }
+    if let Err(e) = sqlx::query("DELETE FROM characters WHERE obj_id = ?").bind(obj_id).execute(&mut *tx).await {
+        return Err(format!("Error deleting character {}: {}", obj_id, e));
+    }
+
+    match tx.commit().await {
+        Ok(_) => Ok(freed),
+        Err(e) => Err(format!("Error deleting character {}: {}", obj_id, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameserver::player::synthetic::character;
+
+    #[test]
+    fn logging_out_counts_the_whole_seconds_online() {
+        let mut character = character(1, "Walker", 0, 0);
+        character.online = 1;
+        character.online_time = 100;
+        character.last_access = 10_000;
+
+        log_out(&mut character, 72_500);
+        assert_eq!((character.online, character.online_time, character.last_access), (0, 162, 72_500));
+
+        // Logged out already or a clock gone back adds nothing.
+        log_out(&mut character, 90_000);
+        assert_eq!((character.online_time, character.last_access), (162, 90_000));
+        character.online = 1;
+        log_out(&mut character, 80_000);
+        assert_eq!((character.online, character.online_time, character.last_access), (0, 162, 80_000));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,27 @@
+use super::connection::Database;
+
+/// Unix time in milliseconds at which the chat ban of a character ends, 0 for a ban that never ends. Bans that
+/// already ended are left out.
+pub async fn load(db: &Database, obj_id: u32, now: i64) -> Result<Option<i64>, String> {
+    let query = "SELECT expires FROM chat_bans WHERE obj_id = ? AND (expires = 0 OR expires > ?)";
+    match sqlx::query_scalar::<_, i64>(query).bind(obj_id).bind(now).fetch_optional(&db.pool).await {
+        Ok(expires) => Ok(expires),
+        Err(e) => Err(format!("Error loading chat ban of {}: {}", obj_id, e)),
+    }
+}
+
+/// Bans a character from chatting, replacing any ban it already has.
+pub async fn set(db: &Database, obj_id: u32, expires: i64, reason: &str, banned_by: &str) -> Result<(), String> {
+    let query = "REPLACE INTO chat_bans (obj_id, expires, reason, banned_by) VALUES (?, ?, ?, ?)";
+    match sqlx::query(query).bind(obj_id).bind(expires).bind(reason).bind(banned_by).execute(&db.pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error banning {} from chat: {}", obj_id, e)),
+    }
+}
+
+pub async fn remove(db: &Database, obj_id: u32) -> Result<(), String> {
+    match sqlx::query("DELETE FROM chat_bans WHERE obj_id = ?").bind(obj_id).execute(&db.pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error lifting chat ban of {}: {}", obj_id, e)),
+    }
+}
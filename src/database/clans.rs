@@ -0,0 +1,36 @@
+use sqlx::FromRow;
+
+use super::connection::Database;
+
+/// Withdrawing from the clan warehouse.
+pub const CP_CL_VIEW_WAREHOUSE: u32 = 8;
+
+/// What the server needs of the clan of a player, its id is the `clan_id` of the character.
+#[derive(FromRow, Clone)]
+pub struct Clan {
+    pub clan_level: u8,
+    pub leader_id: u32,
+}
+
+pub async fn load(db: &Database, clan_id: u32) -> Result<Option<Clan>, String> {
+    let query = "SELECT clan_level, leader_id FROM clans WHERE clan_id = ?";
+    match sqlx::query_as::<_, Clan>(query).bind(clan_id).fetch_optional(&db.pool).await {
+        Ok(clan) => Ok(clan),
+        Err(e) => Err(format!("Error loading clan {}: {}", clan_id, e)),
+    }
+}
+
+/// `CP_*` privilege bits the clan gave a member.
+pub async fn privileges(db: &Database, obj_id: u32) -> Result<u32, String> {
+    match sqlx::query_scalar::<_, u32>("SELECT clan_privs FROM characters WHERE obj_id = ?").bind(obj_id).fetch_optional(&db.pool).await {
+        Ok(privileges) => Ok(privileges.unwrap_or(0)),
+        Err(e) => Err(format!("Error loading clan privileges of {}: {}", obj_id, e)),
+    }
+}
+
+pub async fn clan_ids(db: &Database) -> Result<Vec<u32>, String> {
+    match sqlx::query_scalar::<_, u32>("SELECT clan_id FROM clans").fetch_all(&db.pool).await {
+        Ok(ids) => Ok(ids),
+        Err(e) => Err(format!("Error reading clan ids: {}", e)),
+    }
+}
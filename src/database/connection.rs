@@ -0,0 +1,45 @@
+use log::info;
+use sqlx::mysql::{MySqlConnectOptions, MySqlPool, MySqlPoolOptions};
+
+use crate::config::config;
+
+/// Port used when the configuration leaves it at 0.
+const DEFAULT_PORT: u16 = 3306;
+
+#[derive(Clone)]
+pub struct Database {
+    pub pool: MySqlPool,
+}
+
+impl Database {
+    /// Connects to the configured database and brings its schema up to date.
+    pub async fn connect(conf: &config::Database) -> Result<Database, String> {
+        let port = match conf.port {
+            0 => DEFAULT_PORT,
+            port => match u16::try_from(port) {
+                Ok(port) => port,
+                Err(_) => return Err(format!("Invalid database port: {}", port)),
+            },
+        };
+
+        let options = MySqlConnectOptions::new()
+            .host(&conf.host)
+            .port(port)
+            .username(&conf.user)
+            .password(&conf.password)
+            .database(&conf.name);
+
+        let pool = match MySqlPoolOptions::new().max_connections(10).connect_with(options).await {
+            Ok(pool) => pool,
+            Err(e) => return Err(format!("Error connecting to database {}: {}", conf.name, e)),
+        };
+
+        if let Err(e) = sqlx::migrate!("./migrations").run(&pool).await {
+            return Err(format!("Error running database migrations: {}", e));
+        }
+
+        info!("Connected to database {} on {}:{}", conf.name, conf.host, port);
+
+        Ok(Database { pool })
+    }
+}
@@ -0,0 +1,127 @@
+use sqlx::{FromRow, MySqlConnection};
+
+use super::connection::Database;
+
+pub const LOC_INVENTORY: &str = "INVENTORY";
+pub const LOC_PAPERDOLL: &str = "PAPERDOLL";
+pub const LOC_WAREHOUSE: &str = "WAREHOUSE";
+/// Clan warehouse, the owner is the clan.
+pub const LOC_CLANWH: &str = "CLANWH";
+
+const COLUMNS: &str = "object_id, owner_id, item_id, count, enchant_level, loc, loc_data";
+
+#[derive(FromRow, Clone)]
+pub struct Item {
+    pub object_id: u32,
+    pub owner_id: u32,
+    pub item_id: u32,
+    pub count: u64,
+    pub enchant_level: u32,
+    /// Where the item is kept, one of the `LOC_*` constants.
+    pub loc: String,
+    /// Paperdoll slot for equipped items, unused otherwise.
+    pub loc_data: i32,
+}
+
+/// A change made to an item kept in memory, to be written to the database and shown to the client.
+#[derive(Clone)]
+pub enum ItemChange {
+    Added(Item),
+    Modified(Item),
+    Removed(Item),
+}
+
+impl ItemChange {
+    pub fn item(&self) -> &Item {
+        match self {
+            ItemChange::Added(item) | ItemChange::Modified(item) | ItemChange::Removed(item) => item,
+        }
+    }
+}
+
+/// Loads the items a character is wearing.
+pub async fn load_paperdoll(db: &Database, owner_id: u32) -> Result<Vec<Item>, String> {
+    let query = format!("SELECT {} FROM items WHERE owner_id = ? AND loc = ?", COLUMNS);
+    match sqlx::query_as::<_, Item>(&query).bind(owner_id).bind(LOC_PAPERDOLL).fetch_all(&db.pool).await {
+        Ok(items) => Ok(items),
+        Err(e) => Err(format!("Error loading paperdoll of {}: {}", owner_id, e)),
+    }
+}
+
+/// Loads everything a character carries, worn or not, in the order the items were created.
+pub async fn load_inventory(db: &Database, owner_id: u32) -> Result<Vec<Item>, String> {
+    let query = format!("SELECT {} FROM items WHERE owner_id = ? AND loc IN (?, ?) ORDER BY object_id", COLUMNS);
+    match sqlx::query_as::<_, Item>(&query).bind(owner_id).bind(LOC_INVENTORY).bind(LOC_PAPERDOLL).fetch_all(&db.pool).await {
+        Ok(items) => Ok(items),
+        Err(e) => Err(format!("Error loading inventory of {}: {}", owner_id, e)),
+    }
+}
+
+/// Loads the items of a warehouse, `LOC_WAREHOUSE` for the one of a character or `LOC_CLANWH` for the one of a clan.
+pub async fn load_warehouse(db: &Database, owner_id: u32, loc: &str) -> Result<Vec<Item>, String> {
+    let query = format!("SELECT {} FROM items WHERE owner_id = ? AND loc = ? ORDER BY object_id", COLUMNS);
+    match sqlx::query_as::<_, Item>(&query).bind(owner_id).bind(loc).fetch_all(&db.pool).await {
+        Ok(items) => Ok(items),
+        Err(e) => Err(format!("Error loading warehouse of {}: {}", owner_id, e)),
+    }
+}
+
+pub async fn object_ids(db: &Database) -> Result<Vec<u32>, String> {
+    match sqlx::query_scalar::<_, u32>("SELECT object_id FROM items").fetch_all(&db.pool).await {
+        Ok(ids) => Ok(ids),
+        Err(e) => Err(format!("Error reading item object ids: {}", e)),
+    }
+}
+
+pub async fn insert(conn: &mut MySqlConnection, item: &Item) -> Result<(), sqlx::Error> {
+    let query = format!("INSERT INTO items ({}) VALUES (?, ?, ?, ?, ?, ?, ?)", COLUMNS);
+    sqlx::query(&query)
+        .bind(item.object_id)
+        .bind(item.owner_id)
+        .bind(item.item_id)
+        .bind(item.count)
+        .bind(item.enchant_level)
+        .bind(&item.loc)
+        .bind(item.loc_data)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+async fn update(conn: &mut MySqlConnection, item: &Item) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE items SET owner_id = ?, item_id = ?, count = ?, enchant_level = ?, loc = ?, loc_data = ? WHERE object_id = ?")
+        .bind(item.owner_id)
+        .bind(item.item_id)
+        .bind(item.count)
+        .bind(item.enchant_level)
+        .bind(&item.loc)
+        .bind(item.loc_data)
+        .bind(item.object_id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Writes a batch of changes in one transaction, in order, so an item moved between owners is never stored
+/// twice or lost.
+pub async fn apply(db: &Database, changes: &[ItemChange]) -> Result<(), String> {
+    let mut tx = match db.pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return Err(format!("Error starting item update: {}", e)),
+    };
+
+    for change in changes {
+        let result = match change {
+            ItemChange::Added(item) => insert(&mut tx, item).await,
+            ItemChange::Modified(item) => update(&mut tx, item).await,
+            ItemChange::Removed(item) => sqlx::query("DELETE FROM items WHERE object_id = ?").bind(item.object_id).execute(&mut *tx).await.map(|_| ()),
+        };
+        if let Err(e) = result {
+            return Err(format!("Error updating items: {}", e));
+        }
+    }
+
+    match tx.commit().await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error updating items: {}", e)),
+    }
+}
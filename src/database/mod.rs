@@ -0,0 +1,8 @@
+pub mod connection;
+pub mod characters;
+pub mod items;
+pub mod blocks;
+pub mod chat_bans;
+pub mod clans;
+pub mod private_stores;
+pub mod skill_reuse;
\ No newline at end of file
@@ -0,0 +1,83 @@
+use sqlx::{FromRow, MySqlConnection};
+
+use super::connection::Database;
+
+/// A private store left open by a character that went offline.
+#[derive(Clone)]
+pub struct StoredStore {
+    pub obj_id: u32,
+    /// Store type as the client knows it.
+    pub store_type: u8,
+    pub message: String,
+    pub items: Vec<StoredItem>,
+}
+
+#[derive(FromRow, Clone)]
+pub struct StoredItem {
+    /// Object id of an item sold, or item id of an item bought.
+    pub item: u32,
+    pub count: u64,
+    pub price: u64,
+}
+
+async fn delete(conn: &mut MySqlConnection, obj_id: u32) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM private_store_items WHERE obj_id = ?").bind(obj_id).execute(&mut *conn).await?;
+    sqlx::query("DELETE FROM private_stores WHERE obj_id = ?").bind(obj_id).execute(&mut *conn).await?;
+    Ok(())
+}
+
+/// Replaces what is stored of the store of a character.
+pub async fn save(db: &Database, store: &StoredStore) -> Result<(), String> {
+    let mut tx = match db.pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return Err(format!("Error starting save of store {}: {}", store.obj_id, e)),
+    };
+
+    if let Err(e) = delete(&mut tx, store.obj_id).await {
+        return Err(format!("Error clearing store {}: {}", store.obj_id, e));
+    }
+    let query = "INSERT INTO private_stores (obj_id, store_type, message) VALUES (?, ?, ?)";
+    if let Err(e) = sqlx::query(query).bind(store.obj_id).bind(store.store_type).bind(&store.message).execute(&mut *tx).await {
+        return Err(format!("Error saving store {}: {}", store.obj_id, e));
+    }
+    for item in &store.items {
+        let query = "INSERT INTO private_store_items (obj_id, item, count, price) VALUES (?, ?, ?, ?)";
+        if let Err(e) = sqlx::query(query).bind(store.obj_id).bind(item.item).bind(item.count).bind(item.price).execute(&mut *tx).await {
+            return Err(format!("Error saving item {} of store {}: {}", item.item, store.obj_id, e));
+        }
+    }
+
+    match tx.commit().await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error saving store {}: {}", store.obj_id, e)),
+    }
+}
+
+pub async fn remove(db: &Database, obj_id: u32) -> Result<(), String> {
+    let mut conn = match db.pool.acquire().await {
+        Ok(conn) => conn,
+        Err(e) => return Err(format!("Error removing store {}: {}", obj_id, e)),
+    };
+    match delete(&mut conn, obj_id).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error removing store {}: {}", obj_id, e)),
+    }
+}
+
+pub async fn load_all(db: &Database) -> Result<Vec<StoredStore>, String> {
+    let stores = match sqlx::query_as::<_, (u32, u8, String)>("SELECT obj_id, store_type, message FROM private_stores").fetch_all(&db.pool).await {
+        Ok(stores) => stores,
+        Err(e) => return Err(format!("Error loading private stores: {}", e)),
+    };
+
+    let mut loaded = Vec::new();
+    for (obj_id, store_type, message) in stores {
+        let query = "SELECT item, count, price FROM private_store_items WHERE obj_id = ?";
+        let items = match sqlx::query_as::<_, StoredItem>(query).bind(obj_id).fetch_all(&db.pool).await {
+            Ok(items) => items,
+            Err(e) => return Err(format!("Error loading items of store {}: {}", obj_id, e)),
+        };
+        loaded.push(StoredStore { obj_id, store_type, message, items });
+    }
+    Ok(loaded)
+}
@@ -0,0 +1,33 @@
+use super::connection::Database;
+
+/// Skills of a character still being prepared for reuse, with the Unix time in milliseconds they are ready at.
+pub async fn load(db: &Database, obj_id: u32, now: i64) -> Result<Vec<(u32, i64)>, String> {
+    let query = "SELECT skill_id, expires FROM skill_reuse WHERE obj_id = ? AND expires > ?";
+    match sqlx::query_as::<_, (u32, i64)>(query).bind(obj_id).bind(now).fetch_all(&db.pool).await {
+        Ok(reuse) => Ok(reuse),
+        Err(e) => Err(format!("Error loading skill reuse of {}: {}", obj_id, e)),
+    }
+}
+
+/// Replaces the stored reuse times of a character.
+pub async fn save(db: &Database, obj_id: u32, reuse: &[(u32, i64)]) -> Result<(), String> {
+    let mut tx = match db.pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return Err(format!("Error starting save of skill reuse of {}: {}", obj_id, e)),
+    };
+
+    if let Err(e) = sqlx::query("DELETE FROM skill_reuse WHERE obj_id = ?").bind(obj_id).execute(&mut *tx).await {
+        return Err(format!("Error clearing skill reuse of {}: {}", obj_id, e));
+    }
+    for (skill_id, expires) in reuse {
+        let query = "INSERT INTO skill_reuse (obj_id, skill_id, expires) VALUES (?, ?, ?)";
+        if let Err(e) = sqlx::query(query).bind(obj_id).bind(skill_id).bind(expires).execute(&mut *tx).await {
+            return Err(format!("Error saving reuse of skill {} of {}: {}", skill_id, obj_id, e));
+        }
+    }
+
+    match tx.commit().await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Error saving skill reuse of {}: {}", obj_id, e)),
+    }
+}
@@ -0,0 +1,54 @@
+use std::time::Instant;
+
+use crate::gameserver::bypass;
+use crate::gameserver::client::world as request;
+use crate::gameserver::combat;
+use crate::gameserver::drops;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::models::Client;
+use crate::gameserver::server::lobby::action_failed;
+use crate::gameserver::server::world as response;
+use crate::gameserver::store;
+
+/// Click on an object. An item on the ground is picked up at once. Otherwise the first click picks it as target,
+/// clicking the target again uses it: attacks a monster, talks to another NPC or shows the store of a player.
+pub async fn action(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let action = request::new_action(data)?;
+
+    let mut world = context.world();
+    let player = match world.player(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} is not in the world", obj_id)),
+    };
+    if player.known_items.contains(&action.object_id) {
+        return drops::pick_up(context, &mut world, obj_id, action.object_id, Instant::now());
+    }
+    if action.object_id != obj_id && !player.known.contains(&action.object_id) {
+        player.send(action_failed());
+        return Ok(());
+    }
+    if player.target != Some(action.object_id) {
+        player.send(response::my_target_selected(action.object_id, 0));
+        if let Some(player) = world.player_mut(obj_id) {
+            player.target = Some(action.object_id);
+        }
+        return Ok(());
+    }
+
+    if let Some(npc) = world.npc(action.object_id) {
+        if action.shift {
+            player.send(action_failed());
+            return Ok(());
+        }
+        if npc.attackable {
+            return combat::start_attack(&mut world, obj_id, action.object_id);
+        }
+        return bypass::talk(context, &mut world, obj_id, action.object_id);
+    }
+    match world.player(action.object_id) {
+        Some(other) if other.store.is_some() && !action.shift => store::show_store(context, &world, obj_id, action.object_id),
+        _ => player.send(action_failed()),
+    }
+    Ok(())
+}
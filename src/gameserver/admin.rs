@@ -0,0 +1,159 @@
+use log::info;
+
+use crate::database::{characters, chat_bans};
+use crate::gameserver::client::admin as request;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::inventory;
+use crate::gameserver::lobby::now_millis;
+use crate::gameserver::models::Client;
+use crate::gameserver::server::system_message;
+use crate::gameserver::teleport;
+
+pub async fn build_command(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let command = request::new_build_command(data)?;
+    let gm_name = match context.world().player(obj_id) {
+        Some(player) if player.is_gm() => player.character.char_name.clone(),
+        Some(player) => return Err(format!("{} used //{} without GM rights", player.character.char_name, command)),
+        None => return Err(format!("Player {} of {} is not in the world", obj_id, client.account_name)),
+    };
+    info!("{} used //{}", gm_name, command);
+
+    let args: Vec<&str> = command.split_whitespace().collect();
+    match args.as_slice() {
+        ["chat_ban", name, minutes, reason @ ..] => match minutes.parse::<i64>() {
+            Ok(minutes) if minutes >= 0 => chat_ban(context, client, &gm_name, name, minutes, &reason.join(" ")).await,
+            _ => {
+                client.send(system_message::text("Usage: //chat_ban <name> <minutes> [reason], 0 minutes for good"));
+                Ok(())
+            }
+        },
+        ["chat_unban", name] => chat_unban(context, client, &gm_name, name).await,
+        ["create_item", item_id, count @ ..] => {
+            let item_id = item_id.parse::<u32>().ok();
+            let count = match count {
+                [] => Some(1),
+                [count] => count.parse::<u64>().ok(),
+                _ => None,
+            };
+            match (item_id, count) {
+                (Some(item_id), Some(count)) => create_item(context, client, obj_id, item_id, count),
+                _ => client.send(system_message::text("Usage: //create_item <item id> [count]")),
+            }
+            Ok(())
+        },
+        ["teleport", x, y, z] => {
+            match (x.parse::<i32>(), y.parse::<i32>(), z.parse::<i32>()) {
+                (Ok(x), Ok(y), Ok(z)) => {
+                    let z = context.geodata.height(x, y, z);
+                    teleport::teleport(&mut context.world(), obj_id, (x, y, z));
+                },
+                _ => client.send(system_message::text("Usage: //teleport <x> <y> <z>")),
+            }
+            Ok(())
+        },
+        ["goto", name] => {
+            goto(context, client, obj_id, name);
+            Ok(())
+        },
+        ["recall", name] => {
+            recall(context, client, obj_id, name);
+            Ok(())
+        },
+        _ => {
+            client.send(system_message::text(&format!("Unknown command //{}", command)));
+            Ok(())
+        }
+    }
+}
+
+async fn chat_ban(context: &Context, client: &mut Client, gm_name: &str, name: &str, minutes: i64, reason: &str) -> Result<(), String> {
+    let (target_id, target_name) = match characters::find_by_name(&context.database, name).await? {
+        Some(found) => found,
+        None => {
+            client.send(system_message::text(&format!("There is no character named {}", name)));
+            return Ok(());
+        }
+    };
+
+    let expires = if minutes == 0 { 0 } else { now_millis() + minutes * 60_000 };
+    chat_bans::set(&context.database, target_id, expires, reason, gm_name).await?;
+    if let Some(target) = context.world().player_mut(target_id) {
+        target.chat_ban = Some(expires);
+        target.send(system_message::system_message(system_message::CHATTING_IS_CURRENTLY_PROHIBITED, &[]));
+    }
+
+    info!(target: "chat", "{} banned {} from chat for {} minutes: {}", gm_name, target_name, minutes, reason);
+    client.send(system_message::text(&format!("{} is banned from chat", target_name)));
+    Ok(())
+}
+
+async fn chat_unban(context: &Context, client: &mut Client, gm_name: &str, name: &str) -> Result<(), String> {
+    let (target_id, target_name) = match characters::find_by_name(&context.database, name).await? {
+        Some(found) => found,
+        None => {
+            client.send(system_message::text(&format!("There is no character named {}", name)));
+            return Ok(());
+        }
+    };
+
+    chat_bans::remove(&context.database, target_id).await?;
+    if let Some(target) = context.world().player_mut(target_id) {
+        target.chat_ban = None;
+    }
+
+    info!(target: "chat", "{} lifted the chat ban of {}", gm_name, target_name);
+    client.send(system_message::text(&format!("{} can chat again", target_name)));
+    Ok(())
+}
+
+/// Puts new items in the inventory of the GM.
+fn create_item(context: &Context, client: &Client, obj_id: u32, item_id: u32, count: u64) {
+    let mut world = context.world();
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return,
+    };
+    let name = context.datapack.items.get(item_id).map_or("unknown item", |template| template.name.as_str());
+    match player.inventory.add(&context.datapack.items, &context.ids, item_id, count) {
+        Ok(changes) => {
+            info!("{} created {} {} ({})", player.character.char_name, count, name, item_id);
+            inventory::commit(context, player, changes);
+        },
+        Err(e) => client.send(system_message::text(&format!("Can't create {} {} ({}): {}", count, name, item_id, e))),
+    }
+}
+
+/// Takes the GM to a player.
+fn goto(context: &Context, client: &Client, obj_id: u32, name: &str) {
+    let mut world = context.world();
+    let position = match world.player_by_name(name) {
+        Some(target) => target.position(),
+        None => {
+            client.send(system_message::with_text(system_message::S1_IS_NOT_ONLINE, name));
+            return;
+        }
+    };
+    teleport::teleport(&mut world, obj_id, position);
+}
+
+/// Brings a player to the GM. Offline stores stay where they are, they have no client to load the new place.
+fn recall(context: &Context, client: &Client, obj_id: u32, name: &str) {
+    let mut world = context.world();
+    let target_id = match world.player_by_name(name) {
+        Some(target) if target.offline => {
+            client.send(system_message::text(&format!("{} is an offline store", target.character.char_name)));
+            return;
+        },
+        Some(target) => target.obj_id(),
+        None => {
+            client.send(system_message::with_text(system_message::S1_IS_NOT_ONLINE, name));
+            return;
+        }
+    };
+    let position = match world.get(obj_id) {
+        Some(gm) => gm.position(),
+        None => return,
+    };
+    teleport::teleport(&mut world, target_id, position);
+}
@@ -0,0 +1,584 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::gameserver::combat;
+use crate::gameserver::datapack::npcs::{AiType, NpcTemplate};
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::movement::{self, Route, RouteGoal};
+use crate::gameserver::skills;
+use crate::gameserver::stats::{self, Stat};
+use crate::gameserver::teleport;
+use crate::gameserver::world::{World, WorldObject};
+
+/// How often NPCs think.
+const AI_TICK: Duration = Duration::from_secs(1);
+/// Farthest an NPC goes from home after its enemies before it gives up and goes back.
+const LEASH_RANGE: f64 = 2000.0;
+/// How far from home NPCs walk around.
+const WANDER_RANGE: i32 = 300;
+/// Chance an NPC with nothing to do walks somewhere on a tick.
+const WANDER_CHANCE: f64 = 0.1;
+/// Chance a fighting NPC casts one of its skills on a tick.
+const CAST_CHANCE: f64 = 0.3;
+/// How close a follower stays to the ally it follows.
+const FOLLOW_DISTANCE: f64 = 100.0;
+/// Distance from home that counts as being there.
+const HOME_DISTANCE: f64 = 30.0;
+
+/// What an NPC is up to, looked at on every AI tick.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Intention {
+    /// No player is around, the NPC waits without thinking.
+    #[default]
+    Idle,
+    /// Nothing to do: it walks around home and, if aggressive, looks out for players.
+    Active,
+    /// Fights the creature it hates most, this one at first.
+    Attack(u32),
+    /// Casts a skill on a creature, then goes back to attacking.
+    Cast(u32),
+    /// Goes after an ally until it sees the enemy the ally fights.
+    Follow(u32),
+    MoveTo((i32, i32, i32)),
+}
+
+/// Hate of an NPC toward the creatures that attacked it, the most hated one is fought.
+#[derive(Default)]
+pub struct HateList {
+    hate: HashMap<u32, f64>,
+}
+
+impl HateList {
+    pub fn add(&mut self, obj_id: u32, hate: f64) {
+        *self.hate.entry(obj_id).or_default() += hate;
+    }
+
+    pub fn remove(&mut self, obj_id: u32) {
+        self.hate.remove(&obj_id);
+    }
+
+    pub fn clear(&mut self) {
+        self.hate.clear();
+    }
+
+    /// Drops the creatures `valid` rejects and gives the most hated of the rest. Ties go to the lowest object id.
+    pub fn most_hated(&mut self, valid: impl Fn(u32) -> bool) -> Option<u32> {
+        self.hate.retain(|obj_id, _| valid(*obj_id));
+        self.hate.iter()
+            .max_by(|(a, hate_a), (b, hate_b)| hate_a.total_cmp(hate_b).then(b.cmp(a)))
+            .map(|(obj_id, _)| *obj_id)
+    }
+}
+
+/// Mind of an NPC.
+#[derive(Default)]
+pub struct Ai {
+    pub intention: Intention,
+    pub hate: HateList,
+    /// Going back home, deaf to attacks until it gets there.
+    pub returning: bool,
+    /// When each skill the NPC used lately can be used again, by skill id.
+    pub reuse: HashMap<u32, Instant>,
+}
+
+/// Has the NPCs near players think for as long as the server runs. NPCs whose region goes quiet are put to
+/// sleep, back home and healed.
+pub async fn run(context: Arc<Context>) {
+    let mut interval = tokio::time::interval(AI_TICK);
+    let mut thinking = HashSet::new();
+    loop {
+        interval.tick().await;
+        thinking = update(&context, &mut context.world(), &thinking, Instant::now());
+        movement::find_routes(&context);
+    }
+}
+
+fn update(context: &Context, world: &mut World, thinking: &HashSet<u32>, now: Instant) -> HashSet<u32> {
+    let active: HashSet<u32> = world.active_npcs().into_iter().collect();
+    for obj_id in thinking.difference(&active) {
+        go_idle(context, world, *obj_id);
+    }
+    for obj_id in &active {
+        think(context, world, *obj_id, now);
+    }
+    active
+}
+
+fn think(context: &Context, world: &mut World, obj_id: u32, now: Instant) {
+    let (intention, template) = match world.npc(obj_id) {
+        Some(npc) if npc.attackable && !npc.is_dead() => match context.datapack.npcs.get(npc.npc_id) {
+            Some(template) => (npc.ai.intention, template),
+            None => return,
+        },
+        _ => return,
+    };
+    match intention {
+        Intention::Idle => set_intention(world, obj_id, Intention::Active),
+        Intention::Active => think_active(context, world, obj_id, template, now),
+        Intention::Attack(_) => think_attack(context, world, obj_id, template, now),
+        Intention::Cast(target_id) => {
+            // Back to the fight once the cast is over.
+            if world.npc(obj_id).is_some_and(|npc| npc.cast.is_none()) {
+                attack(world, obj_id, target_id);
+            }
+        },
+        Intention::Follow(ally_id) => think_follow(context, world, obj_id, ally_id, now),
+        Intention::MoveTo(_) => think_move(context, world, obj_id),
+    }
+}
+
+fn set_intention(world: &mut World, obj_id: u32, intention: Intention) {
+    if let Some(npc) = world.npc_mut(obj_id) {
+        npc.ai.intention = intention;
+    }
+}
+
+/// NPCs run while fighting and walk otherwise, which everyone around sees.
+fn set_running(world: &mut World, obj_id: u32, running: bool) {
+    match world.npc_mut(obj_id) {
+        Some(npc) if npc.running != running => npc.running = running,
+        _ => return,
+    }
+    world.broadcast_info(obj_id);
+}
+
+/// Whether an NPC can go on fighting a creature: it is alive, seen and not on its way elsewhere.
+fn is_enemy(world: &World, obj_id: u32, enemy_id: u32) -> bool {
+    let known = world.npc(obj_id).is_some_and(|npc| npc.known.contains(&enemy_id));
+    known && match world.get(enemy_id) {
+        Some(WorldObject::Player(player)) => !player.is_dead() && !player.teleporting,
+        Some(WorldObject::Npc(npc)) => !npc.is_dead(),
+        None => false,
+    }
+}
+
+fn attack(world: &mut World, obj_id: u32, target_id: u32) {
+    set_intention(world, obj_id, Intention::Attack(target_id));
+    set_running(world, obj_id, true);
+    combat::attack(world, obj_id, target_id);
+}
+
+/// An attack or a debuff landed on a creature. An NPC hates the attacker for it, fights back unless it is busy
+/// fighting already, and calls its faction for help.
+pub fn notify_attacked(context: &Context, world: &mut World, obj_id: u32, attacker_id: u32, hate: f64) {
+    let npc = match world.npc_mut(obj_id) {
+        Some(npc) if npc.attackable && !npc.is_dead() && !npc.ai.returning && attacker_id != obj_id => npc,
+        _ => return,
+    };
+    npc.ai.hate.add(attacker_id, hate);
+    if !matches!(npc.ai.intention, Intention::Attack(_) | Intention::Cast(_)) {
+        attack(world, obj_id, attacker_id);
+    }
+    call_faction(context, world, obj_id, attacker_id);
+}
+
+/// Has the allies of an attacked NPC within its faction range join the fight. Those that don't see the
+/// attacker yet come over first.
+fn call_faction(context: &Context, world: &mut World, obj_id: u32, attacker_id: u32) {
+    let (faction, position, known) = match world.npc(obj_id) {
+        Some(npc) => match context.datapack.npcs.get(npc.npc_id).and_then(|template| template.faction.as_ref()) {
+            Some(faction) => (faction, npc.position(), npc.known.clone()),
+            None => return,
+        },
+        None => return,
+    };
+    for ally_id in known {
+        let (sees_attacker, ally_position) = match world.npc(ally_id) {
+            Some(ally) if !ally.is_dead() && !ally.ai.returning
+                && matches!(ally.ai.intention, Intention::Idle | Intention::Active | Intention::MoveTo(_))
+                && context.datapack.npcs.get(ally.npc_id)
+                    .and_then(|template| template.faction.as_ref())
+                    .is_some_and(|ally_faction| ally_faction.name == faction.name) =>
+                (ally.known.contains(&attacker_id), ally.position()),
+            _ => continue,
+        };
+        if combat::distance_2d(position, ally_position) > faction.range as f64 {
+            continue;
+        }
+        if sees_attacker {
+            if let Some(ally) = world.npc_mut(ally_id) {
+                ally.ai.hate.add(attacker_id, 1.0);
+            }
+            attack(world, ally_id, attacker_id);
+        } else {
+            set_intention(world, ally_id, Intention::Follow(obj_id));
+            set_running(world, ally_id, true);
+        }
+    }
+}
+
+/// Aggressive NPCs go for the nearest player they see within their aggro range, everyone else may take a walk.
+fn think_active(context: &Context, world: &mut World, obj_id: u32, template: &NpcTemplate, now: Instant) {
+    let npc = match world.npc(obj_id) {
+        Some(npc) => npc,
+        None => return,
+    };
+    let position = npc.position();
+    if template.ai == AiType::Aggressive {
+        let prey = npc.known.iter()
+            .filter_map(|other| world.player(*other))
+            .filter(|player| !player.is_dead() && !player.teleporting)
+            .map(|player| (player.obj_id(), combat::distance_2d(position, player.position()), player.position()))
+            .filter(|(_, distance, _)| *distance <= template.stats.aggro_range as f64)
+            .filter(|(_, _, to)| context.geodata.can_see(position, *to))
+            .min_by(|(_, a, _), (_, b, _)| a.total_cmp(b))
+            .map(|(prey, _, _)| prey);
+        if let Some(prey) = prey {
+            if let Some(npc) = world.npc_mut(obj_id) {
+                npc.ai.hate.add(prey, 1.0);
+            }
+            attack(world, obj_id, prey);
+            return;
+        }
+    }
+    if template.ai != AiType::None && npc.movement.is_none() && rand::thread_rng().gen_bool(WANDER_CHANCE) {
+        wander(context, world, obj_id, now);
+    }
+}
+
+/// Walks to a random spot around home.
+fn wander(context: &Context, world: &mut World, obj_id: u32, now: Instant) {
+    let (position, home) = match world.npc(obj_id) {
+        Some(npc) => (npc.position(), npc.home),
+        None => return,
+    };
+    let mut rng = rand::thread_rng();
+    let spot = (home.0 + rng.gen_range(-WANDER_RANGE..=WANDER_RANGE), home.1 + rng.gen_range(-WANDER_RANGE..=WANDER_RANGE), home.2);
+    let reached = context.geodata.move_check(position, spot);
+    if (reached.0, reached.1) == (position.0, position.1) {
+        return;
+    }
+    set_running(world, obj_id, false);
+    movement::walk(world, obj_id, position, vec![reached], now);
+    set_intention(world, obj_id, Intention::MoveTo(reached));
+}
+
+/// Fights the most hated enemy, now and then with a skill. With no enemy left, or too far from home, the NPC
+/// goes back. Enemies it finds no way to are dropped by the chase.
+fn think_attack(context: &Context, world: &mut World, obj_id: u32, template: &NpcTemplate, now: Instant) {
+    let (position, home, fighting) = match world.npc(obj_id) {
+        Some(npc) => (npc.position(), npc.home, npc.combat.target),
+        None => return,
+    };
+    if combat::distance_2d(position, home) > LEASH_RANGE {
+        go_home(context, world, obj_id, now);
+        return;
+    }
+    let valid: Vec<u32> = match world.npc(obj_id) {
+        Some(npc) => npc.known.iter().copied().filter(|other| is_enemy(world, obj_id, *other)).collect(),
+        None => return,
+    };
+    let target_id = match world.npc_mut(obj_id).and_then(|npc| npc.ai.hate.most_hated(|other| valid.contains(&other))) {
+        Some(target_id) => target_id,
+        None => {
+            go_home(context, world, obj_id, now);
+            return;
+        }
+    };
+    if fighting != Some(target_id) {
+        attack(world, obj_id, target_id);
+        return;
+    }
+    if rand::thread_rng().gen_bool(CAST_CHANCE) {
+        cast(context, world, obj_id, template, target_id, now);
+    }
+}
+
+/// Casts one of the offensive skills of an NPC on its enemy, if one is ready, affordable and in range.
+fn cast(context: &Context, world: &mut World, obj_id: u32, template: &NpcTemplate, target_id: u32, now: Instant) {
+    let npc = match world.npc(obj_id) {
+        Some(npc) if npc.cast.is_none() => npc,
+        _ => return,
+    };
+    let ready: Vec<_> = template.skills.iter()
+        .filter_map(|skill| context.datapack.skills.get(skill.id, skill.level))
+        .filter(|skill| skill.kind.is_offensive() && skill.mp <= npc.cur_mp)
+        .filter(|skill| npc.ai.reuse.get(&skill.skill_id).is_none_or(|ready_at| *ready_at <= now))
+        .filter(|skill| skills::in_range(world, obj_id, target_id, skill))
+        .collect();
+    let skill = match ready.choose(&mut rand::thread_rng()) {
+        Some(skill) => *skill,
+        None => return,
+    };
+    let can_see = world.get(target_id).is_some_and(|target| context.geodata.can_see(npc.position(), target.position()));
+    if !can_see {
+        return;
+    }
+    if let Some(npc) = world.npc_mut(obj_id) {
+        npc.cur_mp -= skill.mp;
+        npc.ai.reuse.insert(skill.skill_id, now + skill.reuse);
+        npc.ai.intention = Intention::Cast(target_id);
+    }
+    world.start_recovering(obj_id);
+    skills::begin_cast(world, obj_id, skill, target_id, now);
+}
+
+/// Goes after an ally until the enemy it fights comes into sight, and home once the ally stops fighting.
+fn think_follow(context: &Context, world: &mut World, obj_id: u32, ally_id: u32, now: Instant) {
+    let (position, home, moving) = match world.npc(obj_id) {
+        Some(npc) => (npc.position(), npc.home, npc.movement.is_some()),
+        None => return,
+    };
+    let (ally_position, enemy) = match world.npc(ally_id) {
+        Some(ally) if !ally.is_dead() => (ally.position(), ally.combat.target),
+        _ => {
+            go_home(context, world, obj_id, now);
+            return;
+        }
+    };
+    let enemy = match enemy {
+        Some(enemy) if combat::distance_2d(position, home) <= LEASH_RANGE => enemy,
+        _ => {
+            go_home(context, world, obj_id, now);
+            return;
+        }
+    };
+    if is_enemy(world, obj_id, enemy) {
+        if let Some(npc) = world.npc_mut(obj_id) {
+            npc.ai.hate.add(enemy, 1.0);
+        }
+        attack(world, obj_id, enemy);
+        return;
+    }
+    if !moving && combat::distance_2d(position, ally_position) > FOLLOW_DISTANCE {
+        let reached = context.geodata.move_check(position, ally_position);
+        if (reached.0, reached.1) != (position.0, position.1) {
+            movement::walk(world, obj_id, position, vec![reached], now);
+        }
+    }
+}
+
+/// Done walking: a wander is over, and an NPC back home heals up. One that couldn't make it home is put there.
+fn think_move(context: &Context, world: &mut World, obj_id: u32) {
+    let (position, home, returning) = match world.npc(obj_id) {
+        Some(npc) if npc.movement.is_none() => (npc.position(), npc.home, npc.ai.returning),
+        _ => return,
+    };
+    if returning {
+        if combat::distance_2d(position, home) > HOME_DISTANCE {
+            teleport::teleport(world, obj_id, home);
+        }
+        heal(context, world, obj_id);
+        set_running(world, obj_id, false);
+        if let Some(npc) = world.npc_mut(obj_id) {
+            npc.ai.returning = false;
+        }
+    }
+    set_intention(world, obj_id, Intention::Active);
+}
+
+/// Drops every enemy and heads back home, around walls when it has to.
+fn go_home(context: &Context, world: &mut World, obj_id: u32, now: Instant) {
+    let (position, home) = match world.npc_mut(obj_id) {
+        Some(npc) => {
+            npc.ai.hate.clear();
+            npc.ai.returning = true;
+            npc.ai.intention = Intention::MoveTo(npc.home);
+            (npc.position(), npc.home)
+        },
+        None => return,
+    };
+    combat::stop_attack(world, obj_id);
+    skills::cancel(world, obj_id);
+    let reached = context.geodata.move_check(position, home);
+    if (reached.0, reached.1) == (home.0, home.1) {
+        movement::walk(world, obj_id, position, vec![reached], now);
+    } else {
+        world.stop_moving(obj_id);
+        world.request_route(obj_id, Route { from: position, to: home, goal: RouteGoal::Home });
+    }
+}
+
+/// Sets an NPC on the path found back home, if it is still going there from where it asked. With no way there
+/// it stays put, and the next thought puts it home.
+pub fn head_home(world: &mut World, obj_id: u32, from: (i32, i32, i32), path: Option<Vec<(i32, i32, i32)>>, now: Instant) {
+    let still_returning = world.npc(obj_id).is_some_and(|npc| npc.ai.returning && npc.position() == from);
+    if let (true, Some(path)) = (still_returning, path) {
+        movement::walk(world, obj_id, from, path, now);
+    }
+}
+
+/// Brings an NPC back to full HP and MP, the damage it took no longer counts toward rewards.
+fn heal(context: &Context, world: &mut World, obj_id: u32) {
+    if let Some(npc) = world.npc_mut(obj_id) {
+        npc.damage.clear();
+        npc.cur_hp = npc.stats.get(Stat::MaxHp);
+        npc.cur_mp = npc.stats.get(Stat::MaxMp);
+    }
+    // Functions may depend on HP.
+    stats::refresh(context, world, obj_id);
+    combat::show_hp(world, obj_id);
+}
+
+/// Puts an NPC no player is near to sleep: it forgets its enemies and is back home, healed.
+fn go_idle(context: &Context, world: &mut World, obj_id: u32) {
+    let home = match world.npc_mut(obj_id) {
+        Some(npc) if npc.ai.intention != Intention::Idle => {
+            npc.ai.hate.clear();
+            npc.ai.returning = false;
+            npc.ai.intention = Intention::Idle;
+            npc.home
+        },
+        _ => return,
+    };
+    combat::stop_attack(world, obj_id);
+    skills::cancel(world, obj_id);
+    world.stop_moving(obj_id);
+    if world.npc(obj_id).is_some_and(|npc| !npc.is_dead()) {
+        teleport::teleport(world, obj_id, home);
+        heal(context, world, obj_id);
+        set_running(world, obj_id, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameserver::gameserver::synthetic::context;
+    use crate::gameserver::geodata::Geodata;
+    use crate::gameserver::npc::Npc;
+    use crate::gameserver::player::synthetic::player;
+
+    #[test]
+    fn the_most_hated_valid_creature_is_fought() {
+        let mut hate = HateList::default();
+        hate.add(1, 10.0);
+        hate.add(2, 30.0);
+        hate.add(3, 30.0);
+        hate.add(1, 25.0);
+        assert_eq!(hate.most_hated(|_| true), Some(1));
+        assert_eq!(hate.most_hated(|obj_id| obj_id != 1), Some(2));
+        // Rejected creatures are forgotten.
+        assert_eq!(hate.most_hated(|_| true), Some(2));
+        hate.clear();
+        assert_eq!(hate.most_hated(|_| true), None);
+    }
+
+    /// Wolves are aggressive with an aggro range of 300 and call the wolves within 500, gremlins are passive.
+    const WOLF: u32 = 20120;
+    const GREMLIN: u32 = 20001;
+
+    fn spawn(context: &Context, world: &mut World, obj_id: u32, npc_id: u32, (x, y): (i32, i32)) {
+        let template = context.datapack.npcs.get(npc_id).unwrap();
+        world.add(WorldObject::Npc(Box::new(Npc::new(obj_id, template, (x, y, 0), 0, None)))).unwrap();
+    }
+
+    fn enter(context: &Context, world: &mut World, obj_id: u32, (x, y): (i32, i32)) {
+        world.add(WorldObject::Player(Box::new(player(&context.datapack, obj_id, "Hunter", x, y)))).unwrap();
+    }
+
+    fn intention(world: &World, obj_id: u32) -> Intention {
+        world.npc(obj_id).unwrap().ai.intention
+    }
+
+    #[tokio::test]
+    async fn aggressive_npcs_attack_players_within_their_aggro_range() {
+        let context = context(Geodata::empty());
+        let mut world = World::new();
+        spawn(&context, &mut world, 10, WOLF, (1000, 1000));
+        spawn(&context, &mut world, 11, WOLF, (3000, 1000));
+        spawn(&context, &mut world, 12, GREMLIN, (5000, 1000));
+        enter(&context, &mut world, 1, (1250, 1000));
+        enter(&context, &mut world, 2, (3400, 1000));
+        enter(&context, &mut world, 3, (5050, 1000));
+
+        // The first thought wakes them up, the next one looks around.
+        let now = Instant::now();
+        let thinking = update(&context, &mut world, &HashSet::new(), now);
+        update(&context, &mut world, &thinking, now);
+        assert_eq!(intention(&world, 10), Intention::Attack(1));
+        assert!(!matches!(intention(&world, 11), Intention::Attack(_)));
+        assert!(!matches!(intention(&world, 12), Intention::Attack(_)));
+    }
+
+    #[tokio::test]
+    async fn npcs_led_too_far_go_back_home_and_heal() {
+        let context = context(Geodata::empty());
+        let mut world = World::new();
+        spawn(&context, &mut world, 10, WOLF, (1000, 1000));
+        enter(&context, &mut world, 1, (1050, 1000));
+        notify_attacked(&context, &mut world, 10, 1, 10.0);
+        assert_eq!(intention(&world, 10), Intention::Attack(1));
+
+        // Within the leash it keeps fighting.
+        let now = Instant::now();
+        world.move_to(10, 2900, 1000, 0);
+        world.move_to(1, 2950, 1000, 0);
+        think(&context, &mut world, 10, now);
+        assert_eq!(intention(&world, 10), Intention::Attack(1));
+
+        world.move_to(10, 3100, 1000, 0);
+        world.move_to(1, 3150, 1000, 0);
+        world.npc_mut(10).unwrap().cur_hp = 1.0;
+        think(&context, &mut world, 10, now);
+        let npc = world.npc(10).unwrap();
+        assert_eq!(npc.ai.intention, Intention::MoveTo((1000, 1000, 0)));
+        assert!(npc.ai.returning && npc.movement.is_some() && npc.combat.target.is_none());
+        // Deaf to attacks on the way.
+        notify_attacked(&context, &mut world, 10, 1, 10.0);
+        assert_eq!(intention(&world, 10), Intention::MoveTo((1000, 1000, 0)));
+
+        // Stopped short of home it is put there.
+        world.stop_moving(10);
+        think(&context, &mut world, 10, now);
+        let npc = world.npc(10).unwrap();
+        assert_eq!(npc.position(), (1000, 1000, 0));
+        assert_eq!(npc.cur_hp, npc.stats.get(Stat::MaxHp));
+        assert!(!npc.ai.returning);
+        assert_eq!(npc.ai.intention, Intention::Active);
+    }
+
+    #[tokio::test]
+    async fn attacked_npcs_call_their_faction_in_range() {
+        let context = context(Geodata::empty());
+        let mut world = World::new();
+        spawn(&context, &mut world, 10, WOLF, (1000, 1000));
+        spawn(&context, &mut world, 11, WOLF, (1400, 1000));
+        spawn(&context, &mut world, 12, WOLF, (1000, 1400));
+        spawn(&context, &mut world, 13, WOLF, (1000, 1600));
+        spawn(&context, &mut world, 14, GREMLIN, (1100, 1000));
+        enter(&context, &mut world, 1, (950, 1000));
+        // This one is in range but doesn't see the attacker.
+        world.npc_mut(12).unwrap().known.remove(&1);
+
+        notify_attacked(&context, &mut world, 10, 1, 10.0);
+        assert_eq!(intention(&world, 10), Intention::Attack(1));
+        assert_eq!(intention(&world, 11), Intention::Attack(1));
+        assert_eq!(intention(&world, 12), Intention::Follow(10));
+        // Too far, or of another faction.
+        assert_eq!(intention(&world, 13), Intention::Idle);
+        assert_eq!(intention(&world, 14), Intention::Idle);
+    }
+
+    #[tokio::test]
+    async fn npcs_no_player_is_near_go_to_sleep_at_home() {
+        let context = context(Geodata::empty());
+        let mut world = World::new();
+        spawn(&context, &mut world, 10, WOLF, (1000, 1000));
+        spawn(&context, &mut world, 11, WOLF, (20000, 20000));
+        enter(&context, &mut world, 1, (20050, 20000));
+        let now = Instant::now();
+        let thinking = update(&context, &mut world, &HashSet::new(), now);
+        assert_eq!(thinking, HashSet::from([11]));
+
+        // The wolf was fighting someone who left, far from home.
+        let npc = world.npc_mut(10).unwrap();
+        npc.ai.intention = Intention::Attack(2);
+        npc.ai.hate.add(2, 10.0);
+        npc.cur_hp = 1.0;
+        world.move_to(10, 1500, 1000, 0);
+
+        let thinking = update(&context, &mut world, &HashSet::from([10, 11]), now);
+        assert_eq!(thinking, HashSet::from([11]));
+        let npc = world.npc_mut(10).unwrap();
+        assert_eq!(npc.ai.intention, Intention::Idle);
+        assert_eq!(npc.position(), (1000, 1000, 0));
+        assert_eq!(npc.cur_hp, npc.stats.get(Stat::MaxHp));
+        assert_eq!(npc.ai.hate.most_hated(|_| true), None);
+        assert_ne!(intention(&world, 11), Intention::Idle);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,208 @@
+use crate::gameserver::client::npc as request;
+use crate::gameserver::datapack::npcs::NpcType;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::html::{self, Dialog};
+use crate::gameserver::merchant;
+use crate::gameserver::models::Client;
+use crate::gameserver::server::lobby::action_failed;
+use crate::gameserver::server::npc as response;
+use crate::gameserver::server::system_message;
+use crate::gameserver::teleport;
+use crate::gameserver::warehouse::{self, WarehouseKind};
+use crate::gameserver::world::World;
+
+/// Dialog shown when an NPC has no file of its own.
+const DEFAULT_DIALOG: &str = "default/npc.htm";
+const NO_QUEST_DIALOG: &str = "default/noquest.htm";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Shop {
+    /// Buy list of a merchant.
+    Buy(u32),
+    Sell,
+    Multisell(u32),
+}
+
+/// What a bypass asks the NPC for.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Bypass {
+    /// Page of the NPC's own dialog, 0 being the first one.
+    Chat(u32),
+    /// Any dialog file.
+    Link(String),
+    Shop(Shop),
+    Teleport(u32),
+    /// Quests the NPC takes part in, or the one named.
+    Quest(Option<String>),
+    Warehouse { kind: WarehouseKind, deposit: bool },
+}
+
+/// Reads an `npc_<objectId>_<command>` bypass, the only kind there is for now.
+pub fn parse(command: &str) -> Option<(u32, Bypass)> {
+    let rest = command.strip_prefix("npc_")?;
+    let (npc, rest) = rest.split_once('_')?;
+    let npc = npc.parse().ok()?;
+    let (name, argument) = match rest.split_once(' ') {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (rest, ""),
+    };
+
+    let bypass = match name {
+        "Chat" if argument.is_empty() => Bypass::Chat(0),
+        "Chat" => Bypass::Chat(argument.parse().ok()?),
+        "Link" if !argument.is_empty() && !argument.contains("..") => Bypass::Link(argument.to_string()),
+        "Buy" => Bypass::Shop(Shop::Buy(argument.parse().ok()?)),
+        "Sell" => Bypass::Shop(Shop::Sell),
+        "Multisell" => Bypass::Shop(Shop::Multisell(argument.parse().ok()?)),
+        "teleport" => Bypass::Teleport(argument.parse().ok()?),
+        "Quest" if argument.is_empty() => Bypass::Quest(None),
+        "Quest" => Bypass::Quest(Some(argument.to_string())),
+        "DepositP" => Bypass::Warehouse { kind: WarehouseKind::Private, deposit: true },
+        "WithdrawP" => Bypass::Warehouse { kind: WarehouseKind::Private, deposit: false },
+        "DepositC" => Bypass::Warehouse { kind: WarehouseKind::Clan, deposit: true },
+        "WithdrawC" => Bypass::Warehouse { kind: WarehouseKind::Clan, deposit: false },
+        _ => return None,
+    };
+    Some((npc, bypass))
+}
+
+/// Directory of the dialogs of a type of NPC.
+fn dialog_dir(npc_type: NpcType) -> &'static str {
+    match npc_type {
+        NpcType::Merchant => "merchant",
+        NpcType::Warehouse => "warehouse",
+        NpcType::Teleporter => "teleporter",
+        NpcType::Guard => "guard",
+        NpcType::Monster | NpcType::RaidBoss | NpcType::Folk => "default",
+    }
+}
+
+/// Path of a page of an NPC's dialog: `<dir>/<npcId>.htm` first, then `<dir>/<npcId>-<page>.htm`.
+fn chat_path(npc_type: NpcType, npc_id: u32, page: u32) -> String {
+    match page {
+        0 => format!("{}/{}.htm", dialog_dir(npc_type), npc_id),
+        page => format!("{}/{}-{}.htm", dialog_dir(npc_type), npc_id, page),
+    }
+}
+
+/// Sends a dialog file to a player in the name of an NPC, remembering the bypasses it offers.
+pub fn show_page(context: &Context, world: &mut World, obj_id: u32, npc_obj_id: u32, path: &str) -> Result<(), String> {
+    let html = match context.html.get(path) {
+        Some(html) => html,
+        None => return Err(format!("No dialog {}", path)),
+    };
+    let (player, npc) = match (world.player(obj_id), world.npc(npc_obj_id)) {
+        (Some(player), Some(npc)) => (player, npc),
+        _ => return Ok(()),
+    };
+
+    let html = html::fill(html, &[
+        ("objectId", &npc_obj_id.to_string()),
+        ("npcname", &npc.name),
+        ("playername", &player.character.char_name),
+    ]);
+    player.send(response::npc_html_message(npc_obj_id, &html));
+    if let Some(player) = world.player_mut(obj_id) {
+        player.dialog = Some(Dialog { bypasses: html::bypasses(&html) });
+    }
+    Ok(())
+}
+
+/// Shows a page of the NPC's own dialog, its first page falling back to the default dialog.
+fn show_chat(context: &Context, world: &mut World, obj_id: u32, npc_obj_id: u32, page: u32) -> Result<(), String> {
+    let npc_id = match world.npc(npc_obj_id) {
+        Some(npc) => npc.npc_id,
+        None => return Ok(()),
+    };
+    let npc_type = match context.datapack.npcs.get(npc_id) {
+        Some(template) => template.npc_type,
+        None => NpcType::Folk,
+    };
+    let path = chat_path(npc_type, npc_id, page);
+    let path = if page == 0 && context.html.get(&path).is_none() { DEFAULT_DIALOG.to_string() } else { path };
+    show_page(context, world, obj_id, npc_obj_id, &path)
+}
+
+/// A player talking to an NPC it clicked on twice.
+pub fn talk(context: &Context, world: &mut World, obj_id: u32, npc_obj_id: u32) -> Result<(), String> {
+    let (player, npc) = match (world.player(obj_id), world.npc(npc_obj_id)) {
+        (Some(player), Some(npc)) => (player, npc),
+        _ => return Ok(()),
+    };
+    let (x, y, _) = player.position();
+    if !npc.is_within_reach((x, y)) {
+        player.send(system_message::system_message(system_message::TARGET_TOO_FAR, &[]));
+        player.send(action_failed());
+        return Ok(());
+    }
+    show_chat(context, world, obj_id, npc_obj_id, 0)
+}
+
+pub async fn request_bypass_to_server(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let command = request::new_request_bypass_to_server(data)?;
+
+    {
+        let world = context.world();
+        let player = match world.player(obj_id) {
+            Some(player) => player,
+            None => return Err(format!("Player {} is not in the world", obj_id)),
+        };
+        let offered = player.dialog.as_ref().is_some_and(|dialog| html::is_offered(&dialog.bypasses, &command));
+        if !offered {
+            return Err(format!("{} sent bypass '{}' it wasn't offered", player.character.char_name, command));
+        }
+        let npc_obj_id = match parse(&command) {
+            Some((npc_obj_id, _)) => npc_obj_id,
+            None => return Err(format!("Unknown bypass '{}'", command)),
+        };
+        let (x, y, _) = player.position();
+        if !world.npc(npc_obj_id).is_some_and(|npc| npc.is_within_reach((x, y))) {
+            player.send(system_message::system_message(system_message::TARGET_TOO_FAR, &[]));
+            return Ok(());
+        }
+    }
+
+    let (npc_obj_id, bypass) = match parse(&command) {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    match bypass {
+        Bypass::Chat(page) => show_chat(context, &mut context.world(), obj_id, npc_obj_id, page),
+        Bypass::Link(path) => show_page(context, &mut context.world(), obj_id, npc_obj_id, &path),
+        Bypass::Shop(shop) => merchant::open_shop(context, obj_id, npc_obj_id, shop),
+        Bypass::Teleport(id) => teleport_bypass(context, obj_id, npc_obj_id, id),
+        Bypass::Quest(_) => show_page(context, &mut context.world(), obj_id, npc_obj_id, NO_QUEST_DIALOG),
+        Bypass::Warehouse { kind, deposit } => warehouse::open(context, obj_id, npc_obj_id, kind, deposit).await,
+    }
+}
+
+fn teleport_bypass(context: &Context, obj_id: u32, npc_obj_id: u32, id: u32) -> Result<(), String> {
+    let destination = match context.datapack.teleports.get(id) {
+        Some(destination) => destination,
+        None => return Err(format!("NPC {} offered unknown teleport {}", npc_obj_id, id)),
+    };
+    teleport::travel(context, &mut context.world(), obj_id, destination)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_npc_bypasses() {
+        assert_eq!(parse("npc_268435457_Chat 2"), Some((268435457, Bypass::Chat(2))));
+        assert_eq!(parse("npc_268435457_Chat"), Some((268435457, Bypass::Chat(0))));
+        assert_eq!(parse("npc_268435457_Buy 3"), Some((268435457, Bypass::Shop(Shop::Buy(3)))));
+        assert_eq!(parse("npc_268435457_WithdrawC"), Some((268435457, Bypass::Warehouse { kind: WarehouseKind::Clan, deposit: false })));
+        assert_eq!(parse("npc_268435457_Link merchant/30001-1.htm"), Some((268435457, Bypass::Link("merchant/30001-1.htm".to_string()))));
+    }
+
+    #[test]
+    fn rejects_malformed_bypasses() {
+        assert_eq!(parse("npc_268435457_Link ../../config/network.toml"), None);
+        assert_eq!(parse("npc_x_Chat 1"), None);
+        assert_eq!(parse("npc_268435457_Buy"), None);
+        assert_eq!(parse("admin_kill"), None);
+    }
+}
@@ -0,0 +1,425 @@
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::config::config::{ChatFilter, ChatRange, FilterAction, Flood};
+use crate::database::{blocks, characters};
+use crate::gameserver::client::chat as request;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::lobby::now_millis;
+use crate::gameserver::models::Client;
+use crate::gameserver::player::Player;
+use crate::gameserver::server::chat as response;
+use crate::gameserver::server::system_message;
+use crate::gameserver::world::World;
+
+pub const ALL: u32 = 0;
+pub const SHOUT: u32 = 1;
+pub const TELL: u32 = 2;
+pub const PARTY: u32 = 3;
+pub const CLAN: u32 = 4;
+pub const GM: u32 = 5;
+pub const PETITION_PLAYER: u32 = 6;
+pub const PETITION_GM: u32 = 7;
+pub const TRADE: u32 = 8;
+pub const ALLIANCE: u32 = 9;
+pub const HERO_VOICE: u32 = 17;
+
+/// Longest message the client lets players type.
+const MAX_TEXT_LENGTH: usize = 105;
+/// Distance general chat is heard at.
+const GENERAL_RANGE: f64 = 1250.0;
+/// Shout and trade chat limited to the region reach the players in the same 32768x32768 map tile.
+const TILE_SHIFT: i32 = 15;
+
+fn channel_name(channel: u32) -> &'static str {
+    match channel {
+        ALL => "ALL",
+        SHOUT => "SHOUT",
+        TELL => "TELL",
+        PARTY => "PARTY",
+        CLAN => "CLAN",
+        GM => "GM",
+        PETITION_PLAYER | PETITION_GM => "PETITION",
+        TRADE => "TRADE",
+        ALLIANCE => "ALLIANCE",
+        HERO_VOICE => "HERO",
+        _ => "UNKNOWN",
+    }
+}
+
+fn flood_interval(flood: &Flood, channel: u32) -> u64 {
+    match channel {
+        ALL => flood.all,
+        SHOUT => flood.shout,
+        TELL => flood.tell,
+        TRADE => flood.trade,
+        CLAN => flood.clan,
+        HERO_VOICE => flood.hero_voice,
+        _ => 0,
+    }
+}
+
+/// Whether the player spoke on the channel too recently, the message is recorded when it is not.
+fn is_flooding(speaker: &mut Player, flood: &Flood, channel: u32, now: Instant) -> bool {
+    let interval = Duration::from_millis(flood_interval(flood, channel));
+    if let Some(last) = speaker.last_chat.get(&channel) {
+        if now.duration_since(*last) < interval {
+            return true;
+        }
+    }
+    speaker.last_chat.insert(channel, now);
+    false
+}
+
+/// Replaces the filtered words of a message, `None` when the filter blocks it instead.
+fn apply_filter(filter: &ChatFilter, text: &str) -> Option<String> {
+    let mut chars: Vec<char> = text.chars().collect();
+    for word in &filter.words {
+        let word: Vec<char> = word.chars().collect();
+        if word.is_empty() {
+            continue;
+        }
+        let mut start = 0;
+        while start + word.len() <= chars.len() {
+            let found = chars[start..start + word.len()].iter()
+                .zip(&word)
+                .all(|(letter, expected)| letter.to_lowercase().eq(expected.to_lowercase()));
+            if !found {
+                start += 1;
+                continue;
+            }
+            if filter.action == FilterAction::Block {
+                return None;
+            }
+            chars.splice(start..start + word.len(), filter.replacement.chars());
+            start += filter.replacement.chars().count();
+        }
+    }
+    Some(chars.into_iter().collect())
+}
+
+fn is_within(listener: &Player, (x, y): (i32, i32), range: f64) -> bool {
+    let (other_x, other_y, _) = listener.position();
+    ((other_x - x) as f64).hypot((other_y - y) as f64) <= range
+}
+
+/// Sends a chat packet to every listener that doesn't ignore the speaker.
+fn deliver<'a>(speaker: &Player, listeners: impl Iterator<Item = &'a Player>, packet: &[u8]) {
+    for listener in listeners {
+        if !listener.ignores(speaker) {
+            listener.send(packet.to_vec());
+        }
+    }
+}
+
+/// Sends a shout or trade message as far as the configuration allows.
+fn deliver_ranged(world: &World, speaker: &Player, range: ChatRange, packet: &[u8]) {
+    let (x, y, _) = speaker.position();
+    let tile = (x >> TILE_SHIFT, y >> TILE_SHIFT);
+    match range {
+        ChatRange::Off => speaker.send(packet.to_vec()),
+        ChatRange::Region => {
+            let listeners = world.players().filter(|listener| {
+                let (other_x, other_y, _) = listener.position();
+                (other_x >> TILE_SHIFT, other_y >> TILE_SHIFT) == tile
+            });
+            deliver(speaker, listeners, packet);
+        },
+        ChatRange::Global => deliver(speaker, world.players(), packet),
+    }
+}
+
+fn whisper(world: &World, speaker: &Player, target: &str, text: &str) {
+    let receiver = match world.player_by_name(target) {
+        Some(receiver) => receiver,
+        None => {
+            speaker.send(system_message::with_text(system_message::S1_IS_NOT_ONLINE, target));
+            return;
+        }
+    };
+
+    if !speaker.is_gm() && (receiver.message_refusal || receiver.ignores(speaker)) {
+        speaker.send(system_message::system_message(system_message::THE_PERSON_IS_IN_MESSAGE_REFUSAL_MODE, &[]));
+        return;
+    }
+
+    receiver.send(response::creature_say(speaker.obj_id(), TELL, &speaker.character.char_name, text));
+    speaker.send(response::creature_say(speaker.obj_id(), TELL, &format!("->{}", receiver.character.char_name), text));
+}
+
+pub async fn say2(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let say = request::new_say2(data)?;
+    let length = say.text.chars().count();
+    if length == 0 || length > MAX_TEXT_LENGTH {
+        return Err(format!("{} sent a chat message of {} characters", client.account_name, length));
+    }
+
+    let mut world = context.world();
+    let speaker = match world.player_mut(obj_id) {
+        Some(speaker) => speaker,
+        None => return Err(format!("Player {} of {} is not in the world", obj_id, client.account_name)),
+    };
+    // Banned players can still reach the GMs through petition chat.
+    if speaker.is_chat_banned(now_millis()) && say.channel != PETITION_PLAYER {
+        speaker.send(system_message::system_message(system_message::CHATTING_IS_CURRENTLY_PROHIBITED, &[]));
+        return Ok(());
+    }
+    if !speaker.is_gm() && is_flooding(speaker, &context.conf.chat.flood, say.channel, Instant::now()) {
+        speaker.send(system_message::text("You are sending messages too quickly."));
+        return Ok(());
+    }
+
+    let name = &speaker.character.char_name;
+    let to = match &say.target {
+        Some(target) => format!(" -> {}", target),
+        None => String::new(),
+    };
+    let text = match apply_filter(&context.conf.chat.filter, &say.text) {
+        Some(text) => text,
+        None => {
+            info!(target: "chat", "[{}] {}{} (blocked): {}", channel_name(say.channel), name, to, say.text);
+            speaker.send(system_message::text("Your message was not sent, it contains a forbidden word."));
+            return Ok(());
+        }
+    };
+    info!(target: "chat", "[{}] {}{}: {}", channel_name(say.channel), name, to, text);
+
+    let speaker = match world.player(obj_id) {
+        Some(speaker) => speaker,
+        None => return Ok(()),
+    };
+    let packet = response::creature_say(obj_id, say.channel, &speaker.character.char_name, &text);
+    let gms = || world.players().filter(|player| player.is_gm());
+
+    match say.channel {
+        ALL => {
+            let (x, y, _) = speaker.position();
+            let listeners = speaker.known.iter()
+                .filter_map(|known| world.player(*known))
+                .filter(|listener| is_within(listener, (x, y), GENERAL_RANGE));
+            speaker.send(packet.clone());
+            deliver(speaker, listeners, &packet);
+        },
+        SHOUT => deliver_ranged(&world, speaker, context.conf.chat.shout, &packet),
+        TRADE => deliver_ranged(&world, speaker, context.conf.chat.trade, &packet),
+        TELL => whisper(&world, speaker, say.target.as_deref().unwrap_or_default(), &text),
+        // Parties and alliances don't exist yet, there is never anyone else to hear these.
+        PARTY | ALLIANCE => {},
+        CLAN if speaker.character.clan_id != 0 => {
+            let clan_id = speaker.character.clan_id;
+            deliver(speaker, world.players().filter(|player| player.character.clan_id == clan_id), &packet);
+        },
+        CLAN => {},
+        PETITION_PLAYER => {
+            speaker.send(packet.clone());
+            deliver(speaker, gms().filter(|gm| gm.obj_id() != obj_id), &packet);
+        },
+        GM | PETITION_GM if speaker.is_gm() => deliver(speaker, gms(), &packet),
+        // There are no heroes yet, GMs use the channel for announcements.
+        HERO_VOICE if speaker.is_gm() => deliver(speaker, world.players(), &packet),
+        GM | PETITION_GM | HERO_VOICE => {
+            return Err(format!("{} used chat channel {} without the rights for it", speaker.character.char_name, say.channel));
+        },
+        channel => return Err(format!("{} used unknown chat channel {}", speaker.character.char_name, channel)),
+    }
+    Ok(())
+}
+
+pub async fn request_block(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let block = request::new_request_block(data)?;
+
+    match (block.kind, block.name) {
+        (request::BLOCK, Some(name)) => {
+            let (blocked_id, blocked_name) = match characters::find_by_name(&context.database, &name).await? {
+                Some(found) if found.0 != obj_id => found,
+                _ => {
+                    client.send(system_message::text(&format!("{} can't be added to your ignore list.", name)));
+                    return Ok(());
+                }
+            };
+            blocks::add(&context.database, obj_id, blocked_id).await?;
+            if let Some(player) = context.world().player_mut(obj_id) {
+                player.blocked.insert(blocked_id, blocked_name.clone());
+            }
+            client.send(system_message::with_text(system_message::S1_WAS_ADDED_TO_YOUR_IGNORE_LIST, &blocked_name));
+        },
+        (request::UNBLOCK, Some(name)) => {
+            let found = context.world().player(obj_id).and_then(|player| {
+                player.blocked.iter()
+                    .find(|(_, blocked_name)| blocked_name.eq_ignore_ascii_case(&name))
+                    .map(|(blocked_id, blocked_name)| (*blocked_id, blocked_name.clone()))
+            });
+            let (blocked_id, blocked_name) = match found {
+                Some(found) => found,
+                None => return Ok(()),
+            };
+            blocks::remove(&context.database, obj_id, blocked_id).await?;
+            if let Some(player) = context.world().player_mut(obj_id) {
+                player.blocked.remove(&blocked_id);
+            }
+            client.send(system_message::with_text(system_message::S1_WAS_REMOVED_FROM_YOUR_IGNORE_LIST, &blocked_name));
+        },
+        (request::BLOCK_LIST, _) => {
+            let mut names: Vec<String> = match context.world().player(obj_id) {
+                Some(player) => player.blocked.values().cloned().collect(),
+                None => Vec::new(),
+            };
+            names.sort();
+            client.send(system_message::text("======<Ignore List>======"));
+            for name in names {
+                client.send(system_message::text(&name));
+            }
+        },
+        (request::ALL_BLOCK | request::ALL_UNBLOCK, _) => {
+            let refusal = block.kind == request::ALL_BLOCK;
+            if let Some(player) = context.world().player_mut(obj_id) {
+                player.message_refusal = refusal;
+            }
+            let id = if refusal { system_message::MESSAGE_REFUSAL_MODE } else { system_message::MESSAGE_ACCEPTANCE_MODE };
+            client.send(system_message::system_message(id, &[]));
+        },
+        (kind, _) => return Err(format!("Unknown block request {} from {}", kind, client.account_name)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    use crate::gameserver::models::Sender;
+    use crate::gameserver::player::synthetic::{datapack, player};
+    use crate::gameserver::world::WorldObject;
+
+    /// Puts a player in the world, giving what is sent to it.
+    fn join(world: &mut World, mut player: Player) -> UnboundedReceiver<Vec<u8>> {
+        let (sender, packets) = Sender::capture();
+        player.sender = sender;
+        world.add(WorldObject::Player(Box::new(player))).unwrap();
+        packets
+    }
+
+    fn received(packets: &mut UnboundedReceiver<Vec<u8>>) -> Vec<Vec<u8>> {
+        let mut received = Vec::new();
+        while let Ok(packet) = packets.try_recv() {
+            received.push(packet);
+        }
+        received
+    }
+
+    #[test]
+    fn shouts_reach_as_far_as_the_range() {
+        let datapack = datapack();
+        let mut world = World::new();
+        let mut speaker = join(&mut world, player(&datapack, 1, "Speaker", 100, 100));
+        let mut same_tile = join(&mut world, player(&datapack, 2, "Near", 32000, 32000));
+        let mut next_tile = join(&mut world, player(&datapack, 3, "Far", 33000, 100));
+        let mut ignoring = player(&datapack, 4, "Ignoring", 200, 200);
+        ignoring.blocked.insert(1, "Speaker".to_string());
+        let mut ignoring = join(&mut world, ignoring);
+        // Leaves out what they saw of each other coming in.
+        for packets in [&mut speaker, &mut same_tile, &mut next_tile, &mut ignoring] {
+            received(packets);
+        }
+        let packet = vec![1, 2, 3];
+
+        deliver_ranged(&world, world.player(1).unwrap(), ChatRange::Off, &packet);
+        assert_eq!(received(&mut speaker), vec![packet.clone()]);
+        assert!(received(&mut same_tile).is_empty());
+
+        deliver_ranged(&world, world.player(1).unwrap(), ChatRange::Region, &packet);
+        assert_eq!(received(&mut speaker), vec![packet.clone()]);
+        assert_eq!(received(&mut same_tile), vec![packet.clone()]);
+        assert!(received(&mut next_tile).is_empty());
+
+        deliver_ranged(&world, world.player(1).unwrap(), ChatRange::Global, &packet);
+        assert_eq!(received(&mut next_tile), vec![packet.clone()]);
+        assert!(received(&mut ignoring).is_empty());
+
+        // General chat only reaches the players around.
+        assert!(is_within(world.player(4).unwrap(), (100, 100), GENERAL_RANGE));
+        assert!(!is_within(world.player(2).unwrap(), (100, 100), GENERAL_RANGE));
+    }
+
+    #[test]
+    fn whispers_tell_why_they_are_refused() {
+        let datapack = datapack();
+        let mut world = World::new();
+        let mut speaker = join(&mut world, player(&datapack, 1, "Speaker", 100, 100));
+        let mut blocking = player(&datapack, 2, "Blocking", 100, 100);
+        blocking.blocked.insert(1, "Speaker".to_string());
+        let mut blocking = join(&mut world, blocking);
+        let mut refusing = player(&datapack, 3, "Refusing", 100, 100);
+        refusing.message_refusal = true;
+        let mut refusing = join(&mut world, refusing);
+        for packets in [&mut speaker, &mut blocking, &mut refusing] {
+            received(packets);
+        }
+        let refused = system_message::system_message(system_message::THE_PERSON_IS_IN_MESSAGE_REFUSAL_MODE, &[]);
+
+        whisper(&world, world.player(1).unwrap(), "Nobody", "hi");
+        assert_eq!(received(&mut speaker), vec![system_message::with_text(system_message::S1_IS_NOT_ONLINE, "Nobody")]);
+
+        whisper(&world, world.player(1).unwrap(), "Blocking", "hi");
+        assert_eq!(received(&mut speaker), vec![refused.clone()]);
+        assert!(received(&mut blocking).is_empty());
+
+        whisper(&world, world.player(1).unwrap(), "Refusing", "hi");
+        assert_eq!(received(&mut speaker), vec![refused]);
+        assert!(received(&mut refusing).is_empty());
+
+        // GMs get through both.
+        world.player_mut(1).unwrap().character.access_level = 1;
+        whisper(&world, world.player(1).unwrap(), "Blocking", "hi");
+        assert_eq!(received(&mut blocking), vec![response::creature_say(1, TELL, "Speaker", "hi")]);
+        assert_eq!(received(&mut speaker), vec![response::creature_say(1, TELL, "->Blocking", "hi")]);
+    }
+
+    #[test]
+    fn filtered_words_are_replaced_or_blocked() {
+        let mut filter = ChatFilter { words: vec!["darn".to_string(), "".to_string()], ..ChatFilter::default() };
+        assert_eq!(apply_filter(&filter, "Darn it, DARNdarn").as_deref(), Some("*** it, ******"));
+        assert_eq!(apply_filter(&filter, "fine words").as_deref(), Some("fine words"));
+
+        filter.action = FilterAction::Block;
+        assert_eq!(apply_filter(&filter, "oh dArN"), None);
+        assert_eq!(apply_filter(&filter, "fine words").as_deref(), Some("fine words"));
+    }
+
+    #[test]
+    fn flooding_holds_messages_per_channel() {
+        let datapack = datapack();
+        let mut speaker = player(&datapack, 1, "Speaker", 0, 0);
+        let flood = Flood::default();
+        let start = Instant::now();
+
+        assert!(!is_flooding(&mut speaker, &flood, SHOUT, start));
+        assert!(is_flooding(&mut speaker, &flood, SHOUT, start + Duration::from_millis(4999)));
+        assert!(!is_flooding(&mut speaker, &flood, ALL, start + Duration::from_millis(100)));
+        assert!(!is_flooding(&mut speaker, &flood, SHOUT, start + Duration::from_millis(5000)));
+        // A held message doesn't push the next one back.
+        assert!(is_flooding(&mut speaker, &flood, ALL, start + Duration::from_millis(300)));
+        assert!(!is_flooding(&mut speaker, &flood, ALL, start + Duration::from_millis(400)));
+        // Channels without an interval are never held.
+        assert!(!is_flooding(&mut speaker, &flood, PARTY, start));
+        assert!(!is_flooding(&mut speaker, &flood, PARTY, start));
+    }
+
+    #[test]
+    fn chat_bans_expire() {
+        let datapack = datapack();
+        let mut speaker = player(&datapack, 1, "Speaker", 0, 0);
+        assert!(!speaker.is_chat_banned(1000));
+
+        speaker.chat_ban = Some(2000);
+        assert!(speaker.is_chat_banned(1999));
+        assert!(!speaker.is_chat_banned(2000));
+
+        speaker.chat_ban = Some(0);
+        assert!(speaker.is_chat_banned(i64::MAX));
+    }
+}
\ No newline at end of file
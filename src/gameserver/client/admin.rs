@@ -0,0 +1,7 @@
+use crate::packet::packet::PacketRead;
+
+/// A GM command typed as `//command arguments`, the client sends it without the slashes.
+pub fn new_build_command(request: Vec<u8>) -> Result<String, String> {
+    let mut packet = PacketRead::new(request);
+    packet.read_string()
+}
@@ -0,0 +1,39 @@
+use crate::gameserver::chat::TELL;
+use crate::packet::packet::PacketRead;
+
+pub struct Say2 {
+    pub text: String,
+    pub channel: u32,
+    /// Name of the receiver, only sent for whispers.
+    pub target: Option<String>,
+}
+
+pub const BLOCK: u32 = 0;
+pub const UNBLOCK: u32 = 1;
+pub const BLOCK_LIST: u32 = 2;
+pub const ALL_BLOCK: u32 = 3;
+pub const ALL_UNBLOCK: u32 = 4;
+
+pub struct RequestBlock {
+    pub kind: u32,
+    /// Character to block or unblock.
+    pub name: Option<String>,
+}
+
+pub fn new_say2(request: Vec<u8>) -> Result<Say2, String> {
+    let mut packet = PacketRead::new(request);
+    let text = packet.read_string()?;
+    let channel = packet.read_u32()?;
+    let target = if channel == TELL { Some(packet.read_string()?) } else { None };
+    Ok(Say2 { text, channel, target })
+}
+
+pub fn new_request_block(request: Vec<u8>) -> Result<RequestBlock, String> {
+    let mut packet = PacketRead::new(request);
+    let kind = packet.read_u32()?;
+    let name = match kind {
+        BLOCK | UNBLOCK => Some(packet.read_string()?),
+        _ => None,
+    };
+    Ok(RequestBlock { kind, name })
+}
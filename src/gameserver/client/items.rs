@@ -0,0 +1,30 @@
+use crate::packet::packet::PacketRead;
+
+/// Object id of the item used.
+pub fn new_use_item(request: Vec<u8>) -> Result<u32, String> {
+    let mut packet = PacketRead::new(request);
+    packet.read_u32()
+}
+
+/// Body part to take off, one of the `SLOT_*` bits.
+pub fn new_unequip_item(request: Vec<u8>) -> Result<u32, String> {
+    let mut packet = PacketRead::new(request);
+    packet.read_u32()
+}
+
+
+/// Object ids and counts of the items put in or taken out of a warehouse.
+pub fn new_warehouse_items(request: Vec<u8>) -> Result<Vec<(u32, u64)>, String> {
+    let mut packet = PacketRead::new(request);
+    let count = packet.read_u32()? as usize;
+    if count * 8 > packet.remaining() {
+        return Err(format!("Warehouse request announces {} items but is too short for them", count));
+    }
+    let mut items = Vec::with_capacity(count);
+    for _ in 0..count {
+        let object_id = packet.read_u32()?;
+        let count = packet.read_u32()?;
+        items.push((object_id, count as u64));
+    }
+    Ok(items)
+}
@@ -0,0 +1,50 @@
+use crate::packet::packet::PacketRead;
+
+pub struct AuthLogin {
+    pub account_name: String,
+    pub play_key1: u32,
+}
+
+pub struct CharacterCreate {
+    pub name: String,
+    pub race: u32,
+    pub sex: u32,
+    pub class_id: u32,
+    pub hair_style: u32,
+    pub hair_color: u32,
+    pub face: u32,
+}
+
+pub fn new_protocol_version(request: Vec<u8>) -> Result<u32, String> {
+    PacketRead::new(request).read_u32()
+}
+
+pub fn new_auth_login(request: Vec<u8>) -> Result<AuthLogin, String> {
+    let mut packet = PacketRead::new(request);
+    let account_name = packet.read_string()?;
+    // The second play key and the login keys only matter once the login server hands the keys over.
+    packet.read_u32()?;
+    let play_key1 = packet.read_u32()?;
+    Ok(AuthLogin { account_name, play_key1 })
+}
+
+pub fn new_character_create(request: Vec<u8>) -> Result<CharacterCreate, String> {
+    let mut packet = PacketRead::new(request);
+    let name = packet.read_string()?;
+    let race = packet.read_u32()?;
+    let sex = packet.read_u32()?;
+    let class_id = packet.read_u32()?;
+    // The client also sends INT, STR, CON, MEN, DEX and WIT, those always come from the class template.
+    for _ in 0..6 {
+        packet.read_u32()?;
+    }
+    let hair_style = packet.read_u32()?;
+    let hair_color = packet.read_u32()?;
+    let face = packet.read_u32()?;
+    Ok(CharacterCreate { name, race, sex, class_id, hair_style, hair_color, face })
+}
+
+/// CharacterDelete, CharacterRestore and CharacterSelected all start with the slot they act on.
+pub fn new_character_slot(request: Vec<u8>) -> Result<usize, String> {
+    Ok(PacketRead::new(request).read_u32()? as usize)
+}
\ No newline at end of file
@@ -0,0 +1,63 @@
+use crate::packet::packet::PacketRead;
+
+/// Most entries the client sends in one buy or sell request.
+const MAX_ENTRIES: u32 = 100;
+
+pub struct RequestBuyItem {
+    pub list_id: u32,
+    /// Item ids with counts.
+    pub items: Vec<(u32, u64)>,
+}
+
+pub struct SoldToMerchant {
+    pub object_id: u32,
+    pub item_id: u32,
+    pub count: u64,
+}
+
+pub struct MultiSellChoose {
+    pub list_id: u32,
+    pub entry_id: u32,
+    pub amount: u64,
+}
+
+fn read_count(packet: &mut PacketRead) -> Result<u32, String> {
+    let count = packet.read_u32()?;
+    if count > MAX_ENTRIES {
+        return Err(format!("{} entries in one request", count));
+    }
+    Ok(count)
+}
+
+pub fn new_request_buy_item(request: Vec<u8>) -> Result<RequestBuyItem, String> {
+    let mut packet = PacketRead::new(request);
+    let list_id = packet.read_u32()?;
+    let mut items = Vec::new();
+    for _ in 0..read_count(&mut packet)? {
+        let item_id = packet.read_u32()?;
+        let count = packet.read_u32()? as u64;
+        items.push((item_id, count));
+    }
+    Ok(RequestBuyItem { list_id, items })
+}
+
+pub fn new_request_sell_item(request: Vec<u8>) -> Result<Vec<SoldToMerchant>, String> {
+    let mut packet = PacketRead::new(request);
+    packet.read_u32()?; // list id, there is only one way to sell
+    let mut items = Vec::new();
+    for _ in 0..read_count(&mut packet)? {
+        let object_id = packet.read_u32()?;
+        let item_id = packet.read_u32()?;
+        let count = packet.read_u32()? as u64;
+        items.push(SoldToMerchant { object_id, item_id, count });
+    }
+    Ok(items)
+}
+
+pub fn new_multisell_choose(request: Vec<u8>) -> Result<MultiSellChoose, String> {
+    let mut packet = PacketRead::new(request);
+    let list_id = packet.read_u32()?;
+    let entry_id = packet.read_u32()?;
+    let amount = packet.read_u32()? as u64;
+    Ok(MultiSellChoose { list_id, entry_id, amount })
+}
@@ -0,0 +1,11 @@
+pub mod lobby;
+pub mod movement;
+pub mod chat;
+pub mod admin;
+pub mod items;
+pub mod trade;
+pub mod store;
+pub mod world;
+pub mod npc;
+pub mod merchant;
+pub mod skills;
\ No newline at end of file
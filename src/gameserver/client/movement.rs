@@ -0,0 +1,36 @@
+use crate::packet::packet::PacketRead;
+
+pub struct MoveToLocation {
+    pub target: (i32, i32, i32),
+    /// Clicked with the mouse, keyboard movement is disabled in this chronicle.
+    pub mouse: bool,
+}
+
+/// Where the client believes its character is, sent by ValidatePosition and CannotMoveAnymore.
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub heading: i32,
+}
+
+pub fn new_move_to_location(request: Vec<u8>) -> Result<MoveToLocation, String> {
+    let mut packet = PacketRead::new(request);
+    let target = (packet.read_i32()?, packet.read_i32()?, packet.read_i32()?);
+    // The client also sends where it starts from, movement always starts from the server position.
+    for _ in 0..3 {
+        packet.read_i32()?;
+    }
+    // Some clients leave the movement type out when it is the mouse.
+    let mouse = packet.remaining() < 4 || packet.read_u32()? == 1;
+    Ok(MoveToLocation { target, mouse })
+}
+
+pub fn new_position(request: Vec<u8>) -> Result<Position, String> {
+    let mut packet = PacketRead::new(request);
+    let x = packet.read_i32()?;
+    let y = packet.read_i32()?;
+    let z = packet.read_i32()?;
+    let heading = packet.read_i32()?;
+    Ok(Position { x, y, z, heading })
+}
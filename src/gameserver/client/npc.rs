@@ -0,0 +1,7 @@
+use crate::packet::packet::PacketRead;
+
+/// Command of the bypass the player clicked.
+pub fn new_request_bypass_to_server(request: Vec<u8>) -> Result<String, String> {
+    let mut packet = PacketRead::new(request);
+    packet.read_string()
+}
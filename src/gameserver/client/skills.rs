@@ -0,0 +1,15 @@
+use crate::packet::packet::PacketRead;
+
+pub struct MagicSkillUse {
+    pub skill_id: u32,
+    /// Ctrl held, which forces offensive skills on players.
+    pub ctrl: bool,
+}
+
+pub fn new_magic_skill_use(request: Vec<u8>) -> Result<MagicSkillUse, String> {
+    let mut packet = PacketRead::new(request);
+    let skill_id = packet.read_u32()?;
+    let ctrl = packet.read_u32()? != 0;
+    packet.read_u8()?; // shift
+    Ok(MagicSkillUse { skill_id, ctrl })
+}
@@ -0,0 +1,114 @@
+use crate::gameserver::store::StoreItem;
+use crate::packet::packet::PacketRead;
+
+pub struct SetPrivateStoreListSell {
+    /// Everything has to be bought at once.
+    pub package: bool,
+    pub items: Vec<StoreItem>,
+}
+
+/// Items bought from a sell store, `id` is the object id of the item in the store.
+pub struct RequestPrivateStoreBuy {
+    pub store_id: u32,
+    pub items: Vec<StoreItem>,
+}
+
+pub struct SoldItem {
+    pub object_id: u32,
+    pub item_id: u32,
+    pub count: u64,
+    pub price: u64,
+}
+
+/// Items sold to a buy store from the inventory of the seller.
+pub struct RequestPrivateStoreSell {
+    pub store_id: u32,
+    pub items: Vec<SoldItem>,
+}
+
+/// Reads the item count of a list and checks the packet is long enough for that many entries.
+fn read_count(packet: &mut PacketRead, entry_size: usize) -> Result<usize, String> {
+    let count = packet.read_u32()? as usize;
+    if count * entry_size > packet.remaining() {
+        return Err(format!("Store packet announces {} items but is too short for them", count));
+    }
+    Ok(count)
+}
+
+fn read_items(packet: &mut PacketRead) -> Result<Vec<StoreItem>, String> {
+    let count = read_count(packet, 12)?;
+    let mut items = Vec::with_capacity(count);
+    for _ in 0..count {
+        let id = packet.read_u32()?;
+        let count = packet.read_u32()? as u64;
+        let price = packet.read_u32()? as u64;
+        items.push(StoreItem { id, count, price });
+    }
+    Ok(items)
+}
+
+pub fn new_set_private_store_list_sell(request: Vec<u8>) -> Result<SetPrivateStoreListSell, String> {
+    let mut packet = PacketRead::new(request);
+    let package = packet.read_u32()? == 1;
+    let items = read_items(&mut packet)?;
+    Ok(SetPrivateStoreListSell { package, items })
+}
+
+/// Items a buy store asks for, `id` is the item id.
+pub fn new_set_private_store_list_buy(request: Vec<u8>) -> Result<Vec<StoreItem>, String> {
+    let mut packet = PacketRead::new(request);
+    let count = read_count(&mut packet, 16)?;
+    let mut items = Vec::with_capacity(count);
+    for _ in 0..count {
+        let id = packet.read_u32()?;
+        packet.read_u16()?;
+        packet.read_u16()?;
+        let count = packet.read_u32()? as u64;
+        let price = packet.read_u32()? as u64;
+        items.push(StoreItem { id, count, price });
+    }
+    Ok(items)
+}
+
+/// Recipes a manufacture store makes, `id` is the recipe id and `price` the fee.
+pub fn new_set_recipe_shop_list(request: Vec<u8>) -> Result<Vec<StoreItem>, String> {
+    let mut packet = PacketRead::new(request);
+    let count = read_count(&mut packet, 8)?;
+    let mut recipes = Vec::with_capacity(count);
+    for _ in 0..count {
+        let id = packet.read_u32()?;
+        let price = packet.read_u32()? as u64;
+        recipes.push(StoreItem { id, count: 1, price });
+    }
+    Ok(recipes)
+}
+
+/// Title shown above a store.
+pub fn new_set_private_store_msg(request: Vec<u8>) -> Result<String, String> {
+    let mut packet = PacketRead::new(request);
+    packet.read_string()
+}
+
+pub fn new_request_private_store_buy(request: Vec<u8>) -> Result<RequestPrivateStoreBuy, String> {
+    let mut packet = PacketRead::new(request);
+    let store_id = packet.read_u32()?;
+    let items = read_items(&mut packet)?;
+    Ok(RequestPrivateStoreBuy { store_id, items })
+}
+
+pub fn new_request_private_store_sell(request: Vec<u8>) -> Result<RequestPrivateStoreSell, String> {
+    let mut packet = PacketRead::new(request);
+    let store_id = packet.read_u32()?;
+    let count = read_count(&mut packet, 20)?;
+    let mut items = Vec::with_capacity(count);
+    for _ in 0..count {
+        let object_id = packet.read_u32()?;
+        let item_id = packet.read_u32()?;
+        packet.read_u16()?;
+        packet.read_u16()?;
+        let count = packet.read_u32()? as u64;
+        let price = packet.read_u32()? as u64;
+        items.push(SoldItem { object_id, item_id, count, price });
+    }
+    Ok(RequestPrivateStoreSell { store_id, items })
+}
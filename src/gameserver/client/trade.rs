@@ -0,0 +1,32 @@
+use crate::packet::packet::PacketRead;
+
+pub struct AddTradeItem {
+    pub object_id: u32,
+    pub count: u64,
+}
+
+/// Object id of the player asked to trade.
+pub fn new_trade_request(request: Vec<u8>) -> Result<u32, String> {
+    let mut packet = PacketRead::new(request);
+    packet.read_u32()
+}
+
+/// Whether the request was accepted.
+pub fn new_answer_trade_request(request: Vec<u8>) -> Result<bool, String> {
+    let mut packet = PacketRead::new(request);
+    Ok(packet.read_u32()? == 1)
+}
+
+pub fn new_add_trade_item(request: Vec<u8>) -> Result<AddTradeItem, String> {
+    let mut packet = PacketRead::new(request);
+    packet.read_u32()?; // trade id, there is only ever one trade
+    let object_id = packet.read_u32()?;
+    let count = packet.read_u32()? as u64;
+    Ok(AddTradeItem { object_id, count })
+}
+
+/// Whether the player confirmed the trade rather than cancelled it.
+pub fn new_trade_done(request: Vec<u8>) -> Result<bool, String> {
+    let mut packet = PacketRead::new(request);
+    Ok(packet.read_u32()? == 1)
+}
@@ -0,0 +1,28 @@
+use crate::packet::packet::PacketRead;
+
+pub struct Action {
+    pub object_id: u32,
+    /// Shift click, which asks for information instead.
+    pub shift: bool,
+}
+
+pub fn new_action(request: Vec<u8>) -> Result<Action, String> {
+    let mut packet = PacketRead::new(request);
+    let object_id = packet.read_u32()?;
+    packet.read_i32()?; // x, y and z of the player
+    packet.read_i32()?;
+    packet.read_i32()?;
+    let shift = packet.read_u8()? == 1;
+    Ok(Action { object_id, shift })
+}
+
+pub struct RequestRestartPoint {
+    /// 0 for town, then clan hall, castle and siege camp.
+    pub point: u32,
+}
+
+pub fn new_request_restart_point(request: Vec<u8>) -> Result<RequestRestartPoint, String> {
+    let mut packet = PacketRead::new(request);
+    let point = packet.read_u32()?;
+    Ok(RequestRestartPoint { point })
+}
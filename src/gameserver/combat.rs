@@ -0,0 +1,727 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::gameserver::ai;
+use crate::gameserver::client::world as request;
+use crate::gameserver::datapack::items::{Grade, WeaponType};
+use crate::gameserver::death;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::inventory;
+use crate::gameserver::models::{self, Client};
+use crate::gameserver::geodata::Geodata;
+use crate::gameserver::movement::{self, Route, RouteGoal};
+use crate::gameserver::player::Player;
+use crate::gameserver::server::combat as response;
+use crate::gameserver::server::lobby::action_failed;
+use crate::gameserver::server::system_message::{self, Param};
+use crate::gameserver::server::world as world_response;
+use crate::gameserver::skills;
+use crate::gameserver::spawn;
+use crate::gameserver::stats::{self, Stat, Stats};
+use crate::gameserver::world::{World, WorldObject};
+
+/// How often swings are started and landed.
+const ATTACK_TICK: Duration = Duration::from_millis(100);
+/// Time a creature stays in combat after its last swing given or taken. Players can't log out or teleport
+/// meanwhile.
+pub const COMBAT_TIME: Duration = Duration::from_secs(15);
+/// Reach of melee weapons and bare hands, between the edges of both creatures.
+const MELEE_RANGE: f64 = 40.0;
+const BOW_RANGE: f64 = 500.0;
+/// Time between a bow shot landing and the next one at 345 attack speed, in milliseconds.
+const BOW_REUSE: u64 = 1500;
+/// Chance out of 1000 that a shield blocks a hit.
+const SHIELD_RATE: f64 = 200.0;
+/// Most a hit strays from its damage, either way.
+const RANDOM_DAMAGE: f64 = 0.1;
+
+/// Soulshot items, the weapon grade they fit and the skill showing their animation.
+const SOULSHOTS: [(u32, Grade, u32); 6] = [
+    (1835, Grade::None, 2039),
+    (1463, Grade::D, 2150),
+    (1464, Grade::C, 2151),
+    (1465, Grade::B, 2152),
+    (1466, Grade::A, 2153),
+    (1467, Grade::S, 2154),
+];
+
+/// One hit of a swing, rolled when the swing starts and applied when it lands.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    pub target: u32,
+    pub damage: u32,
+    pub miss: bool,
+    pub critical: bool,
+    pub shield: bool,
+    /// Grade of the soulshot behind the hit.
+    pub soulshot: Option<Grade>,
+}
+
+/// Fighting state of a player or an NPC.
+#[derive(Default)]
+pub struct CombatState {
+    /// Creature attacked swing after swing until the attacker is told otherwise.
+    pub target: Option<u32>,
+    /// Earliest the next swing can start.
+    pub ready_at: Option<Instant>,
+    /// Hits of the swing in the air, with when they land.
+    pub landing: Option<(Instant, Vec<Hit>)>,
+    /// End of the combat stance, `None` out of combat.
+    pub stance_until: Option<Instant>,
+}
+
+impl CombatState {
+    pub fn in_combat(&self) -> bool {
+        self.stance_until.is_some()
+    }
+}
+
+/// What the rolls of a swing need to know about either side.
+#[derive(Clone, Copy)]
+pub struct Fighter {
+    pub p_atk: f64,
+    pub p_def: f64,
+    pub accuracy: f64,
+    pub evasion: f64,
+    /// Critical rate out of 1000.
+    pub critical: f64,
+    /// P.Def of the shield, `None` without one.
+    pub shield: Option<f64>,
+    pub attack_speed: u32,
+    /// Reach, not counting the size of either side.
+    pub range: f64,
+    pub weapon: Option<WeaponType>,
+    pub collision_radius: f64,
+}
+
+fn fighter(context: &Context, object: &WorldObject) -> Option<Fighter> {
+    match object {
+        WorldObject::Player(player) => {
+            let weapon = player.equipment.weapon;
+            let bow = weapon.is_some_and(|weapon| weapon.weapon_type == WeaponType::Bow);
+            Some(Fighter {
+                shield: player.equipment.shield.map(|p_def| p_def as f64),
+                range: if bow { BOW_RANGE } else { MELEE_RANGE },
+                weapon: weapon.map(|weapon| weapon.weapon_type),
+                collision_radius: player.collision.0,
+                ..from_stats(&player.stats)
+            })
+        },
+        WorldObject::Npc(npc) => {
+            let attack_range = context.datapack.npcs.get(npc.npc_id)?.stats.attack_range;
+            Some(Fighter {
+                range: if attack_range > 0 { attack_range as f64 } else { MELEE_RANGE },
+                collision_radius: npc.collision.0,
+                ..from_stats(&npc.stats)
+            })
+        },
+    }
+}
+
+/// What the stats give, fighting bare handed in melee.
+fn from_stats(stats: &Stats) -> Fighter {
+    Fighter {
+        p_atk: stats.get(Stat::PAtk),
+        p_def: stats.get(Stat::PDef),
+        accuracy: stats.get(Stat::Accuracy),
+        evasion: stats.get(Stat::Evasion),
+        critical: stats.get(Stat::Critical),
+        shield: None,
+        attack_speed: stats.get(Stat::PAtkSpd) as u32,
+        range: MELEE_RANGE,
+        weapon: None,
+        collision_radius: 0.0,
+    }
+}
+
+/// Chance out of 1000 that a swing hits.
+pub fn hit_chance(accuracy: f64, evasion: f64) -> f64 {
+    ((80.0 + 2.0 * (accuracy - evasion)) * 10.0).clamp(200.0, 980.0)
+}
+
+/// Damage of a hit before its random part.
+pub fn damage(attacker: &Fighter, target: &Fighter, soulshot: bool, critical: bool, shield: bool) -> f64 {
+    let mut p_atk = attacker.p_atk;
+    if soulshot {
+        p_atk *= 2.0;
+    }
+    if critical {
+        p_atk *= 2.0;
+    }
+    let p_def = target.p_def + if shield { target.shield.unwrap_or(0.0) } else { 0.0 };
+    70.0 * p_atk / p_def.max(1.0)
+}
+
+/// Time from the start of a swing to its hits landing, and to the next swing.
+pub fn swing_times(attack_speed: u32, bow: bool) -> (Duration, Duration) {
+    let speed = attack_speed.max(1) as u64;
+    if bow {
+        let hit = 1500 * 345 / speed;
+        (Duration::from_millis(hit), Duration::from_millis(hit + BOW_REUSE * 345 / speed))
+    } else {
+        let swing = 500_000 / speed;
+        (Duration::from_millis(swing / 2), Duration::from_millis(swing))
+    }
+}
+
+fn roll(attacker: &Fighter, target: &Fighter, target_id: u32, soulshot: Option<Grade>, rng: &mut impl Rng) -> Hit {
+    let mut hit = Hit { target: target_id, damage: 0, miss: false, critical: false, shield: false, soulshot };
+    if rng.gen_range(0.0..1000.0) >= hit_chance(attacker.accuracy, target.evasion) {
+        hit.miss = true;
+        return hit;
+    }
+    hit.critical = rng.gen_range(0.0..1000.0) < attacker.critical;
+    hit.shield = target.shield.is_some() && rng.gen_range(0.0..1000.0) < SHIELD_RATE;
+    let spread = 1.0 + rng.gen_range(-RANDOM_DAMAGE..=RANDOM_DAMAGE);
+    hit.damage = (damage(attacker, target, soulshot.is_some(), hit.critical, hit.shield) * spread).max(1.0) as u32;
+    hit
+}
+
+pub fn distance_2d(a: (i32, i32, i32), b: (i32, i32, i32)) -> f64 {
+    ((a.0 - b.0) as f64).hypot((a.1 - b.1) as f64)
+}
+
+/// Has a creature attack another one until told otherwise, the attack tick takes it from there.
+pub fn attack(world: &mut World, obj_id: u32, target_id: u32) {
+    if let Some(object) = world.get_mut(obj_id) {
+        object.combat_mut().target = Some(target_id);
+    }
+    world.start_fighting(obj_id);
+}
+
+/// Stops the attacks of a creature, a swing in the air still lands.
+pub fn stop_attack(world: &mut World, obj_id: u32) {
+    if let Some(object) = world.get_mut(obj_id) {
+        object.combat_mut().target = None;
+    }
+}
+
+/// Whether a creature fought too recently to log out or teleport.
+pub fn in_combat(world: &World, obj_id: u32) -> bool {
+    world.get(obj_id).is_some_and(|object| object.combat().in_combat())
+}
+
+/// Has a player attack a target it sees, when both can fight.
+pub fn start_attack(world: &mut World, obj_id: u32, target_id: u32) -> Result<(), String> {
+    let player = match world.player(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} is not in the world", obj_id)),
+    };
+    let can_fight = !player.is_dead() && !player.sitting && !player.teleporting && player.store.is_none() && player.cast.is_none();
+    let attackable = target_id != obj_id && player.known.contains(&target_id) && match world.get(target_id) {
+        Some(WorldObject::Npc(npc)) => npc.attackable && !npc.is_dead(),
+        Some(WorldObject::Player(other)) => !other.is_dead(),
+        None => false,
+    };
+    if !can_fight || !attackable {
+        player.send(action_failed());
+        return Ok(());
+    }
+    if player.target != Some(target_id) {
+        player.send(world_response::my_target_selected(target_id, 0));
+    }
+    if let Some(player) = world.player_mut(obj_id) {
+        player.target = Some(target_id);
+    }
+    attack(world, obj_id, target_id);
+    Ok(())
+}
+
+/// Ctrl click on a creature, or a click on a target the client knows it can attack.
+pub async fn attack_request(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    // AttackRequest is laid out like Action.
+    let request = request::new_action(data)?;
+    start_attack(&mut context.world(), obj_id, request.object_id)
+}
+
+/// Lands, starts and ends the swings of every fighting creature for as long as the server runs.
+pub async fn run(context: Arc<Context>) {
+    let mut interval = tokio::time::interval(ATTACK_TICK);
+    loop {
+        interval.tick().await;
+        update(&context, &mut context.world(), Instant::now());
+        movement::find_routes(&context);
+    }
+}
+
+fn update(context: &Context, world: &mut World, now: Instant) {
+    for obj_id in world.fighting() {
+        land(context, world, obj_id, now);
+        follow_up(context, world, obj_id, now);
+        calm_down(world, obj_id, now);
+    }
+}
+
+fn land(context: &Context, world: &mut World, obj_id: u32, now: Instant) {
+    let hits = match world.get_mut(obj_id) {
+        Some(object) if object.combat().landing.as_ref().is_some_and(|(at, _)| *at <= now) => {
+            object.combat_mut().landing.take().map(|(_, hits)| hits).unwrap_or_default()
+        },
+        _ => return,
+    };
+    for hit in hits {
+        apply(context, world, obj_id, hit, now);
+    }
+}
+
+fn apply(context: &Context, world: &mut World, attacker_id: u32, hit: Hit, now: Instant) {
+    let attacker_name = match world.get(attacker_id) {
+        Some(attacker) => attacker.name().to_string(),
+        None => return,
+    };
+    if world.get(hit.target).is_none_or(|target| target.is_dead()) {
+        return;
+    }
+
+    if let Some(attacker) = world.player(attacker_id) {
+        if hit.miss {
+            attacker.send(system_message::system_message(system_message::MISSED_TARGET, &[]));
+        } else {
+            if hit.critical {
+                attacker.send(system_message::system_message(system_message::CRITICAL_HIT, &[]));
+            }
+            attacker.send(system_message::system_message(system_message::YOU_DID_S1_DMG, &[Param::Number(hit.damage)]));
+        }
+    }
+    if let Some(target) = world.player(hit.target) {
+        if hit.miss {
+            target.send(system_message::with_text(system_message::AVOIDED_S1S_ATTACK, &attacker_name));
+        } else {
+            if hit.shield {
+                target.send(system_message::system_message(system_message::SHIELD_DEFENCE_SUCCESSFULL, &[]));
+            }
+            target.send(system_message::system_message(system_message::S1_GAVE_YOU_S2_DMG,
+                &[Param::Text(attacker_name.clone()), Param::Number(hit.damage)]));
+        }
+    }
+    if hit.miss {
+        return;
+    }
+
+    hurt(context, world, attacker_id, hit.target, hit.damage, now);
+}
+
+/// Takes damage off a creature, which dies when its HP runs out and hates the attacker otherwise. NPCs keep count
+/// of the HP each attacker took.
+pub fn hurt(context: &Context, world: &mut World, attacker_id: u32, target_id: u32, damage: u32, now: Instant) {
+    let hp = match world.get_mut(target_id) {
+        Some(WorldObject::Player(player)) => {
+            player.character.cur_hp = (player.character.cur_hp - damage as f64).max(0.0);
+            player.character.cur_hp
+        },
+        Some(WorldObject::Npc(npc)) => {
+            let before = npc.cur_hp;
+            npc.cur_hp = (npc.cur_hp - damage as f64).max(0.0);
+            *npc.damage.entry(attacker_id).or_default() += (before - npc.cur_hp) as u64;
+            npc.cur_hp
+        },
+        None => return,
+    };
+    show_hp(world, target_id);
+    if hp <= 0.0 {
+        die(context, world, target_id, attacker_id, now);
+        return;
+    }
+    world.start_recovering(target_id);
+    // Functions may depend on HP.
+    stats::refresh(context, world, target_id);
+    ai::notify_attacked(context, world, target_id, attacker_id, damage as f64);
+}
+
+/// Shows the HP of a creature to itself and to every player that has it targeted.
+pub fn show_hp(world: &World, obj_id: u32) {
+    let object = match world.get(obj_id) {
+        Some(object) => object,
+        None => return,
+    };
+    let (hp, max_hp) = match object {
+        WorldObject::Player(player) => (player.character.cur_hp, player.character.max_hp),
+        WorldObject::Npc(npc) => (npc.cur_hp, npc.stats.get(Stat::MaxHp)),
+    };
+    let packet = world_response::status_update(obj_id, &[
+        (world_response::STATUS_CUR_HP, hp as u32),
+        (world_response::STATUS_MAX_HP, max_hp as u32),
+    ]);
+    if let WorldObject::Player(player) = object {
+        player.send(packet.clone());
+    }
+    for other in object.known() {
+        if let Some(player) = world.player(*other).filter(|player| player.target == Some(obj_id)) {
+            player.send(packet.clone());
+        }
+    }
+}
+
+/// A creature running out of HP at the hands of `killer_id`. It stops fighting and casting, loses its effects, and
+/// the corpse of an NPC stays a while before it goes.
+pub fn die(context: &Context, world: &mut World, obj_id: u32, killer_id: u32, now: Instant) {
+    world.stop_moving(obj_id);
+    skills::cancel(world, obj_id);
+    skills::clear_effects(context, world, obj_id);
+    if let Some(object) = world.get_mut(obj_id) {
+        let combat = object.combat_mut();
+        combat.target = None;
+        combat.landing = None;
+    }
+    death::on_death(context, world, obj_id, killer_id, now);
+    let sweepable = world.npc(obj_id).is_some_and(|npc| !npc.sweep.is_empty());
+    world.broadcast_with_self(obj_id, &response::die(obj_id, sweepable));
+    if world.npc(obj_id).is_some() {
+        world.schedule_decay(obj_id, now + spawn::DECAY_TIME);
+    }
+}
+
+/// Moves an attacker toward its target until it is in reach and in sight, then swings whenever its attack speed
+/// allows.
+fn follow_up(context: &Context, world: &mut World, obj_id: u32, now: Instant) {
+    let (attacker, target_id) = match world.get(obj_id) {
+        Some(attacker) => match attacker.combat().target {
+            Some(target_id) => (attacker, target_id),
+            None => return,
+        },
+        None => return,
+    };
+    // A teleport empties the known list, so a target left behind is no longer known.
+    let target = match world.get(target_id) {
+        Some(target) if !target.is_dead() && !attacker.is_dead() && attacker.known().contains(&target_id) => target,
+        _ => {
+            stop_attack(world, obj_id);
+            return;
+        }
+    };
+    let (fighter_a, fighter_t) = match (fighter(context, attacker), fighter(context, target)) {
+        (Some(fighter_a), Some(fighter_t)) => (fighter_a, fighter_t),
+        _ => {
+            stop_attack(world, obj_id);
+            return;
+        }
+    };
+    let (from, to) = (attacker.position(), target.position());
+    let busy = attacker.combat().landing.is_some() || attacker.combat().ready_at.is_some_and(|at| at > now);
+
+    // A target out of sight is walked to, around the wall in the way if it has to.
+    let reach = fighter_a.range + fighter_a.collision_radius + fighter_t.collision_radius;
+    if distance_2d(from, to) > reach || !context.geodata.can_see(from, to) {
+        chase(&context.geodata, world, obj_id, target_id, (from, to), reach, now);
+        return;
+    }
+    if world.get_mut(obj_id).is_some_and(|attacker| attacker.movement_mut().is_some()) {
+        movement::stop(world, obj_id);
+    }
+    if !busy {
+        swing(context, world, obj_id, target_id, &fighter_a, &fighter_t, now);
+    }
+}
+
+/// Walks an attacker toward its target, unless it already walks close enough to where the target is. A wall in
+/// the way has a path searched around it once the world is released.
+fn chase(geodata: &Geodata, world: &mut World, obj_id: u32, target_id: u32, (from, to): ((i32, i32, i32), (i32, i32, i32)), reach: f64, now: Instant) {
+    let end = world.get_mut(obj_id).and_then(|attacker| attacker.movement_mut().as_ref().map(|movement| movement.end()));
+    if end.is_some_and(|end| distance_2d(end, to) <= reach) {
+        return;
+    }
+    let reached = geodata.move_check(from, to);
+    if (reached.0, reached.1) == (to.0, to.1) {
+        movement::walk(world, obj_id, from, vec![reached], now);
+        return;
+    }
+    world.stop_moving(obj_id);
+    world.request_route(obj_id, Route { from, to, goal: RouteGoal::Chase(target_id) });
+}
+
+/// Sets an attacker on the path found to its target, if it still attacks it from where it asked. With no way
+/// there the attack ends, and an NPC forgets the target.
+pub fn chase_along(world: &mut World, obj_id: u32, target_id: u32, from: (i32, i32, i32), path: Option<Vec<(i32, i32, i32)>>, now: Instant) {
+    let still_chasing = world.get(obj_id)
+        .is_some_and(|attacker| !attacker.is_dead() && attacker.combat().target == Some(target_id) && attacker.position() == from);
+    if !still_chasing {
+        return;
+    }
+    match path {
+        Some(path) => movement::walk(world, obj_id, from, path, now),
+        None => {
+            stop_attack(world, obj_id);
+            if let Some(player) = world.player(obj_id) {
+                player.send(action_failed());
+            }
+            if let Some(npc) = world.npc_mut(obj_id) {
+                npc.ai.hate.remove(target_id);
+            }
+        },
+    }
+}
+
+fn swing(context: &Context, world: &mut World, obj_id: u32, target_id: u32, attacker: &Fighter, target: &Fighter, now: Instant) {
+    let bow = attacker.weapon == Some(WeaponType::Bow);
+    let mut soulshot = None;
+    if let Some(player) = world.player_mut(obj_id) {
+        if bow && !shoot_arrow(context, player) {
+            player.send(system_message::system_message(system_message::NOT_ENOUGH_ARROWS, &[]));
+            player.send(action_failed());
+            player.combat.target = None;
+            return;
+        }
+        soulshot = spend_soulshot(context, player);
+    }
+
+    let mut rng = rand::thread_rng();
+    let hits = match attacker.weapon {
+        // Both blades hit, each for half.
+        Some(WeaponType::Dual) | Some(WeaponType::DualFist) => (0..2).map(|_| {
+            let mut hit = roll(attacker, target, target_id, soulshot, &mut rng);
+            if !hit.miss {
+                hit.damage = (hit.damage / 2).max(1);
+            }
+            hit
+        }).collect(),
+        _ => vec![roll(attacker, target, target_id, soulshot, &mut rng)],
+    };
+
+    let (land_after, next_after) = swing_times(attacker.attack_speed, bow);
+    let (position, target_position) = match (world.get(obj_id), world.get(target_id)) {
+        (Some(attacker), Some(target)) => (attacker.position(), target.position()),
+        _ => return,
+    };
+    world.broadcast_with_self(obj_id, &response::attack(obj_id, position, &hits));
+    if let Some(attacker) = world.get_mut(obj_id) {
+        attacker.set_heading(movement::heading_to((position.0, position.1), (target_position.0, target_position.1)));
+        let combat = attacker.combat_mut();
+        combat.landing = Some((now + land_after, hits));
+        combat.ready_at = Some(now + next_after);
+    }
+    enter_combat(world, obj_id, now);
+    enter_combat(world, target_id, now);
+}
+
+/// Takes the arrow a bow shoots from the stack worn with it, which has to be of the grade of the bow.
+fn shoot_arrow(context: &Context, player: &mut Player) -> bool {
+    let templates = &context.datapack.items;
+    let bow = player.weapon_object().and_then(|bow| player.inventory.get(bow)).and_then(|bow| templates.get(bow.item_id));
+    let arrows = match (bow, player.inventory.equipped_in(models::PAPERDOLL_LHAND)) {
+        (Some(bow), Some(item)) if templates.get(item.item_id).is_some_and(|arrows| arrows.fits_bow(bow)) => item.object_id,
+        _ => return false,
+    };
+    match player.inventory.remove(&context.datapack.items, &context.ids, arrows, 1) {
+        Ok(changes) => {
+            inventory::commit(context, player, changes);
+            true
+        },
+        Err(_) => false,
+    }
+}
+
+/// Takes the soulshot loaded in the weapon in hand, giving its grade. A shot loaded in another weapon is lost.
+fn spend_soulshot(context: &Context, player: &mut Player) -> Option<Grade> {
+    let loaded = player.soulshot.take()?;
+    if player.weapon_object() != Some(loaded) {
+        return None;
+    }
+    player.inventory.get(loaded).and_then(|item| context.datapack.items.get(item.item_id)).map(|template| template.grade)
+}
+
+/// Puts a creature in its combat stance, or keeps it there longer.
+pub fn enter_combat(world: &mut World, obj_id: u32, now: Instant) {
+    let started = match world.get_mut(obj_id) {
+        Some(object) => {
+            let combat = object.combat_mut();
+            let started = combat.stance_until.is_none();
+            combat.stance_until = Some(now + COMBAT_TIME);
+            started
+        },
+        None => return,
+    };
+    if started {
+        world.broadcast_with_self(obj_id, &response::auto_attack_start(obj_id));
+    }
+    world.start_fighting(obj_id);
+}
+
+/// Ends the combat stance of a creature that stopped attacking long enough, and the attack tick stops looking
+/// after it once nothing is left to do.
+fn calm_down(world: &mut World, obj_id: u32, now: Instant) {
+    let (ended, idle) = match world.get_mut(obj_id) {
+        Some(object) => {
+            let combat = object.combat_mut();
+            let ended = combat.target.is_none() && combat.stance_until.is_some_and(|until| until <= now);
+            if ended {
+                combat.stance_until = None;
+            }
+            (ended, combat.target.is_none() && combat.landing.is_none() && combat.stance_until.is_none())
+        },
+        None => {
+            world.stop_fighting(obj_id);
+            return;
+        }
+    };
+    if ended {
+        world.broadcast_with_self(obj_id, &response::auto_attack_stop(obj_id));
+    }
+    if idle {
+        world.stop_fighting(obj_id);
+    }
+}
+
+pub fn is_soulshot(item_id: u32) -> bool {
+    SOULSHOTS.iter().any(|(soulshot, _, _)| *soulshot == item_id)
+}
+
+/// Loads a soulshot in the weapon in hand, spent by its next swing.
+pub fn load_soulshot(context: &Context, world: &mut World, obj_id: u32, item_id: u32) -> Result<(), String> {
+    let (grade, skill_id) = match SOULSHOTS.iter().find(|(soulshot, _, _)| *soulshot == item_id) {
+        Some(&(_, grade, skill_id)) => (grade, skill_id),
+        None => return Ok(()),
+    };
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Ok(()),
+    };
+    let weapon = player.weapon_object()
+        .and_then(|object_id| player.inventory.get(object_id))
+        .and_then(|item| context.datapack.items.get(item.item_id).map(|template| (item.object_id, template)));
+    let (weapon_id, template) = match weapon {
+        Some((weapon_id, template)) if template.weapon.is_some_and(|weapon| weapon.soulshots > 0) => (weapon_id, template),
+        _ => {
+            player.send(system_message::system_message(system_message::CANNOT_USE_SOULSHOTS, &[]));
+            return Ok(());
+        }
+    };
+    if template.grade != grade {
+        player.send(system_message::system_message(system_message::SOULSHOTS_GRADE_MISMATCH, &[]));
+        return Ok(());
+    }
+    if player.soulshot == Some(weapon_id) {
+        return Ok(());
+    }
+    let shots = template.weapon.map_or(0, |weapon| weapon.soulshots) as u64;
+    if player.inventory.count_of(item_id) < shots {
+        player.send(system_message::system_message(system_message::NOT_ENOUGH_SOULSHOTS, &[]));
+        return Ok(());
+    }
+
+    let changes = match player.inventory.remove_by_item_id(&context.datapack.items, &context.ids, item_id, shots) {
+        Ok(changes) => changes,
+        Err(e) => return Err(format!("Can't take {} soulshots from {}: {}", shots, player.character.char_name, e)),
+    };
+    inventory::commit(context, player, changes);
+    player.soulshot = Some(weapon_id);
+    player.send(system_message::system_message(system_message::ENABLED_SOULSHOT, &[]));
+    let position = player.position();
+    world.broadcast_with_self(obj_id, &response::magic_skill_use(obj_id, obj_id, skill_id, 1, 0, 0, position));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameserver::gameserver::synthetic::context;
+    use crate::gameserver::geodata::synthetic::{block, geodata, wall_block};
+    use crate::gameserver::npc::synthetic::npc;
+    use crate::gameserver::npc::Npc;
+    use crate::gameserver::player::synthetic::datapack;
+    use crate::gameserver::pathfinding::Pathfinder;
+
+    fn fighter(p_atk: f64, p_def: f64) -> Fighter {
+        Fighter {
+            p_atk,
+            p_def,
+            accuracy: 33.0,
+            evasion: 33.0,
+            critical: 0.0,
+            shield: Some(50.0),
+            attack_speed: 300,
+            range: MELEE_RANGE,
+            weapon: None,
+            collision_radius: 9.0,
+        }
+    }
+
+    #[test]
+    fn hit_chance_stays_within_bounds() {
+        assert_eq!(hit_chance(33.0, 33.0), 800.0);
+        assert_eq!(hit_chance(100.0, 0.0), 980.0);
+        assert_eq!(hit_chance(0.0, 100.0), 200.0);
+    }
+
+    #[test]
+    fn soulshots_criticals_and_shields_change_damage() {
+        let (attacker, target) = (fighter(100.0, 0.0), fighter(0.0, 70.0));
+        assert_eq!(damage(&attacker, &target, false, false, false), 100.0);
+        assert_eq!(damage(&attacker, &target, true, false, false), 200.0);
+        assert_eq!(damage(&attacker, &target, true, true, false), 400.0);
+        assert!(damage(&attacker, &target, false, false, true) < 100.0);
+    }
+
+    #[test]
+    fn bows_are_slower_than_swords() {
+        let (land, next) = swing_times(379, false);
+        assert_eq!(next, Duration::from_millis(1319));
+        assert!(land < next);
+        let (bow_land, bow_next) = swing_times(293, true);
+        assert!(bow_land > land && bow_next > next);
+    }
+
+    #[test]
+    fn attackers_go_around_walls() {
+        // Wall along x 1600 from the top of the region down to y 1536.
+        let geodata = geodata(&(0..12).map(|block_y| (block(12, block_y), wall_block())).collect::<Vec<_>>());
+        let (from, to) = ((1500, 1000, 0), (1700, 1000, 0));
+        let mut world = World::new();
+        world.add(npc(1, from.0, from.1)).unwrap();
+        world.add(npc(2, to.0, to.1)).unwrap();
+        world.get_mut(1).unwrap().combat_mut().target = Some(2);
+        let now = Instant::now();
+
+        chase(&geodata, &mut world, 1, 2, (from, to), MELEE_RANGE, now);
+        assert_eq!(world.take_routes(), vec![(1, Route { from, to, goal: RouteGoal::Chase(2) })]);
+        assert!(world.moving().is_empty());
+
+        let path = Pathfinder::new().find_path(&geodata, from, to);
+        assert!(path.as_ref().is_some_and(|path| path.len() > 1));
+        chase_along(&mut world, 1, 2, from, path, now);
+        let movement = world.get_mut(1).unwrap().movement_mut().as_ref().map(|movement| (movement.destination, movement.end()));
+        assert!(movement.is_some_and(|(destination, end)| destination.1 >= 1536 && end == to));
+        assert_eq!(world.get(1).unwrap().combat().target, Some(2));
+
+        // A search asked from elsewhere is stale, one that finds no way ends the attack.
+        chase_along(&mut world, 1, 2, (0, 0, 0), None, now);
+        assert_eq!(world.get(1).unwrap().combat().target, Some(2));
+        chase_along(&mut world, 1, 2, from, None, now);
+        assert_eq!(world.get(1).unwrap().combat().target, None);
+    }
+
+    #[tokio::test]
+    async fn targets_behind_a_wall_are_walked_to_before_swinging() {
+        let context = context(geodata(&[(block(0, 0), wall_block())]));
+        let mut world = World::new();
+        // Wolves standing in reach of each other, with the wall between x 63 and 64.
+        for (obj_id, x) in [(1, 40), (2, 90), (3, 120)] {
+            let template = context.datapack.npcs.get(20120).unwrap();
+            world.add(WorldObject::Npc(Box::new(Npc::new(obj_id, template, (x, 8, 0), 0, None)))).unwrap();
+        }
+        let now = Instant::now();
+
+        attack(&mut world, 1, 2);
+        follow_up(&context, &mut world, 1, now);
+        assert!(world.get(1).unwrap().combat().landing.is_none());
+        assert_eq!(world.take_routes(), vec![(1, Route { from: (40, 8, 0), to: (90, 8, 0), goal: RouteGoal::Chase(2) })]);
+
+        // On the same side it swings.
+        attack(&mut world, 3, 2);
+        follow_up(&context, &mut world, 3, now);
+        assert!(world.get(3).unwrap().combat().landing.is_some());
+    }
+
+    #[test]
+    fn bows_shoot_arrows_of_their_grade() {
+        let items = datapack().items;
+        let (short_bow, bonebreaker) = (items.get(13).unwrap(), items.get(159).unwrap());
+        let (wooden, bone, potion) = (items.get(17).unwrap(), items.get(1341).unwrap(), items.get(1060).unwrap());
+        assert!(wooden.fits_bow(short_bow));
+        assert!(!bone.fits_bow(short_bow));
+        assert!(!potion.fits_bow(short_bow));
+        assert!(!bone.fits_bow(bonebreaker));
+    }
+}
\ No newline at end of file
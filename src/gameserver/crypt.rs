@@ -0,0 +1,51 @@
+/// Static half of the game server key, only the first 8 bytes are sent to the client.
+const STATIC_KEY: [u8; 8] = [0xc8, 0x27, 0x93, 0x01, 0xa1, 0x6c, 0x31, 0x97];
+
+/// Rolling XOR cipher used by the game server protocol. Each direction keeps its own copy of the key
+/// because the key changes with every packet.
+#[derive(Clone)]
+pub struct GameCrypt {
+    key: [u8; 16],
+}
+
+impl GameCrypt {
+    pub fn new() -> GameCrypt {
+        let mut key = [0u8; 16];
+        for byte in key.iter_mut().take(8) {
+            *byte = rand::random::<u8>();
+        }
+        key[8..].copy_from_slice(&STATIC_KEY);
+        GameCrypt { key }
+    }
+
+    /// The part of the key the client needs, sent in the KeyPacket.
+    pub fn client_key(&self) -> Vec<u8> {
+        self.key[0..8].to_vec()
+    }
+
+    pub fn decrypt(&mut self, raw: &mut [u8]) {
+        let mut previous: u8 = 0;
+        for (i, byte) in raw.iter_mut().enumerate() {
+            let encrypted = *byte;
+            *byte = encrypted ^ self.key[i & 15] ^ previous;
+            previous = encrypted;
+        }
+        self.shift_key(raw.len());
+    }
+
+    pub fn encrypt(&mut self, raw: &mut [u8]) {
+        let mut previous: u8 = 0;
+        for (i, byte) in raw.iter_mut().enumerate() {
+            previous ^= *byte ^ self.key[i & 15];
+            *byte = previous;
+        }
+        self.shift_key(raw.len());
+    }
+
+    fn shift_key(&mut self, size: usize) {
+        let mut old = [0u8; 4];
+        old.copy_from_slice(&self.key[8..12]);
+        let shifted = u32::from_le_bytes(old).wrapping_add(size as u32);
+        self.key[8..12].copy_from_slice(&shifted.to_le_bytes());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use super::items::ItemRegistry;
+use super::loader::{self, DataError};
+use super::npcs::NpcRegistry;
+
+/// How many of an item a merchant has, and how long after the first sale it gets them all back.
+#[derive(Clone, Copy)]
+pub struct Stock {
+    pub count: u64,
+    pub restock: Duration,
+}
+
+pub struct BuyListItem {
+    pub item_id: u32,
+    /// Adena for each.
+    pub price: u64,
+    /// `None` when the merchant never runs out.
+    pub stock: Option<Stock>,
+}
+
+pub struct BuyList {
+    pub list_id: u32,
+    /// Merchants selling from this list.
+    pub npcs: Vec<u32>,
+    pub items: Vec<BuyListItem>,
+}
+
+impl BuyList {
+    pub fn item(&self, item_id: u32) -> Option<&BuyListItem> {
+        self.items.iter().find(|item| item.item_id == item_id)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BuyListFile {
+    #[serde(rename = "buylist", default)]
+    lists: Vec<Spanned<BuyListEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BuyListEntry {
+    id: u32,
+    npcs: Spanned<Vec<u32>>,
+    items: Vec<Spanned<ItemEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ItemEntry {
+    item: u32,
+    /// Defaults to the price of the item template.
+    price: Option<u64>,
+    count: Option<u64>,
+    /// Seconds.
+    restock: Option<u64>,
+}
+
+/// Buy lists by list id.
+pub struct BuyListRegistry {
+    lists: BTreeMap<u32, BuyList>,
+}
+
+impl BuyListRegistry {
+    pub fn get(&self, list_id: u32) -> Option<&BuyList> {
+        self.lists.get(&list_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lists.len()
+    }
+}
+
+/// Loads every buy list file of `dir`. List ids must be unique across files, merchants and items must exist, each
+/// item is listed once and limited stock comes with a restock time.
+pub fn load(dir: &Path, item_templates: &ItemRegistry, npc_templates: &NpcRegistry) -> Result<BuyListRegistry, DataError> {
+    let mut lists = BTreeMap::new();
+    let mut origins: BTreeMap<u32, (usize, usize)> = BTreeMap::new();
+
+    let files = loader::read_dir(dir)?;
+    for (index, file) in files.iter().enumerate() {
+        let data: BuyListFile = file.parse()?;
+
+        for entry in data.lists {
+            let span = entry.span();
+            let entry = entry.into_inner();
+
+            if let Some((other_file, other_line)) = origins.get(&entry.id) {
+                return Err(file.error(span, format!(
+                    "buy list {} already defined at {}:{}", entry.id, files[*other_file].path.display(), other_line)));
+            }
+            if let Some(npc_id) = entry.npcs.get_ref().iter().find(|npc_id| npc_templates.get(**npc_id).is_none()) {
+                return Err(file.error(entry.npcs.span(), format!("buy list {} has unknown npc {}", entry.id, npc_id)));
+            }
+
+            let mut items: Vec<BuyListItem> = Vec::new();
+            for item in entry.items {
+                let item_span = item.span();
+                let item = item.into_inner();
+                let template = match item_templates.get(item.item) {
+                    Some(template) => template,
+                    None => return Err(file.error(item_span, format!("unknown item {}", item.item))),
+                };
+                if items.iter().any(|listed| listed.item_id == item.item) {
+                    return Err(file.error(item_span, format!("item {} listed twice", item.item)));
+                }
+                let stock = match (item.count, item.restock) {
+                    (Some(count), Some(restock)) if count > 0 => Some(Stock { count, restock: Duration::from_secs(restock) }),
+                    (None, None) => None,
+                    _ => return Err(file.error(item_span, format!("item {} needs both a count and a restock time", item.item))),
+                };
+                items.push(BuyListItem { item_id: item.item, price: item.price.unwrap_or(template.price), stock });
+            }
+
+            origins.insert(entry.id, (index, file.line_of(span.start)));
+            lists.insert(entry.id, BuyList { list_id: entry.id, npcs: entry.npcs.into_inner(), items });
+        }
+    }
+
+    Ok(BuyListRegistry { lists })
+}
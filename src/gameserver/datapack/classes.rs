@@ -0,0 +1,434 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::gameserver::models;
+
+use super::items::ItemRegistry;
+use super::skills::SkillRegistry;
+use super::loader::{self, DataError};
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct BaseStats {
+    pub str: u32,
+    pub dex: u32,
+    pub con: u32,
+    pub int: u32,
+    pub wit: u32,
+    pub men: u32,
+}
+
+/// Growth of HP, MP or CP with the level, using the same base/add/mod formula as the official templates.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Growth {
+    pub base: f64,
+    pub add: f64,
+    #[serde(rename = "mod")]
+    pub modifier: f64,
+}
+
+impl Growth {
+    /// Value at `level` for a class whose data starts at `base_level`.
+    pub fn at(&self, level: u8, base_level: u8) -> f64 {
+        let levels = level.saturating_sub(base_level) as f64;
+        let modifier = self.modifier * levels;
+        let max = (self.add + modifier) * levels;
+        let min = self.add * levels + modifier;
+        self.base + (max + min) / 2.0
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Combat {
+    pub p_atk: u32,
+    pub m_atk: u32,
+    pub p_def: u32,
+    pub m_def: u32,
+    pub p_atk_spd: u32,
+    pub m_atk_spd: u32,
+}
+
+pub struct StartingItem {
+    pub item_id: u32,
+    pub count: u64,
+    /// Paperdoll slot the item is equipped in, `None` to leave it in the inventory.
+    pub slot: Option<i32>,
+}
+
+/// Skill a class gets on reaching a level.
+pub struct ClassSkill {
+    pub skill_id: u32,
+    pub level: u32,
+    pub min_level: u8,
+}
+
+pub struct ClassTemplate {
+    pub class_id: u8,
+    pub name: String,
+    pub race: u8,
+    /// Class this one is reached from, `None` for the classes picked on the creation screen.
+    pub parent: Option<u8>,
+    /// Level the HP/MP/CP growth of the class starts at.
+    pub base_level: u8,
+    pub stats: BaseStats,
+    pub hp: Growth,
+    pub mp: Growth,
+    pub cp: Growth,
+    pub combat: Combat,
+    pub run_speed: u32,
+    pub walk_speed: u32,
+    /// Collision radius and height, male then female.
+    pub collision: [(f64, f64); 2],
+    pub spawns: Vec<(i32, i32, i32)>,
+    pub items: Vec<StartingItem>,
+    /// Skills of the class itself, those of its parents come with them.
+    pub skills: Vec<ClassSkill>,
+}
+
+impl ClassTemplate {
+    pub fn max_hp(&self, level: u8) -> f64 {
+        self.hp.at(level, self.base_level)
+    }
+
+    pub fn max_mp(&self, level: u8) -> f64 {
+        self.mp.at(level, self.base_level)
+    }
+
+    pub fn max_cp(&self, level: u8) -> f64 {
+        self.cp.at(level, self.base_level)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClassFile {
+    #[serde(rename = "class", default)]
+    classes: Vec<Spanned<ClassEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClassEntry {
+    id: u8,
+    name: String,
+    race: Spanned<u8>,
+    parent: Option<Spanned<u8>>,
+    #[serde(default = "default_base_level")]
+    base_level: u8,
+    stats: BaseStats,
+    hp: Growth,
+    mp: Growth,
+    cp: Growth,
+    combat: Combat,
+    run_speed: u32,
+    walk_speed: u32,
+    collision_male: [f64; 2],
+    collision_female: [f64; 2],
+    spawns: Spanned<Vec<[i32; 3]>>,
+    #[serde(default)]
+    items: Vec<Spanned<ItemEntry>>,
+    #[serde(default)]
+    skills: Vec<Spanned<SkillEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkillEntry {
+    id: u32,
+    #[serde(default = "default_skill_level")]
+    level: u32,
+    #[serde(default = "default_base_level")]
+    min_level: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ItemEntry {
+    id: u32,
+    #[serde(default = "default_count")]
+    count: u64,
+    slot: Option<Spanned<String>>,
+}
+
+fn default_base_level() -> u8 {
+    1
+}
+
+fn default_count() -> u64 {
+    1
+}
+
+fn default_skill_level() -> u32 {
+    1
+}
+
+const MAX_RACE: u8 = 4;
+
+fn paperdoll_slot(name: &str) -> Option<i32> {
+    match name {
+        "under" => Some(models::PAPERDOLL_UNDER),
+        "rear" => Some(models::PAPERDOLL_REAR),
+        "lear" => Some(models::PAPERDOLL_LEAR),
+        "neck" => Some(models::PAPERDOLL_NECK),
+        "rfinger" => Some(models::PAPERDOLL_RFINGER),
+        "lfinger" => Some(models::PAPERDOLL_LFINGER),
+        "head" => Some(models::PAPERDOLL_HEAD),
+        "rhand" => Some(models::PAPERDOLL_RHAND),
+        "lhand" => Some(models::PAPERDOLL_LHAND),
+        "gloves" => Some(models::PAPERDOLL_GLOVES),
+        "chest" => Some(models::PAPERDOLL_CHEST),
+        "legs" => Some(models::PAPERDOLL_LEGS),
+        "feet" => Some(models::PAPERDOLL_FEET),
+        "back" => Some(models::PAPERDOLL_BACK),
+        "lrhand" => Some(models::PAPERDOLL_LRHAND),
+        "hair" => Some(models::PAPERDOLL_HAIR),
+        "face" => Some(models::PAPERDOLL_FACE),
+        _ => None,
+    }
+}
+
+/// Class templates by class id.
+pub struct ClassRegistry {
+    classes: BTreeMap<u8, ClassTemplate>,
+}
+
+impl ClassRegistry {
+    pub fn get(&self, class_id: u8) -> Option<&ClassTemplate> {
+        self.classes.get(&class_id)
+    }
+
+    /// Classes offered on the character creation screen, by class id.
+    pub fn creatable(&self) -> Vec<&ClassTemplate> {
+        self.classes.values().filter(|template| template.parent.is_none()).collect()
+    }
+
+    /// Whether `class_id` is `ancestor` or a class reached from it.
+    pub fn descends_from(&self, class_id: u8, ancestor: u8) -> bool {
+        let mut current = Some(class_id);
+        while let Some(class_id) = current {
+            if class_id == ancestor {
+                return true;
+            }
+            current = self.get(class_id).and_then(|template| template.parent);
+        }
+        false
+    }
+
+    /// Skills a character of `class_id` knows at `level`, by skill id with their highest level reached. Skills
+    /// of the classes it comes from count too.
+    pub fn skills_at(&self, class_id: u8, level: u8) -> BTreeMap<u32, u32> {
+        let mut skills = BTreeMap::new();
+        let mut current = self.get(class_id);
+        while let Some(class) = current {
+            for skill in class.skills.iter().filter(|skill| skill.min_level <= level) {
+                let known = skills.entry(skill.skill_id).or_insert(skill.level);
+                *known = (*known).max(skill.level);
+            }
+            current = class.parent.and_then(|parent| self.get(parent));
+        }
+        skills
+    }
+
+    pub fn len(&self) -> usize {
+        self.classes.len()
+    }
+}
+
+/// Loads every class file of `dir`. Class ids must be unique across files, parents must exist without a class
+/// coming back among its own ancestors, starting items must be known to `item_templates` and equipable when
+/// they are given a slot, and skills must be known to `skill_templates`.
+pub fn load(dir: &Path, item_templates: &ItemRegistry, skill_templates: &SkillRegistry) -> Result<ClassRegistry, DataError> {
+    let mut classes = BTreeMap::new();
+    // File index and line each class was defined at, to point at the first definition on duplicates.
+    let mut origins: BTreeMap<u8, (usize, usize)> = BTreeMap::new();
+    let mut parents = Vec::new();
+
+    let files = loader::read_dir(dir)?;
+    for (index, file) in files.iter().enumerate() {
+        let data: ClassFile = file.parse()?;
+
+        for entry in data.classes {
+            let span = entry.span();
+            let entry = entry.into_inner();
+
+            if let Some((other_file, other_line)) = origins.get(&entry.id) {
+                return Err(file.error(span, format!(
+                    "class {} already defined at {}:{}", entry.id, files[*other_file].path.display(), other_line)));
+            }
+            if *entry.race.get_ref() > MAX_RACE {
+                return Err(file.error(entry.race.span(), format!("unknown race {}", entry.race.get_ref())));
+            }
+            if entry.spawns.get_ref().is_empty() {
+                return Err(file.error(entry.spawns.span(), format!("class {} has no spawn location", entry.id)));
+            }
+            if entry.base_level == 0 {
+                return Err(file.error(span, format!("class {} has base level 0", entry.id)));
+            }
+
+            let mut items = Vec::new();
+            for item in entry.items {
+                let item_span = item.span();
+                let item = item.into_inner();
+                if item.count == 0 {
+                    return Err(file.error(item_span, format!("starting item {} has count 0", item.id)));
+                }
+                let template = match item_templates.get(item.id) {
+                    Some(template) => template,
+                    None => return Err(file.error(item_span, format!("unknown starting item {}", item.id))),
+                };
+                if item.slot.is_some() && !template.is_equipable() {
+                    return Err(file.error(item_span, format!("starting item {} can't be equipped", item.id)));
+                }
+                let slot = match item.slot {
+                    Some(slot) => match paperdoll_slot(slot.get_ref()) {
+                        Some(index) => Some(index),
+                        None => return Err(file.error(slot.span(), format!("unknown paperdoll slot '{}'", slot.get_ref()))),
+                    },
+                    None => None,
+                };
+                items.push(StartingItem { item_id: item.id, count: item.count, slot });
+            }
+
+            let mut skills = Vec::new();
+            for skill in entry.skills {
+                let skill_span = skill.span();
+                let skill = skill.into_inner();
+                if skill_templates.get(skill.id, skill.level).is_none() {
+                    return Err(file.error(skill_span, format!("unknown skill {} level {}", skill.id, skill.level)));
+                }
+                skills.push(ClassSkill { skill_id: skill.id, level: skill.level, min_level: skill.min_level });
+            }
+
+            if let Some(parent) = &entry.parent {
+                parents.push((entry.id, *parent.get_ref(), index, parent.span()));
+            }
+
+            origins.insert(entry.id, (index, file.line_of(span.start)));
+            classes.insert(entry.id, ClassTemplate {
+                class_id: entry.id,
+                name: entry.name,
+                race: entry.race.into_inner(),
+                parent: entry.parent.map(|parent| parent.into_inner()),
+                base_level: entry.base_level,
+                stats: entry.stats,
+                hp: entry.hp,
+                mp: entry.mp,
+                cp: entry.cp,
+                combat: entry.combat,
+                run_speed: entry.run_speed,
+                walk_speed: entry.walk_speed,
+                collision: [
+                    (entry.collision_male[0], entry.collision_male[1]),
+                    (entry.collision_female[0], entry.collision_female[1]),
+                ],
+                spawns: entry.spawns.into_inner().into_iter().map(|[x, y, z]| (x, y, z)).collect(),
+                items,
+                skills,
+            });
+        }
+    }
+
+    for (class_id, parent, file, span) in &parents {
+        if !classes.contains_key(parent) {
+            return Err(files[*file].error(span.clone(), format!("class {} has unknown parent {}", class_id, parent)));
+        }
+    }
+    // Walks up the tree would never end on a cycle.
+    for (class_id, parent, file, span) in parents {
+        let mut seen = BTreeSet::from([class_id]);
+        let mut current = Some(parent);
+        while let Some(ancestor) = current {
+            if !seen.insert(ancestor) {
+                return Err(files[file].error(span, format!("parents of class {} form a cycle through class {}", class_id, ancestor)));
+            }
+            current = classes.get(&ancestor).and_then(|template| template.parent);
+        }
+    }
+
+    Ok(ClassRegistry { classes })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::gameserver::datapack::{items, skills};
+
+    fn class(id: u8, parent: Option<u8>) -> String {
+        let parent = parent.map(|parent| format!("parent = {}\n", parent)).unwrap_or_default();
+        format!(r#"
+[[class]]
+id = {}
+name = "Class {}"
+race = 0
+{}stats = {{ str = 40, dex = 30, con = 43, int = 21, wit = 11, men = 25 }}
+hp = {{ base = 80.0, add = 11.83, mod = 0.01 }}
+mp = {{ base = 30.0, add = 5.46, mod = 0.003 }}
+cp = {{ base = 32.0, add = 4.73, mod = 0.004 }}
+combat = {{ p_atk = 4, m_atk = 6, p_def = 80, m_def = 41, p_atk_spd = 300, m_atk_spd = 333 }}
+run_speed = 115
+walk_speed = 80
+collision_male = [9.0, 23.0]
+collision_female = [8.0, 23.5]
+spawns = [[-71338, 258271, -3104]]
+"#, id, id, parent)
+    }
+
+    /// Loads class files written to a directory of their own, with no items or skills.
+    fn load_files(name: &str, files: &[String]) -> Result<ClassRegistry, DataError> {
+        let dir: PathBuf = std::env::temp_dir().join(format!("l2-classes-{}-{}", name, std::process::id()));
+        let classes = dir.join("classes");
+        let empty = dir.join("empty");
+        fs::create_dir_all(&classes).unwrap();
+        fs::create_dir_all(&empty).unwrap();
+        for (index, content) in files.iter().enumerate() {
+            fs::write(classes.join(format!("{}.toml", index)), content).unwrap();
+        }
+        let item_templates = items::load(&empty).unwrap();
+        let skill_templates = skills::load(&empty, &item_templates).unwrap();
+        let result = load(&classes, &item_templates, &skill_templates);
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    #[test]
+    fn loads_class_tree() {
+        let registry = load_files("tree", &[class(0, None) + &class(1, Some(0)), class(2, Some(1))]).unwrap();
+        assert_eq!(registry.len(), 3);
+        assert!(registry.descends_from(2, 0));
+        assert!(!registry.descends_from(0, 2));
+        assert_eq!(registry.creatable().len(), 1);
+    }
+
+    #[test]
+    fn duplicate_ids_point_at_both_definitions() {
+        let e = load_files("duplicate", &[class(0, None), class(1, None) + &class(0, None)]).err().unwrap();
+        assert!(e.file.ends_with("1.toml"));
+        assert_eq!(e.line, Some(17));
+        assert!(e.message.contains("class 0 already defined at") && e.message.ends_with("0.toml:2"));
+    }
+
+    #[test]
+    fn unknown_parents_are_refused() {
+        let e = load_files("unknown", &[class(0, None) + &class(1, Some(5))]).err().unwrap();
+        assert_eq!(e.line, Some(21));
+        assert_eq!(e.message, "class 1 has unknown parent 5");
+    }
+
+    #[test]
+    fn cycles_are_refused() {
+        let e = load_files("self", &[class(3, Some(3))]).err().unwrap();
+        assert_eq!(e.line, Some(6));
+        assert!(e.message.starts_with("parents of class 3 form a cycle"));
+
+        let e = load_files("cycle", &[class(0, None) + &class(1, Some(2)) + &class(2, Some(1))]).err().unwrap();
+        assert!(e.message.contains("form a cycle"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::gameserver::stats::FuncTemplate;
+
+use super::loader::{self, DataError};
+
+pub const ADENA: u32 = 57;
+
+// Body parts as the client knows them, an item sent with several bits fits any of those slots.
+pub const SLOT_UNDERWEAR: u32 = 0x0001;
+pub const SLOT_R_EAR: u32 = 0x0002;
+pub const SLOT_L_EAR: u32 = 0x0004;
+pub const SLOT_NECK: u32 = 0x0008;
+pub const SLOT_R_FINGER: u32 = 0x0010;
+pub const SLOT_L_FINGER: u32 = 0x0020;
+pub const SLOT_HEAD: u32 = 0x0040;
+pub const SLOT_R_HAND: u32 = 0x0080;
+pub const SLOT_L_HAND: u32 = 0x0100;
+pub const SLOT_GLOVES: u32 = 0x0200;
+pub const SLOT_CHEST: u32 = 0x0400;
+pub const SLOT_LEGS: u32 = 0x0800;
+pub const SLOT_FEET: u32 = 0x1000;
+pub const SLOT_BACK: u32 = 0x2000;
+pub const SLOT_LR_HAND: u32 = 0x4000;
+/// Chest piece that covers the legs too.
+pub const SLOT_FULL_ARMOR: u32 = 0x8000;
+pub const SLOT_HAIR: u32 = 0x010000;
+pub const SLOT_FACE: u32 = 0x040000;
+/// Hair accessory covering both the hair and face slots.
+pub const SLOT_DHAIR: u32 = 0x080000;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemKind {
+    Weapon,
+    Armor,
+    Etc,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Grade {
+    None,
+    D,
+    C,
+    B,
+    A,
+    S,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum WeaponType {
+    Sword,
+    Blunt,
+    Dagger,
+    Bow,
+    Pole,
+    Fist,
+    Dual,
+    DualFist,
+    BigSword,
+    BigBlunt,
+    Etc,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct WeaponStats {
+    #[serde(rename = "type")]
+    pub weapon_type: WeaponType,
+    pub p_atk: u32,
+    pub m_atk: u32,
+    pub atk_speed: u32,
+    pub critical: u32,
+    /// Soulshots used by a shot, 0 when the weapon can't use them.
+    #[serde(default)]
+    pub soulshots: u32,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct ArmorStats {
+    #[serde(default)]
+    pub p_def: u32,
+    #[serde(default)]
+    pub m_def: u32,
+}
+
+pub struct ItemTemplate {
+    pub item_id: u32,
+    pub name: String,
+    pub kind: ItemKind,
+    /// `SLOT_*` bits of the body part the item is worn on, 0 for items that can't be equipped.
+    pub body_part: u32,
+    pub grade: Grade,
+    pub weight: u32,
+    pub price: u64,
+    pub stackable: bool,
+    pub weapon: Option<WeaponStats>,
+    pub armor: Option<ArmorStats>,
+    /// Classes allowed to equip the item, along with the classes reached from them. Empty for everyone.
+    pub classes: Vec<u8>,
+    /// Stat functions put on whoever wears the item.
+    pub stats: Vec<FuncTemplate>,
+}
+
+impl ItemTemplate {
+    pub fn is_equipable(&self) -> bool {
+        self.body_part != 0
+    }
+
+    pub fn is_bow(&self) -> bool {
+        self.weapon.is_some_and(|weapon| weapon.weapon_type == WeaponType::Bow)
+    }
+
+    /// Whether these are arrows `bow` shoots: worn in the left hand and of the grade of the bow.
+    pub fn fits_bow(&self, bow: &ItemTemplate) -> bool {
+        self.kind == ItemKind::Etc && self.body_part == SLOT_L_HAND && bow.is_bow() && self.grade == bow.grade
+    }
+
+    fn is_jewelry(&self) -> bool {
+        self.body_part & (SLOT_R_EAR | SLOT_L_EAR | SLOT_NECK | SLOT_R_FINGER | SLOT_L_FINGER) != 0
+    }
+
+    /// First item type of the client: weapons and jewelry, armor and shields, everything else.
+    pub fn type1(&self) -> u16 {
+        match self.kind {
+            ItemKind::Weapon => 0,
+            ItemKind::Armor if self.is_jewelry() => 0,
+            ItemKind::Armor => 1,
+            ItemKind::Etc => 4,
+        }
+    }
+
+    /// Second item type of the client, which decides the inventory tab and icon frame.
+    pub fn type2(&self) -> u16 {
+        match self.kind {
+            ItemKind::Weapon => 0,
+            ItemKind::Armor if self.is_jewelry() => 2,
+            ItemKind::Armor => 1,
+            ItemKind::Etc if self.item_id == ADENA => 4,
+            ItemKind::Etc => 5,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ItemFile {
+    #[serde(rename = "item", default)]
+    items: Vec<Spanned<ItemEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ItemEntry {
+    id: u32,
+    name: String,
+    kind: ItemKind,
+    slot: Option<Spanned<String>>,
+    #[serde(default = "default_grade")]
+    grade: Grade,
+    #[serde(default)]
+    weight: u32,
+    #[serde(default)]
+    price: u64,
+    #[serde(default)]
+    stackable: bool,
+    weapon: Option<WeaponStats>,
+    armor: Option<ArmorStats>,
+    #[serde(default)]
+    classes: Vec<u8>,
+    #[serde(default)]
+    stats: Vec<FuncTemplate>,
+}
+
+fn default_grade() -> Grade {
+    Grade::None
+}
+
+fn body_part(name: &str) -> Option<u32> {
+    match name {
+        "underwear" => Some(SLOT_UNDERWEAR),
+        "ear" => Some(SLOT_R_EAR | SLOT_L_EAR),
+        "neck" => Some(SLOT_NECK),
+        "finger" => Some(SLOT_R_FINGER | SLOT_L_FINGER),
+        "head" => Some(SLOT_HEAD),
+        "rhand" => Some(SLOT_R_HAND),
+        "lhand" => Some(SLOT_L_HAND),
+        "gloves" => Some(SLOT_GLOVES),
+        "chest" => Some(SLOT_CHEST),
+        "legs" => Some(SLOT_LEGS),
+        "feet" => Some(SLOT_FEET),
+        "back" => Some(SLOT_BACK),
+        "lrhand" => Some(SLOT_LR_HAND),
+        "fullarmor" => Some(SLOT_FULL_ARMOR),
+        "hair" => Some(SLOT_HAIR),
+        "face" => Some(SLOT_FACE),
+        "dhair" => Some(SLOT_DHAIR),
+        _ => None,
+    }
+}
+
+/// Item templates by item id.
+pub struct ItemRegistry {
+    items: BTreeMap<u32, ItemTemplate>,
+}
+
+impl ItemRegistry {
+    pub fn get(&self, item_id: u32) -> Option<&ItemTemplate> {
+        self.items.get(&item_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+}
+
+/// Loads every item file of `dir`. Item ids must be unique across files, weapons and armor need their stats
+/// and a body part, and only etc items can stack.
+pub fn load(dir: &Path) -> Result<ItemRegistry, DataError> {
+    let mut items = BTreeMap::new();
+    let mut origins: BTreeMap<u32, (usize, usize)> = BTreeMap::new();
+
+    let files = loader::read_dir(dir)?;
+    for (index, file) in files.iter().enumerate() {
+        let data: ItemFile = file.parse()?;
+
+        for entry in data.items {
+            let span = entry.span();
+            let entry = entry.into_inner();
+
+            if let Some((other_file, other_line)) = origins.get(&entry.id) {
+                return Err(file.error(span, format!(
+                    "item {} already defined at {}:{}", entry.id, files[*other_file].path.display(), other_line)));
+            }
+
+            let body_part = match &entry.slot {
+                Some(slot) => match body_part(slot.get_ref()) {
+                    Some(body_part) => body_part,
+                    None => return Err(file.error(slot.span(), format!("unknown body part '{}'", slot.get_ref()))),
+                },
+                None => 0,
+            };
+            let problem = match entry.kind {
+                ItemKind::Weapon if entry.weapon.is_none() => Some("weapon without weapon stats"),
+                ItemKind::Weapon if body_part & (SLOT_R_HAND | SLOT_LR_HAND) == 0 => Some("weapon not held in the right hand or both hands"),
+                ItemKind::Armor if body_part == 0 => Some("armor without a body part"),
+                ItemKind::Weapon | ItemKind::Armor if entry.stackable => Some("only etc items can stack"),
+                _ if entry.weapon.is_some() && entry.kind != ItemKind::Weapon => Some("weapon stats on an item that is not a weapon"),
+                _ if entry.armor.is_some() && entry.kind != ItemKind::Armor => Some("armor stats on an item that is not armor"),
+                _ => None,
+            };
+            if let Some(problem) = problem {
+                return Err(file.error(span, format!("item {}: {}", entry.id, problem)));
+            }
+
+            origins.insert(entry.id, (index, file.line_of(span.start)));
+            items.insert(entry.id, ItemTemplate {
+                item_id: entry.id,
+                name: entry.name,
+                kind: entry.kind,
+                body_part,
+                grade: entry.grade,
+                weight: entry.weight,
+                price: entry.price,
+                stackable: entry.stackable,
+                weapon: entry.weapon,
+                armor: entry.armor,
+                classes: entry.classes,
+                stats: entry.stats,
+            });
+        }
+    }
+
+    Ok(ItemRegistry { items })
+}
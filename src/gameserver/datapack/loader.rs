@@ -0,0 +1,74 @@
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+
+/// Problem found while loading the datapack, pointing at the file and line responsible for it.
+#[derive(Debug)]
+pub struct DataError {
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file.display(), line, self.message),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+/// A datapack file kept in memory so errors found after parsing can still be traced back to a line.
+pub struct DataFile {
+    pub path: PathBuf,
+    content: String,
+}
+
+impl DataFile {
+    pub fn read(path: &Path) -> Result<DataFile, DataError> {
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(DataFile { path: path.to_path_buf(), content }),
+            Err(e) => Err(DataError { file: path.to_path_buf(), line: None, message: format!("can't read file: {}", e) }),
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T, DataError> {
+        match toml::from_str(&self.content) {
+            Ok(data) => Ok(data),
+            Err(e) => Err(DataError {
+                file: self.path.clone(),
+                line: e.span().map(|span| self.line_of(span.start)),
+                message: e.message().to_string(),
+            }),
+        }
+    }
+
+    pub fn line_of(&self, offset: usize) -> usize {
+        self.content[..offset.min(self.content.len())].matches('\n').count() + 1
+    }
+
+    /// Error about the value found at `span`, usually taken from a `toml::Spanned` field.
+    pub fn error(&self, span: Range<usize>, message: String) -> DataError {
+        DataError { file: self.path.clone(), line: Some(self.line_of(span.start)), message }
+    }
+}
+
+/// Reads every `.toml` file of a datapack directory, sorted by name so the load order is stable.
+pub fn read_dir(dir: &Path) -> Result<Vec<DataFile>, DataError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return Err(DataError { file: dir.to_path_buf(), line: None, message: format!("can't read directory: {}", e) }),
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+        .collect();
+    paths.sort();
+
+    paths.iter().map(|path| DataFile::read(path)).collect()
+}
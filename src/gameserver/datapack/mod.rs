@@ -0,0 +1,10 @@
+pub mod loader;
+pub mod registry;
+pub mod classes;
+pub mod items;
+pub mod npcs;
+pub mod spawns;
+pub mod buylists;
+pub mod multisell;
+pub mod teleports;
+pub mod skills;
\ No newline at end of file
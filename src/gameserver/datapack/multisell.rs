@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use super::items::ItemRegistry;
+use super::loader::{self, DataError};
+use super::npcs::NpcRegistry;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct MultisellItem {
+    pub item: u32,
+    pub count: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MultisellEntry {
+    pub products: Vec<MultisellItem>,
+    pub ingredients: Vec<MultisellItem>,
+}
+
+pub struct Multisell {
+    pub list_id: u32,
+    pub npcs: Vec<u32>,
+    /// Products keep the enchant level of the equipment given for them.
+    pub keep_enchant: bool,
+    pub entries: Vec<MultisellEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MultisellFile {
+    #[serde(rename = "multisell", default)]
+    lists: Vec<Spanned<ListEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListEntry {
+    id: u32,
+    npcs: Spanned<Vec<u32>>,
+    #[serde(default)]
+    keep_enchant: bool,
+    entries: Vec<Spanned<MultisellEntry>>,
+}
+
+/// Multisell lists by list id.
+pub struct MultisellRegistry {
+    lists: BTreeMap<u32, Multisell>,
+}
+
+impl MultisellRegistry {
+    pub fn get(&self, list_id: u32) -> Option<&Multisell> {
+        self.lists.get(&list_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lists.len()
+    }
+}
+
+/// Loads every multisell file of `dir`. List ids must be unique across files, merchants and items must exist and
+/// each entry needs products and ingredients with counts.
+pub fn load(dir: &Path, item_templates: &ItemRegistry, npc_templates: &NpcRegistry) -> Result<MultisellRegistry, DataError> {
+    let mut lists = BTreeMap::new();
+    let mut origins: BTreeMap<u32, (usize, usize)> = BTreeMap::new();
+
+    let files = loader::read_dir(dir)?;
+    for (index, file) in files.iter().enumerate() {
+        let data: MultisellFile = file.parse()?;
+
+        for list in data.lists {
+            let span = list.span();
+            let list = list.into_inner();
+
+            if let Some((other_file, other_line)) = origins.get(&list.id) {
+                return Err(file.error(span, format!(
+                    "multisell {} already defined at {}:{}", list.id, files[*other_file].path.display(), other_line)));
+            }
+            if let Some(npc_id) = list.npcs.get_ref().iter().find(|npc_id| npc_templates.get(**npc_id).is_none()) {
+                return Err(file.error(list.npcs.span(), format!("multisell {} has unknown npc {}", list.id, npc_id)));
+            }
+
+            let mut entries = Vec::new();
+            for entry in list.entries {
+                let entry_span = entry.span();
+                let entry = entry.into_inner();
+                if entry.products.is_empty() || entry.ingredients.is_empty() {
+                    return Err(file.error(entry_span, format!("multisell {} has an entry without products or ingredients", list.id)));
+                }
+                for item in entry.products.iter().chain(&entry.ingredients) {
+                    if item_templates.get(item.item).is_none() {
+                        return Err(file.error(entry_span, format!("unknown item {}", item.item)));
+                    }
+                    if item.count == 0 {
+                        return Err(file.error(entry_span, format!("item {} has count 0", item.item)));
+                    }
+                }
+                entries.push(entry);
+            }
+
+            origins.insert(list.id, (index, file.line_of(span.start)));
+            lists.insert(list.id, Multisell { list_id: list.id, npcs: list.npcs.into_inner(), keep_enchant: list.keep_enchant, entries });
+        }
+    }
+
+    Ok(MultisellRegistry { lists })
+}
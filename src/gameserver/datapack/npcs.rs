@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use super::items::ItemRegistry;
+use super::skills::SkillRegistry;
+use super::loader::{self, DataError};
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum NpcType {
+    Monster,
+    RaidBoss,
+    Guard,
+    Merchant,
+    Warehouse,
+    Teleporter,
+    /// Any other NPC players talk to.
+    Folk,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum AiType {
+    /// Stands where it was spawned.
+    None,
+    /// Walks around its spawn and only fights back.
+    Passive,
+    /// Attacks the players it sees.
+    Aggressive,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct NpcStats {
+    pub hp: f64,
+    pub mp: f64,
+    pub p_atk: u32,
+    pub m_atk: u32,
+    pub p_def: u32,
+    pub m_def: u32,
+    pub p_atk_spd: u32,
+    pub m_atk_spd: u32,
+    pub run_speed: u32,
+    pub walk_speed: u32,
+    /// Distance it attacks from, 0 for melee.
+    #[serde(default)]
+    pub attack_range: u32,
+    /// Distance aggressive NPCs notice players from.
+    #[serde(default)]
+    pub aggro_range: u32,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct NpcSkill {
+    pub id: u32,
+    #[serde(default = "default_skill_level")]
+    pub level: u32,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Drop {
+    pub item: u32,
+    pub min: u64,
+    pub max: u64,
+    /// Chance out of 1000000.
+    pub chance: u32,
+}
+
+/// Group of NPCs that come to help each other.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Faction {
+    pub name: String,
+    /// Distance from the attacked NPC its allies are called from.
+    pub range: u32,
+}
+
+pub struct NpcTemplate {
+    pub npc_id: u32,
+    pub name: String,
+    pub title: String,
+    pub npc_type: NpcType,
+    pub ai: AiType,
+    pub level: u8,
+    pub stats: NpcStats,
+    pub faction: Option<Faction>,
+    /// Collision radius and height.
+    pub collision: (f64, f64),
+    pub exp: u64,
+    pub sp: u32,
+    pub skills: Vec<NpcSkill>,
+    /// Items dropped when killed.
+    pub drops: Vec<Drop>,
+    /// Items a spoiler gets from the corpse.
+    pub spoil: Vec<Drop>,
+}
+
+impl NpcTemplate {
+    pub fn is_attackable(&self) -> bool {
+        matches!(self.npc_type, NpcType::Monster | NpcType::RaidBoss)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NpcFile {
+    #[serde(rename = "npc", default)]
+    npcs: Vec<Spanned<NpcEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NpcEntry {
+    id: u32,
+    name: String,
+    #[serde(default)]
+    title: String,
+    #[serde(rename = "type")]
+    npc_type: NpcType,
+    #[serde(default = "default_ai")]
+    ai: AiType,
+    level: u8,
+    stats: NpcStats,
+    faction: Option<Faction>,
+    collision: [f64; 2],
+    #[serde(default)]
+    exp: u64,
+    #[serde(default)]
+    sp: u32,
+    #[serde(default)]
+    skills: Vec<NpcSkill>,
+    #[serde(default)]
+    drops: Vec<Spanned<Drop>>,
+    #[serde(default)]
+    spoil: Vec<Spanned<Drop>>,
+}
+
+fn default_ai() -> AiType {
+    AiType::None
+}
+
+fn default_skill_level() -> u32 {
+    1
+}
+
+const MAX_CHANCE: u32 = 1_000_000;
+
+/// NPC templates by npc id.
+pub struct NpcRegistry {
+    npcs: BTreeMap<u32, NpcTemplate>,
+}
+
+impl NpcRegistry {
+    pub fn get(&self, npc_id: u32) -> Option<&NpcTemplate> {
+        self.npcs.get(&npc_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.npcs.len()
+    }
+}
+
+/// Loads every NPC file of `dir`. NPC ids must be unique across files, levels start at 1, factions need a name
+/// and a range, skills must be known and drops need a known item, a count range and a chance that can happen.
+pub fn load(dir: &Path, item_templates: &ItemRegistry, skill_templates: &SkillRegistry) -> Result<NpcRegistry, DataError> {
+    let mut npcs = BTreeMap::new();
+    let mut origins: BTreeMap<u32, (usize, usize)> = BTreeMap::new();
+
+    let files = loader::read_dir(dir)?;
+    for (index, file) in files.iter().enumerate() {
+        let data: NpcFile = file.parse()?;
+
+        for entry in data.npcs {
+            let span = entry.span();
+            let entry = entry.into_inner();
+
+            if let Some((other_file, other_line)) = origins.get(&entry.id) {
+                return Err(file.error(span, format!(
+                    "npc {} already defined at {}:{}", entry.id, files[*other_file].path.display(), other_line)));
+            }
+            if entry.level == 0 {
+                return Err(file.error(span, format!("npc {} has level 0", entry.id)));
+            }
+            if entry.stats.hp <= 0.0 {
+                return Err(file.error(span, format!("npc {} has no HP", entry.id)));
+            }
+            if entry.faction.as_ref().is_some_and(|faction| faction.name.is_empty() || faction.range == 0) {
+                return Err(file.error(span, format!("npc {} has a faction without a name or range", entry.id)));
+            }
+
+            if let Some(skill) = entry.skills.iter().find(|skill| skill_templates.get(skill.id, skill.level).is_none()) {
+                return Err(file.error(span, format!("npc {} has unknown skill {} level {}", entry.id, skill.id, skill.level)));
+            }
+
+            let mut lists = [Vec::new(), Vec::new()];
+            for (list, drops) in lists.iter_mut().zip([entry.drops, entry.spoil]) {
+                for drop in drops {
+                    let drop_span = drop.span();
+                    let drop = drop.into_inner();
+                    let problem = if item_templates.get(drop.item).is_none() {
+                        Some("unknown item")
+                    } else if drop.min == 0 || drop.min > drop.max {
+                        Some("bad count range")
+                    } else if drop.chance == 0 || drop.chance > MAX_CHANCE {
+                        Some("chance out of range")
+                    } else {
+                        None
+                    };
+                    if let Some(problem) = problem {
+                        return Err(file.error(drop_span, format!("drop of item {} by npc {}: {}", drop.item, entry.id, problem)));
+                    }
+                    list.push(drop);
+                }
+            }
+            let [drops, spoil] = lists;
+
+            origins.insert(entry.id, (index, file.line_of(span.start)));
+            npcs.insert(entry.id, NpcTemplate {
+                npc_id: entry.id,
+                name: entry.name,
+                title: entry.title,
+                npc_type: entry.npc_type,
+                ai: entry.ai,
+                level: entry.level,
+                stats: entry.stats,
+                faction: entry.faction,
+                collision: (entry.collision[0], entry.collision[1]),
+                exp: entry.exp,
+                sp: entry.sp,
+                skills: entry.skills,
+                drops,
+                spoil,
+            });
+        }
+    }
+
+    Ok(NpcRegistry { npcs })
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+
+use log::info;
+
+use super::buylists::{self, BuyListRegistry};
+use super::classes::{self, ClassRegistry};
+use super::items::{self, ItemRegistry};
+use super::loader::DataError;
+use super::multisell::{self, MultisellRegistry};
+use super::npcs::{self, NpcRegistry};
+use super::skills::{self, SkillRegistry};
+use super::spawns::{self, SpawnTable};
+use super::teleports::{self, TeleportRegistry};
+
+/// Static game data loaded once at startup and shared read-only by every client task.
+pub struct Datapack {
+    pub classes: ClassRegistry,
+    pub items: ItemRegistry,
+    pub skills: SkillRegistry,
+    pub npcs: NpcRegistry,
+    pub spawns: SpawnTable,
+    pub buylists: BuyListRegistry,
+    pub multisells: MultisellRegistry,
+    pub teleports: TeleportRegistry,
+}
+
+pub fn load(data_dir: &str) -> Result<Datapack, DataError> {
+    let root = Path::new(data_dir);
+
+    let items = items::load(&root.join("items"))?;
+    info!("Loaded {} item templates", items.len());
+
+    let skills = skills::load(&root.join("skills"), &items)?;
+    info!("Loaded {} skills", skills.len());
+
+    let classes = classes::load(&root.join("classes"), &items, &skills)?;
+    info!("Loaded {} class templates", classes.len());
+
+    let npcs = npcs::load(&root.join("npcs"), &items, &skills)?;
+    info!("Loaded {} npc templates", npcs.len());
+
+    let spawns = spawns::load(&root.join("spawns"), &npcs)?;
+    info!("Loaded {} spawns", spawns.len());
+
+    let buylists = buylists::load(&root.join("buylists"), &items, &npcs)?;
+    info!("Loaded {} buy lists", buylists.len());
+
+    let multisells = multisell::load(&root.join("multisell"), &items, &npcs)?;
+    info!("Loaded {} multisell lists", multisells.len());
+
+    let teleports = teleports::load(&root.join("teleports"), &items)?;
+    info!("Loaded {} teleports", teleports.len());
+
+    Ok(Datapack { classes, items, skills, npcs, spawns, buylists, multisells, teleports })
+}
\ No newline at end of file
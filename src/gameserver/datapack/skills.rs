@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::gameserver::stats::FuncTemplate;
+
+use super::items::ItemRegistry;
+use super::loader::{self, DataError};
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SkillKind {
+    /// Always on, its stat functions apply for as long as the skill is known.
+    Passive,
+    /// Puts an effect on friends.
+    Buff,
+    /// Puts an effect on enemies.
+    Debuff,
+    Heal,
+    /// Magic damage.
+    Damage,
+    /// Marks a monster, whose corpse can then be swept by the caster.
+    Spoil,
+    /// Takes the spoil of a corpse.
+    Sweep,
+}
+
+impl SkillKind {
+    /// Whether the skill is used on enemies.
+    pub fn is_offensive(self) -> bool {
+        matches!(self, SkillKind::Debuff | SkillKind::Damage | SkillKind::Spoil)
+    }
+}
+
+/// Who a skill lands on.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetType {
+    /// The caster only.
+    #[serde(rename = "self")]
+    Myself,
+    /// The target of the caster.
+    One,
+    /// The target of the caster and the creatures around it.
+    Area,
+    /// The caster and its party around it. There are no parties yet, until there are the caster is a party of
+    /// one and these skills only reach it.
+    Party,
+    /// The caster and its clan members around it.
+    Clan,
+}
+
+/// Effect a buff or debuff leaves on its targets.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EffectTemplate {
+    /// Effects of the same abnormal type don't stack, the one of highest order stays.
+    pub abnormal: String,
+    #[serde(default)]
+    pub order: u32,
+    /// Seconds it lasts.
+    pub duration: u64,
+    #[serde(default)]
+    pub stats: Vec<FuncTemplate>,
+}
+
+impl EffectTemplate {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.duration)
+    }
+}
+
+pub struct SkillTemplate {
+    pub skill_id: u32,
+    pub level: u32,
+    pub kind: SkillKind,
+    pub target: TargetType,
+    /// Distance it is cast from, between the edges of the caster and its target.
+    pub range: u32,
+    /// Reach around the target of area, party and clan skills.
+    pub radius: u32,
+    /// Cast time at 333 casting speed.
+    pub hit_time: Duration,
+    pub reuse: Duration,
+    pub mp: f64,
+    pub hp: f64,
+    /// Item and count taken by each cast.
+    pub item: Option<(u32, u64)>,
+    /// HP healed, or power of the damage.
+    pub power: f64,
+    pub effect: Option<EffectTemplate>,
+    /// Stat functions of a passive skill.
+    pub stats: Vec<FuncTemplate>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkillFile {
+    #[serde(rename = "skill", default)]
+    skills: Vec<Spanned<SkillEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkillEntry {
+    id: u32,
+    #[serde(default = "default_level")]
+    level: u32,
+    name: String,
+    #[serde(rename = "type")]
+    kind: SkillKind,
+    #[serde(default = "default_target")]
+    target: TargetType,
+    #[serde(default)]
+    range: u32,
+    #[serde(default)]
+    radius: u32,
+    /// Milliseconds.
+    #[serde(default)]
+    hit_time: u64,
+    /// Milliseconds.
+    #[serde(default)]
+    reuse: u64,
+    #[serde(default)]
+    mp: f64,
+    #[serde(default)]
+    hp: f64,
+    item: Option<ItemCost>,
+    #[serde(default)]
+    power: f64,
+    effect: Option<EffectTemplate>,
+    #[serde(default)]
+    stats: Vec<FuncTemplate>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ItemCost {
+    id: u32,
+    #[serde(default = "default_count")]
+    count: u64,
+}
+
+fn default_level() -> u32 {
+    1
+}
+
+fn default_target() -> TargetType {
+    TargetType::Myself
+}
+
+fn default_count() -> u64 {
+    1
+}
+
+/// Skill templates by skill id and level.
+pub struct SkillRegistry {
+    skills: BTreeMap<(u32, u32), SkillTemplate>,
+}
+
+impl SkillRegistry {
+    pub fn get(&self, skill_id: u32, level: u32) -> Option<&SkillTemplate> {
+        self.skills.get(&(skill_id, level))
+    }
+
+    pub fn len(&self) -> usize {
+        self.skills.len()
+    }
+}
+
+/// Loads every skill file of `dir`. A skill id and level are defined once across files, passives only have stat
+/// functions, buffs and debuffs need an effect that lasts, heals and damage need power, spoil and sweep have one
+/// target, and consumed items must be known.
+pub fn load(dir: &Path, item_templates: &ItemRegistry) -> Result<SkillRegistry, DataError> {
+    let mut skills = BTreeMap::new();
+    let mut origins: BTreeMap<(u32, u32), (usize, usize)> = BTreeMap::new();
+
+    let files = loader::read_dir(dir)?;
+    for (index, file) in files.iter().enumerate() {
+        let data: SkillFile = file.parse()?;
+
+        for entry in data.skills {
+            let span = entry.span();
+            let entry = entry.into_inner();
+            let key = (entry.id, entry.level);
+
+            if let Some((other_file, other_line)) = origins.get(&key) {
+                return Err(file.error(span, format!(
+                    "skill {} level {} already defined at {}:{}", entry.id, entry.level, files[*other_file].path.display(), other_line)));
+            }
+            let problem = match entry.kind {
+                _ if entry.level == 0 => Some("level 0"),
+                SkillKind::Passive if entry.stats.is_empty() => Some("passive skill without stats"),
+                SkillKind::Passive if entry.effect.is_some() || entry.power > 0.0 => Some("passive skill with an effect or power"),
+                SkillKind::Buff | SkillKind::Debuff if entry.effect.as_ref().is_none_or(|effect| effect.duration == 0) =>
+                    Some("buff or debuff without a lasting effect"),
+                SkillKind::Heal | SkillKind::Damage if entry.power <= 0.0 => Some("heal or damage without power"),
+                _ if entry.kind != SkillKind::Passive && !entry.stats.is_empty() => Some("stats on a skill that is not passive"),
+                SkillKind::Damage | SkillKind::Debuff if matches!(entry.target, TargetType::Myself | TargetType::Party | TargetType::Clan) =>
+                    Some("offensive skill cast on friends"),
+                SkillKind::Spoil | SkillKind::Sweep if entry.target != TargetType::One => Some("spoil or sweep not cast on one target"),
+                _ if entry.item.as_ref().is_some_and(|item| item.count == 0 || item_templates.get(item.id).is_none()) =>
+                    Some("consumes an unknown item or none of it"),
+                _ => None,
+            };
+            if let Some(problem) = problem {
+                return Err(file.error(span, format!("skill {} level {} ({}): {}", entry.id, entry.level, entry.name, problem)));
+            }
+
+            origins.insert(key, (index, file.line_of(span.start)));
+            skills.insert(key, SkillTemplate {
+                skill_id: entry.id,
+                level: entry.level,
+                kind: entry.kind,
+                target: entry.target,
+                range: entry.range,
+                radius: entry.radius,
+                hit_time: Duration::from_millis(entry.hit_time),
+                reuse: Duration::from_millis(entry.reuse),
+                mp: entry.mp,
+                hp: entry.hp,
+                item: entry.item.map(|item| (item.id, item.count)),
+                power: entry.power,
+                effect: entry.effect,
+                stats: entry.stats,
+            });
+        }
+    }
+
+    Ok(SkillRegistry { skills })
+}
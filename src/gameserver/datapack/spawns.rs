@@ -0,0 +1,243 @@
+use std::path::Path;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::Deserialize;
+use toml::Spanned;
+
+use super::loader::{self, DataError};
+use super::npcs::NpcRegistry;
+
+/// Random points tried before a territory falls back to its first corner.
+const PLACEMENT_ATTEMPTS: usize = 100;
+
+/// Polygon NPCs are placed in at random, between two heights.
+pub struct Territory {
+    pub points: Vec<(i32, i32)>,
+    pub min_z: i32,
+    pub max_z: i32,
+}
+
+impl Territory {
+    /// Whether `(x, y)` is inside the polygon, by counting the edges a ray going right from it crosses.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let mut inside = false;
+        let mut previous = self.points[self.points.len() - 1];
+        for &point in &self.points {
+            let ((x1, y1), (x2, y2)) = (previous, point);
+            if (y1 > y) != (y2 > y) {
+                let crossing = x1 as f64 + (y - y1) as f64 * (x2 - x1) as f64 / (y2 - y1) as f64;
+                if (x as f64) < crossing {
+                    inside = !inside;
+                }
+            }
+            previous = point;
+        }
+        inside
+    }
+
+    /// Random point of the territory, at its highest z. The caller brings it down to the ground.
+    pub fn random_point(&self, rng: &mut impl Rng) -> (i32, i32, i32) {
+        let min_x = self.points.iter().map(|(x, _)| *x).min().unwrap_or_default();
+        let max_x = self.points.iter().map(|(x, _)| *x).max().unwrap_or_default();
+        let min_y = self.points.iter().map(|(_, y)| *y).min().unwrap_or_default();
+        let max_y = self.points.iter().map(|(_, y)| *y).max().unwrap_or_default();
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let (x, y) = (rng.gen_range(min_x..=max_x), rng.gen_range(min_y..=max_y));
+            if self.contains(x, y) {
+                return (x, y, self.max_z);
+            }
+        }
+        let (x, y) = self.points[0];
+        (x, y, self.max_z)
+    }
+}
+
+pub enum SpawnLocation {
+    Point { position: (i32, i32, i32), heading: i32 },
+    Territory(Territory),
+}
+
+pub struct Spawn {
+    pub npc_id: u32,
+    /// NPCs kept alive by this spawn.
+    pub count: u32,
+    pub location: SpawnLocation,
+    pub respawn: Duration,
+    /// Most the respawn delay can be shortened or lengthened by.
+    pub respawn_random: Duration,
+}
+
+impl Spawn {
+    /// Where to put one of the NPCs and which way it faces.
+    pub fn place(&self, rng: &mut impl Rng) -> ((i32, i32, i32), i32) {
+        match &self.location {
+            SpawnLocation::Point { position, heading } => (*position, *heading),
+            SpawnLocation::Territory(territory) => (territory.random_point(rng), rng.gen_range(0..65536)),
+        }
+    }
+
+    /// Time before a killed NPC of this spawn comes back.
+    pub fn respawn_delay(&self, rng: &mut impl Rng) -> Duration {
+        if self.respawn_random.is_zero() {
+            return self.respawn;
+        }
+        let random = self.respawn_random.as_millis() as i64;
+        let delay = self.respawn.as_millis() as i64 + rng.gen_range(-random..=random);
+        Duration::from_millis(delay.max(0) as u64)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpawnFile {
+    #[serde(rename = "spawn", default)]
+    spawns: Vec<Spanned<SpawnEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpawnEntry {
+    npc: Spanned<u32>,
+    #[serde(default = "default_count")]
+    count: u32,
+    position: Option<[i32; 3]>,
+    #[serde(default)]
+    heading: i32,
+    territory: Option<Spanned<TerritoryEntry>>,
+    /// Seconds.
+    respawn: u64,
+    #[serde(default)]
+    respawn_random: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TerritoryEntry {
+    points: Vec<[i32; 2]>,
+    min_z: i32,
+    max_z: i32,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+/// Every spawn of the datapack, an NPC remembers the index of the spawn it came from.
+pub struct SpawnTable {
+    spawns: Vec<Spawn>,
+}
+
+impl SpawnTable {
+    pub fn get(&self, index: usize) -> Option<&Spawn> {
+        self.spawns.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Spawn)> {
+        self.spawns.iter().enumerate()
+    }
+
+    pub fn len(&self) -> usize {
+        self.spawns.len()
+    }
+}
+
+/// Loads every spawn file of `dir`. Spawns need a known NPC and either a position or a territory, which has at
+/// least three corners and its lowest height below its highest.
+pub fn load(dir: &Path, npc_templates: &NpcRegistry) -> Result<SpawnTable, DataError> {
+    let mut spawns = Vec::new();
+
+    for file in loader::read_dir(dir)? {
+        let data: SpawnFile = file.parse()?;
+
+        for entry in data.spawns {
+            let span = entry.span();
+            let entry = entry.into_inner();
+            let npc_id = *entry.npc.get_ref();
+
+            if npc_templates.get(npc_id).is_none() {
+                return Err(file.error(entry.npc.span(), format!("unknown npc {}", npc_id)));
+            }
+            if entry.count == 0 {
+                return Err(file.error(span, format!("spawn of npc {} has count 0", npc_id)));
+            }
+            let location = match (entry.position, entry.territory) {
+                (Some([x, y, z]), None) => SpawnLocation::Point { position: (x, y, z), heading: entry.heading },
+                (None, Some(territory)) => {
+                    let territory_span = territory.span();
+                    let territory = territory.into_inner();
+                    if territory.points.len() < 3 {
+                        return Err(file.error(territory_span, "territory with less than three points".to_string()));
+                    }
+                    if territory.min_z > territory.max_z {
+                        return Err(file.error(territory_span, "territory min_z above max_z".to_string()));
+                    }
+                    SpawnLocation::Territory(Territory {
+                        points: territory.points.into_iter().map(|[x, y]| (x, y)).collect(),
+                        min_z: territory.min_z,
+                        max_z: territory.max_z,
+                    })
+                },
+                _ => return Err(file.error(span, format!("spawn of npc {} needs either a position or a territory", npc_id))),
+            };
+
+            spawns.push(Spawn {
+                npc_id,
+                count: entry.count,
+                location,
+                respawn: Duration::from_secs(entry.respawn),
+                respawn_random: Duration::from_secs(entry.respawn_random),
+            });
+        }
+    }
+
+    Ok(SpawnTable { spawns })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> Territory {
+        Territory { points: vec![(0, 0), (100, 0), (100, 100), (0, 100)], min_z: -10, max_z: 10 }
+    }
+
+    #[test]
+    fn contains_points_inside_only() {
+        let territory = square();
+        assert!(territory.contains(50, 50));
+        assert!(!territory.contains(150, 50));
+        assert!(!territory.contains(-1, 50));
+
+        let triangle = Territory { points: vec![(0, 0), (100, 0), (0, 100)], min_z: 0, max_z: 0 };
+        assert!(triangle.contains(10, 10));
+        assert!(!triangle.contains(90, 90));
+    }
+
+    #[test]
+    fn random_points_stay_in_territory() {
+        let territory = Territory { points: vec![(0, 0), (100, 0), (0, 100)], min_z: -10, max_z: 10 };
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let (x, y, z) = territory.random_point(&mut rng);
+            assert!(territory.contains(x, y));
+            assert_eq!(z, 10);
+        }
+    }
+
+    #[test]
+    fn respawn_delay_stays_within_variance() {
+        let spawn = Spawn {
+            npc_id: 1,
+            count: 1,
+            location: SpawnLocation::Territory(square()),
+            respawn: Duration::from_secs(60),
+            respawn_random: Duration::from_secs(10),
+        };
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let delay = spawn.respawn_delay(&mut rng);
+            assert!(delay >= Duration::from_secs(50) && delay <= Duration::from_secs(70));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use super::items::{ItemRegistry, ADENA};
+use super::loader::{self, DataError};
+
+/// Noblesse Gate Pass, what noble teleports are paid with.
+pub const NOBLE_PASS: u32 = 6651;
+
+/// Where a gatekeeper can send a player.
+pub struct Teleport {
+    pub id: u32,
+    pub name: String,
+    pub position: (i32, i32, i32),
+    /// Item paid with, adena or noble passes.
+    pub currency: u32,
+    pub price: u64,
+    /// Scrolls of Escape and players going back to town after dying end up at the nearest town.
+    pub town: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TeleportFile {
+    #[serde(rename = "teleport", default)]
+    teleports: Vec<Spanned<TeleportEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TeleportEntry {
+    id: u32,
+    name: String,
+    position: [i32; 3],
+    #[serde(default)]
+    price: u64,
+    /// Paid with noble passes instead of adena.
+    #[serde(default)]
+    noble: bool,
+    #[serde(default)]
+    town: bool,
+}
+
+/// Teleport locations by id.
+pub struct TeleportRegistry {
+    teleports: BTreeMap<u32, Teleport>,
+}
+
+impl TeleportRegistry {
+    pub fn get(&self, id: u32) -> Option<&Teleport> {
+        self.teleports.get(&id)
+    }
+
+    /// Town closest to `(x, y)`.
+    pub fn nearest_town(&self, x: i32, y: i32) -> Option<&Teleport> {
+        self.teleports.values()
+            .filter(|teleport| teleport.town)
+            .min_by_key(|teleport| {
+                let (dx, dy) = ((teleport.position.0 - x) as i64, (teleport.position.1 - y) as i64);
+                dx * dx + dy * dy
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.teleports.len()
+    }
+}
+
+/// Loads every teleport file of `dir`. Ids must be unique across files and noble teleports need a price.
+pub fn load(dir: &Path, item_templates: &ItemRegistry) -> Result<TeleportRegistry, DataError> {
+    let mut teleports = BTreeMap::new();
+    let mut origins: BTreeMap<u32, (usize, usize)> = BTreeMap::new();
+
+    let files = loader::read_dir(dir)?;
+    for (index, file) in files.iter().enumerate() {
+        let data: TeleportFile = file.parse()?;
+
+        for entry in data.teleports {
+            let span = entry.span();
+            let entry = entry.into_inner();
+
+            if let Some((other_file, other_line)) = origins.get(&entry.id) {
+                return Err(file.error(span, format!(
+                    "teleport {} already defined at {}:{}", entry.id, files[*other_file].path.display(), other_line)));
+            }
+            let currency = if entry.noble { NOBLE_PASS } else { ADENA };
+            if item_templates.get(currency).is_none() {
+                return Err(file.error(span, format!("teleport {} is paid with unknown item {}", entry.id, currency)));
+            }
+            if entry.noble && entry.price == 0 {
+                return Err(file.error(span, format!("noble teleport {} has no price", entry.id)));
+            }
+
+            origins.insert(entry.id, (index, file.line_of(span.start)));
+            let [x, y, z] = entry.position;
+            teleports.insert(entry.id, Teleport {
+                id: entry.id,
+                name: entry.name,
+                position: (x, y, z),
+                currency,
+                price: entry.price,
+                town: entry.town,
+            });
+        }
+    }
+
+    Ok(TeleportRegistry { teleports })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn town(id: u32, x: i32, y: i32) -> Teleport {
+        Teleport { id, name: String::new(), position: (x, y, 0), currency: ADENA, price: 0, town: true }
+    }
+
+    #[test]
+    fn finds_nearest_town() {
+        let mut teleports = BTreeMap::new();
+        teleports.insert(1, town(1, 0, 0));
+        teleports.insert(2, town(2, 10000, 10000));
+        teleports.insert(3, Teleport { town: false, ..town(3, 9000, 9000) });
+        let registry = TeleportRegistry { teleports };
+
+        assert_eq!(registry.nearest_town(1000, -500).map(|teleport| teleport.id), Some(1));
+        assert_eq!(registry.nearest_town(8000, 8000).map(|teleport| teleport.id), Some(2));
+        assert!(TeleportRegistry { teleports: BTreeMap::new() }.nearest_town(0, 0).is_none());
+    }
+}
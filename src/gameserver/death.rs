@@ -0,0 +1,306 @@
+use std::time::Instant;
+
+use log::info;
+
+use crate::gameserver::client::world as request;
+use crate::gameserver::combat;
+use crate::gameserver::drops;
+use crate::gameserver::experience;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::models::Client;
+use crate::gameserver::server::combat as response;
+use crate::gameserver::server::lobby::action_failed;
+use crate::gameserver::server::system_message;
+use crate::gameserver::server::world as world_response;
+use crate::gameserver::spawn;
+use crate::gameserver::stats;
+use crate::gameserver::teleport;
+use crate::gameserver::world::{World, WorldObject};
+
+/// Share of their HP players stand up with in town.
+const REVIVE_HP: f64 = 0.65;
+/// Restart point of the town, the others are a clan hall, a castle and a siege camp.
+const RESTART_TOWN: u32 = 0;
+
+/// What comes of a death: a monster rewards the players that hurt it and leaves its loot, a player loses some
+/// experience and counts as a kill for the player that killed it.
+pub fn on_death(context: &Context, world: &mut World, obj_id: u32, killer_id: u32, now: Instant) {
+    match world.get(obj_id) {
+        Some(WorldObject::Npc(_)) => {
+            reward(context, world, obj_id);
+            loot(context, world, obj_id, now);
+        },
+        Some(WorldObject::Player(_)) => {
+            count_kill(context, world, killer_id, obj_id);
+            penalize(context, world, obj_id, killer_id);
+        },
+        None => {},
+    }
+}
+
+/// Shares the experience and SP of a monster between the players around it, by the damage each one did.
+fn reward(context: &Context, world: &mut World, obj_id: u32) {
+    let npc = match world.npc(obj_id) {
+        Some(npc) => npc,
+        None => return,
+    };
+    let template = match context.datapack.npcs.get(npc.npc_id) {
+        Some(template) => template,
+        None => return,
+    };
+    let total: u64 = npc.damage.values().sum();
+    if total == 0 {
+        return;
+    }
+    let rates = &context.conf.rates;
+    let rewards: Vec<(u32, u64, u32)> = npc.damage.iter()
+        .filter_map(|(attacker_id, damage)| world.player(*attacker_id)
+            .filter(|player| !player.is_dead() && player.known.contains(&obj_id))
+            .map(|player| {
+                let share = *damage as f64 / total as f64 * experience::level_penalty(player.character.level, npc.level);
+                let exp = template.exp as f64 * share * rates.exp;
+                let sp = template.sp as f64 * share * rates.sp;
+                (*attacker_id, exp as u64, sp as u32)
+            }))
+        .filter(|(_, exp, sp)| *exp > 0 || *sp > 0)
+        .collect();
+    for (player_id, exp, sp) in rewards {
+        experience::gain(context, world, player_id, exp, sp);
+    }
+}
+
+/// Drops the loot of a monster for the player that did the most damage, or gives it to them right away with auto
+/// loot on, and fills the corpse for its spoiler.
+fn loot(context: &Context, world: &mut World, obj_id: u32, now: Instant) {
+    let npc = match world.npc(obj_id) {
+        Some(npc) => npc,
+        None => return,
+    };
+    let template = match context.datapack.npcs.get(npc.npc_id) {
+        Some(template) => template,
+        None => return,
+    };
+    let rates = &context.conf.rates;
+    let mut rng = rand::thread_rng();
+    let items = drops::roll(&template.drops, rates.drop, rates.adena, &mut rng);
+    let sweep = match npc.spoiler {
+        Some(_) => drops::roll(&template.spoil, rates.spoil, rates.adena, &mut rng),
+        None => Vec::new(),
+    };
+    let owner = npc.damage.iter()
+        .filter(|(attacker_id, _)| world.player(**attacker_id).is_some())
+        .max_by(|(a, damage_a), (b, damage_b)| damage_a.cmp(damage_b).then(b.cmp(a)))
+        .map(|(attacker_id, _)| *attacker_id);
+    if let Some(npc) = world.npc_mut(obj_id) {
+        npc.sweep = sweep;
+    }
+    let items = match owner {
+        Some(owner) if context.conf.options.auto_loot => drops::give(context, world, owner, items),
+        _ => items,
+    };
+    drops::drop_around(context, world, obj_id, items, owner, now);
+}
+
+/// Counts a player kill for the killer: killing a player with karma is PvP, killing one without is PK and gives
+/// karma.
+fn count_kill(context: &Context, world: &mut World, killer_id: u32, obj_id: u32) {
+    let victim_karma = match world.player(obj_id) {
+        Some(victim) => victim.character.karma,
+        None => return,
+    };
+    let killer = match world.player_mut(killer_id) {
+        Some(killer) if killer_id != obj_id => killer,
+        _ => return,
+    };
+    let character = &mut killer.character;
+    if victim_karma > 0 {
+        character.pvp_kills += 1;
+    } else {
+        character.pk_kills += 1;
+        character.karma = character.karma.saturating_add(context.conf.karma.per_kill);
+    }
+    // The counts only show in the user info.
+    if let Some(class) = context.datapack.classes.get(killer.character.class_id) {
+        killer.send(world_response::user_info(killer, class));
+    }
+    world.broadcast_info(killer_id);
+}
+
+/// Takes the experience a player loses when a monster kills it, or on any death once it has karma.
+fn penalize(context: &Context, world: &mut World, obj_id: u32, killer_id: u32) {
+    let death = &context.conf.death;
+    let (level, karma) = match world.player(obj_id) {
+        Some(player) => (player.character.level, player.character.karma),
+        None => return,
+    };
+    if (world.npc(killer_id).is_none() && karma == 0) || level < death.min_level || death.exp_loss <= 0.0 {
+        return;
+    }
+    experience::lose(context, world, obj_id, experience::death_loss(level, death.exp_loss), death.delevel);
+}
+
+/// Gives a spoiler the items of the corpse it spoiled. The corpse goes away once swept, items that don't fit
+/// stay on it.
+pub fn sweep(context: &Context, world: &mut World, obj_id: u32, target_id: u32) {
+    let items = match world.npc_mut(target_id) {
+        Some(npc) if npc.is_dead() && npc.spoiler == Some(obj_id) && !npc.sweep.is_empty() => std::mem::take(&mut npc.sweep),
+        _ => {
+            if let Some(player) = world.player(obj_id) {
+                player.send(system_message::system_message(system_message::SWEEPER_FAILED_TARGET_NOT_SPOILED, &[]));
+            }
+            return;
+        }
+    };
+    let left = drops::give(context, world, obj_id, items);
+    match world.npc_mut(target_id) {
+        Some(npc) if !left.is_empty() => npc.sweep = left,
+        _ => spawn::despawn(context, world, target_id),
+    }
+}
+
+/// The dead player asks to go back to town, where it stands up with part of its HP. There are no clan halls,
+/// castles or siege camps yet, asking for them is refused and the player stays dead until it picks the town.
+pub async fn request_restart_point(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let request = request::new_request_restart_point(data)?;
+    if request.point != RESTART_TOWN {
+        client.send(action_failed());
+        return Ok(());
+    }
+
+    let mut world = context.world();
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} of {} is not in the world", obj_id, client.account_name)),
+    };
+    if !player.is_dead() {
+        return Ok(());
+    }
+    let (x, y, _) = player.position();
+    let town = match context.datapack.teleports.nearest_town(x, y) {
+        Some(town) => town,
+        None => return Err("No town to restart in".to_string()),
+    };
+    info!("{} restarts in {}", player.character.char_name, town.name);
+    player.character.cur_hp = player.character.max_hp * REVIVE_HP;
+    world.broadcast_with_self(obj_id, &response::revive(obj_id));
+    combat::show_hp(&world, obj_id);
+    world.start_recovering(obj_id);
+    // Functions may depend on HP.
+    stats::refresh(context, &mut world, obj_id);
+    teleport::teleport(&mut world, obj_id, town.position);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameserver::experience::{death_loss, exp_for_level};
+    use crate::gameserver::gameserver::synthetic::context;
+    use crate::gameserver::geodata::Geodata;
+    use crate::gameserver::idfactory::FIRST_OBJECT_ID;
+    use crate::gameserver::npc::Npc;
+    use crate::gameserver::player::synthetic::player;
+
+    /// Level 3 monster worth 61 exp and 4 SP.
+    const WOLF: u32 = 20120;
+    /// Level 1 monster dropping a potion at 2%.
+    const GREMLIN: u32 = 20001;
+    const POTION: u32 = 1060;
+
+    fn spawn(context: &Context, world: &mut World, obj_id: u32, npc_id: u32, (x, y): (i32, i32)) {
+        let template = context.datapack.npcs.get(npc_id).unwrap();
+        world.add(WorldObject::Npc(Box::new(Npc::new(obj_id, template, (x, y, 0), 0, None)))).unwrap();
+    }
+
+    fn enter(context: &Context, world: &mut World, obj_id: u32, name: &str, (x, y): (i32, i32)) {
+        world.add(WorldObject::Player(Box::new(player(&context.datapack, obj_id, name, x, y)))).unwrap();
+    }
+
+    #[tokio::test]
+    async fn monster_rewards_are_shared_by_damage() {
+        let context = context(Geodata::empty());
+        let mut world = World::new();
+        spawn(&context, &mut world, 10, WOLF, (1000, 1000));
+        enter(&context, &mut world, 1, "Tank", (1050, 1000));
+        enter(&context, &mut world, 2, "Archer", (1300, 1000));
+        let damage = &mut world.npc_mut(10).unwrap().damage;
+        damage.insert(1, 300);
+        damage.insert(2, 100);
+        // Damage of whoever left still counts toward the shares.
+        damage.insert(3, 400);
+
+        reward(&context, &mut world, 10);
+        let earned = |obj_id| world.player(obj_id).map(|player| (player.character.exp, player.character.sp)).unwrap();
+        assert_eq!(earned(1), (22, 1));
+        assert_eq!(earned(2), (7, 0));
+    }
+
+    #[tokio::test]
+    async fn loot_is_kept_for_the_top_damager() {
+        let mut context = context(Geodata::empty());
+        // The potion drops every time.
+        context.conf.rates.drop = 50.0;
+        let mut world = World::new();
+        spawn(&context, &mut world, 10, GREMLIN, (1000, 1000));
+        spawn(&context, &mut world, 11, GREMLIN, (1100, 1000));
+        enter(&context, &mut world, 1, "Tank", (1050, 1000));
+        enter(&context, &mut world, 2, "Archer", (1300, 1000));
+        let damage = &mut world.npc_mut(10).unwrap().damage;
+        damage.insert(1, 100);
+        damage.insert(2, 300);
+        // Only players own loot.
+        damage.insert(11, 1000);
+
+        loot(&context, &mut world, 10, Instant::now());
+        let items: Vec<_> = (FIRST_OBJECT_ID..FIRST_OBJECT_ID + 2).filter_map(|obj_id| world.ground_item(obj_id)).collect();
+        assert!(items.iter().any(|item| item.item_id == POTION));
+        assert!(items.iter().all(|item| item.owner == Some(2)));
+    }
+
+    #[tokio::test]
+    async fn players_lose_experience_to_monsters_or_with_karma() {
+        let context = context(Geodata::empty());
+        let mut world = World::new();
+        spawn(&context, &mut world, 10, WOLF, (1000, 1000));
+        enter(&context, &mut world, 1, "Victim", (1050, 1000));
+        enter(&context, &mut world, 2, "Killer", (1100, 1000));
+        let loss = death_loss(20, context.conf.death.exp_loss);
+        let start = exp_for_level(20) + 3 * loss;
+        let character = &mut world.player_mut(1).unwrap().character;
+        character.level = 20;
+        character.exp = start;
+        let exp = |world: &World| world.player(1).unwrap().character.exp;
+
+        penalize(&context, &mut world, 1, 10);
+        assert_eq!(exp(&world), start - loss);
+        penalize(&context, &mut world, 1, 2);
+        assert_eq!(exp(&world), start - loss);
+        world.player_mut(1).unwrap().character.karma = 10;
+        penalize(&context, &mut world, 1, 2);
+        assert_eq!(exp(&world), start - 2 * loss);
+
+        // Below the minimum level nothing is lost.
+        let character = &mut world.player_mut(2).unwrap().character;
+        character.exp = 50;
+        penalize(&context, &mut world, 2, 10);
+        assert_eq!(world.player(2).unwrap().character.exp, 50);
+    }
+
+    #[tokio::test]
+    async fn killing_players_without_karma_is_pk() {
+        let context = context(Geodata::empty());
+        let mut world = World::new();
+        enter(&context, &mut world, 1, "Killer", (1000, 1000));
+        enter(&context, &mut world, 2, "Innocent", (1050, 1000));
+        enter(&context, &mut world, 3, "Outlaw", (1100, 1000));
+        world.player_mut(3).unwrap().character.karma = 100;
+
+        count_kill(&context, &mut world, 1, 2);
+        count_kill(&context, &mut world, 1, 3);
+        count_kill(&context, &mut world, 1, 1);
+        let character = &world.player(1).unwrap().character;
+        assert_eq!((character.pk_kills, character.pvp_kills), (1, 1));
+        assert_eq!(character.karma, context.conf.karma.per_kill);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,305 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::warn;
+use rand::Rng;
+
+use crate::gameserver::combat;
+use crate::gameserver::datapack::items::ADENA;
+use crate::gameserver::datapack::npcs::Drop;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::inventory::{self, InventoryError};
+use crate::gameserver::movement::{self, Movement};
+use crate::gameserver::player::Player;
+use crate::gameserver::server::items as response;
+use crate::gameserver::server::lobby::action_failed;
+use crate::gameserver::server::movement as movement_response;
+use crate::gameserver::server::system_message::{self, Param};
+use crate::gameserver::world::World;
+
+/// How often players walking to an item are checked and old items go away.
+const DROPS_TICK: Duration = Duration::from_millis(200);
+/// Distance a player picks up an item from.
+const PICKUP_RANGE: f64 = 60.0;
+/// Time only the owner of a dropped item can pick it up.
+const PROTECTION_TIME: Duration = Duration::from_secs(15);
+/// Time an item stays on the ground before it goes away.
+const ITEM_LIFETIME: Duration = Duration::from_secs(180);
+/// Farthest from the NPC its loot lands.
+const SCATTER: i32 = 70;
+/// Chances of drops are out of this.
+const MAX_CHANCE: f64 = 1_000_000.0;
+
+/// An item lying on the ground.
+#[derive(Clone)]
+pub struct GroundItem {
+    pub obj_id: u32,
+    pub item_id: u32,
+    pub count: u64,
+    pub stackable: bool,
+    pub position: (i32, i32, i32),
+    /// Player the item was dropped for, the only one that can pick it up until `protected_until`.
+    pub owner: Option<u32>,
+    pub protected_until: Instant,
+    pub expires_at: Instant,
+}
+
+/// Items and counts a drop list gives. The rate multiplies the chance of every drop but adena, whose count is
+/// multiplied by the adena rate instead. A chance over 100% drops the item that many times over.
+pub fn roll(drops: &[Drop], rate: f64, adena_rate: f64, rng: &mut impl Rng) -> Vec<(u32, u64)> {
+    let mut rolled = Vec::new();
+    for drop in drops {
+        let chance = drop.chance as f64 * if drop.item == ADENA { 1.0 } else { rate };
+        let mut times = (chance / MAX_CHANCE) as u64;
+        if rng.gen_range(0.0..MAX_CHANCE) < chance % MAX_CHANCE {
+            times += 1;
+        }
+        let mut count: u64 = (0..times).map(|_| rng.gen_range(drop.min..=drop.max)).sum();
+        if drop.item == ADENA {
+            count = (count as f64 * adena_rate) as u64;
+        }
+        if count > 0 {
+            rolled.push((drop.item, count));
+        }
+    }
+    rolled
+}
+
+/// Scatters items on the ground around a creature, kept for `owner` a while. Items that don't stack land one by
+/// one.
+pub fn drop_around(context: &Context, world: &mut World, dropper_id: u32, items: Vec<(u32, u64)>, owner: Option<u32>, now: Instant) {
+    let from = match world.get(dropper_id) {
+        Some(dropper) => dropper.position(),
+        None => return,
+    };
+    let mut rng = rand::thread_rng();
+    for (item_id, count) in items {
+        let stackable = match context.datapack.items.get(item_id) {
+            Some(template) => template.stackable,
+            None => continue,
+        };
+        let (stacks, count) = if stackable { (1, count) } else { (count, 1) };
+        for _ in 0..stacks {
+            let obj_id = match context.ids.next_id() {
+                Ok(obj_id) => obj_id,
+                Err(e) => {
+                    warn!("Couldn't drop item {}: {}", item_id, e);
+                    return;
+                }
+            };
+            let to = (from.0 + rng.gen_range(-SCATTER..=SCATTER), from.1 + rng.gen_range(-SCATTER..=SCATTER), from.2);
+            let position = context.geodata.move_check(from, to);
+            world.drop_item(dropper_id, GroundItem {
+                obj_id,
+                item_id,
+                count,
+                stackable,
+                position,
+                owner,
+                protected_until: now + PROTECTION_TIME,
+                expires_at: now + ITEM_LIFETIME,
+            });
+        }
+    }
+}
+
+/// What a player is told about the items it got.
+pub fn earned(item_id: u32, count: u64) -> Vec<u8> {
+    let number = Param::Number(count.min(u32::MAX as u64) as u32);
+    match item_id {
+        ADENA => system_message::system_message(system_message::EARNED_S1_ADENA, &[number]),
+        _ if count > 1 => system_message::system_message(system_message::EARNED_S2_S1_S, &[Param::Item(item_id), number]),
+        _ => system_message::system_message(system_message::EARNED_ITEM_S1, &[Param::Item(item_id)]),
+    }
+}
+
+/// Tells a player why items didn't fit in its inventory.
+pub fn refuse(player: &Player, e: InventoryError) {
+    let packet = match e {
+        InventoryError::SlotsFull => system_message::system_message(system_message::SLOTS_FULL, &[]),
+        InventoryError::TooHeavy => system_message::system_message(system_message::WEIGHT_LIMIT_EXCEEDED, &[]),
+        e => system_message::text(&format!("You can't take the item: {}.", e)),
+    };
+    player.send(packet);
+    player.send(action_failed());
+}
+
+/// Puts items straight in the inventory of a player. Gives back those that don't fit.
+pub fn give(context: &Context, world: &mut World, obj_id: u32, items: Vec<(u32, u64)>) -> Vec<(u32, u64)> {
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return items,
+    };
+    let mut left = Vec::new();
+    for (item_id, count) in items {
+        match player.inventory.add(&context.datapack.items, &context.ids, item_id, count) {
+            Ok(changes) => {
+                inventory::commit(context, player, changes);
+                player.send(earned(item_id, count));
+            },
+            Err(e) => {
+                if left.is_empty() {
+                    refuse(player, e);
+                }
+                left.push((item_id, count));
+            },
+        }
+    }
+    left
+}
+
+/// Has a player pick up an item it sees, walking to it first when it is too far. The drops tick takes a walk from
+/// there.
+pub fn pick_up(context: &Context, world: &mut World, obj_id: u32, item_id: u32, now: Instant) -> Result<(), String> {
+    let player = match world.player(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} is not in the world", obj_id)),
+    };
+    let to = match world.ground_item(item_id) {
+        Some(item) => item.position,
+        None => return Ok(()),
+    };
+    if player.is_dead() || player.sitting || player.teleporting || player.store.is_some() || player.cast.is_some() {
+        player.send(action_failed());
+        return Ok(());
+    }
+    let from = player.position();
+    if combat::distance_2d(from, to) <= PICKUP_RANGE {
+        return take(context, world, obj_id, item_id, now);
+    }
+    let reached = context.geodata.move_check(from, to);
+    if combat::distance_2d(reached, to) > PICKUP_RANGE {
+        player.send(action_failed());
+        return Ok(());
+    }
+    combat::stop_attack(world, obj_id);
+    if let Some(player) = world.player_mut(obj_id) {
+        player.character.heading = movement::heading_to((from.0, from.1), (reached.0, reached.1));
+        player.pickup = Some(item_id);
+    }
+    world.start_moving(obj_id, Movement::new(from, reached, now));
+    world.start_picking(obj_id);
+    world.broadcast_with_self(obj_id, &movement_response::move_to_location(obj_id, reached, from));
+    Ok(())
+}
+
+/// Puts an item in reach in the inventory of a player, unless it is kept for someone else or doesn't fit.
+fn take(context: &Context, world: &mut World, obj_id: u32, item_id: u32, now: Instant) -> Result<(), String> {
+    if world.get_mut(obj_id).is_some_and(|player| player.movement_mut().is_some()) {
+        movement::stop(world, obj_id);
+    }
+    let (player, item) = match (world.player(obj_id), world.ground_item(item_id)) {
+        (Some(player), Some(item)) => (player, item),
+        _ => return Ok(()),
+    };
+    if item.owner.is_some_and(|owner| owner != obj_id) && item.protected_until > now {
+        let number = Param::Number(item.count.min(u32::MAX as u64) as u32);
+        player.send(match item.item_id {
+            ADENA => system_message::system_message(system_message::FAILED_TO_PICKUP_S1_ADENA, &[number]),
+            item_id => system_message::system_message(system_message::FAILED_TO_PICKUP_S1, &[Param::Item(item_id)]),
+        });
+        player.send(action_failed());
+        return Ok(());
+    }
+    let template = match context.datapack.items.get(item.item_id) {
+        Some(template) => template,
+        None => return Err(format!("Item {} on the ground has no template", item.item_id)),
+    };
+    if let Err(e) = player.inventory.check_add(template, item.count) {
+        refuse(player, e);
+        return Ok(());
+    }
+
+    world.broadcast_with_self(obj_id, &response::get_item(obj_id, item));
+    let item = match world.take_item(item_id) {
+        Some(item) => item,
+        None => return Ok(()),
+    };
+    context.ids.release(item_id);
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Ok(()),
+    };
+    let changes = match player.inventory.add(&context.datapack.items, &context.ids, item.item_id, item.count) {
+        Ok(changes) => changes,
+        Err(e) => return Err(format!("Can't give {} of item {} to {}: {}", item.count, item.item_id, player.character.char_name, e)),
+    };
+    inventory::commit(context, player, changes);
+    player.send(earned(item.item_id, item.count));
+    Ok(())
+}
+
+/// Picks up the items players walked to and clears the items that stayed on the ground too long, for as long as
+/// the server runs.
+pub async fn run(context: Arc<Context>) {
+    let mut interval = tokio::time::interval(DROPS_TICK);
+    loop {
+        interval.tick().await;
+        update(&context, &mut context.world(), Instant::now());
+    }
+}
+
+fn update(context: &Context, world: &mut World, now: Instant) {
+    for obj_id in world.picking() {
+        if let Err(e) = follow_up(context, world, obj_id, now) {
+            warn!("Error picking up an item: {}", e);
+        }
+    }
+    for item_id in world.due_item_expiries(now) {
+        if world.ground_item(item_id).is_some_and(|item| item.expires_at <= now) {
+            world.take_item(item_id);
+            context.ids.release(item_id);
+        }
+    }
+}
+
+/// Picks up the item a player walks to once in reach. A player that stopped short or went elsewhere gives up.
+fn follow_up(context: &Context, world: &mut World, obj_id: u32, now: Instant) -> Result<(), String> {
+    let target = world.player(obj_id)
+        .filter(|player| !player.is_dead())
+        .and_then(|player| player.pickup.map(|item_id| (player, item_id)))
+        .and_then(|(player, item_id)| world.ground_item(item_id).map(|item| (player, item_id, item.position)));
+    let (player, item_id, to) = match target {
+        Some(target) => target,
+        None => {
+            give_up(world, obj_id);
+            return Ok(());
+        }
+    };
+    if combat::distance_2d(player.position(), to) <= PICKUP_RANGE {
+        give_up(world, obj_id);
+        return take(context, world, obj_id, item_id, now);
+    }
+    if !player.movement.as_ref().is_some_and(|movement| combat::distance_2d(movement.destination, to) <= PICKUP_RANGE) {
+        give_up(world, obj_id);
+    }
+    Ok(())
+}
+
+fn give_up(world: &mut World, obj_id: u32) {
+    if let Some(player) = world.player_mut(obj_id) {
+        player.pickup = None;
+    }
+    world.stop_picking(obj_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn drop(item: u32, min: u64, max: u64, chance: u32) -> Drop {
+        Drop { item, min, max, chance }
+    }
+
+    #[test]
+    fn rates_raise_chances_and_adena_counts() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let drops = [drop(ADENA, 10, 10, 1_000_000), drop(1060, 1, 1, 500_000)];
+        assert_eq!(roll(&drops, 2.0, 3.0, &mut rng), vec![(ADENA, 30), (1060, 1)]);
+        assert_eq!(roll(&drops, 4.0, 1.0, &mut rng), vec![(ADENA, 10), (1060, 2)]);
+        assert!(roll(&[drop(1060, 1, 1, 1)], 0.0, 1.0, &mut rng).is_empty());
+    }
+}
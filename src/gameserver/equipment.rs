@@ -0,0 +1,234 @@
+use crate::database::items::{self, ItemChange};
+use crate::gameserver::client::items as request;
+use crate::gameserver::combat;
+use crate::gameserver::datapack::items::{self as templates, Grade, ItemKind, ItemRegistry, ItemTemplate};
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::inventory::{self, Inventory};
+use crate::gameserver::models::{self, Client};
+use crate::gameserver::player::Player;
+use crate::gameserver::server::lobby::action_failed;
+use crate::gameserver::server::system_message::{self, Param};
+use crate::gameserver::server::world as world_response;
+use crate::gameserver::teleport;
+use crate::gameserver::world::World;
+
+pub enum EquipError {
+    NotEquipable,
+    /// Arrows are only worn with a bow.
+    NoBow,
+}
+
+/// Highest grade a character of this level can wear.
+fn grade_of_level(level: u8) -> Grade {
+    match level {
+        76.. => Grade::S,
+        61.. => Grade::A,
+        52.. => Grade::B,
+        40.. => Grade::C,
+        20.. => Grade::D,
+        _ => Grade::None,
+    }
+}
+
+/// Paperdoll slot of a single `SLOT_*` body part.
+fn paperdoll_slot(body_part: u32) -> Option<i32> {
+    match body_part {
+        templates::SLOT_UNDERWEAR => Some(models::PAPERDOLL_UNDER),
+        templates::SLOT_R_EAR => Some(models::PAPERDOLL_REAR),
+        templates::SLOT_L_EAR => Some(models::PAPERDOLL_LEAR),
+        templates::SLOT_NECK => Some(models::PAPERDOLL_NECK),
+        templates::SLOT_R_FINGER => Some(models::PAPERDOLL_RFINGER),
+        templates::SLOT_L_FINGER => Some(models::PAPERDOLL_LFINGER),
+        templates::SLOT_HEAD => Some(models::PAPERDOLL_HEAD),
+        templates::SLOT_R_HAND => Some(models::PAPERDOLL_RHAND),
+        templates::SLOT_L_HAND => Some(models::PAPERDOLL_LHAND),
+        templates::SLOT_GLOVES => Some(models::PAPERDOLL_GLOVES),
+        templates::SLOT_CHEST | templates::SLOT_FULL_ARMOR => Some(models::PAPERDOLL_CHEST),
+        templates::SLOT_LEGS => Some(models::PAPERDOLL_LEGS),
+        templates::SLOT_FEET => Some(models::PAPERDOLL_FEET),
+        templates::SLOT_BACK => Some(models::PAPERDOLL_BACK),
+        templates::SLOT_LR_HAND => Some(models::PAPERDOLL_LRHAND),
+        templates::SLOT_HAIR | templates::SLOT_DHAIR => Some(models::PAPERDOLL_HAIR),
+        templates::SLOT_FACE => Some(models::PAPERDOLL_FACE),
+        _ => None,
+    }
+}
+
+fn worn_in<'a>(inventory: &Inventory, templates: &'a ItemRegistry, slot: i32) -> Option<&'a ItemTemplate> {
+    inventory.equipped_in(slot).and_then(|item| templates.get(item.item_id))
+}
+
+/// The free one of a pair of slots, the first one when both are taken.
+fn free_of(inventory: &Inventory, first: i32, second: i32) -> i32 {
+    if inventory.equipped_in(first).is_some() && inventory.equipped_in(second).is_none() { second } else { first }
+}
+
+/// Whether the level and class of a player let it wear an item.
+pub fn can_wear(context: &Context, player: &Player, template: &ItemTemplate) -> bool {
+    let class_id = player.character.class_id;
+    template.grade <= grade_of_level(player.character.level)
+        && (template.classes.is_empty() || template.classes.iter().any(|allowed| context.datapack.classes.descends_from(class_id, *allowed)))
+}
+
+/// Wears an item, first taking off what is in the way: both hands for a two-handed weapon, except the
+/// arrows a bow keeps, the two-handed weapon for a one-handed one or a shield, the legs for full armor, and
+/// the first of a pair of rings or earrings when both are worn.
+pub fn equip(inventory: &mut Inventory, templates: &ItemRegistry, object_id: u32) -> Result<Vec<ItemChange>, EquipError> {
+    let template = match inventory.get(object_id).and_then(|item| templates.get(item.item_id)) {
+        Some(template) => template,
+        None => return Err(EquipError::NotEquipable),
+    };
+    let holds = |slot| worn_in(inventory, templates, slot);
+
+    let (slot, mut clear) = match template.body_part {
+        templates::SLOT_LR_HAND => {
+            let keeps_arrows = template.is_bow() && holds(models::PAPERDOLL_LHAND).is_some_and(|held| held.kind == ItemKind::Etc);
+            let clear = if keeps_arrows { vec![models::PAPERDOLL_RHAND] } else { vec![models::PAPERDOLL_RHAND, models::PAPERDOLL_LHAND] };
+            (models::PAPERDOLL_LRHAND, clear)
+        },
+        templates::SLOT_R_HAND => (models::PAPERDOLL_RHAND, vec![models::PAPERDOLL_LRHAND]),
+        templates::SLOT_L_HAND if template.kind == ItemKind::Etc => {
+            if !holds(models::PAPERDOLL_LRHAND).is_some_and(|held| held.is_bow()) {
+                return Err(EquipError::NoBow);
+            }
+            (models::PAPERDOLL_LHAND, Vec::new())
+        },
+        templates::SLOT_L_HAND => (models::PAPERDOLL_LHAND, vec![models::PAPERDOLL_LRHAND]),
+        body_part if body_part == templates::SLOT_R_EAR | templates::SLOT_L_EAR => {
+            (free_of(inventory, models::PAPERDOLL_REAR, models::PAPERDOLL_LEAR), Vec::new())
+        },
+        body_part if body_part == templates::SLOT_R_FINGER | templates::SLOT_L_FINGER => {
+            (free_of(inventory, models::PAPERDOLL_RFINGER, models::PAPERDOLL_LFINGER), Vec::new())
+        },
+        templates::SLOT_FULL_ARMOR => (models::PAPERDOLL_CHEST, vec![models::PAPERDOLL_LEGS]),
+        templates::SLOT_LEGS => {
+            let full_armor = holds(models::PAPERDOLL_CHEST).is_some_and(|held| held.body_part == templates::SLOT_FULL_ARMOR);
+            (models::PAPERDOLL_LEGS, if full_armor { vec![models::PAPERDOLL_CHEST] } else { Vec::new() })
+        },
+        templates::SLOT_DHAIR => (models::PAPERDOLL_HAIR, vec![models::PAPERDOLL_FACE]),
+        templates::SLOT_FACE => {
+            let covered = holds(models::PAPERDOLL_HAIR).is_some_and(|held| held.body_part == templates::SLOT_DHAIR);
+            (models::PAPERDOLL_FACE, if covered { vec![models::PAPERDOLL_HAIR] } else { Vec::new() })
+        },
+        body_part => match paperdoll_slot(body_part) {
+            Some(slot) => (slot, Vec::new()),
+            None => return Err(EquipError::NotEquipable),
+        },
+    };
+    clear.push(slot);
+
+    let mut changes = Vec::new();
+    for slot in clear {
+        changes.extend(unequip(inventory, templates, slot));
+    }
+    changes.extend(inventory.set_slot(object_id, Some(slot)));
+    Ok(changes)
+}
+
+/// Takes off what is worn in a paperdoll slot. Taking off a bow takes the arrows off too.
+pub fn unequip(inventory: &mut Inventory, templates: &ItemRegistry, slot: i32) -> Vec<ItemChange> {
+    let (object_id, bow) = match inventory.equipped_in(slot) {
+        Some(item) => (item.object_id, templates.get(item.item_id).is_some_and(|template| template.is_bow())),
+        None => return Vec::new(),
+    };
+
+    let mut changes: Vec<ItemChange> = inventory.set_slot(object_id, None).into_iter().collect();
+    if bow {
+        let arrows = worn_in(inventory, templates, models::PAPERDOLL_LHAND).is_some_and(|held| held.kind == ItemKind::Etc);
+        if arrows {
+            changes.extend(unequip(inventory, templates, models::PAPERDOLL_LHAND));
+        }
+    }
+    changes
+}
+
+/// Applies an equipment change: the stats of the player, its inventory and look on its own client, and its
+/// look for everyone around.
+fn show_equipment(context: &Context, world: &mut World, obj_id: u32, changes: Vec<ItemChange>) {
+    if changes.is_empty() {
+        return;
+    }
+    if let Some(player) = world.player_mut(obj_id) {
+        let class = match context.datapack.classes.get(player.character.class_id) {
+            Some(class) => class,
+            None => return,
+        };
+        player.refresh_equipment(&context.datapack.items, class);
+        for change in &changes {
+            if let ItemChange::Modified(item) = change {
+                let id = if item.loc == items::LOC_PAPERDOLL { system_message::S1_EQUIPPED } else { system_message::S1_DISARMED };
+                player.send(system_message::system_message(id, &[Param::Item(item.item_id)]));
+            }
+        }
+        inventory::commit(context, player, changes);
+        player.send(world_response::user_info(player, class));
+    }
+    world.broadcast_info(obj_id);
+}
+
+/// Double click on an item. Only equipment does something so far: it is put on, or taken off when worn.
+pub async fn use_item(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let object_id = request::new_use_item(data)?;
+
+    let mut world = context.world();
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} of {} is not in the world", obj_id, client.account_name)),
+    };
+    let (item_id, worn_slot) = match player.inventory.get(object_id) {
+        Some(item) => (item.item_id, if item.loc == items::LOC_PAPERDOLL { Some(item.loc_data) } else { None }),
+        None => return Err(format!("{} used item {} it doesn't have", player.character.char_name, object_id)),
+    };
+    if item_id == teleport::SCROLL_OF_ESCAPE {
+        return teleport::escape(context, &mut world, obj_id, object_id);
+    }
+    if combat::is_soulshot(item_id) {
+        return combat::load_soulshot(context, &mut world, obj_id, item_id);
+    }
+    let template = match context.datapack.items.get(item_id) {
+        Some(template) if template.is_equipable() => template,
+        _ => {
+            player.send(action_failed());
+            return Ok(());
+        }
+    };
+
+    let changes = match worn_slot {
+        Some(slot) => unequip(&mut player.inventory, &context.datapack.items, slot),
+        None if !can_wear(context, player, template) => {
+            player.send(system_message::system_message(system_message::CANNOT_EQUIP_ITEM_DUE_TO_BAD_CONDITION, &[]));
+            return Ok(());
+        },
+        None => match equip(&mut player.inventory, &context.datapack.items, object_id) {
+            Ok(changes) => changes,
+            Err(EquipError::NoBow) => {
+                player.send(system_message::text("You need a bow to equip arrows."));
+                return Ok(());
+            },
+            Err(EquipError::NotEquipable) => {
+                player.send(action_failed());
+                return Ok(());
+            },
+        },
+    };
+    show_equipment(context, &mut world, obj_id, changes);
+    Ok(())
+}
+
+pub async fn unequip_item(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let body_part = request::new_unequip_item(data)?;
+    let slot = match paperdoll_slot(body_part) {
+        Some(slot) => slot,
+        None => return Err(format!("{} asked to unequip unknown body part {:#x}", client.account_name, body_part)),
+    };
+
+    let mut world = context.world();
+    let changes = match world.player_mut(obj_id) {
+        Some(player) => unequip(&mut player.inventory, &context.datapack.items, slot),
+        None => return Err(format!("Player {} of {} is not in the world", obj_id, client.account_name)),
+    };
+    show_equipment(context, &mut world, obj_id, changes);
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::server::system_message::{self, Param};
+use crate::gameserver::server::world as world_response;
+use crate::gameserver::skills;
+use crate::gameserver::world::World;
+
+pub const MAX_LEVEL: u8 = 80;
+/// Level over the level of a monster from which its rewards shrink.
+const PENALTY_FREE_LEVELS: i32 = 5;
+const SOCIAL_LEVEL_UP: u32 = 15;
+
+/// Experience a character has at the start of each level, from level 1. The last entry caps the experience of
+/// the last level.
+const EXP_TABLE: [u64; MAX_LEVEL as usize + 1] = [
+    0, 68, 363, 1168, 2884, 6038, 11287, 19423, 31378, 48229,
+    71201, 101676, 141192, 191452, 254327, 331864, 426284, 539995, 675590, 835854,
+    1023775, 1242536, 1495531, 1786365, 2118860, 2497067, 2925269, 3407985, 3949981, 4556273,
+    5232150, 5983184, 6815240, 7734497, 8747453, 9860945, 11082164, 12418659, 13878364, 15469607,
+    17201118, 19082050, 21121998, 23330993, 25719535, 28298595, 31079633, 34074598, 37295952, 40756674,
+    44470273, 48450802, 52712874, 57271672, 62142966, 67343124, 72889131, 78798600, 85089797, 91781653,
+    98893776, 106446468, 114460734, 122958303, 131961640, 141493960, 151579245, 162242250, 173508521, 185404410,
+    197957087, 211194556, 225145668, 239840135, 255308539, 271582352, 288693946, 306676605, 325564538, 345392886,
+    366197728,
+];
+
+/// Experience at the start of a level, `level` from 1 to `MAX_LEVEL + 1`.
+pub fn exp_for_level(level: u8) -> u64 {
+    EXP_TABLE[(level.clamp(1, MAX_LEVEL + 1) - 1) as usize]
+}
+
+pub fn level_for_exp(exp: u64) -> u8 {
+    (1..=MAX_LEVEL).rev().find(|level| exp >= exp_for_level(*level)).unwrap_or(1)
+}
+
+/// Share of its rewards a monster gives to a character of `level`: whole up to 5 levels over it, shrinking
+/// beyond.
+pub fn level_penalty(level: u8, monster_level: u8) -> f64 {
+    let over = level as i32 - monster_level as i32 - PENALTY_FREE_LEVELS;
+    if over > 0 { (5.0f64 / 6.0).powi(over) } else { 1.0 }
+}
+
+/// Experience a character of `level` loses on death: `percent` of what its level takes to go through.
+pub fn death_loss(level: u8, percent: f64) -> u64 {
+    ((exp_for_level(level + 1) - exp_for_level(level)) as f64 * percent / 100.0) as u64
+}
+
+/// Gives experience and SP to a player, which levels up once it has enough. The experience also works off
+/// karma.
+pub fn gain(context: &Context, world: &mut World, obj_id: u32, exp: u64, sp: u32) {
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return,
+    };
+    let character = &mut player.character;
+    character.exp = (character.exp + exp).min(exp_for_level(MAX_LEVEL + 1) - 1);
+    character.sp = character.sp.saturating_add(sp);
+    let karma = character.karma;
+    let redeemed = (exp / context.conf.karma.exp_per_point).min(u32::MAX as u64) as u32;
+    character.karma = karma.saturating_sub(redeemed);
+    let karma_changed = character.karma != karma;
+    let level = level_for_exp(character.exp);
+    player.send(system_message::system_message(system_message::YOU_EARNED_S1_EXP_AND_S2_SP,
+        &[Param::Number(exp.min(u32::MAX as u64) as u32), Param::Number(sp)]));
+    if level != player.character.level {
+        set_level(context, world, obj_id, level);
+    } else {
+        show_exp(world, obj_id);
+    }
+    if karma_changed {
+        show_karma(world, obj_id);
+    }
+}
+
+/// Sends the karma of a player to it, and its new look to everyone around.
+pub fn show_karma(world: &World, obj_id: u32) {
+    if let Some(player) = world.player(obj_id) {
+        player.send(world_response::status_update(obj_id, &[(world_response::STATUS_KARMA, player.character.karma)]));
+    }
+    world.broadcast_info(obj_id);
+}
+
+/// Takes experience from a player, down to the start of its level unless it may lose levels.
+pub fn lose(context: &Context, world: &mut World, obj_id: u32, exp: u64, delevel: bool) {
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return,
+    };
+    let character = &mut player.character;
+    let floor = if delevel { 0 } else { exp_for_level(character.level) };
+    character.exp = character.exp.saturating_sub(exp).max(floor);
+    let level = level_for_exp(character.exp);
+    if level != character.level {
+        set_level(context, world, obj_id, level);
+    } else {
+        show_exp(world, obj_id);
+    }
+}
+
+fn show_exp(world: &World, obj_id: u32) {
+    if let Some(player) = world.player(obj_id) {
+        player.send(world_response::status_update(obj_id, &[
+            (world_response::STATUS_EXP, player.character.exp.min(u32::MAX as u64) as u32),
+            (world_response::STATUS_SP, player.character.sp),
+        ]));
+    }
+}
+
+/// Moves a player to another level: it knows the skills of the new level, gets its stats, and is healed when
+/// it went up.
+fn set_level(context: &Context, world: &mut World, obj_id: u32, level: u8) {
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return,
+    };
+    let class = match context.datapack.classes.get(player.character.class_id) {
+        Some(class) => class,
+        None => return,
+    };
+    let gained = level > player.character.level;
+    player.character.level = level;
+    skills::learn(&context.datapack, player);
+    player.refresh_stats(class);
+    if gained {
+        let character = &mut player.character;
+        character.cur_hp = character.max_hp;
+        character.cur_mp = character.max_mp;
+        character.cur_cp = character.max_cp;
+        player.send(system_message::system_message(system_message::YOU_INCREASED_YOUR_LEVEL, &[]));
+    }
+    player.send(world_response::user_info(player, class));
+    player.send(skills::skill_list(&context.datapack, player));
+    if gained {
+        world.broadcast_with_self(obj_id, &world_response::social_action(obj_id, SOCIAL_LEVEL_UP));
+    }
+    world.broadcast_info(obj_id);
+    world.start_recovering(obj_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_follow_the_table() {
+        assert_eq!(level_for_exp(0), 1);
+        assert_eq!(level_for_exp(67), 1);
+        assert_eq!(level_for_exp(68), 2);
+        assert_eq!(level_for_exp(u64::MAX), MAX_LEVEL);
+        assert_eq!(exp_for_level(MAX_LEVEL + 5), exp_for_level(MAX_LEVEL + 1));
+    }
+
+    #[test]
+    fn rewards_shrink_past_five_levels_over_the_monster() {
+        assert_eq!(level_penalty(10, 5), 1.0);
+        assert_eq!(level_penalty(1, 20), 1.0);
+        assert!((level_penalty(11, 5) - 5.0 / 6.0).abs() < 1e-9);
+        assert!(level_penalty(20, 5) < level_penalty(11, 5));
+    }
+
+    #[test]
+    fn death_takes_a_share_of_the_level() {
+        assert_eq!(death_loss(1, 100.0), 68);
+        assert_eq!(death_loss(2, 4.0), 11);
+        assert_eq!(death_loss(MAX_LEVEL, 0.0), 0);
+    }
+}
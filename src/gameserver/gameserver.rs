@@ -1,11 +1,264 @@
-use tokio::net::TcpListener;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use log::{error, info};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::config::config;
+use crate::database::connection::Database;
+use crate::database::{characters, clans, items};
+
+use super::admin;
+use super::ai;
+use super::bypass;
+use super::chat;
+use super::combat;
+use super::death;
+use super::drops;
+use super::datapack::registry::{self, Datapack};
+use super::equipment;
+use super::geodata::{self, Geodata};
+use super::html::HtmlCache;
+use super::pathfinding::Pathfinder;
+use super::idfactory::IdFactory;
+use super::inventory::{self, ItemWriter};
+use super::lobby;
+use super::merchant;
+use super::movement;
+use super::models::{self, ClientState};
+use super::skills;
+use super::spawn;
+use super::stats;
+use super::action;
+use super::store;
+use super::teleport;
+use super::trade;
+use super::warehouse;
+use super::world::World;
+
+/// Interlude client protocol revisions accepted by the server.
+const PROTOCOL_VERSIONS: [u32; 4] = [737, 740, 744, 746];
+
+/// State shared by every client task of the game server.
+pub struct Context {
+    pub conf: config::GameServer,
+    pub database: Database,
+    pub datapack: Datapack,
+    pub geodata: Geodata,
+    pub html: HtmlCache,
+    pub pathfinder: Pathfinder,
+    /// Never held across an `.await`, every world change is done in one go.
+    pub world: Mutex<World>,
+    pub ids: IdFactory,
+    pub item_writer: ItemWriter,
+}
+
+impl Context {
+    pub fn world(&self) -> MutexGuard<'_, World> {
+        // A panic while holding the lock leaves the world as it was at that point, still usable.
+        match self.world.lock() {
+            Ok(world) => world,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
 
 pub struct GameServer {
-    login_listener: TcpListener
+    client_listener: TcpListener,
+    context: Arc<Context>,
 }
 
 impl GameServer {
-    pub fn new(){
-        println!("Creating game server");
+    pub async fn new(conf: config::GameServer) -> Result<GameServer, String> {
+        let datapack = match registry::load(&conf.data_dir) {
+            Ok(datapack) => datapack,
+            Err(e) => return Err(format!("Error loading datapack: {}", e)),
+        };
+
+        let geodata = match geodata::load(&Path::new(&conf.data_dir).join("geodata")) {
+            Ok(geodata) => geodata,
+            Err(e) => return Err(format!("Error loading geodata: {}", e)),
+        };
+        info!("Loaded {} geodata regions", geodata.regions());
+
+        let html = HtmlCache::load(&Path::new(&conf.data_dir).join("html"))?;
+        info!("Loaded {} html files", html.len());
+
+        let database = Database::connect(&conf.database).await?;
+        characters::reset_online(&database).await?;
+
+        let mut used_ids = characters::object_ids(&database).await?;
+        used_ids.extend(items::object_ids(&database).await?);
+        used_ids.extend(clans::clan_ids(&database).await?);
+        let ids = IdFactory::new(used_ids);
+        info!("{} object ids in use", ids.used());
+        let item_writer = ItemWriter::start(database.clone());
+
+        let client_listener = match TcpListener::bind(format!("0.0.0.0:{}", conf.port)).await {
+            Ok(listener) => {
+                info!("Listening for game clients on port {}", conf.port);
+                listener
+            },
+            Err(e) => return Err(format!("Error binding game client listener: {}", e))
+        };
+
+        Ok(GameServer {
+            client_listener,
+            context: Arc::new(Context {
+                conf,
+                database,
+                datapack,
+                geodata,
+                html,
+                pathfinder: Pathfinder::new(),
+                world: Mutex::new(World::new()),
+                ids,
+                item_writer,
+            }),
+        })
+    }
+
+    pub async fn start(&mut self) {
+        if self.context.conf.options.testing {
+            info!("Game server {} started as a test server", self.context.conf.name);
+        } else {
+            info!("Game server {} started", self.context.conf.name);
+        }
+        tokio::spawn(movement::run(self.context.clone()));
+        tokio::spawn(combat::run(self.context.clone()));
+        tokio::spawn(stats::run(self.context.clone()));
+        tokio::spawn(skills::run(self.context.clone()));
+        tokio::spawn(ai::run(self.context.clone()));
+        tokio::spawn(drops::run(self.context.clone()));
+        spawn::spawn_all(&self.context);
+        tokio::spawn(spawn::run(self.context.clone()));
+        if let Err(e) = store::restore_offline(&self.context).await {
+            error!("Error restoring offline stores: {}", e);
+        }
+
+        loop {
+            let (socket, addr) = match self.client_listener.accept().await {
+                Ok((socket, addr)) => (socket, addr),
+                Err(e) => {
+                    error!("Couldn't accept the incoming connection: {}", e);
+                    continue;
+                }
+            };
+            info!("Game client connected from {}", addr);
+
+            tokio::spawn(handle_client_packets(self.context.clone(), socket));
+        }
+    }
+}
+
+async fn handle_client_packets(context: Arc<Context>, socket: TcpStream) {
+    let mut client = match models::Client::handshake(socket, &PROTOCOL_VERSIONS).await {
+        Ok(client) => client,
+        Err(e) => {
+            info!("Game client handshake failed: {}", e);
+            return;
+        }
+    };
+
+    loop {
+        let (packet_id, data) = match client.receive().await {
+            Ok((packet_id, data)) => (packet_id, data),
+            Err(e) => {
+                info!("Game client {} disconnected: {}", client.account_name, e);
+                break;
+            }
+        };
+
+        let result = match packet_id {
+            0x01 => movement::move_backward_to_location(&context, &mut client, data).await,
+            0x03 => lobby::enter_world(&context, &mut client).await,
+            0x04 => action::action(&context, &mut client, data).await,
+            0x08 => lobby::auth_login(&context, &mut client, data).await,
+            0x09 => lobby::logout(&context, &mut client).await,
+            0x0a => combat::attack_request(&context, &mut client, data).await,
+            0x0b => lobby::character_create(&context, &mut client, data).await,
+            0x0c => lobby::character_delete(&context, &mut client, data).await,
+            0x0d => lobby::character_selected(&context, &mut client, data).await,
+            0x0e => lobby::new_character(&context, &mut client).await,
+            0x0f => inventory::request_item_list(&context, &mut client).await,
+            0x11 => equipment::unequip_item(&context, &mut client, data).await,
+            0x14 => equipment::use_item(&context, &mut client, data).await,
+            0x15 => trade::trade_request(&context, &mut client, data).await,
+            0x16 => trade::add_trade_item(&context, &mut client, data).await,
+            0x17 => trade::trade_done(&context, &mut client, data).await,
+            0x1e => merchant::request_sell_item(&context, &mut client, data).await,
+            0x1f => merchant::request_buy_item(&context, &mut client, data).await,
+            0x21 => bypass::request_bypass_to_server(&context, &mut client, data).await,
+            0x2f => skills::request_magic_skill_use(&context, &mut client, data).await,
+            0x30 => teleport::appearing(&context, &mut client).await,
+            0x31 => warehouse::deposit(&context, &mut client, data).await,
+            0x32 => warehouse::withdraw(&context, &mut client, data).await,
+            0x36 => movement::cannot_move_anymore(&context, &mut client, data).await,
+            0x38 => chat::say2(&context, &mut client, data).await,
+            0x3f => skills::request_skill_list(&context, &mut client).await,
+            0x40 => trade::answer_trade_request(&context, &mut client, data).await,
+            0x46 => lobby::restart(&context, &mut client).await,
+            0x48 => movement::validate_position(&context, &mut client, data).await,
+            0x5b => admin::build_command(&context, &mut client, data).await,
+            0x62 => lobby::character_restore(&context, &mut client, data).await,
+            0x6d => death::request_restart_point(&context, &mut client, data).await,
+            0x73 => store::manage_sell(&context, &mut client).await,
+            0x74 => store::set_list_sell(&context, &mut client, data).await,
+            0x76 | 0x93 => store::quit(&context, &mut client).await,
+            0x77 => store::set_message_sell(&context, &mut client, data).await,
+            0x79 => store::buy(&context, &mut client, data).await,
+            0x90 => store::manage_buy(&context, &mut client).await,
+            0x91 => store::set_list_buy(&context, &mut client, data).await,
+            0x94 => store::set_message_buy(&context, &mut client, data).await,
+            0x96 => store::sell(&context, &mut client, data).await,
+            0xa0 => chat::request_block(&context, &mut client, data).await,
+            0xa7 => merchant::multisell_choose(&context, &mut client, data).await,
+            0xb0 => store::manage_manufacture(&context, &mut client).await,
+            0xb1 => store::set_message_manufacture(&context, &mut client, data).await,
+            0xb2 => store::set_list_manufacture(&context, &mut client, data).await,
+            0xb3 => store::quit(&context, &mut client).await,
+            _ => {
+                info!("Unknown game packet id: {:#04x}", packet_id);
+                Ok(())
+            }
+        };
+
+        if let Err(e) = result {
+            error!("Error handling game packet {:#04x}: {}", packet_id, e);
+        }
+
+        if client.state == ClientState::Closed {
+            break;
+        }
+    }
+
+    store::stay_offline(&context, &mut client);
+    if let Err(e) = lobby::leave_world(&context, &mut client).await {
+        error!("Error saving character of {}: {}", client.account_name, e);
+    }
+}
+
+/// Context for the tests of what needs the whole server.
+#[cfg(test)]
+pub mod synthetic {
+    use sqlx::mysql::{MySqlConnectOptions, MySqlPoolOptions};
+
+    use super::*;
+
+    /// Context with the shipped configuration and datapack and the given geodata. The database is never
+    /// connected to, so it only fits what doesn't reach it, and the item writer needs a runtime.
+    pub fn context(geodata: Geodata) -> Context {
+        let database = Database { pool: MySqlPoolOptions::new().connect_lazy_with(MySqlConnectOptions::new()) };
+        Context {
+            conf: config::new_config().unwrap().gameserver,
+            datapack: registry::load("data").unwrap(),
+            geodata,
+            html: HtmlCache::load(Path::new("data/html")).unwrap(),
+            pathfinder: Pathfinder::new(),
+            world: Mutex::new(World::new()),
+            ids: IdFactory::new(Vec::new()),
+            item_writer: ItemWriter::start(database.clone()),
+            database,
+        }
     }
 }
\ No newline at end of file
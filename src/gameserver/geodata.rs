@@ -0,0 +1,510 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::gameserver::datapack::loader::DataError;
+
+/// Cells are 16x16 units, grouped in blocks of 8x8 cells, a region file holds 256x256 blocks.
+const CELL_SHIFT: i32 = 4;
+const BLOCK_SHIFT: i32 = 3;
+const REGION_SHIFT: i32 = 11;
+const REGION_BLOCKS: usize = 256;
+const BLOCK_CELLS: usize = 64;
+/// Region files are named after their tile, tile 20_18 starts at world coordinates 0,0.
+const TILE_X_ZERO: i32 = 20;
+const TILE_Y_ZERO: i32 = 18;
+
+const BLOCK_FLAT: u8 = 0;
+const BLOCK_COMPLEX: u8 = 1;
+const BLOCK_MULTILAYER: u8 = 2;
+
+pub const EAST: u8 = 1;
+pub const WEST: u8 = 2;
+pub const SOUTH: u8 = 4;
+pub const NORTH: u8 = 8;
+pub const ALL: u8 = EAST | WEST | SOUTH | NORTH;
+
+/// Highest step up from one cell to the next that can still be walked.
+pub const MAX_CLIMB: i32 = 64;
+/// Height above the ground of the line of sight, roughly the eyes of a character.
+const SIGHT_HEIGHT: i32 = 40;
+/// A blocked cell side hides what is behind it up to this height above its ground.
+const WALL_HEIGHT: i32 = 80;
+
+/// Walkable surface of a cell, a cell has several of them under bridges and inside buildings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Layer {
+    pub height: i32,
+    /// Directions a character can leave the cell to.
+    pub nswe: u8,
+}
+
+fn decode(value: i16) -> Layer {
+    Layer { height: ((value & !0x0f) >> 1) as i32, nswe: (value & 0x0f) as u8 }
+}
+
+enum Block {
+    /// Same height everywhere and no wall.
+    Flat(i16),
+    Complex(Box<[i16; BLOCK_CELLS]>),
+    /// Layers of every cell, cell `i` uses `values[offsets[i]..offsets[i + 1]]`.
+    Multilayer { offsets: Box<[u16; BLOCK_CELLS + 1]>, values: Vec<i16> },
+}
+
+enum CellLayers<'a> {
+    Flat(Option<i16>),
+    Stored(std::slice::Iter<'a, i16>),
+}
+
+impl Iterator for CellLayers<'_> {
+    type Item = Layer;
+
+    fn next(&mut self) -> Option<Layer> {
+        match self {
+            CellLayers::Flat(height) => height.take().map(|height| Layer { height: height as i32, nswe: ALL }),
+            CellLayers::Stored(values) => values.next().map(|value| decode(*value)),
+        }
+    }
+}
+
+/// Cell of a world position, counted from the first possible tile so every cell coordinate is positive.
+pub fn cell_of(x: i32, y: i32) -> (i32, i32) {
+    ((x >> CELL_SHIFT) + (TILE_X_ZERO << REGION_SHIFT), (y >> CELL_SHIFT) + (TILE_Y_ZERO << REGION_SHIFT))
+}
+
+/// World position of the center of a cell.
+pub fn world_of(cell_x: i32, cell_y: i32) -> (i32, i32) {
+    (
+        ((cell_x - (TILE_X_ZERO << REGION_SHIFT)) << CELL_SHIFT) + (1 << (CELL_SHIFT - 1)),
+        ((cell_y - (TILE_Y_ZERO << REGION_SHIFT)) << CELL_SHIFT) + (1 << (CELL_SHIFT - 1)),
+    )
+}
+
+/// Direction bit to go from a cell to the next one along a single axis.
+pub fn direction(dx: i32, dy: i32) -> u8 {
+    match (dx.signum(), dy.signum()) {
+        (1, 0) => EAST,
+        (-1, 0) => WEST,
+        (0, 1) => SOUTH,
+        (0, -1) => NORTH,
+        _ => 0,
+    }
+}
+
+/// Cells crossed by the line between two cells, one axis at a time so every move is to a neighbour.
+fn line(from: (i32, i32), to: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+    let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let (count_x, count_y) = (((to.0 - from.0) as i64).abs(), ((to.1 - from.1) as i64).abs());
+    let (mut done_x, mut done_y) = (0i64, 0i64);
+    let mut cell = from;
+
+    std::iter::from_fn(move || {
+        if done_x == count_x && done_y == count_y {
+            return None;
+        }
+        // Step along the axis whose next cell border the line crosses first.
+        if done_y == count_y || (done_x < count_x && (1 + 2 * done_x) * count_y < (1 + 2 * done_y) * count_x) {
+            done_x += 1;
+            cell.0 += step_x;
+        } else {
+            done_y += 1;
+            cell.1 += step_y;
+        }
+        Some(cell)
+    })
+}
+
+/// L2J format geodata, one file per 32768x32768 tile. Positions outside the loaded tiles are treated as open
+/// ground, so a server without geodata behaves as before.
+pub struct Geodata {
+    regions: HashMap<(i32, i32), Vec<Block>>,
+}
+
+impl Geodata {
+    pub fn empty() -> Geodata {
+        Geodata { regions: HashMap::new() }
+    }
+
+    /// Adds the region of a tile from the content of its file.
+    pub fn add_region(&mut self, tile: (i32, i32), bytes: &[u8]) -> Result<(), String> {
+        self.regions.insert(tile, parse_region(bytes)?);
+        Ok(())
+    }
+
+    pub fn regions(&self) -> usize {
+        self.regions.len()
+    }
+
+    fn layers(&self, cell_x: i32, cell_y: i32) -> Option<CellLayers<'_>> {
+        let blocks = self.regions.get(&(cell_x >> REGION_SHIFT, cell_y >> REGION_SHIFT))?;
+        let block_x = ((cell_x >> BLOCK_SHIFT) as usize) % REGION_BLOCKS;
+        let block_y = ((cell_y >> BLOCK_SHIFT) as usize) % REGION_BLOCKS;
+        let cell = ((cell_x & 7) * 8 + (cell_y & 7)) as usize;
+
+        Some(match &blocks[block_x * REGION_BLOCKS + block_y] {
+            Block::Flat(height) => CellLayers::Flat(Some(*height)),
+            Block::Complex(cells) => CellLayers::Stored(cells[cell..cell + 1].iter()),
+            Block::Multilayer { offsets, values } => {
+                CellLayers::Stored(values[offsets[cell] as usize..offsets[cell + 1] as usize].iter())
+            },
+        })
+    }
+
+    pub fn has_cell(&self, cell_x: i32, cell_y: i32) -> bool {
+        self.regions.contains_key(&(cell_x >> REGION_SHIFT, cell_y >> REGION_SHIFT))
+    }
+
+    /// Layer of a cell closest to `z`, `None` outside the geodata.
+    pub fn nearest_layer(&self, cell_x: i32, cell_y: i32, z: i32) -> Option<Layer> {
+        self.layers(cell_x, cell_y)?.min_by_key(|layer| (layer.height - z).abs())
+    }
+
+    /// Highest layer of a cell that is not above `z`.
+    fn layer_below(&self, cell_x: i32, cell_y: i32, z: i32) -> Option<Layer> {
+        self.layers(cell_x, cell_y)?.filter(|layer| layer.height <= z).max_by_key(|layer| layer.height)
+    }
+
+    /// Ground height at a position, `z` itself where there is no geodata.
+    pub fn height(&self, x: i32, y: i32, z: i32) -> i32 {
+        let (cell_x, cell_y) = cell_of(x, y);
+        match self.nearest_layer(cell_x, cell_y, z) {
+            Some(layer) => layer.height,
+            None => z,
+        }
+    }
+
+    /// Layer reached by walking from `current` into the neighbour cell `next`, `None` if a wall or a step too
+    /// high is in the way.
+    pub fn step(&self, current: (i32, i32), layer: Layer, next: (i32, i32)) -> Option<Layer> {
+        if layer.nswe & direction(next.0 - current.0, next.1 - current.1) == 0 {
+            return None;
+        }
+        let next_layer = self.nearest_layer(next.0, next.1, layer.height)?;
+        if next_layer.height - layer.height > MAX_CLIMB {
+            return None;
+        }
+        Some(next_layer)
+    }
+
+    /// Walks in a straight line towards `to` and returns the farthest position reached.
+    pub fn move_check(&self, from: (i32, i32, i32), to: (i32, i32, i32)) -> (i32, i32, i32) {
+        let start = cell_of(from.0, from.1);
+        let end = cell_of(to.0, to.1);
+        let mut layer = match self.nearest_layer(start.0, start.1, from.2) {
+            Some(layer) => layer,
+            None => return to,
+        };
+
+        let mut current = start;
+        for next in line(start, end) {
+            if !self.has_cell(next.0, next.1) {
+                return to;
+            }
+            layer = match self.step(current, layer, next) {
+                Some(layer) => layer,
+                None if current == start => return from,
+                None => {
+                    let (x, y) = world_of(current.0, current.1);
+                    return (x, y, layer.height);
+                },
+            };
+            current = next;
+        }
+        (to.0, to.1, layer.height)
+    }
+
+    pub fn can_move_to(&self, from: (i32, i32, i32), to: (i32, i32, i32)) -> bool {
+        let reached = self.move_check(from, to);
+        (reached.0, reached.1) == (to.0, to.1)
+    }
+
+    /// Whether the line between two objects standing at `from` and `to` clears the terrain and the walls.
+    pub fn can_see(&self, from: (i32, i32, i32), to: (i32, i32, i32)) -> bool {
+        let start = cell_of(from.0, from.1);
+        let end = cell_of(to.0, to.1);
+        let total = ((end.0 - start.0).abs() + (end.1 - start.1).abs()) as i64;
+        let from_z = (from.2 + SIGHT_HEIGHT) as i64;
+        let to_z = (to.2 + SIGHT_HEIGHT) as i64;
+
+        let mut current = start;
+        for (index, next) in line(start, end).enumerate() {
+            let line_z = (from_z + (to_z - from_z) * (index as i64 + 1) / total.max(1)) as i32;
+
+            // Under a wall side that is taller than the line, the target is hidden.
+            if let Some(layer) = self.layer_below(current.0, current.1, line_z) {
+                let blocked = layer.nswe & direction(next.0 - current.0, next.1 - current.1) == 0;
+                if blocked && line_z < layer.height + WALL_HEIGHT {
+                    return false;
+                }
+            }
+            // No ground under the line means terrain above it.
+            if self.has_cell(next.0, next.1) && self.layer_below(next.0, next.1, line_z).is_none() {
+                return false;
+            }
+            current = next;
+        }
+        true
+    }
+}
+
+/// Reads a region file, one block type byte followed by the block data for each of its 65536 blocks.
+fn parse_region(bytes: &[u8]) -> Result<Vec<Block>, String> {
+    let mut position = 0;
+    let mut take = |size: usize| -> Result<&[u8], String> {
+        match bytes.get(position..position + size) {
+            Some(data) => {
+                position += size;
+                Ok(data)
+            },
+            None => Err(format!("file ends at byte {} in the middle of a block", bytes.len())),
+        }
+    };
+    let read_i16 = |data: &[u8]| i16::from_le_bytes([data[0], data[1]]);
+
+    let mut blocks = Vec::with_capacity(REGION_BLOCKS * REGION_BLOCKS);
+    for index in 0..REGION_BLOCKS * REGION_BLOCKS {
+        let block = match take(1)?[0] {
+            BLOCK_FLAT => Block::Flat(read_i16(take(2)?)),
+            BLOCK_COMPLEX => {
+                let data = take(2 * BLOCK_CELLS)?;
+                let mut cells = [0; BLOCK_CELLS];
+                for (cell, value) in cells.iter_mut().zip(data.chunks_exact(2)) {
+                    *cell = read_i16(value);
+                }
+                Block::Complex(Box::new(cells))
+            },
+            BLOCK_MULTILAYER => {
+                let mut offsets = [0u16; BLOCK_CELLS + 1];
+                let mut values = Vec::new();
+                for cell in 0..BLOCK_CELLS {
+                    let count = take(1)?[0] as usize;
+                    if count == 0 {
+                        return Err(format!("cell {} of block {} has no layer", cell, index));
+                    }
+                    values.extend(take(2 * count)?.chunks_exact(2).map(read_i16));
+                    offsets[cell + 1] = values.len() as u16;
+                }
+                Block::Multilayer { offsets: Box::new(offsets), values }
+            },
+            other => return Err(format!("unknown type {} for block {}", other, index)),
+        };
+        blocks.push(block);
+    }
+
+    if position != bytes.len() {
+        return Err(format!("{} bytes left after the last block", bytes.len() - position));
+    }
+    Ok(blocks)
+}
+
+/// Tile of a region file name such as `22_22.l2j`.
+fn tile_of(path: &Path) -> Option<(i32, i32)> {
+    if path.extension()? != "l2j" {
+        return None;
+    }
+    let (x, y) = path.file_stem()?.to_str()?.split_once('_')?;
+    Some((x.parse().ok()?, y.parse().ok()?))
+}
+
+/// Loads every region file of `dir`. A missing directory means no geodata, every check then passes.
+pub fn load(dir: &Path) -> Result<Geodata, DataError> {
+    let mut geodata = Geodata::empty();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(geodata),
+    };
+
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        let tile = match tile_of(&path) {
+            Some(tile) => tile,
+            None => continue,
+        };
+        let error = |message: String| DataError { file: path.clone(), line: None, message };
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(error(format!("can't read file: {}", e))),
+        };
+        if let Err(message) = geodata.add_region(tile, &bytes) {
+            return Err(error(message));
+        }
+    }
+    Ok(geodata)
+}
+
+/// Small hand made regions for the tests of the geodata users.
+#[cfg(test)]
+pub mod synthetic {
+    use super::*;
+
+    pub const GROUND: i16 = 0;
+
+    pub fn cell(height: i16, nswe: u8) -> [u8; 2] {
+        (((height << 1) & !0x0f) | nswe as i16).to_le_bytes()
+    }
+
+    /// Region made of flat blocks at `GROUND`, except the blocks given with their raw data.
+    pub fn region(special: &[(usize, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for index in 0..REGION_BLOCKS * REGION_BLOCKS {
+            match special.iter().find(|(block, _)| *block == index) {
+                Some((_, data)) => bytes.extend(data),
+                None => {
+                    bytes.push(BLOCK_FLAT);
+                    bytes.extend(GROUND.to_le_bytes());
+                },
+            }
+        }
+        bytes
+    }
+
+    /// Index of a block in its region.
+    pub fn block(block_x: usize, block_y: usize) -> usize {
+        block_x * REGION_BLOCKS + block_y
+    }
+
+    /// Complex block at `height` with the directions open on every cell given by `nswe(cell_x, cell_y)`.
+    pub fn complex_block(height: i16, nswe: impl Fn(usize, usize) -> u8) -> Vec<u8> {
+        let mut data = vec![BLOCK_COMPLEX];
+        for cell_x in 0..8 {
+            for cell_y in 0..8 {
+                data.extend(cell(height, nswe(cell_x, cell_y)));
+            }
+        }
+        data
+    }
+
+    /// Complex block with a wall between the cells of column 3 and column 4.
+    pub fn wall_block() -> Vec<u8> {
+        complex_block(GROUND, |cell_x, _| match cell_x {
+            3 => ALL & !EAST,
+            4 => ALL & !WEST,
+            _ => ALL,
+        })
+    }
+
+    /// Multilayer block with the same open floors at the given heights on every cell.
+    pub fn multilayer_block(floors: &[i16]) -> Vec<u8> {
+        let mut data = vec![BLOCK_MULTILAYER];
+        for _ in 0..BLOCK_CELLS {
+            data.push(floors.len() as u8);
+            for floor in floors {
+                data.extend(cell(*floor, ALL));
+            }
+        }
+        data
+    }
+
+    /// Geodata with a single region at tile 20_18, which covers world coordinates 0..32768.
+    pub fn geodata(special: &[(usize, Vec<u8>)]) -> Geodata {
+        let mut geodata = Geodata::empty();
+        geodata.add_region((TILE_X_ZERO, TILE_Y_ZERO), &region(special)).unwrap();
+        geodata
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::synthetic::*;
+    use super::*;
+
+    /// Multilayer block with a floor at the ground and a second one 400 units above.
+    fn bridge_block() -> Vec<u8> {
+        multilayer_block(&[400, GROUND])
+    }
+
+    /// Complex block 1000 units above the ground.
+    fn hill_block() -> Vec<u8> {
+        complex_block(1000, |_, _| ALL)
+    }
+
+    fn write_region(name: &str, bytes: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("l2rust-geodata-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("20_18.l2j"), bytes).unwrap();
+        dir
+    }
+
+    fn load_test_region(name: &str) -> Geodata {
+        // Block 0 is world 0..128 x 0..128, block 1 is the one south of it, block 512 is two blocks east.
+        let bytes = region(&[(block(0, 0), wall_block()), (block(0, 1), bridge_block()), (block(2, 0), hill_block())]);
+        let dir = write_region(name, &bytes);
+        let geodata = load(&dir).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        geodata
+    }
+
+    #[test]
+    fn cell_coordinates_round_trip() {
+        assert_eq!(world_of(cell_of(0, 0).0, cell_of(0, 0).1), (8, 8));
+        assert_eq!(world_of(cell_of(-20, 33).0, cell_of(-20, 33).1), (-24, 40));
+        assert_eq!(cell_of(0, 0), (TILE_X_ZERO << REGION_SHIFT, TILE_Y_ZERO << REGION_SHIFT));
+    }
+
+    #[test]
+    fn heights() {
+        let geodata = load_test_region("heights");
+        assert_eq!(geodata.regions(), 1);
+        assert_eq!(geodata.height(1000, 1000, 300), 0);
+        assert_eq!(geodata.height(300, 8, 0), 1000);
+        assert_eq!(geodata.height(8, 136, 350), 400);
+        assert_eq!(geodata.height(8, 136, 100), 0);
+        // Outside the loaded tile the height is taken as given.
+        assert_eq!(geodata.height(-100, 8, 1234), 1234);
+    }
+
+    #[test]
+    fn walls_stop_movement() {
+        let geodata = load_test_region("walls");
+        assert!(!geodata.can_move_to((8, 8, 0), (120, 8, 0)));
+        assert_eq!(geodata.move_check((8, 8, 0), (120, 8, 0)), (56, 8, 0));
+        assert!(geodata.can_move_to((8, 8, 0), (56, 120, 0)));
+        // Going around the block is fine.
+        assert!(geodata.can_move_to((8, 8, 0), (8, 200, 0)));
+        assert!(geodata.can_move_to((8, 200, 0), (120, 200, 0)));
+    }
+
+    #[test]
+    fn steep_steps_stop_movement() {
+        let geodata = load_test_region("steps");
+        assert!(!geodata.can_move_to((200, 8, 0), (300, 8, 0)));
+        assert_eq!(geodata.move_check((200, 8, 0), (300, 8, 0)), (248, 8, 0));
+    }
+
+    #[test]
+    fn multilayer_keeps_the_floor() {
+        let geodata = load_test_region("layers");
+        assert_eq!(geodata.move_check((8, 136, 400), (120, 136, 400)), (120, 136, 400));
+        assert_eq!(geodata.move_check((8, 136, 0), (120, 136, 0)), (120, 136, 0));
+    }
+
+    #[test]
+    fn line_of_sight() {
+        let geodata = load_test_region("sight");
+        assert!(geodata.can_see((1000, 1000, 0), (1500, 1200, 0)));
+        assert!(!geodata.can_see((8, 8, 0), (120, 8, 0)));
+        assert!(!geodata.can_see((200, 8, 0), (450, 8, 0)));
+        // High enough above the wall the line clears it.
+        assert!(geodata.can_see((8, 8, 400), (120, 8, 400)));
+    }
+
+    #[test]
+    fn broken_files() {
+        let mut truncated = region(&[]);
+        truncated.truncate(truncated.len() - 1);
+        let dir = write_region("truncated", &truncated);
+        let error = load(&dir).err().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(error.to_string().contains("20_18.l2j"));
+        assert!(error.message.contains("middle of a block"));
+
+        let mut unknown = region(&[]);
+        unknown[3] = 7;
+        let dir = write_region("unknown", &unknown);
+        let error = load(&dir).err().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(error.message, "unknown type 7 for block 1");
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// HTML files of the datapack by their path under the html directory, read once at startup.
+pub struct HtmlCache {
+    files: HashMap<String, String>,
+}
+
+impl HtmlCache {
+    pub fn load(dir: &Path) -> Result<HtmlCache, String> {
+        let mut files = HashMap::new();
+        read_dir(dir, "", &mut files)?;
+        Ok(HtmlCache { files })
+    }
+
+    /// File at `path`, with `/` separators and no leading slash.
+    pub fn get(&self, path: &str) -> Option<&str> {
+        self.files.get(path).map(|html| html.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+}
+
+fn read_dir(dir: &Path, prefix: &str, files: &mut HashMap<String, String>) -> Result<(), String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return Err(format!("Error reading html directory {}: {}", dir.display(), e)),
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if path.is_dir() {
+            read_dir(&path, &format!("{}{}/", prefix, name), files)?;
+        } else if name.ends_with(".htm") || name.ends_with(".html") {
+            match fs::read_to_string(&path) {
+                Ok(html) => files.insert(format!("{}{}", prefix, name), html),
+                Err(e) => return Err(format!("Error reading {}: {}", path.display(), e)),
+            };
+        }
+    }
+    Ok(())
+}
+
+/// Replaces each `%name%` of a dialog with its value.
+pub fn fill(html: &str, variables: &[(&str, &str)]) -> String {
+    let mut html = html.to_string();
+    for (name, value) in variables {
+        html = html.replace(&format!("%{}%", name), value);
+    }
+    html
+}
+
+/// What a player was last shown by an NPC. Only the bypasses it offers are accepted back.
+pub struct Dialog {
+    pub bypasses: Vec<String>,
+}
+
+/// Commands of the `bypass` and `bypass -h` actions of a dialog.
+pub fn bypasses(html: &str) -> Vec<String> {
+    let mut bypasses = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find("bypass ") {
+        rest = &rest[start + "bypass ".len()..];
+        let command = rest.trim_start();
+        let command = command.strip_prefix("-h ").unwrap_or(command).trim_start();
+        let end = command.find(['"', '\'', '>']).unwrap_or(command.len());
+        let command = command[..end].trim();
+        if !command.is_empty() {
+            bypasses.push(command.to_string());
+        }
+    }
+    bypasses
+}
+
+/// Whether `command` is one of the bypasses offered. Bypasses using what the player types in an edit box
+/// (`$name`) are matched up to the first variable.
+pub fn is_offered(offered: &[String], command: &str) -> bool {
+    offered.iter().any(|bypass| match bypass.find('$') {
+        Some(variable) => command.starts_with(&bypass[..variable]),
+        None => bypass == command,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_bypasses_of_links_and_buttons() {
+        let html = "<html><body><a action=\"bypass -h npc_268435457_Chat 1\">More</a><br>\
+            <button value=\"Deposit\" action=\"bypass -h npc_268435457_DepositP\" width=80>\
+            <a action='bypass npc_268435457_Quest'>Quest</a></body></html>";
+        assert_eq!(bypasses(html), vec!["npc_268435457_Chat 1", "npc_268435457_DepositP", "npc_268435457_Quest"]);
+    }
+
+    #[test]
+    fn accepts_only_offered_bypasses() {
+        let offered = vec!["npc_268435457_Chat 1".to_string(), "npc_268435457_Quest $name".to_string()];
+        assert!(is_offered(&offered, "npc_268435457_Chat 1"));
+        assert!(is_offered(&offered, "npc_268435457_Quest Q001"));
+        assert!(!is_offered(&offered, "npc_268435457_Chat 2"));
+        assert!(!is_offered(&offered, "npc_268435458_Chat 1"));
+        assert!(!is_offered(&[], "npc_268435457_Chat 1"));
+    }
+
+    #[test]
+    fn fills_variables() {
+        let html = fill("<a action=\"bypass -h npc_%objectId%_Chat 1\">%playername%</a>", &[("objectId", "7"), ("playername", "Dora")]);
+        assert_eq!(html, "<a action=\"bypass -h npc_7_Chat 1\">Dora</a>");
+    }
+}
@@ -0,0 +1,162 @@
+use std::sync::{Mutex, MutexGuard};
+
+/// Object ids below this value are used by the client for its own objects.
+pub const FIRST_OBJECT_ID: u32 = 0x10000000;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// Hands out object ids for every player, item, NPC and door. Ids in use are tracked in a bitset starting at
+/// `FIRST_OBJECT_ID`, so released ids are given out again and the set stays as small as the highest id used.
+pub struct IdFactory {
+    ids: Mutex<Ids>,
+}
+
+struct Ids {
+    words: Vec<u64>,
+    /// No word before this one has a free bit.
+    first_free: usize,
+    used: usize,
+}
+
+impl IdFactory {
+    /// Creates the factory with the ids already stored, ids below `FIRST_OBJECT_ID` are ignored.
+    pub fn new(used: impl IntoIterator<Item = u32>) -> IdFactory {
+        let mut ids = Ids { words: Vec::new(), first_free: 0, used: 0 };
+        for id in used {
+            if id < FIRST_OBJECT_ID {
+                continue;
+            }
+            let (word, bit) = position(id);
+            if word >= ids.words.len() {
+                ids.words.resize(word + 1, 0);
+            }
+            if ids.words[word] & bit == 0 {
+                ids.words[word] |= bit;
+                ids.used += 1;
+            }
+        }
+        IdFactory { ids: Mutex::new(ids) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Ids> {
+        // Every change to the set is a single bit flip, a panic elsewhere can't leave it half updated.
+        match self.ids.lock() {
+            Ok(ids) => ids,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Lowest free id.
+    pub fn next_id(&self) -> Result<u32, String> {
+        let mut ids = self.lock();
+
+        let mut word = ids.first_free;
+        while word < ids.words.len() && ids.words[word] == u64::MAX {
+            word += 1;
+        }
+        if word == ids.words.len() {
+            ids.words.push(0);
+        }
+
+        let bit = ids.words[word].trailing_ones() as usize;
+        let index = word * WORD_BITS + bit;
+        if index > (u32::MAX - FIRST_OBJECT_ID) as usize {
+            return Err("No object id left".to_string());
+        }
+
+        ids.words[word] |= 1 << bit;
+        ids.first_free = word;
+        ids.used += 1;
+        Ok(FIRST_OBJECT_ID + index as u32)
+    }
+
+    /// Gives an id back so it can be handed out again. Releasing an id that is not in use does nothing.
+    pub fn release(&self, id: u32) {
+        if id < FIRST_OBJECT_ID {
+            return;
+        }
+        let (word, bit) = position(id);
+
+        let mut ids = self.lock();
+        if let Some(value) = ids.words.get_mut(word) {
+            if *value & bit != 0 {
+                *value &= !bit;
+                ids.first_free = ids.first_free.min(word);
+                ids.used -= 1;
+            }
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        self.lock().used
+    }
+}
+
+fn position(id: u32) -> (usize, u64) {
+    let index = (id - FIRST_OBJECT_ID) as usize;
+    (index / WORD_BITS, 1 << (index % WORD_BITS))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn skips_stored_ids() {
+        let factory = IdFactory::new([FIRST_OBJECT_ID, FIRST_OBJECT_ID + 1, FIRST_OBJECT_ID + 3, 5]);
+        assert_eq!(factory.used(), 3);
+        assert_eq!(factory.next_id(), Ok(FIRST_OBJECT_ID + 2));
+        assert_eq!(factory.next_id(), Ok(FIRST_OBJECT_ID + 4));
+    }
+
+    #[test]
+    fn reuses_released_ids() {
+        let factory = IdFactory::new([]);
+        let ids: Vec<u32> = (0..200).map(|_| factory.next_id().unwrap()).collect();
+        factory.release(ids[150]);
+        factory.release(ids[70]);
+        factory.release(ids[70]);
+        assert_eq!(factory.used(), 198);
+        assert_eq!(factory.next_id(), Ok(ids[70]));
+        assert_eq!(factory.next_id(), Ok(ids[150]));
+        assert_eq!(factory.next_id(), Ok(FIRST_OBJECT_ID + 200));
+    }
+
+    #[test]
+    fn no_duplicates_under_contention() {
+        const THREADS: usize = 8;
+        const ROUNDS: usize = 5_000;
+
+        let factory = Arc::new(IdFactory::new((0..1000).map(|i| FIRST_OBJECT_ID + i * 3)));
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let factory = factory.clone();
+                thread::spawn(move || {
+                    let mut kept = Vec::new();
+                    for round in 0..ROUNDS {
+                        let id = factory.next_id().unwrap();
+                        // Give back part of the ids right away so allocation races with reuse.
+                        if round % 3 == 0 {
+                            factory.release(id);
+                        } else {
+                            kept.push(id);
+                        }
+                    }
+                    kept
+                })
+            })
+            .collect();
+
+        let mut seen: HashSet<u32> = (0..1000).map(|i| FIRST_OBJECT_ID + i * 3).collect();
+        for handle in handles {
+            for id in handle.join().unwrap() {
+                assert!(seen.insert(id), "id {:#x} handed out twice", id);
+            }
+        }
+        assert_eq!(factory.used(), seen.len());
+    }
+}
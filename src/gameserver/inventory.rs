@@ -0,0 +1,765 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{error, warn};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::database::connection::Database;
+use crate::database::items::{self, Item, ItemChange};
+use crate::database::private_stores::{self, StoredStore};
+use crate::gameserver::datapack::items::{ItemRegistry, ItemTemplate, ADENA};
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::idfactory::IdFactory;
+use crate::gameserver::models::Client;
+use crate::gameserver::player::Player;
+use crate::gameserver::server::items as response;
+use crate::gameserver::server::world as world_response;
+
+/// Largest count of a stack, the client shows counts as signed 32 bit numbers.
+pub const MAX_COUNT: u64 = i32::MAX as u64;
+const INVENTORY_SLOTS: usize = 80;
+const DWARF_INVENTORY_SLOTS: usize = 100;
+pub const DWARF: u8 = 4;
+/// Weight carried with a constitution bonus of 1.
+const BASE_MAX_LOAD: f64 = 69000.0;
+
+/// Inventory slots of a character of this race.
+pub fn max_slots(race: u8) -> usize {
+    if race == DWARF { DWARF_INVENTORY_SLOTS } else { INVENTORY_SLOTS }
+}
+
+/// Weight a character can carry, scaled by the official constitution bonus.
+pub fn max_load(con: u32) -> u64 {
+    let bonus = (1.03_f64.powf(con as f64 - 27.632) * 100.0).round() / 100.0;
+    (BASE_MAX_LOAD * bonus) as u64
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InventoryError {
+    UnknownItem(u32),
+    NoSuchItem(u32),
+    /// The count asked is 0 or more than there is.
+    NotEnough,
+    /// Equipped items have to be taken off before they are given away.
+    Equipped,
+    SlotsFull,
+    TooHeavy,
+    /// The stack would go over `MAX_COUNT`.
+    TooMany,
+    NoObjectId,
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InventoryError::UnknownItem(item_id) => write!(f, "unknown item {}", item_id),
+            InventoryError::NoSuchItem(object_id) => write!(f, "no item {}", object_id),
+            InventoryError::NotEnough => write!(f, "not enough items"),
+            InventoryError::Equipped => write!(f, "item is equipped"),
+            InventoryError::SlotsFull => write!(f, "no free slot"),
+            InventoryError::TooHeavy => write!(f, "weight limit exceeded"),
+            InventoryError::TooMany => write!(f, "stack would exceed {}", MAX_COUNT),
+            InventoryError::NoObjectId => write!(f, "no object id left"),
+        }
+    }
+}
+
+fn weight_of(templates: &ItemRegistry, item_id: u32, count: u64) -> u64 {
+    templates.get(item_id).map_or(0, |template| template.weight as u64 * count)
+}
+
+/// The items of one owner kept at one place: a character with what it wears, or a warehouse. Operations check
+/// everything before changing anything, so a failed one leaves the inventory as it was. Each returns the
+/// changes made, which the caller writes with the `ItemWriter` and shows the client.
+#[derive(Clone)]
+pub struct Inventory {
+    pub owner_id: u32,
+    /// Location given to items added to this inventory.
+    loc: &'static str,
+    items: BTreeMap<u32, Item>,
+    pub max_slots: usize,
+    pub max_weight: u64,
+    weight: u64,
+}
+
+impl Inventory {
+    pub fn new(owner_id: u32, loc: &'static str, stored: Vec<Item>, max_slots: usize, max_weight: u64, templates: &ItemRegistry) -> Inventory {
+        let weight = stored.iter().map(|item| weight_of(templates, item.item_id, item.count)).sum();
+        let items = stored.into_iter().map(|item| (item.object_id, item)).collect();
+        Inventory { owner_id, loc, items, max_slots, max_weight, weight }
+    }
+
+    pub fn get(&self, object_id: u32) -> Option<&Item> {
+        self.items.get(&object_id)
+    }
+
+    /// Every item, worn or not, oldest first.
+    pub fn items(&self) -> impl Iterator<Item = &Item> {
+        self.items.values()
+    }
+
+    pub fn equipped(&self) -> impl Iterator<Item = &Item> {
+        self.items.values().filter(|item| item.loc == items::LOC_PAPERDOLL)
+    }
+
+    /// Item worn in a paperdoll slot.
+    pub fn equipped_in(&self, slot: i32) -> Option<&Item> {
+        self.equipped().find(|item| item.loc_data == slot)
+    }
+
+    /// Wears an item in a paperdoll slot, or puts it back in the inventory for `None`. Which slot is free
+    /// and fits the item is up to the caller.
+    pub fn set_slot(&mut self, object_id: u32, slot: Option<i32>) -> Option<ItemChange> {
+        let loc = self.loc;
+        let item = self.items.get_mut(&object_id)?;
+        match slot {
+            Some(slot) => {
+                item.loc = items::LOC_PAPERDOLL.to_string();
+                item.loc_data = slot;
+            },
+            None => {
+                item.loc = loc.to_string();
+                item.loc_data = 0;
+            },
+        }
+        Some(ItemChange::Modified(item.clone()))
+    }
+
+    pub fn count_of(&self, item_id: u32) -> u64 {
+        self.items.values().filter(|item| item.item_id == item_id).map(|item| item.count).sum()
+    }
+
+    pub fn adena(&self) -> u64 {
+        self.count_of(ADENA)
+    }
+
+    pub fn weight(&self) -> u64 {
+        self.weight
+    }
+
+    pub fn slots_used(&self) -> usize {
+        self.items.len()
+    }
+
+    /// The stack new items of this template join, for items that stack.
+    fn stack_of(&self, template: &ItemTemplate) -> Option<u32> {
+        if !template.stackable {
+            return None;
+        }
+        self.items.values().find(|item| item.item_id == template.item_id).map(|item| item.object_id)
+    }
+
+    /// Checks that `count` items of `template` fit in the free slots and under the weight limit.
+    pub fn check_add(&self, template: &ItemTemplate, count: u64) -> Result<(), InventoryError> {
+        let stack = self.stack_of(template);
+        let new_slots = match stack {
+            Some(_) => 0,
+            None if template.stackable => 1,
+            None => count as usize,
+        };
+        if self.items.len() + new_slots > self.max_slots {
+            return Err(InventoryError::SlotsFull);
+        }
+        let stacked = stack.and_then(|object_id| self.items.get(&object_id)).map_or(0, |item| item.count);
+        if stacked + count > MAX_COUNT {
+            return Err(InventoryError::TooMany);
+        }
+        if self.weight + template.weight as u64 * count > self.max_weight {
+            return Err(InventoryError::TooHeavy);
+        }
+        Ok(())
+    }
+
+    /// Like `check_add` for several items at once, given as item ids with counts, such as what a warehouse hands
+    /// out in one go. Items of the same id that stack count once.
+    pub fn check_add_all(&self, templates: &ItemRegistry, added: &[(u32, u64)]) -> Result<(), InventoryError> {
+        let mut stacks: HashMap<u32, u64> = HashMap::new();
+        let mut new_slots = 0;
+        let mut weight = 0;
+        for &(item_id, count) in added {
+            let template = match templates.get(item_id) {
+                Some(template) => template,
+                None => return Err(InventoryError::UnknownItem(item_id)),
+            };
+            if template.stackable {
+                let stacked = stacks.entry(item_id).or_insert_with(|| self.count_of(item_id));
+                if *stacked == 0 {
+                    new_slots += 1;
+                }
+                *stacked += count;
+                if *stacked > MAX_COUNT {
+                    return Err(InventoryError::TooMany);
+                }
+            } else {
+                new_slots += count as usize;
+            }
+            weight += template.weight as u64 * count;
+        }
+        if self.items.len() + new_slots > self.max_slots {
+            return Err(InventoryError::SlotsFull);
+        }
+        if self.weight + weight > self.max_weight {
+            return Err(InventoryError::TooHeavy);
+        }
+        Ok(())
+    }
+
+    /// Creates `count` new items, joining the existing stack for items that stack.
+    pub fn add(&mut self, templates: &ItemRegistry, ids: &IdFactory, item_id: u32, count: u64) -> Result<Vec<ItemChange>, InventoryError> {
+        let template = match templates.get(item_id) {
+            Some(template) => template,
+            None => return Err(InventoryError::UnknownItem(item_id)),
+        };
+        if count == 0 {
+            return Err(InventoryError::NotEnough);
+        }
+        self.check_add(template, count)?;
+
+        if let Some(stack) = self.stack_of(template).and_then(|object_id| self.items.get_mut(&object_id)) {
+            stack.count += count;
+            self.weight += template.weight as u64 * count;
+            return Ok(vec![ItemChange::Modified(stack.clone())]);
+        }
+
+        // Every id is taken before the first item is made, running out halfway must not leave part of them.
+        let stacks = if template.stackable { 1 } else { count };
+        let mut object_ids = Vec::new();
+        for _ in 0..stacks {
+            match ids.next_id() {
+                Ok(object_id) => object_ids.push(object_id),
+                Err(_) => {
+                    for object_id in object_ids {
+                        ids.release(object_id);
+                    }
+                    return Err(InventoryError::NoObjectId);
+                }
+            }
+        }
+
+        let mut changes = Vec::new();
+        for object_id in object_ids {
+            let item = Item {
+                object_id,
+                owner_id: self.owner_id,
+                item_id,
+                count: if template.stackable { count } else { 1 },
+                enchant_level: 0,
+                loc: self.loc.to_string(),
+                loc_data: 0,
+            };
+            changes.push(ItemChange::Added(item.clone()));
+            self.items.insert(object_id, item);
+        }
+        self.weight += template.weight as u64 * count;
+        Ok(changes)
+    }
+
+    /// Destroys `count` of an item, the whole item and its object id once nothing is left of it.
+    pub fn remove(&mut self, templates: &ItemRegistry, ids: &IdFactory, object_id: u32, count: u64) -> Result<Vec<ItemChange>, InventoryError> {
+        let (change, freed) = self.take(templates, object_id, count)?;
+        if let Some(freed) = freed {
+            ids.release(freed);
+        }
+        Ok(vec![change])
+    }
+
+    /// `remove` that leaves the id of a whole item destroyed to the caller.
+    fn take(&mut self, templates: &ItemRegistry, object_id: u32, count: u64) -> Result<(ItemChange, Option<u32>), InventoryError> {
+        let item = match self.items.get_mut(&object_id) {
+            Some(item) => item,
+            None => return Err(InventoryError::NoSuchItem(object_id)),
+        };
+        if count == 0 || count > item.count {
+            return Err(InventoryError::NotEnough);
+        }
+
+        self.weight -= weight_of(templates, item.item_id, count);
+        if count < item.count {
+            item.count -= count;
+            return Ok((ItemChange::Modified(item.clone()), None));
+        }
+        match self.items.remove(&object_id) {
+            Some(item) => Ok((ItemChange::Removed(item), Some(object_id))),
+            None => Err(InventoryError::NoSuchItem(object_id)),
+        }
+    }
+
+    /// Destroys `count` items with this item id, such as adena paid or arrows shot. Worn items are left alone.
+    pub fn remove_by_item_id(&mut self, templates: &ItemRegistry, ids: &IdFactory, item_id: u32, count: u64) -> Result<Vec<ItemChange>, InventoryError> {
+        let mut left = count;
+        let mut taken = Vec::new();
+        for item in self.items.values().filter(|item| item.item_id == item_id && item.loc != items::LOC_PAPERDOLL) {
+            if left == 0 {
+                break;
+            }
+            let part = left.min(item.count);
+            taken.push((item.object_id, part));
+            left -= part;
+        }
+        if count == 0 || left > 0 {
+            return Err(InventoryError::NotEnough);
+        }
+
+        let mut changes = Vec::new();
+        for (object_id, part) in taken {
+            changes.extend(self.remove(templates, ids, object_id, part)?);
+        }
+        Ok(changes)
+    }
+}
+
+/// What `move_item` did to both sides.
+pub struct Moved {
+    pub given: Vec<ItemChange>,
+    pub received: Vec<ItemChange>,
+    /// Id taken for the part of a stack that became a new item.
+    pub taken: Option<u32>,
+    /// Id of a whole item that joined a stack, not released yet.
+    pub freed: Option<u32>,
+}
+
+/// Moves `count` of an item to another inventory, joining the stack there for items that stack. Both sides
+/// are checked before either changes, so under the world lock the item can't end up in both or in neither.
+/// The id it frees is left to the caller, for moves that may still be undone: the id can only be released once
+/// the move is kept, and the id taken has to be given back when it is not.
+pub fn move_item(from: &mut Inventory, to: &mut Inventory, templates: &ItemRegistry, ids: &IdFactory, object_id: u32, count: u64)
+    -> Result<Moved, InventoryError> {
+    let item = match from.items.get(&object_id) {
+        Some(item) => item,
+        None => return Err(InventoryError::NoSuchItem(object_id)),
+    };
+    if item.loc == items::LOC_PAPERDOLL {
+        return Err(InventoryError::Equipped);
+    }
+    if count == 0 || count > item.count {
+        return Err(InventoryError::NotEnough);
+    }
+    let template = match templates.get(item.item_id) {
+        Some(template) => template,
+        None => return Err(InventoryError::UnknownItem(item.item_id)),
+    };
+    to.check_add(template, count)?;
+
+    let whole = count == item.count;
+    let stack = to.stack_of(template);
+    // A part of a stack becomes a new item unless it joins a stack, the id is taken before anything changes.
+    let split_id = match (whole, stack) {
+        (false, None) => match ids.next_id() {
+            Ok(object_id) => Some(object_id),
+            Err(_) => return Err(InventoryError::NoObjectId),
+        },
+        _ => None,
+    };
+    let weight = template.weight as u64 * count;
+
+    let mut given = Vec::new();
+    let mut moved = None;
+    if whole {
+        if let Some(item) = from.items.remove(&object_id) {
+            given.push(ItemChange::Removed(item.clone()));
+            moved = Some(item);
+        }
+    } else if let Some(item) = from.items.get_mut(&object_id) {
+        item.count -= count;
+        given.push(ItemChange::Modified(item.clone()));
+        moved = Some(Item { count, ..item.clone() });
+    }
+    from.weight -= weight;
+
+    let mut received = Vec::new();
+    let mut freed = None;
+    if let Some(mut item) = moved {
+        match stack.and_then(|stack| to.items.get_mut(&stack)) {
+            Some(stack) => {
+                stack.count += count;
+                received.push(ItemChange::Modified(stack.clone()));
+                // The whole item went into the stack, its id is free now.
+                if whole {
+                    freed = Some(object_id);
+                }
+            },
+            None => {
+                if let Some(split_id) = split_id {
+                    item.object_id = split_id;
+                }
+                item.owner_id = to.owner_id;
+                item.loc = to.loc.to_string();
+                item.loc_data = 0;
+                received.push(ItemChange::Added(item.clone()));
+                to.items.insert(item.object_id, item);
+            },
+        }
+    }
+    to.weight += weight;
+    Ok(Moved { given, received, taken: split_id, freed })
+}
+
+/// Both sides of an `exchange`: the changes each inventory shows its owner, and every change in the order it was
+/// made for the `ItemWriter`.
+pub struct Exchange {
+    pub first: Vec<ItemChange>,
+    pub second: Vec<ItemChange>,
+    pub writes: Vec<ItemChange>,
+}
+
+/// Moves the items offered by each side to the other, given as object ids with counts. The moves are made on
+/// copies of both inventories that only replace them once every item moved, so any failure leaves both
+/// inventories, and the ids, as they were.
+pub fn exchange(first: &mut Inventory, first_offer: &[(u32, u64)], second: &mut Inventory, second_offer: &[(u32, u64)],
+    templates: &ItemRegistry, ids: &IdFactory) -> Result<Exchange, InventoryError> {
+    let mut new_first = first.clone();
+    let mut new_second = second.clone();
+    let mut result = Exchange { first: Vec::new(), second: Vec::new(), writes: Vec::new() };
+    let mut taken = Vec::new();
+    let mut freed = Vec::new();
+
+    let offers = first_offer.iter().map(|offer| (true, offer)).chain(second_offer.iter().map(|offer| (false, offer)));
+    for (from_first, &(object_id, count)) in offers {
+        let moved = if from_first {
+            move_item(&mut new_first, &mut new_second, templates, ids, object_id, count)
+        } else {
+            move_item(&mut new_second, &mut new_first, templates, ids, object_id, count)
+        };
+        let moved = match moved {
+            Ok(moved) => moved,
+            Err(e) => {
+                for object_id in taken {
+                    ids.release(object_id);
+                }
+                return Err(e);
+            }
+        };
+        taken.extend(moved.taken);
+        freed.extend(moved.freed);
+
+        let (giver, receiver) = if from_first { (&mut result.first, &mut result.second) } else { (&mut result.second, &mut result.first) };
+        giver.extend(moved.given.iter().cloned());
+        receiver.extend(moved.received.iter().cloned());
+        result.writes.extend(moved.given);
+        result.writes.extend(moved.received);
+    }
+
+    *first = new_first;
+    *second = new_second;
+    for object_id in freed {
+        ids.release(object_id);
+    }
+    Ok(result)
+}
+
+/// Moves items to another inventory for a fee in adena, paid by the giving side first, as depositing in a
+/// warehouse does. Like `exchange`, any failure leaves both inventories, and the ids, as they were.
+pub fn move_for_fee(from: &mut Inventory, to: &mut Inventory, moved: &[(u32, u64)], fee: u64, templates: &ItemRegistry, ids: &IdFactory)
+    -> Result<Exchange, InventoryError> {
+    let mut new_from = from.clone();
+    let mut paid = None;
+    if fee > 0 {
+        let adena = new_from.items().find(|item| item.item_id == ADENA && item.loc != items::LOC_PAPERDOLL).map(|item| item.object_id);
+        paid = match adena {
+            Some(object_id) => Some(new_from.take(templates, object_id, fee)?),
+            None => return Err(InventoryError::NotEnough),
+        };
+    }
+    let mut exchange = exchange(&mut new_from, moved, to, &[], templates, ids)?;
+
+    *from = new_from;
+    if let Some((change, freed)) = paid {
+        exchange.first.insert(0, change.clone());
+        exchange.writes.insert(0, change);
+        if let Some(freed) = freed {
+            ids.release(freed);
+        }
+    }
+    Ok(exchange)
+}
+
+/// Item made by `replace`.
+pub struct Made {
+    pub item_id: u32,
+    pub count: u64,
+    /// Enchant level the items keep, for items that don't stack.
+    pub enchant_level: u32,
+}
+
+/// Destroys items and creates others in one go, as buying from a shop or a multisell exchange does. Items are
+/// destroyed first, given as object ids with counts, so what they freed can hold what is made. Works on a copy
+/// of the inventory that only replaces it once everything is done, so any failure leaves the inventory, and the
+/// ids, as they were.
+pub fn replace(inventory: &mut Inventory, destroyed: &[(u32, u64)], made: &[Made], templates: &ItemRegistry, ids: &IdFactory)
+    -> Result<Vec<ItemChange>, InventoryError> {
+    let mut new_inventory = inventory.clone();
+    let mut changes = Vec::new();
+    let mut freed = Vec::new();
+    let mut taken = Vec::new();
+
+    let result = (|| {
+        for &(object_id, count) in destroyed {
+            if new_inventory.get(object_id).is_some_and(|item| item.loc == items::LOC_PAPERDOLL) {
+                return Err(InventoryError::Equipped);
+            }
+            let (change, free) = new_inventory.take(templates, object_id, count)?;
+            changes.push(change);
+            freed.extend(free);
+        }
+        for made in made {
+            for change in new_inventory.add(templates, ids, made.item_id, made.count)? {
+                match change {
+                    ItemChange::Added(mut item) => {
+                        taken.push(item.object_id);
+                        if made.enchant_level > 0 && !templates.get(item.item_id).is_some_and(|template| template.stackable) {
+                            item.enchant_level = made.enchant_level;
+                            if let Some(added) = new_inventory.items.get_mut(&item.object_id) {
+                                added.enchant_level = made.enchant_level;
+                            }
+                        }
+                        changes.push(ItemChange::Added(item));
+                    },
+                    change => changes.push(change),
+                }
+            }
+        }
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        for object_id in taken {
+            ids.release(object_id);
+        }
+        return Err(e);
+    }
+    *inventory = new_inventory;
+    for object_id in freed {
+        ids.release(object_id);
+    }
+    Ok(changes)
+}
+
+/// Times a batch of item changes is tried before the owners of the items are given up on.
+const WRITE_ATTEMPTS: u32 = 3;
+/// Wait before trying a batch again, longer after each failure.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+enum Job {
+    Write(Vec<ItemChange>),
+    SaveStore(StoredStore),
+    RemoveStore(u32),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Writes item changes in the order they were made, each batch in its own transaction. Inventories are
+/// changed in memory under the world lock and queued here before the lock is released, so the database
+/// sees the changes in the same order the world did. Offline private stores go through here too, what they
+/// list changes along with the items they trade.
+///
+/// A batch that still fails after being tried again can't be skipped, the changes after it build on it. Its
+/// owners are marked instead and nothing more of theirs is written, so the database keeps the last state of
+/// their items that was whole.
+pub struct ItemWriter {
+    jobs: mpsc::UnboundedSender<Job>,
+    failed: Arc<Mutex<HashSet<u32>>>,
+}
+
+impl ItemWriter {
+    pub fn start(database: Database) -> ItemWriter {
+        let (jobs, queue) = mpsc::unbounded_channel();
+        let failed = Arc::new(Mutex::new(HashSet::new()));
+        tokio::spawn(write_items(database, queue, failed.clone()));
+        ItemWriter { jobs, failed }
+    }
+
+    pub fn write(&self, changes: Vec<ItemChange>) {
+        if !changes.is_empty() {
+            let _ = self.jobs.send(Job::Write(changes));
+        }
+    }
+
+    pub fn save_store(&self, store: StoredStore) {
+        let _ = self.jobs.send(Job::SaveStore(store));
+    }
+
+    pub fn remove_store(&self, obj_id: u32) {
+        let _ = self.jobs.send(Job::RemoveStore(obj_id));
+    }
+
+    /// Waits until every change queued so far is written.
+    pub async fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.jobs.send(Job::Flush(done)).is_ok() {
+            let _ = wait.await;
+        }
+    }
+
+    /// Whether changes to the items of the owner were lost, nothing else of it should be saved then.
+    pub fn has_failed(&self, owner_id: u32) -> bool {
+        self.failed.lock().unwrap().contains(&owner_id)
+    }
+}
+
+/// Writes a batch of item changes, trying again a few times since most failures come from the connection.
+async fn apply_with_retries(database: &Database, changes: &[ItemChange]) -> Result<(), String> {
+    let mut attempt = 1;
+    loop {
+        match items::apply(database, changes).await {
+            Err(e) if attempt < WRITE_ATTEMPTS => {
+                warn!("{}, trying again", e);
+                tokio::time::sleep(RETRY_DELAY * attempt).await;
+                attempt += 1;
+            },
+            result => return result,
+        }
+    }
+}
+
+async fn write_items(database: Database, mut queue: mpsc::UnboundedReceiver<Job>, failed: Arc<Mutex<HashSet<u32>>>) {
+    while let Some(job) = queue.recv().await {
+        match job {
+            Job::Write(changes) => {
+                let owners: HashSet<u32> = changes.iter().map(|change| change.item().owner_id).collect();
+                let given_up = owners.iter().any(|owner_id| failed.lock().unwrap().contains(owner_id));
+                let result = if given_up {
+                    Err("earlier changes to their items were lost".to_string())
+                } else {
+                    apply_with_retries(&database, &changes).await
+                };
+                if let Err(e) = result {
+                    error!("Not saving the items of {:?} anymore: {}", owners, e);
+                    failed.lock().unwrap().extend(owners);
+                }
+            },
+            Job::SaveStore(store) => {
+                if failed.lock().unwrap().contains(&store.obj_id) {
+                    continue;
+                }
+                if let Err(e) = private_stores::save(&database, &store).await {
+                    error!("{}", e);
+                }
+            },
+            Job::RemoveStore(obj_id) => {
+                if let Err(e) = private_stores::remove(&database, obj_id).await {
+                    error!("{}", e);
+                }
+            },
+            Job::Flush(done) => {
+                let _ = done.send(());
+            },
+        }
+    }
+}
+
+/// Queues the changes made to the inventory of a player for writing and shows them to the player. Called while
+/// the world is still locked, so the writes keep the order the changes were made in.
+pub fn commit(context: &Context, player: &Player, changes: Vec<ItemChange>) {
+    show(context, player, &changes);
+    context.item_writer.write(changes);
+}
+
+/// Shows a player the changes made to its inventory, for operations that write them along with the changes of
+/// another inventory.
+pub fn show(context: &Context, player: &Player, changes: &[ItemChange]) {
+    if changes.is_empty() {
+        return;
+    }
+    player.send(response::inventory_update(changes, &context.datapack.items));
+    player.send(world_response::status_update(player.obj_id(), &[
+        (world_response::STATUS_CUR_LOAD, player.inventory.weight() as u32),
+        (world_response::STATUS_MAX_LOAD, player.inventory.max_weight as u32),
+    ]));
+}
+
+pub async fn request_item_list(context: &Context, client: &mut Client) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    match context.world().player(obj_id) {
+        Some(player) => player.send(response::item_list(&player.inventory, &context.datapack.items, true)),
+        None => return Err(format!("Player {} of {} is not in the world", obj_id, client.account_name)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::thread;
+
+    use crate::gameserver::player::synthetic::datapack;
+
+    fn inventory(owner_id: u32) -> Inventory {
+        Inventory::new(owner_id, items::LOC_INVENTORY, Vec::new(), INVENTORY_SLOTS, u64::MAX, &datapack().items)
+    }
+
+    /// Two players moving the same stack at once, each to its own inventory, the way two clients would: every
+    /// move takes the lock the world is behind.
+    fn move_twice(count: u64) -> (Inventory, Vec<Inventory>, Vec<Result<(), InventoryError>>) {
+        let datapack = datapack();
+        let ids = IdFactory::new(Vec::new());
+        let mut owner = inventory(1);
+        owner.add(&datapack.items, &ids, ADENA, 100).unwrap();
+        let object_id = owner.items().next().unwrap().object_id;
+
+        let world = Mutex::new((owner, vec![inventory(2), inventory(3)]));
+        let start = Barrier::new(2);
+        let results = thread::scope(|scope| {
+            let moves: Vec<_> = (0..2).map(|index| {
+                let (world, start, datapack, ids) = (&world, &start, &datapack, &ids);
+                scope.spawn(move || {
+                    start.wait();
+                    let mut world = world.lock().unwrap();
+                    let (owner, receivers) = &mut *world;
+                    exchange(owner, &[(object_id, count)], &mut receivers[index], &[], &datapack.items, ids).map(|_| ())
+                })
+            }).collect();
+            moves.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        let (owner, receivers) = world.into_inner().unwrap();
+        (owner, receivers, results)
+    }
+
+    #[test]
+    fn concurrent_moves_of_a_stack_move_it_once() {
+        let (owner, receivers, results) = move_twice(70);
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(results.iter().any(|result| matches!(result, Err(InventoryError::NotEnough))));
+        assert_eq!(owner.adena(), 30);
+        assert_eq!(receivers.iter().map(|receiver| receiver.adena()).sum::<u64>(), 70);
+
+        let (owner, receivers, results) = move_twice(100);
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(results.iter().any(|result| matches!(result, Err(InventoryError::NoSuchItem(_)))));
+        assert_eq!(owner.items().count(), 0);
+        assert_eq!(receivers.iter().map(|receiver| receiver.adena()).sum::<u64>(), 100);
+        assert_eq!(receivers.iter().map(|receiver| receiver.items().count()).sum::<usize>(), 1);
+    }
+
+    fn contents(inventory: &Inventory) -> Vec<(u32, u32, u64)> {
+        let mut contents: Vec<_> = inventory.items().map(|item| (item.object_id, item.item_id, item.count)).collect();
+        contents.sort();
+        contents
+    }
+
+    #[test]
+    fn failed_deposits_change_nothing() {
+        let datapack = datapack();
+        let templates = &datapack.items;
+        let ids = IdFactory::new(Vec::new());
+        let mut owner = inventory(1);
+        owner.add(templates, &ids, ADENA, 1000).unwrap();
+        owner.add(templates, &ids, 1, 2).unwrap();
+        let swords: Vec<(u32, u64)> = owner.items().filter(|item| item.item_id == 1).map(|item| (item.object_id, 1)).collect();
+        let mut warehouse = Inventory::new(2, items::LOC_WAREHOUSE, Vec::new(), 1, u64::MAX, templates);
+        let (before, used) = (contents(&owner), ids.used());
+
+        // The fee and the first sword went through before the second sword finds no room.
+        assert_eq!(move_for_fee(&mut owner, &mut warehouse, &swords, 30, templates, &ids).err(), Some(InventoryError::SlotsFull));
+        assert_eq!(contents(&owner), before);
+        assert!(contents(&warehouse).is_empty());
+        assert_eq!(ids.used(), used);
+
+        let moved = move_for_fee(&mut owner, &mut warehouse, &swords[..1], 30, templates, &ids).unwrap();
+        assert_eq!(owner.adena(), 970);
+        assert_eq!(contents(&warehouse), vec![(swords[0].0, 1, 1)]);
+        assert_eq!(moved.first.len(), 2);
+        assert_eq!(moved.writes.len(), 3);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,464 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::info;
+
+use crate::database::{blocks, chat_bans, clans, skill_reuse};
+use crate::database::characters::{self, Character};
+use crate::database::items::{self, Item};
+use crate::gameserver::client::lobby as request;
+use crate::gameserver::combat;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::inventory::{self, Inventory};
+use crate::gameserver::models::{Client, ClientState};
+use crate::gameserver::player::Player;
+use crate::gameserver::server::items as items_response;
+use crate::gameserver::server::lobby as response;
+use crate::gameserver::server::system_message;
+use crate::gameserver::server::world as world_response;
+use crate::gameserver::skills;
+use crate::gameserver::store;
+use crate::gameserver::trade;
+use crate::gameserver::world::WorldObject;
+
+const MAX_CHARACTERS: usize = 7;
+const MAX_NAME_LENGTH: usize = 16;
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+pub fn now_millis() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(time) => time.as_millis() as i64,
+        Err(_) => 0,
+    }
+}
+
+/// In game minutes since midnight, a game day lasts four real hours.
+pub fn game_time() -> u32 {
+    ((now_millis() / 10_000) % 1440) as u32
+}
+
+fn expect_state(client: &Client, state: ClientState) -> Result<(), String> {
+    if client.state != state {
+        return Err(format!("Packet not allowed for {} in state {:?}", client.account_name, client.state));
+    }
+    Ok(())
+}
+
+/// Names are limited to what the client can type on the creation screen.
+pub fn is_valid_name(name: &str) -> bool {
+    let length = name.chars().count();
+    length > 0 && length <= MAX_NAME_LENGTH && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn is_valid_appearance(sex: u32, hair_style: u32, hair_color: u32, face: u32) -> bool {
+    let max_hair_style = if sex == 0 { 4 } else { 6 };
+    sex <= 1 && hair_style <= max_hair_style && hair_color <= 3 && face <= 2
+}
+
+async fn delete_character(context: &Context, obj_id: u32) -> Result<(), String> {
+    for id in characters::delete(&context.database, obj_id).await? {
+        context.ids.release(id);
+    }
+    Ok(())
+}
+
+/// Gives back the ids taken for a character that wasn't created.
+fn release_ids(context: &Context, character: &Character, items: &[Item]) {
+    context.ids.release(character.obj_id);
+    for item in items {
+        context.ids.release(item.object_id);
+    }
+}
+
+/// Sends the character list, removing the characters whose pending deletion expired.
+pub async fn send_char_select_info(context: &Context, client: &mut Client) -> Result<(), String> {
+    let now = now_millis();
+    let mut characters = Vec::new();
+
+    for character in characters::load_by_account(&context.database, &client.account_name).await? {
+        if character.delete_time > 0 && character.delete_time <= now {
+            delete_character(context, character.obj_id).await?;
+            info!("Character {} of {} deleted", character.char_name, client.account_name);
+            continue;
+        }
+        let paperdoll = items::load_paperdoll(&context.database, character.obj_id).await?;
+        characters.push((character, paperdoll));
+    }
+
+    let active = characters.iter()
+        .enumerate()
+        .filter(|(_, (character, _))| character.delete_time == 0)
+        .max_by_key(|(_, (character, _))| character.last_access)
+        .map(|(slot, _)| slot);
+
+    client.send(response::char_select_info(&client.account_name, client.session_id, &characters, active, now));
+    client.slots = characters.into_iter().map(|(character, _)| character).collect();
+    Ok(())
+}
+
+pub async fn auth_login(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    expect_state(client, ClientState::Connected)?;
+
+    let auth = request::new_auth_login(data)?;
+    if auth.account_name.is_empty() {
+        return Err("AuthLogin without account name".to_string());
+    }
+
+    // The login server doesn't hand the session keys to the game servers yet, there is nothing to check the
+    // account against.
+    if !context.conf.options.accept_unverified_logins {
+        info!("Account {} refused, logins can't be verified", auth.account_name);
+        client.state = ClientState::Closed;
+        return Ok(());
+    }
+    let players = context.world().players().count();
+    if players >= context.conf.options.max_players as usize {
+        info!("Account {} refused, the server is full with {} players", auth.account_name, players);
+        client.state = ClientState::Closed;
+        return Ok(());
+    }
+
+    client.account_name = auth.account_name.to_lowercase();
+    client.session_id = auth.play_key1;
+    client.state = ClientState::Authed;
+    info!("Account {} connected to the game server", client.account_name);
+
+    send_char_select_info(context, client).await
+}
+
+pub async fn new_character(context: &Context, client: &mut Client) -> Result<(), String> {
+    expect_state(client, ClientState::Authed)?;
+    client.send(response::char_templates(&context.datapack.classes.creatable()));
+    Ok(())
+}
+
+pub async fn character_create(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    expect_state(client, ClientState::Authed)?;
+    let create = request::new_character_create(data)?;
+
+    if !is_valid_name(&create.name) {
+        client.send(response::char_create_fail(response::CREATE_INVALID_NAME));
+        return Ok(());
+    }
+
+    let template = match context.datapack.classes.get(create.class_id as u8) {
+        Some(template) if create.class_id <= u8::MAX as u32 && template.parent.is_none() && template.race as u32 == create.race => template,
+        _ => {
+            client.send(response::char_create_fail(response::CREATE_FAILED));
+            return Err(format!("{} tried to create a character of class {} race {}", client.account_name, create.class_id, create.race));
+        }
+    };
+
+    if !is_valid_appearance(create.sex, create.hair_style, create.hair_color, create.face) {
+        client.send(response::char_create_fail(response::CREATE_FAILED));
+        return Err(format!("{} sent an invalid appearance for {}", client.account_name, create.name));
+    }
+
+    if characters::load_by_account(&context.database, &client.account_name).await?.len() >= MAX_CHARACTERS {
+        client.send(response::char_create_fail(response::CREATE_TOO_MANY_CHARACTERS));
+        return Ok(());
+    }
+
+    if characters::name_exists(&context.database, &create.name).await? {
+        client.send(response::char_create_fail(response::CREATE_NAME_ALREADY_EXISTS));
+        return Ok(());
+    }
+
+    let (x, y, z) = template.spawns[rand::random::<usize>() % template.spawns.len()];
+    let character = Character {
+        obj_id: context.ids.next_id()?,
+        account_name: client.account_name.clone(),
+        char_name: create.name.clone(),
+        level: 1,
+        max_hp: template.max_hp(1),
+        cur_hp: template.max_hp(1),
+        max_mp: template.max_mp(1),
+        cur_mp: template.max_mp(1),
+        max_cp: template.max_cp(1),
+        cur_cp: template.max_cp(1),
+        face: create.face as u8,
+        hair_style: create.hair_style as u8,
+        hair_color: create.hair_color as u8,
+        sex: create.sex as u8,
+        heading: 0,
+        x,
+        y,
+        z,
+        exp: 0,
+        sp: 0,
+        karma: 0,
+        pvp_kills: 0,
+        pk_kills: 0,
+        title: String::new(),
+        access_level: 0,
+        clan_id: 0,
+        race: template.race,
+        class_id: template.class_id,
+        base_class: template.class_id,
+        delete_time: 0,
+        last_access: 0,
+        online: 0,
+        online_time: 0,
+    };
+
+    let mut starting_items = Vec::new();
+    for starting in &template.items {
+        let object_id = match context.ids.next_id() {
+            Ok(object_id) => object_id,
+            Err(e) => {
+                release_ids(context, &character, &starting_items);
+                return Err(e);
+            }
+        };
+        starting_items.push(Item {
+            object_id,
+            owner_id: character.obj_id,
+            item_id: starting.item_id,
+            count: starting.count,
+            enchant_level: 0,
+            loc: if starting.slot.is_some() { items::LOC_PAPERDOLL } else { items::LOC_INVENTORY }.to_string(),
+            loc_data: starting.slot.unwrap_or(0),
+        });
+    }
+
+    if let Err(e) = characters::create(&context.database, &character, &starting_items).await {
+        release_ids(context, &character, &starting_items);
+        client.send(response::char_create_fail(response::CREATE_FAILED));
+        return Err(e);
+    }
+
+    info!("{} created character {} ({})", client.account_name, character.char_name, template.name);
+    client.send(response::char_create_ok());
+    send_char_select_info(context, client).await
+}
+
+pub async fn character_delete(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    expect_state(client, ClientState::Authed)?;
+    let slot = request::new_character_slot(data)?;
+
+    let character = match client.slots.get(slot) {
+        Some(character) => character.clone(),
+        None => {
+            client.send(response::char_delete_fail(response::DELETE_FAILED));
+            return Ok(());
+        }
+    };
+
+    if character.clan_id != 0 {
+        client.send(response::char_delete_fail(response::DELETE_CLAN_MEMBER));
+        return Ok(());
+    }
+
+    match context.conf.characters.delete_days {
+        0 => delete_character(context, character.obj_id).await?,
+        days => characters::set_delete_time(&context.database, character.obj_id, now_millis() + days as i64 * DAY_MILLIS).await?,
+    }
+
+    info!("{} marked character {} for deletion", client.account_name, character.char_name);
+    client.send(response::char_delete_ok());
+    send_char_select_info(context, client).await
+}
+
+pub async fn character_restore(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    expect_state(client, ClientState::Authed)?;
+    let slot = request::new_character_slot(data)?;
+
+    if let Some(character) = client.slots.get(slot) {
+        if character.delete_time > 0 {
+            characters::set_delete_time(&context.database, character.obj_id, 0).await?;
+            info!("{} restored character {}", client.account_name, character.char_name);
+        }
+    }
+
+    send_char_select_info(context, client).await
+}
+
+pub async fn character_selected(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    expect_state(client, ClientState::Authed)?;
+    let slot = request::new_character_slot(data)?;
+
+    let character = match client.slots.get(slot) {
+        Some(character) if character.delete_time == 0 && character.access_level >= 0 => character.clone(),
+        _ => {
+            client.send(response::action_failed());
+            return Ok(());
+        }
+    };
+
+    let template = match context.datapack.classes.get(character.class_id) {
+        Some(template) => template,
+        None => return Err(format!("No template for class {} of {}", character.class_id, character.char_name)),
+    };
+
+    client.send(response::char_selected(&character, template, client.session_id, game_time()));
+    client.character = Some(character);
+    client.state = ClientState::Entering;
+    Ok(())
+}
+
+pub async fn enter_world(context: &Context, client: &mut Client) -> Result<(), String> {
+    expect_state(client, ClientState::Entering)?;
+
+    let obj_id = match client.character.as_ref() {
+        Some(character) => character.obj_id,
+        None => return Err(format!("{} entered the world without a character", client.account_name)),
+    };
+    store::end_offline(context, obj_id).await?;
+    if context.world().get(obj_id).is_some() {
+        info!("{} tried to enter the world with character {} already in it", client.account_name, obj_id);
+        client.state = ClientState::Closed;
+        return Ok(());
+    }
+    // Reload so the session starts from what is stored, not from the copy taken for the selection screen.
+    let mut character = match characters::load(&context.database, obj_id).await? {
+        Some(character) if character.account_name == client.account_name => character,
+        _ => return Err(format!("Character {} of {} not found", obj_id, client.account_name)),
+    };
+    // The flag is cleared on startup, a character still marked is in another session.
+    if character.online == 1 {
+        info!("{} tried to enter the world with {} which is online", client.account_name, character.char_name);
+        client.state = ClientState::Closed;
+        return Ok(());
+    }
+    let template = match context.datapack.classes.get(character.class_id) {
+        Some(template) => template,
+        None => return Err(format!("No template for class {} of {}", character.class_id, character.char_name)),
+    };
+
+    characters::update_on_login(&context.database, &mut character, now_millis()).await?;
+    let stored = items::load_inventory(&context.database, character.obj_id).await?;
+    let inventory = Inventory::new(obj_id, items::LOC_INVENTORY, stored, inventory::max_slots(character.race),
+        inventory::max_load(template.stats.con), &context.datapack.items);
+
+    info!("{} entered the world", character.char_name);
+    let mut player = Player::new(character, template, inventory, client.sender.clone());
+    skills::learn(&context.datapack, &mut player);
+    player.refresh_equipment(&context.datapack.items, template);
+    player.reuse = skill_reuse::load(&context.database, obj_id, now_millis()).await?.into_iter().collect();
+    player.blocked = blocks::load(&context.database, obj_id).await?.into_iter().collect();
+    player.chat_ban = chat_bans::load(&context.database, obj_id, now_millis()).await?;
+    if player.character.clan_id != 0 {
+        player.clan = clans::load(&context.database, player.character.clan_id).await?;
+        player.clan_privileges = clans::privileges(&context.database, obj_id).await?;
+    }
+    client.send(world_response::user_info(&player, template));
+    client.send(items_response::item_list(&player.inventory, &context.datapack.items, false));
+    client.send(skills::skill_list(&context.datapack, &player));
+    let mut world = context.world();
+    // Another session may have brought the character in while this one was loading it.
+    if let Err(e) = world.add(WorldObject::Player(Box::new(player))) {
+        client.state = ClientState::Closed;
+        return Err(e);
+    }
+    world.start_recovering(obj_id);
+    client.character = None;
+    client.obj_id = Some(obj_id);
+    client.state = ClientState::InGame;
+    Ok(())
+}
+
+/// Saves and releases the character in use, if any. Runs on logout, restart and when the connection drops.
+pub async fn leave_world(context: &Context, client: &mut Client) -> Result<(), String> {
+    client.character = None;
+    let obj_id = match client.obj_id.take() {
+        Some(obj_id) if client.state == ClientState::InGame => obj_id,
+        _ => return Ok(()),
+    };
+    client.state = ClientState::Authed;
+
+    let removed = {
+        let mut world = context.world();
+        trade::cancel(&mut world, obj_id);
+        world.remove(obj_id)
+    };
+    let player = match removed {
+        Some(WorldObject::Player(player)) => player,
+        _ => return Err(format!("Player {} of {} was not in the world", obj_id, client.account_name)),
+    };
+
+    // Whatever happened to the items last has to be stored before the character can be loaded again.
+    context.item_writer.flush().await;
+    if context.item_writer.has_failed(obj_id) {
+        // Saving the rest would store a character that doesn't match its items. It stays marked online, so it
+        // can't be picked again until the next start clears the flag and loads it as it was last saved.
+        return Err(format!("Items of {} couldn't be saved, not saving the character either", player.character.char_name));
+    }
+    let now = now_millis();
+    let reuse: Vec<(u32, i64)> = player.reuse.iter().filter(|(_, ready_at)| **ready_at > now).map(|(skill_id, ready_at)| (*skill_id, *ready_at)).collect();
+    skill_reuse::save(&context.database, obj_id, &reuse).await?;
+    let mut character = player.character;
+    characters::update_on_logout(&context.database, &mut character, now_millis()).await?;
+    info!("{} left the world", character.char_name);
+    Ok(())
+}
+
+/// Players can't leave the world in the middle of a fight.
+fn is_fighting(context: &Context, client: &Client) -> bool {
+    client.obj_id.is_some_and(|obj_id| combat::in_combat(&context.world(), obj_id))
+}
+
+pub async fn logout(context: &Context, client: &mut Client) -> Result<(), String> {
+    if is_fighting(context, client) {
+        client.send(system_message::system_message(system_message::CANT_LOGOUT_WHILE_FIGHTING, &[]));
+        client.send(response::action_failed());
+        return Ok(());
+    }
+    // A player left behind in its store is no longer the client's, leaving the world does nothing then.
+    store::stay_offline(context, client);
+    let result = leave_world(context, client).await;
+    client.send(response::log_out_ok());
+    client.state = ClientState::Closed;
+    result
+}
+
+pub async fn restart(context: &Context, client: &mut Client) -> Result<(), String> {
+    expect_state(client, ClientState::InGame)?;
+    if is_fighting(context, client) {
+        client.send(system_message::system_message(system_message::CANT_RESTART_WHILE_FIGHTING, &[]));
+        client.send(response::action_failed());
+        return Ok(());
+    }
+    leave_world(context, client).await?;
+    client.send(response::restart_response());
+    send_char_select_info(context, client).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_short_and_alphanumeric() {
+        let cases = [
+            ("Aragorn", true),
+            ("player42", true),
+            ("a", true),
+            ("abcdefghijklmnop", true),
+            ("", false),
+            ("abcdefghijklmnopq", false),
+            ("with space", false),
+            ("under_score", false),
+            ("Ärger", false),
+        ];
+        for (name, valid) in cases {
+            assert_eq!(is_valid_name(name), valid, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn appearance_stays_in_the_client_ranges() {
+        // (sex, hair style, hair color, face)
+        let cases = [
+            ((0, 0, 0, 0), true),
+            ((0, 4, 3, 2), true),
+            ((1, 6, 3, 2), true),
+            ((0, 5, 0, 0), false),
+            ((1, 7, 0, 0), false),
+            ((2, 0, 0, 0), false),
+            ((0, 0, 4, 0), false),
+            ((0, 0, 0, 3), false),
+        ];
+        for ((sex, hair_style, hair_color, face), valid) in cases {
+            assert_eq!(is_valid_appearance(sex, hair_style, hair_color, face), valid, "{} {} {} {}", sex, hair_style, hair_color, face);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,493 @@
+use std::collections::BTreeSet;
+use std::time::Instant;
+
+use log::info;
+
+use crate::database::items;
+use crate::gameserver::bypass::Shop;
+use crate::gameserver::client::merchant::{self as request, SoldToMerchant};
+use crate::gameserver::datapack::buylists::Stock;
+use crate::gameserver::datapack::items::{ItemRegistry, ADENA};
+use crate::gameserver::datapack::multisell::{Multisell, MultisellEntry};
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::inventory::{self, Inventory, InventoryError, Made, MAX_COUNT};
+use crate::gameserver::models::Client;
+use crate::gameserver::player::Player;
+use crate::gameserver::server::lobby::action_failed;
+use crate::gameserver::server::merchant as response;
+use crate::gameserver::server::system_message;
+use crate::gameserver::world::World;
+
+/// Object ids with counts of the items a trade with a merchant destroys.
+type Destroyed = Vec<(u32, u64)>;
+
+/// Most of a multisell entry a player can exchange at once.
+const MAX_MULTISELL_AMOUNT: u64 = 5000;
+/// Multisell entry ids carry the enchant level the equipment keeps: `(index + 1) * ENTRY_ID_FACTOR + enchant`.
+const ENTRY_ID_FACTOR: u32 = 100_000;
+
+/// What is left of a limited item of a buy list.
+pub struct ShopStock {
+    left: u64,
+    restock_at: Option<Instant>,
+}
+
+impl ShopStock {
+    pub fn new(stock: &Stock) -> ShopStock {
+        ShopStock { left: stock.count, restock_at: None }
+    }
+
+    /// Count left, the merchant has everything back once the restock time is over.
+    pub fn left(&mut self, stock: &Stock, now: Instant) -> u64 {
+        if self.restock_at.is_some_and(|at| at <= now) {
+            self.left = stock.count;
+            self.restock_at = None;
+        }
+        self.left
+    }
+
+    /// Takes items sold, the restock time runs from the first sale after a restock.
+    fn take(&mut self, stock: &Stock, count: u64, now: Instant) {
+        self.left -= count.min(self.left);
+        self.restock_at.get_or_insert(now + stock.restock);
+    }
+}
+
+/// Npc id of the merchant whose window a player has open, when it is still close enough.
+fn merchant_of(world: &World, player: &Player) -> Option<u32> {
+    let (x, y, _) = player.position();
+    player.merchant
+        .and_then(|npc_obj_id| world.npc(npc_obj_id))
+        .filter(|npc| npc.is_within_reach((x, y)))
+        .map(|npc| npc.npc_id)
+}
+
+fn refuse(player: &Player, e: InventoryError) {
+    let packet = match e {
+        InventoryError::SlotsFull => system_message::system_message(system_message::SLOTS_FULL, &[]),
+        InventoryError::TooHeavy => system_message::system_message(system_message::WEIGHT_LIMIT_EXCEEDED, &[]),
+        InventoryError::NotEnough => system_message::text("You don't have the items needed."),
+        e => system_message::text(&format!("The trade failed: {}.", e)),
+    };
+    player.send(packet);
+}
+
+/// Items of an inventory to destroy for `count` of an item, worn ones left alone and the least enchanted taken
+/// first. Only items of `enchant_level` are taken when one is given. `picked` are parts already taken by the
+/// same operation.
+fn pick(inventory: &Inventory, item_id: u32, count: u64, enchant_level: Option<u32>, picked: &[(u32, u64)]) -> Option<Vec<(u32, u64)>> {
+    let mut candidates: Vec<_> = inventory.items()
+        .filter(|item| item.item_id == item_id && item.loc != items::LOC_PAPERDOLL)
+        .filter(|item| enchant_level.is_none_or(|level| item.enchant_level == level))
+        .collect();
+    candidates.sort_by_key(|item| (item.enchant_level, item.object_id));
+
+    let mut left = count;
+    let mut result = Vec::new();
+    for item in candidates {
+        if left == 0 {
+            break;
+        }
+        let used: u64 = picked.iter().filter(|(object_id, _)| *object_id == item.object_id).map(|(_, count)| count).sum();
+        let part = left.min(item.count - used.min(item.count));
+        if part > 0 {
+            result.push((item.object_id, part));
+            left -= part;
+        }
+    }
+    if left > 0 { None } else { Some(result) }
+}
+
+/// Entries of a multisell as the player sees them. When equipment keeps its enchant level, an entry is shown
+/// once for each enchant level of that equipment the player has.
+fn multisell_entries<'a>(multisell: &'a Multisell, inventory: &Inventory, templates: &ItemRegistry) -> Vec<(u32, &'a MultisellEntry, u32)> {
+    let mut entries = Vec::new();
+    for (index, entry) in multisell.entries.iter().enumerate() {
+        let base = (index as u32 + 1) * ENTRY_ID_FACTOR;
+        let enchantable = entry.ingredients.iter()
+            .find(|ingredient| multisell.keep_enchant && templates.get(ingredient.item).is_some_and(|template| template.is_equipable()));
+        let mut levels = BTreeSet::from([0]);
+        if let Some(ingredient) = enchantable {
+            levels.extend(inventory.items()
+                .filter(|item| item.item_id == ingredient.item && item.loc != items::LOC_PAPERDOLL)
+                .map(|item| item.enchant_level.min(ENTRY_ID_FACTOR - 1)));
+        }
+        for level in levels {
+            entries.push((base + level, entry, level));
+        }
+    }
+    entries
+}
+
+/// Items destroyed for what a player sells to a merchant, with the adena it gets: half the price of each. Fails
+/// with the object id of the first item that can't be sold.
+fn sell_back(inventory: &Inventory, templates: &ItemRegistry, sold: &[SoldToMerchant]) -> Result<(Destroyed, u64), u32> {
+    let mut destroyed: Vec<(u32, u64)> = Vec::new();
+    let mut total: u64 = 0;
+    for item in sold {
+        let price = match (inventory.get(item.object_id), templates.get(item.item_id)) {
+            (Some(owned), Some(template)) if owned.item_id == item.item_id && owned.loc != items::LOC_PAPERDOLL
+                && owned.item_id != ADENA && template.price > 0 && item.count > 0 && item.count <= owned.count
+                && !destroyed.iter().any(|(object_id, _)| *object_id == item.object_id) => template.price / 2,
+            _ => return Err(item.object_id),
+        };
+        destroyed.push((item.object_id, item.count));
+        total = total.saturating_add(price.saturating_mul(item.count));
+    }
+    Ok((destroyed, total))
+}
+
+/// Items destroyed and made by exchanging a multisell entry `amount` times, `None` when the inventory lacks
+/// ingredients. Equipment of a list that keeps enchant levels is only taken at `enchant_level` and made at it.
+fn exchange_of(multisell: &Multisell, entry: &MultisellEntry, enchant_level: u32, amount: u64, inventory: &Inventory, templates: &ItemRegistry)
+    -> Option<(Destroyed, Vec<Made>)> {
+    let keeps_enchant = |item_id: u32| multisell.keep_enchant && templates.get(item_id).is_some_and(|template| template.is_equipable());
+    let mut destroyed: Vec<(u32, u64)> = Vec::new();
+    for ingredient in &entry.ingredients {
+        let count = ingredient.count.saturating_mul(amount);
+        let level = if keeps_enchant(ingredient.item) { Some(enchant_level) } else { None };
+        destroyed.extend(pick(inventory, ingredient.item, count, level, &destroyed)?);
+    }
+    let made = entry.products.iter()
+        .map(|product| Made {
+            item_id: product.item,
+            count: product.count.saturating_mul(amount),
+            enchant_level: if keeps_enchant(product.item) { enchant_level } else { 0 },
+        })
+        .collect();
+    Some((destroyed, made))
+}
+
+/// Opens a shop window of the merchant a player talks to.
+pub fn open_shop(context: &Context, obj_id: u32, npc_obj_id: u32, shop: Shop) -> Result<(), String> {
+    let templates = &context.datapack.items;
+    let mut world = context.world();
+    let npc_id = match world.npc(npc_obj_id) {
+        Some(npc) => npc.npc_id,
+        None => return Ok(()),
+    };
+
+    let packets = match shop {
+        Shop::Buy(list_id) => {
+            let list = match context.datapack.buylists.get(list_id) {
+                Some(list) if list.npcs.contains(&npc_id) => list,
+                _ => return Err(format!("Npc {} has no buy list {}", npc_id, list_id)),
+            };
+            let now = Instant::now();
+            let mut rows = Vec::new();
+            for item in &list.items {
+                if let Some(template) = templates.get(item.item_id) {
+                    let left = item.stock.map(|stock| world.shop_stock(list_id, item.item_id, &stock).left(&stock, now));
+                    rows.push((template, left, item.price));
+                }
+            }
+            let adena = world.player(obj_id).map_or(0, |player| player.inventory.adena());
+            vec![response::buy_list(list_id, adena, &rows)]
+        },
+        Shop::Sell => {
+            let player = match world.player(obj_id) {
+                Some(player) => player,
+                None => return Ok(()),
+            };
+            let sellable: Vec<_> = player.inventory.items()
+                .filter(|item| item.loc != items::LOC_PAPERDOLL && item.item_id != ADENA)
+                .filter_map(|item| templates.get(item.item_id).filter(|template| template.price > 0).map(|template| (item, template.price / 2)))
+                .collect();
+            vec![response::sell_list(player.inventory.adena(), &sellable, templates)]
+        },
+        Shop::Multisell(list_id) => {
+            let multisell = match context.datapack.multisells.get(list_id) {
+                Some(multisell) if multisell.npcs.contains(&npc_id) => multisell,
+                _ => return Err(format!("Npc {} has no multisell {}", npc_id, list_id)),
+            };
+            let player = match world.player(obj_id) {
+                Some(player) => player,
+                None => return Ok(()),
+            };
+            let entries = multisell_entries(multisell, &player.inventory, templates);
+            let pages: Vec<_> = entries.chunks(response::MULTISELL_PAGE_SIZE).collect();
+            if pages.is_empty() {
+                vec![response::multisell_list(list_id, 0, 1, &[], templates)]
+            } else {
+                pages.iter().enumerate().map(|(page, entries)| response::multisell_list(list_id, page, pages.len(), entries, templates)).collect()
+            }
+        },
+    };
+
+    if let Some(player) = world.player_mut(obj_id) {
+        player.merchant = Some(npc_obj_id);
+        for packet in packets {
+            player.send(packet);
+        }
+    }
+    Ok(())
+}
+
+pub async fn request_buy_item(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let purchase = request::new_request_buy_item(data)?;
+    let templates = &context.datapack.items;
+
+    let mut world = context.world();
+    let player = match world.player(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} is not in the world", obj_id)),
+    };
+    let npc_id = match merchant_of(&world, player) {
+        Some(npc_id) => npc_id,
+        None => {
+            player.send(action_failed());
+            return Ok(());
+        }
+    };
+    let list = match context.datapack.buylists.get(purchase.list_id) {
+        Some(list) if list.npcs.contains(&npc_id) => list,
+        _ => return Err(format!("{} bought from list {} npc {} doesn't have", player.character.char_name, purchase.list_id, npc_id)),
+    };
+
+    // An item may be asked more than once, its counts add up.
+    let mut wanted: Vec<(u32, u64)> = Vec::new();
+    for &(item_id, count) in &purchase.items {
+        if count == 0 || count > MAX_COUNT || list.item(item_id).is_none() {
+            return Err(format!("{} bought {} of item {} from list {}", player.character.char_name, count, item_id, list.list_id));
+        }
+        match wanted.iter_mut().find(|(wanted_id, _)| *wanted_id == item_id) {
+            Some((_, wanted_count)) => *wanted_count += count,
+            None => wanted.push((item_id, count)),
+        }
+    }
+    if wanted.is_empty() {
+        return Ok(());
+    }
+
+    let now = Instant::now();
+    let mut total: u64 = 0;
+    for &(item_id, count) in &wanted {
+        let listed = match list.item(item_id) {
+            Some(listed) => listed,
+            None => continue,
+        };
+        if let Some(stock) = listed.stock {
+            if world.shop_stock(list.list_id, item_id, &stock).left(&stock, now) < count {
+                if let Some(player) = world.player(obj_id) {
+                    player.send(system_message::text("The merchant doesn't have that many left."));
+                }
+                return Ok(());
+            }
+        }
+        total = total.saturating_add(listed.price.saturating_mul(count));
+    }
+
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Ok(()),
+    };
+    if total > MAX_COUNT || player.inventory.adena() < total {
+        player.send(system_message::system_message(system_message::YOU_NOT_ENOUGH_ADENA, &[]));
+        return Ok(());
+    }
+    let paid = if total > 0 { pick(&player.inventory, ADENA, total, None, &[]).unwrap_or_default() } else { Vec::new() };
+    let made: Vec<Made> = wanted.iter().map(|&(item_id, count)| Made { item_id, count, enchant_level: 0 }).collect();
+    match inventory::replace(&mut player.inventory, &paid, &made, templates, &context.ids) {
+        Ok(changes) => inventory::commit(context, player, changes),
+        Err(e) => {
+            refuse(player, e);
+            return Ok(());
+        }
+    }
+    info!("{} bought {} items from list {} for {} adena", player.character.char_name, wanted.len(), list.list_id, total);
+
+    for (item_id, count) in wanted {
+        if let Some(stock) = list.item(item_id).and_then(|listed| listed.stock) {
+            world.shop_stock(list.list_id, item_id, &stock).take(&stock, count, now);
+        }
+    }
+    Ok(())
+}
+
+pub async fn request_sell_item(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let sold = request::new_request_sell_item(data)?;
+    let templates = &context.datapack.items;
+
+    let mut world = context.world();
+    let player = match world.player(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} is not in the world", obj_id)),
+    };
+    if merchant_of(&world, player).is_none() {
+        player.send(action_failed());
+        return Ok(());
+    }
+    if sold.is_empty() {
+        return Ok(());
+    }
+
+    let (destroyed, total) = match sell_back(&player.inventory, templates, &sold) {
+        Ok(sale) => sale,
+        Err(object_id) => return Err(format!("{} sold item {} it can't sell", player.character.char_name, object_id)),
+    };
+
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Ok(()),
+    };
+    if total + player.inventory.adena() > MAX_COUNT {
+        refuse(player, InventoryError::TooMany);
+        return Ok(());
+    }
+    let made: Vec<Made> = if total > 0 { vec![Made { item_id: ADENA, count: total, enchant_level: 0 }] } else { Vec::new() };
+    match inventory::replace(&mut player.inventory, &destroyed, &made, templates, &context.ids) {
+        Ok(changes) => inventory::commit(context, player, changes),
+        Err(e) => refuse(player, e),
+    }
+    info!("{} sold {} items for {} adena", player.character.char_name, destroyed.len(), total);
+    Ok(())
+}
+
+pub async fn multisell_choose(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let choice = request::new_multisell_choose(data)?;
+    let templates = &context.datapack.items;
+
+    let mut world = context.world();
+    let player = match world.player(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} is not in the world", obj_id)),
+    };
+    let npc_id = match merchant_of(&world, player) {
+        Some(npc_id) => npc_id,
+        None => {
+            player.send(action_failed());
+            return Ok(());
+        }
+    };
+    let name = &player.character.char_name;
+    let multisell = match context.datapack.multisells.get(choice.list_id) {
+        Some(multisell) if multisell.npcs.contains(&npc_id) => multisell,
+        _ => return Err(format!("{} used multisell {} npc {} doesn't have", name, choice.list_id, npc_id)),
+    };
+    let (index, enchant_level) = (choice.entry_id / ENTRY_ID_FACTOR, choice.entry_id % ENTRY_ID_FACTOR);
+    let entry = match index.checked_sub(1).and_then(|index| multisell.entries.get(index as usize)) {
+        Some(entry) if enchant_level == 0 || multisell.keep_enchant => entry,
+        _ => return Err(format!("{} chose entry {} of multisell {}", name, choice.entry_id, choice.list_id)),
+    };
+    if choice.amount == 0 || choice.amount > MAX_MULTISELL_AMOUNT {
+        return Err(format!("{} exchanged multisell entry {} {} times", name, choice.entry_id, choice.amount));
+    }
+
+    let (destroyed, made) = match exchange_of(multisell, entry, enchant_level, choice.amount, &player.inventory, templates) {
+        Some(exchange) => exchange,
+        None => {
+            refuse(player, InventoryError::NotEnough);
+            return Ok(());
+        }
+    };
+
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Ok(()),
+    };
+    match inventory::replace(&mut player.inventory, &destroyed, &made, templates, &context.ids) {
+        Ok(changes) => {
+            inventory::commit(context, player, changes);
+            info!("{} exchanged entry {} of multisell {} {} times", player.character.char_name, choice.entry_id, multisell.list_id, choice.amount);
+        },
+        Err(e) => refuse(player, e),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::gameserver::idfactory::IdFactory;
+    use crate::gameserver::models::PAPERDOLL_RHAND;
+    use crate::gameserver::player::synthetic::datapack;
+
+    const SWORD: u32 = 1;
+    const LONG_SWORD: u32 = 2;
+    const ARROWS: u32 = 17;
+
+    /// Creates items in an inventory, giving the object id of the first one made.
+    fn add(inventory: &mut Inventory, templates: &ItemRegistry, ids: &IdFactory, item_id: u32, count: u64, enchant_level: u32) -> u32 {
+        let made = [Made { item_id, count, enchant_level }];
+        inventory::replace(inventory, &[], &made, templates, ids).unwrap()[0].item().object_id
+    }
+
+    #[test]
+    fn stock_comes_back_after_the_restock_time() {
+        let stock = Stock { count: 10, restock: Duration::from_secs(60) };
+        let mut shop = ShopStock::new(&stock);
+        let start = Instant::now();
+
+        shop.take(&stock, 3, start);
+        // Later sales don't push the restock back.
+        shop.take(&stock, 20, start + Duration::from_secs(30));
+        assert_eq!(shop.left(&stock, start + Duration::from_secs(59)), 0);
+        assert_eq!(shop.left(&stock, start + Duration::from_secs(60)), 10);
+
+        // The next restock runs from the first sale after this one.
+        assert_eq!(shop.left(&stock, start + Duration::from_secs(200)), 10);
+        shop.take(&stock, 4, start + Duration::from_secs(200));
+        assert_eq!(shop.left(&stock, start + Duration::from_secs(259)), 6);
+        assert_eq!(shop.left(&stock, start + Duration::from_secs(260)), 10);
+    }
+
+    #[test]
+    fn merchants_pay_half_the_price() {
+        let datapack = datapack();
+        let templates = &datapack.items;
+        let ids = IdFactory::new(Vec::new());
+        let mut inventory = Inventory::new(1, items::LOC_INVENTORY, Vec::new(), 80, u64::MAX, templates);
+        let adena = add(&mut inventory, templates, &ids, ADENA, 100, 0);
+        let arrows = add(&mut inventory, templates, &ids, ARROWS, 50, 0);
+        let sword = add(&mut inventory, templates, &ids, SWORD, 1, 0);
+        let worn = add(&mut inventory, templates, &ids, SWORD, 1, 0);
+        inventory.set_slot(worn, Some(PAPERDOLL_RHAND));
+        let sold = |items: &[(u32, u32, u64)]| -> Vec<SoldToMerchant> {
+            items.iter().map(|&(object_id, item_id, count)| SoldToMerchant { object_id, item_id, count }).collect()
+        };
+
+        let sale = sell_back(&inventory, templates, &sold(&[(arrows, ARROWS, 30), (sword, SWORD, 1)]));
+        assert_eq!(sale, Ok((vec![(arrows, 30), (sword, 1)], 30 + 768 / 2)));
+
+        assert_eq!(sell_back(&inventory, templates, &sold(&[(adena, ADENA, 10)])), Err(adena));
+        assert_eq!(sell_back(&inventory, templates, &sold(&[(worn, SWORD, 1)])), Err(worn));
+        assert_eq!(sell_back(&inventory, templates, &sold(&[(sword, LONG_SWORD, 1)])), Err(sword));
+        assert_eq!(sell_back(&inventory, templates, &sold(&[(arrows, ARROWS, 51)])), Err(arrows));
+        assert_eq!(sell_back(&inventory, templates, &sold(&[(arrows, ARROWS, 0)])), Err(arrows));
+        assert_eq!(sell_back(&inventory, templates, &sold(&[(arrows, ARROWS, 10), (arrows, ARROWS, 10)])), Err(arrows));
+    }
+
+    #[test]
+    fn multisells_keep_the_enchant_level() {
+        let datapack = datapack();
+        let templates = &datapack.items;
+        let multisell = datapack.multisells.get(1).unwrap();
+        let ids = IdFactory::new(Vec::new());
+        let mut inventory = Inventory::new(1, items::LOC_INVENTORY, Vec::new(), 80, u64::MAX, templates);
+        add(&mut inventory, templates, &ids, ADENA, 300_000, 0);
+        let plain = add(&mut inventory, templates, &ids, SWORD, 1, 0);
+        let enchanted = add(&mut inventory, templates, &ids, SWORD, 1, 3);
+
+        // The first entry is shown again for the +3 sword.
+        let shown: Vec<(u32, u32)> = multisell_entries(multisell, &inventory, templates).iter().map(|(id, _, level)| (*id, *level)).collect();
+        assert_eq!(shown, vec![(100_000, 0), (100_003, 3), (200_000, 0)]);
+
+        let entry = &multisell.entries[0];
+        let (destroyed, _) = exchange_of(multisell, entry, 0, 1, &inventory, templates).unwrap();
+        assert!(destroyed.contains(&(plain, 1)) && !destroyed.iter().any(|(object_id, _)| *object_id == enchanted));
+        assert!(exchange_of(multisell, entry, 3, 2, &inventory, templates).is_none());
+
+        let (destroyed, made) = exchange_of(multisell, entry, 3, 1, &inventory, templates).unwrap();
+        assert!(destroyed.contains(&(enchanted, 1)));
+        let changes = inventory::replace(&mut inventory, &destroyed, &made, templates, &ids).unwrap();
+        let long_sword = changes.iter().map(|change| change.item()).find(|item| item.item_id == LONG_SWORD).unwrap();
+        assert_eq!(long_sword.enchant_level, 3);
+        assert_eq!(inventory.adena(), 180_000);
+        assert!(inventory.get(plain).is_some() && inventory.get(enchanted).is_none());
+    }
+}
\ No newline at end of file
@@ -1 +1,34 @@
-pub mod gameserver;
\ No newline at end of file
+pub mod gameserver;
+pub mod models;
+pub mod crypt;
+pub mod client;
+pub mod server;
+pub mod lobby;
+pub mod datapack;
+pub mod player;
+pub mod npc;
+pub mod world;
+pub mod idfactory;
+pub mod movement;
+pub mod geodata;
+pub mod pathfinding;
+pub mod chat;
+pub mod admin;
+pub mod inventory;
+pub mod equipment;
+pub mod warehouse;
+pub mod trade;
+pub mod store;
+pub mod action;
+pub mod spawn;
+pub mod html;
+pub mod bypass;
+pub mod merchant;
+pub mod teleport;
+pub mod combat;
+pub mod stats;
+pub mod skills;
+pub mod ai;
+pub mod experience;
+pub mod drops;
+pub mod death;
\ No newline at end of file
@@ -0,0 +1,194 @@
+use std::time::Instant;
+
+use log::{error, info};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::database::characters::Character;
+use crate::database::items::Item;
+use crate::gameserver::crypt::GameCrypt;
+use crate::gameserver::server;
+
+pub const PAPERDOLL_UNDER: i32 = 0;
+pub const PAPERDOLL_REAR: i32 = 1;
+pub const PAPERDOLL_LEAR: i32 = 2;
+pub const PAPERDOLL_NECK: i32 = 3;
+pub const PAPERDOLL_RFINGER: i32 = 4;
+pub const PAPERDOLL_LFINGER: i32 = 5;
+pub const PAPERDOLL_HEAD: i32 = 6;
+pub const PAPERDOLL_RHAND: i32 = 7;
+pub const PAPERDOLL_LHAND: i32 = 8;
+pub const PAPERDOLL_GLOVES: i32 = 9;
+pub const PAPERDOLL_CHEST: i32 = 10;
+pub const PAPERDOLL_LEGS: i32 = 11;
+pub const PAPERDOLL_FEET: i32 = 12;
+pub const PAPERDOLL_BACK: i32 = 13;
+pub const PAPERDOLL_LRHAND: i32 = 14;
+pub const PAPERDOLL_HAIR: i32 = 15;
+pub const PAPERDOLL_FACE: i32 = 16;
+pub const PAPERDOLL_TOTAL: usize = 17;
+
+/// Object and item id worn in every paperdoll slot, the way the appearance packets expect them.
+pub fn paperdoll_view<'a>(items: impl IntoIterator<Item = &'a Item>) -> [(u32, u32); PAPERDOLL_TOTAL] {
+    let mut view = [(0, 0); PAPERDOLL_TOTAL];
+    for item in items {
+        if let Some(slot) = view.get_mut(item.loc_data as usize) {
+            *slot = (item.object_id, item.item_id);
+        }
+    }
+    view
+}
+
+/// Handle used to queue packets for a client. Packets are encrypted and written in order by the
+/// client's writer task, so it can be cloned and used from any task.
+#[derive(Clone)]
+pub struct Sender {
+    channel: UnboundedSender<Vec<u8>>,
+}
+
+impl Sender {
+    /// Sender of a player without a client, whatever is sent to it is dropped.
+    pub fn detached() -> Sender {
+        let (channel, _) = mpsc::unbounded_channel();
+        Sender { channel }
+    }
+
+    /// Sender whose packets are kept in the receiver, for the tests to look at.
+    #[cfg(test)]
+    pub fn capture() -> (Sender, UnboundedReceiver<Vec<u8>>) {
+        let (channel, receiver) = mpsc::unbounded_channel();
+        (Sender { channel }, receiver)
+    }
+
+    pub fn send(&self, packet: Vec<u8>) {
+        // The writer task only stops once the connection is gone, nothing left to deliver to then.
+        let _ = self.channel.send(packet);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClientState {
+    /// Protocol version and key exchanged, waiting for AuthLogin.
+    Connected,
+    /// On the character selection screen.
+    Authed,
+    /// Character picked, waiting for EnterWorld.
+    Entering,
+    InGame,
+    /// Logged out, the connection is closed once the pending packets are sent.
+    Closed,
+}
+
+pub struct Client {
+    pub account_name: String,
+    pub session_id: u32,
+    pub state: ClientState,
+    /// Characters as shown on the last CharSelectInfo, slots sent by the client index this list.
+    pub slots: Vec<Character>,
+    pub character: Option<Character>,
+    /// Object id of the player in the world while in game, the character itself lives in the world.
+    pub obj_id: Option<u32>,
+    /// When a click of the player last had a path searched, searches are rationed per client.
+    pub last_path_search: Option<Instant>,
+    pub sender: Sender,
+    reader: OwnedReadHalf,
+    crypt: GameCrypt,
+}
+
+/// Reads one packet off the socket, returning its raw body without the length header.
+async fn read_packet(reader: &mut (impl AsyncReadExt + Unpin)) -> Result<Vec<u8>, String> {
+    let mut header = [0; 2];
+    if let Err(e) = reader.read_exact(&mut header).await {
+        return Err(format!("An error occured while reading the packet header: {}", e));
+    }
+
+    let size = u16::from_le_bytes(header) as usize;
+    if size <= 2 {
+        return Err(format!("Invalid packet size: {}", size));
+    }
+
+    let mut data = vec![0; size - 2];
+    if let Err(e) = reader.read_exact(&mut data).await {
+        return Err(format!("An error occured while reading the packet data: {}", e));
+    }
+    Ok(data)
+}
+
+fn frame(data: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(data.len() + 2);
+    framed.extend(((data.len() + 2) as u16).to_le_bytes());
+    framed.extend(data);
+    framed
+}
+
+async fn write_packets(mut writer: OwnedWriteHalf, mut crypt: GameCrypt, mut packets: UnboundedReceiver<Vec<u8>>) {
+    while let Some(mut packet) = packets.recv().await {
+        crypt.encrypt(&mut packet);
+        if let Err(e) = writer.write_all(&frame(&packet)).await {
+            error!("Error sending packet to client: {}", e);
+            break;
+        }
+    }
+    let _ = writer.shutdown().await;
+}
+
+impl Client {
+    /// Runs the unencrypted part of the protocol: reads ProtocolVersion, answers with the KeyPacket and
+    /// starts the writer task used by every later packet.
+    pub async fn handshake(mut socket: TcpStream, protocols: &[u32]) -> Result<Client, String> {
+        let data = read_packet(&mut socket).await?;
+        if data[0] != 0x00 {
+            return Err(format!("Expected ProtocolVersion, got packet {:#04x}", data[0]));
+        }
+
+        let protocol = crate::gameserver::client::lobby::new_protocol_version(data[1..].to_vec())?;
+        if !protocols.contains(&protocol) {
+            let _ = socket.write_all(&frame(&server::lobby::key_packet(&[], false))).await;
+            return Err(format!("Unsupported protocol version: {}", protocol));
+        }
+
+        let crypt = GameCrypt::new();
+        if let Err(e) = socket.write_all(&frame(&server::lobby::key_packet(&crypt.client_key(), true))).await {
+            return Err(format!("Error sending KeyPacket: {}", e));
+        }
+
+        let (reader, writer) = socket.into_split();
+        let (channel, packets) = mpsc::unbounded_channel();
+        tokio::spawn(write_packets(writer, crypt.clone(), packets));
+
+        info!("Client handshake done, protocol {}", protocol);
+
+        Ok(Client {
+            account_name: String::new(),
+            session_id: 0,
+            state: ClientState::Connected,
+            slots: Vec::new(),
+            character: None,
+            obj_id: None,
+            last_path_search: None,
+            sender: Sender { channel },
+            reader,
+            crypt,
+        })
+    }
+
+    /// Object id of the player, failing unless the client is in game.
+    pub fn player_id(&self) -> Result<u32, String> {
+        match (self.state, self.obj_id) {
+            (ClientState::InGame, Some(obj_id)) => Ok(obj_id),
+            _ => Err(format!("Packet not allowed for {} in state {:?}", self.account_name, self.state)),
+        }
+    }
+
+    pub async fn receive(&mut self) -> Result<(u8, Vec<u8>), String> {
+        let mut data = read_packet(&mut self.reader).await?;
+        self.crypt.decrypt(&mut data);
+        Ok((data[0], data[1..].to_vec()))
+    }
+
+    pub fn send(&self, packet: Vec<u8>) {
+        self.sender.send(packet);
+    }
+}
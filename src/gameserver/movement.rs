@@ -0,0 +1,451 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::gameserver::ai;
+use crate::gameserver::client::movement as request;
+use crate::gameserver::combat;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::geodata::{cell_of, Geodata};
+use crate::gameserver::models::Client;
+use crate::gameserver::server::lobby::action_failed;
+use crate::gameserver::server::movement as response;
+use crate::gameserver::world::World;
+
+/// How often moving objects are advanced along their path.
+const MOVE_TICK: Duration = Duration::from_millis(100);
+/// Farthest point a single click can move to, the client doesn't send more.
+const MAX_MOVE_DISTANCE: f64 = 9900.0;
+/// Shortest time between two path searches for the clicks of a client, a search can go through thousands of
+/// cells.
+const PATH_SEARCH_INTERVAL: Duration = Duration::from_millis(500);
+/// Distance between the client and the server position accepted without correcting the client.
+const MAX_DRIFT: f64 = 150.0;
+/// How far ahead of the server a moving client can be, in seconds of movement, to cover network lag.
+const MAX_LAG: f64 = 1.0;
+/// Height difference from the server one accepted in a position reported by the client.
+const MAX_Z_DRIFT: i32 = 200;
+
+/// A movement in progress, made of straight segments. The exact position is kept here, the object only has
+/// the rounded one.
+pub struct Movement {
+    /// End of the segment being walked.
+    pub destination: (i32, i32, i32),
+    /// Ends of the segments after this one, the next one last.
+    waypoints: Vec<(i32, i32, i32)>,
+    position: (f64, f64, f64),
+    last_update: Instant,
+}
+
+/// Why an object wants a path searched, checked again once it is found since the world may have changed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RouteGoal {
+    /// Getting in reach of the creature it attacks.
+    Chase(u32),
+    /// An NPC going back home.
+    Home,
+}
+
+/// A path asked for while holding the world, searched once it is released.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Route {
+    pub from: (i32, i32, i32),
+    pub to: (i32, i32, i32),
+    pub goal: RouteGoal,
+}
+
+/// What a movement tick did.
+struct Progress {
+    position: (i32, i32, i32),
+    /// A new segment was started, the client has to be told about it.
+    turned: bool,
+    arrived: bool,
+}
+
+impl Movement {
+    pub fn new(from: (i32, i32, i32), destination: (i32, i32, i32), now: Instant) -> Movement {
+        Movement::along(from, vec![destination], now)
+    }
+
+    /// Follows the waypoints of a path in order.
+    pub fn along(from: (i32, i32, i32), mut path: Vec<(i32, i32, i32)>, now: Instant) -> Movement {
+        path.reverse();
+        let destination = path.pop().unwrap_or(from);
+        Movement {
+            destination,
+            waypoints: path,
+            position: (from.0 as f64, from.1 as f64, from.2 as f64),
+            last_update: now,
+        }
+    }
+
+    /// Where the last segment ends.
+    pub fn end(&self) -> (i32, i32, i32) {
+        self.waypoints.first().copied().unwrap_or(self.destination)
+    }
+
+    /// Moves along the path at `speed` units per second, going on with the next segment when one ends.
+    fn advance(&mut self, speed: f64, now: Instant) -> Progress {
+        let elapsed = now.duration_since(self.last_update).as_secs_f64();
+        self.last_update = now;
+
+        let mut step = speed * elapsed;
+        let mut turned = false;
+        loop {
+            let (x, y, z) = self.position;
+            let dx = self.destination.0 as f64 - x;
+            let dy = self.destination.1 as f64 - y;
+            let dz = self.destination.2 as f64 - z;
+            let remaining = (dx * dx + dy * dy).sqrt();
+
+            if step < remaining {
+                let fraction = step / remaining;
+                self.position = (x + dx * fraction, y + dy * fraction, z + dz * fraction);
+                let position = (self.position.0.round() as i32, self.position.1.round() as i32, self.position.2.round() as i32);
+                return Progress { position, turned, arrived: false };
+            }
+
+            step -= remaining;
+            self.position = (self.destination.0 as f64, self.destination.1 as f64, self.destination.2 as f64);
+            match self.waypoints.pop() {
+                Some(next) => {
+                    self.destination = next;
+                    turned = true;
+                },
+                None => return Progress { position: self.destination, turned, arrived: true },
+            }
+        }
+    }
+}
+
+/// Client heading, 65536 units for a full turn, for an object looking from `from` to `to`.
+pub fn heading_to(from: (i32, i32), to: (i32, i32)) -> i32 {
+    let mut angle = ((to.1 - from.1) as f64).atan2((to.0 - from.0) as f64).to_degrees();
+    if angle < 0.0 {
+        angle += 360.0;
+    }
+    (angle * 182.044444444) as i32
+}
+
+fn distance_2d(a: (i32, i32), b: (i32, i32)) -> f64 {
+    ((a.0 - b.0) as f64).hypot((a.1 - b.1) as f64)
+}
+
+/// Advances every moving object for as long as the server runs.
+pub async fn run(context: Arc<Context>) {
+    let mut interval = tokio::time::interval(MOVE_TICK);
+    loop {
+        interval.tick().await;
+        update(&mut context.world(), &context.geodata, Instant::now());
+    }
+}
+
+pub fn update(world: &mut World, geodata: &Geodata, now: Instant) {
+    for obj_id in world.moving() {
+        let (progress, destination) = match world.get_mut(obj_id) {
+            Some(object) => {
+                let speed = object.move_speed();
+                match object.movement_mut() {
+                    Some(movement) => (movement.advance(speed, now), movement.destination),
+                    None => continue,
+                }
+            },
+            None => continue,
+        };
+
+        let (x, y, z) = progress.position;
+        let z = geodata.height(x, y, z);
+        world.move_to(obj_id, x, y, z);
+
+        if progress.turned {
+            if let Some(object) = world.get_mut(obj_id) {
+                object.set_heading(heading_to((x, y), (destination.0, destination.1)));
+            }
+            world.broadcast_with_self(obj_id, &response::move_to_location(obj_id, destination, (x, y, z)));
+        }
+        // The client stops on its own once it gets there, no StopMove needed.
+        if progress.arrived {
+            world.stop_moving(obj_id);
+        }
+    }
+}
+
+/// Sets an object on its way along a path and shows it to everyone around, itself included.
+pub fn walk(world: &mut World, obj_id: u32, from: (i32, i32, i32), path: Vec<(i32, i32, i32)>, now: Instant) {
+    let first = match path.first() {
+        Some(first) => *first,
+        None => return,
+    };
+    if let Some(object) = world.get_mut(obj_id) {
+        object.set_heading(heading_to((from.0, from.1), (first.0, first.1)));
+    }
+    world.start_moving(obj_id, Movement::along(from, path, now));
+    world.broadcast_with_self(obj_id, &response::move_to_location(obj_id, first, from));
+}
+
+/// Searches the paths asked for during a tick once the world is released, a search can take long enough to
+/// hold up every other task. The objects still after them set out.
+pub fn find_routes(context: &Context) {
+    let routes = context.world().take_routes();
+    if routes.is_empty() {
+        return;
+    }
+    let found: Vec<_> = routes.into_iter()
+        .map(|(obj_id, route)| (obj_id, route, context.pathfinder.find_path(&context.geodata, route.from, route.to)))
+        .collect();
+
+    let mut world = context.world();
+    let now = Instant::now();
+    for (obj_id, route, path) in found {
+        match route.goal {
+            RouteGoal::Chase(target_id) => combat::chase_along(&mut world, obj_id, target_id, route.from, path, now),
+            RouteGoal::Home => ai::head_home(&mut world, obj_id, route.from, path, now),
+        }
+    }
+}
+
+/// Stops an object where the server has it and tells everyone around, itself included.
+pub fn stop(world: &mut World, obj_id: u32) {
+    world.stop_moving(obj_id);
+    if let Some(object) = world.get(obj_id) {
+        let (x, y, z) = object.position();
+        world.broadcast_with_self(obj_id, &response::stop_move(obj_id, x, y, z, object.heading()));
+    }
+}
+
+pub async fn move_backward_to_location(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let request = request::new_move_to_location(data)?;
+    if !request.mouse {
+        client.send(action_failed());
+        return Ok(());
+    }
+
+    let origin = match context.world().player(obj_id) {
+        Some(player) if player.sitting || player.teleporting || player.is_dead() || player.cast.is_some() => None,
+        Some(player) => Some(player.position()),
+        None => return Err(format!("Player {} of {} is not in the world", obj_id, client.account_name)),
+    };
+    let origin = match origin {
+        Some(origin) if distance_2d((origin.0, origin.1), (request.target.0, request.target.1)) <= MAX_MOVE_DISTANCE => origin,
+        _ => {
+            client.send(action_failed());
+            return Ok(());
+        }
+    };
+
+    // The path is searched without holding the world, a wall in the way means going around it and when there
+    // is no way around, or the client clicks faster than searches are allowed, the player walks up to the wall.
+    let reached = context.geodata.move_check(origin, request.target);
+    let now = Instant::now();
+    let path = if (reached.0, reached.1) == (request.target.0, request.target.1) {
+        vec![reached]
+    } else if may_search(client.last_path_search, now) {
+        client.last_path_search = Some(now);
+        match context.pathfinder.find_path(&context.geodata, origin, request.target) {
+            Some(path) => path,
+            None => vec![reached],
+        }
+    } else {
+        vec![reached]
+    };
+    if path.len() == 1 && (path[0].0, path[0].1) == (origin.0, origin.1) {
+        client.send(action_failed());
+        return Ok(());
+    }
+
+    let mut world = context.world();
+    let origin = match world.get_mut(obj_id) {
+        Some(object) => {
+            let origin = object.position();
+            object.set_heading(heading_to((origin.0, origin.1), (path[0].0, path[0].1)));
+            origin
+        },
+        None => return Ok(()),
+    };
+    let first = path[0];
+    combat::stop_attack(&mut world, obj_id);
+    world.start_moving(obj_id, Movement::along(origin, path, now));
+    world.broadcast_with_self(obj_id, &response::move_to_location(obj_id, first, origin));
+    Ok(())
+}
+
+/// Whether a client that last had a path searched at `last` can have another one.
+fn may_search(last: Option<Instant>, now: Instant) -> bool {
+    last.is_none_or(|last| now.duration_since(last) >= PATH_SEARCH_INTERVAL)
+}
+
+/// How far a position reported by the client is from the server one.
+#[derive(Debug, PartialEq)]
+enum Drift {
+    /// Close enough to be the client's rounding, or lag when moving.
+    Accepted,
+    /// Too far, the client is put back.
+    Corrected,
+    /// Twice too far, more than lag explains.
+    Suspicious,
+}
+
+/// Compares a reported position with the server one, `speed` being the speed of a moving object.
+fn drift(server: (i32, i32), reported: (i32, i32), speed: Option<f64>) -> Drift {
+    let allowed = match speed {
+        Some(speed) => MAX_DRIFT + speed * MAX_LAG,
+        None => MAX_DRIFT,
+    };
+    let distance = distance_2d(server, reported);
+    if distance <= allowed {
+        Drift::Accepted
+    } else if distance <= allowed * 2.0 {
+        Drift::Corrected
+    } else {
+        Drift::Suspicious
+    }
+}
+
+/// Height a standing object at `server` is put at from a reported one, `None` when the report is too far
+/// from the server height. The ground is looked up on the layer the server has the object on, so a client
+/// can't step onto another floor, and the reported height is only taken where there is no geodata.
+fn reported_height(geodata: &Geodata, server: (i32, i32, i32), reported_z: i32) -> Option<i32> {
+    let (x, y, z) = server;
+    if (reported_z - z).abs() > MAX_Z_DRIFT {
+        return None;
+    }
+    let (cell_x, cell_y) = cell_of(x, y);
+    if geodata.has_cell(cell_x, cell_y) { Some(geodata.height(x, y, z)) } else { Some(reported_z) }
+}
+
+/// The client reports where it thinks it is. Small differences are the client's rounding and are left alone,
+/// anything farther than the object could have moved is corrected back to the server position.
+pub async fn validate_position(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let reported = request::new_position(data)?;
+
+    let mut world = context.world();
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} of {} is not in the world", obj_id, client.account_name)),
+    };
+    // Until it appears the client still reports where it was before the teleport.
+    if player.teleporting {
+        return Ok(());
+    }
+
+    let (x, y, z) = player.position();
+    let moving = player.movement.is_some();
+    let speed = if moving { Some(player.move_speed()) } else { None };
+
+    match drift((x, y), (reported.x, reported.y), speed) {
+        Drift::Accepted => {},
+        drift => {
+            if drift == Drift::Suspicious {
+                let distance = distance_2d((x, y), (reported.x, reported.y));
+                warn!("{} is {:.0} units away from the server position, possible speed hack", player.character.char_name, distance);
+            }
+            player.send(response::validate_location(obj_id, x, y, z, player.character.heading));
+            return Ok(());
+        }
+    }
+
+    player.character.heading = reported.heading;
+    if !moving {
+        if let Some(height) = reported_height(&context.geodata, (x, y, z), reported.z) {
+            world.move_to(obj_id, x, y, height);
+        }
+    }
+    Ok(())
+}
+
+/// Where an object reported blocked at `reported` is stopped. The client is only followed up to what the
+/// geodata lets it walk to from the server position, and never for the height.
+fn blocked_position(geodata: &Geodata, server: (i32, i32, i32), reported: (i32, i32)) -> (i32, i32, i32) {
+    if distance_2d((server.0, server.1), reported) > MAX_DRIFT {
+        return server;
+    }
+    let (x, y, z) = geodata.move_check(server, (reported.0, reported.1, server.2));
+    (x, y, geodata.height(x, y, z))
+}
+
+/// Sent by the client when it can't go further, usually blocked by something only it knows about.
+pub async fn cannot_move_anymore(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let reported = request::new_position(data)?;
+
+    let mut world = context.world();
+    let server = match world.get(obj_id) {
+        Some(object) => object.position(),
+        None => return Err(format!("Player {} of {} is not in the world", obj_id, client.account_name)),
+    };
+
+    let (x, y, z) = blocked_position(&context.geodata, server, (reported.x, reported.y));
+    if (x, y, z) != server {
+        world.move_to(obj_id, x, y, z);
+        if let Some(player) = world.player_mut(obj_id) {
+            player.character.heading = reported.heading;
+        }
+    }
+    stop(&mut world, obj_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameserver::geodata::synthetic::{block, complex_block, geodata, multilayer_block, wall_block, GROUND};
+    use crate::gameserver::geodata::ALL;
+
+    #[test]
+    fn drift_allows_more_while_moving() {
+        assert_eq!(drift((0, 0), (150, 0), None), Drift::Accepted);
+        assert_eq!(drift((0, 0), (151, 0), None), Drift::Corrected);
+        assert_eq!(drift((0, 0), (301, 0), None), Drift::Suspicious);
+        assert_eq!(drift((0, 0), (250, 0), Some(120.0)), Drift::Accepted);
+        assert_eq!(drift((0, 0), (280, 0), Some(120.0)), Drift::Corrected);
+        assert_eq!(drift((0, 0), (0, 541), Some(120.0)), Drift::Suspicious);
+    }
+
+    #[test]
+    fn reported_height_is_trusted_near_the_server_one() {
+        // Geodata under the object wins.
+        let hills = geodata(&[(block(0, 0), complex_block(-40, |_, _| ALL))]);
+        assert_eq!(reported_height(&hills, (8, 8, 0), 100), Some(-40));
+        assert_eq!(reported_height(&hills, (8, 8, 0), 1000), None);
+        // Without geodata the client only moves the object a little.
+        let none = Geodata::empty();
+        assert_eq!(reported_height(&none, (8, 8, 0), 180), Some(180));
+        assert_eq!(reported_height(&none, (8, 8, 0), 500), None);
+    }
+
+    #[test]
+    fn reported_height_keeps_the_server_floor() {
+        let bridge = geodata(&[(block(0, 0), multilayer_block(&[160, GROUND]))]);
+        // Closer to the upper floor, the client still stands on the one the server has it on.
+        assert_eq!(reported_height(&bridge, (8, 8, 0), 150), Some(0));
+        assert_eq!(reported_height(&bridge, (8, 8, 160), 10), Some(160));
+        // Nor can it jump farther than the height drift.
+        let tower = geodata(&[(block(0, 0), multilayer_block(&[800, GROUND]))]);
+        assert_eq!(reported_height(&tower, (8, 8, 0), 800), None);
+    }
+
+    #[test]
+    fn blocked_clients_are_not_followed_through_walls() {
+        let geodata = geodata(&[(block(0, 0), wall_block())]);
+
+        // Next to the server position on the same side of the wall the client is followed, on the ground.
+        assert_eq!(blocked_position(&geodata, (8, 8, 0), (40, 8)), (40, 8, 0));
+        // Behind the wall it is stopped in the last cell before it.
+        let (x, y, z) = blocked_position(&geodata, (40, 8, 0), (100, 8));
+        assert!(x < 64, "stopped at {} behind the wall", x);
+        assert_eq!((y, z), (8, 0));
+        // Farther than the drift it stays where the server has it.
+        assert_eq!(blocked_position(&geodata, (8, 8, 0), (300, 8)), (8, 8, 0));
+    }
+
+    #[test]
+    fn path_searches_are_rationed() {
+        let now = Instant::now();
+        assert!(may_search(None, now));
+        assert!(!may_search(Some(now), now + Duration::from_millis(100)));
+        assert!(may_search(Some(now), now + PATH_SEARCH_INTERVAL));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,152 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::gameserver::ai::Ai;
+use crate::gameserver::combat::CombatState;
+use crate::gameserver::datapack::npcs::NpcTemplate;
+use crate::gameserver::movement::Movement;
+use crate::gameserver::skills::{Cast, Effect};
+use crate::gameserver::stats::{self, Calculator, Env, Stat, Stats};
+
+/// Distance a player can talk to an NPC and use its services from.
+pub const INTERACTION_DISTANCE: f64 = 150.0;
+
+/// A non player character while it is in the world.
+pub struct Npc {
+    pub obj_id: u32,
+    /// Template id, the client adds 1000000 to find its own data.
+    pub npc_id: u32,
+    pub name: String,
+    pub title: String,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub heading: i32,
+    pub level: u8,
+    pub attackable: bool,
+    pub cur_hp: f64,
+    pub cur_mp: f64,
+    /// Stat functions of the buffs and debuffs on the NPC.
+    pub calculator: Calculator,
+    pub stats: Stats,
+    /// Collision radius and height.
+    pub collision: (f64, f64),
+    pub running: bool,
+    pub movement: Option<Movement>,
+    /// Objects this NPC currently sees.
+    pub known: HashSet<u32>,
+    /// Index of the spawn that placed it and brings it back once killed, `None` for NPCs spawned by hand.
+    pub spawn: Option<usize>,
+    pub combat: CombatState,
+    pub cast: Option<Cast>,
+    pub effects: Vec<Effect>,
+    /// Where it was spawned, it walks around and goes back there.
+    pub home: (i32, i32, i32),
+    pub ai: Ai,
+    /// Damage taken from each attacker, which shares out the rewards of the kill.
+    pub damage: HashMap<u32, u64>,
+    /// Player that spoiled it, the only one that can sweep the corpse.
+    pub spoiler: Option<u32>,
+    /// Items the corpse holds for its spoiler.
+    pub sweep: Vec<(u32, u64)>,
+}
+
+impl Npc {
+    pub fn new(obj_id: u32, template: &NpcTemplate, (x, y, z): (i32, i32, i32), heading: i32, spawn: Option<usize>) -> Npc {
+        let mut npc = Npc {
+            obj_id,
+            npc_id: template.npc_id,
+            name: template.name.clone(),
+            title: template.title.clone(),
+            x,
+            y,
+            z,
+            heading,
+            level: template.level,
+            attackable: template.is_attackable(),
+            cur_hp: template.stats.hp,
+            cur_mp: template.stats.mp,
+            calculator: Calculator::default(),
+            stats: Stats::default(),
+            collision: template.collision,
+            running: false,
+            movement: None,
+            known: HashSet::new(),
+            spawn,
+            combat: CombatState::default(),
+            cast: None,
+            effects: Vec::new(),
+            home: (x, y, z),
+            ai: Ai::default(),
+            damage: HashMap::new(),
+            spoiler: None,
+            sweep: Vec::new(),
+        };
+        npc.refresh_stats(template);
+        npc
+    }
+
+    /// Recomputes the stats from the template through the stat functions, giving the ones that changed.
+    pub fn refresh_stats(&mut self, template: &NpcTemplate) -> Vec<Stat> {
+        let max_hp = self.stats.get(Stat::MaxHp);
+        let env = Env { weapon: None, hp_ratio: if max_hp > 0.0 { self.cur_hp / max_hp } else { 1.0 } };
+        let stats = self.calculator.compute(&stats::npc_bases(self, template), &env);
+        let changed = stats.changed(&self.stats);
+        self.stats = stats;
+        self.cur_hp = self.cur_hp.min(self.stats.get(Stat::MaxHp));
+        self.cur_mp = self.cur_mp.min(self.stats.get(Stat::MaxMp));
+        changed
+    }
+
+    pub fn position(&self) -> (i32, i32, i32) {
+        (self.x, self.y, self.z)
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.cur_hp <= 0.0
+    }
+
+    /// Whether a player standing at `(x, y)` is close enough to use this NPC.
+    pub fn is_within_reach(&self, (x, y): (i32, i32)) -> bool {
+        ((self.x - x) as f64).hypot((self.y - y) as f64) <= INTERACTION_DISTANCE
+    }
+
+    /// Units per second at the current move type.
+    pub fn move_speed(&self) -> f64 {
+        self.stats.get(if self.running { Stat::RunSpeed } else { Stat::WalkSpeed })
+    }
+}
+
+/// NPCs for the tests of the modules that deal with them.
+#[cfg(test)]
+pub mod synthetic {
+    use super::*;
+    use crate::gameserver::datapack::npcs::{AiType, NpcStats, NpcType};
+    use crate::gameserver::world::WorldObject;
+
+    pub fn template() -> NpcTemplate {
+        NpcTemplate {
+            npc_id: 1,
+            name: "Gremlin".to_string(),
+            title: String::new(),
+            npc_type: NpcType::Monster,
+            ai: AiType::None,
+            level: 1,
+            stats: NpcStats {
+                hp: 100.0, mp: 50.0, p_atk: 10, m_atk: 10, p_def: 10, m_def: 10, p_atk_spd: 300, m_atk_spd: 333,
+                run_speed: 100, walk_speed: 50, attack_range: 0, aggro_range: 0,
+            },
+            faction: None,
+            collision: (10.0, 20.0),
+            exp: 0,
+            sp: 0,
+            skills: Vec::new(),
+            drops: Vec::new(),
+            spoil: Vec::new(),
+        }
+    }
+
+    /// A monster standing at `(x, y)` on the ground, ready to go in the world.
+    pub fn npc(obj_id: u32, x: i32, y: i32) -> WorldObject {
+        WorldObject::Npc(Box::new(Npc::new(obj_id, &template(), (x, y, 0), 0, None)))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,304 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::gameserver::geodata::{self, Geodata, Layer};
+
+/// Most cells looked at by a single search, a target farther than that around obstacles is unreachable.
+const MAX_NODES: usize = 20_000;
+/// Searches kept, the oldest one is dropped first.
+const CACHE_SIZE: usize = 1024;
+
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+const NEIGHBOURS: [(i32, i32); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+/// A cell and the layer of it the path goes through.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct Node {
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+struct Visit {
+    node: Node,
+    layer: Layer,
+    cost: u32,
+    parent: Option<usize>,
+}
+
+type Path = Vec<(i32, i32, i32)>;
+
+/// Start and end nodes of a search, geodata never changes so a path between them stays valid.
+type CacheKey = (Node, Node);
+/// Paths found by their search, along with the order they were found in.
+type Cache = (HashMap<CacheKey, Option<Path>>, VecDeque<CacheKey>);
+
+/// A* over the geodata cells, for monsters chasing around obstacles and for click-to-move.
+pub struct Pathfinder {
+    cache: Mutex<Cache>,
+}
+
+/// Octile distance between two cells, never more than the real cost so A* stays exact.
+fn estimate(from: Node, to: Node) -> u32 {
+    let dx = (from.x - to.x).unsigned_abs();
+    let dy = (from.y - to.y).unsigned_abs();
+    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+}
+
+/// Layer reached from `node` one cell away, diagonals need both corner cells to be walkable as well.
+fn neighbour(geodata: &Geodata, node: Node, layer: Layer, (dx, dy): (i32, i32)) -> Option<Layer> {
+    let current = (node.x, node.y);
+    if dx == 0 || dy == 0 {
+        return geodata.step(current, layer, (node.x + dx, node.y + dy));
+    }
+
+    let through_x = geodata.step(current, layer, (node.x + dx, node.y))?;
+    let through_y = geodata.step(current, layer, (node.x, node.y + dy))?;
+    let end = geodata.step((node.x + dx, node.y), through_x, (node.x + dx, node.y + dy))?;
+    geodata.step((node.x, node.y + dy), through_y, (node.x + dx, node.y + dy))?;
+    Some(end)
+}
+
+impl Pathfinder {
+    pub fn new() -> Pathfinder {
+        Pathfinder { cache: Mutex::new((HashMap::new(), VecDeque::new())) }
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        match self.cache.lock() {
+            Ok(cache) => cache,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Waypoints from `from` to `to`, the start excluded and `to` last. `None` when there is no way there
+    /// within the node limit. Outside the geodata the way is a straight line.
+    pub fn find_path(&self, geodata: &Geodata, from: (i32, i32, i32), to: (i32, i32, i32)) -> Option<Path> {
+        let start_cell = geodata::cell_of(from.0, from.1);
+        let end_cell = geodata::cell_of(to.0, to.1);
+        let (start_layer, end_layer) = match (
+            geodata.nearest_layer(start_cell.0, start_cell.1, from.2),
+            geodata.nearest_layer(end_cell.0, end_cell.1, to.2),
+        ) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return Some(vec![to]),
+        };
+
+        let start = Node { x: start_cell.0, y: start_cell.1, z: start_layer.height };
+        let end = Node { x: end_cell.0, y: end_cell.1, z: end_layer.height };
+        if let Some(path) = self.cache().0.get(&(start, end)) {
+            return path.clone().map(|path| finish(path, to, end.z));
+        }
+
+        let path = search(geodata, start, start_layer, end).0.map(|cells| smooth(geodata, from, cells));
+
+        let mut cache = self.cache();
+        let (paths, order) = &mut *cache;
+        if paths.insert((start, end), path.clone()).is_none() {
+            order.push_back((start, end));
+            if order.len() > CACHE_SIZE {
+                if let Some(oldest) = order.pop_front() {
+                    paths.remove(&oldest);
+                }
+            }
+        }
+        path.map(|path| finish(path, to, end.z))
+    }
+}
+
+/// Ends a path on the exact target position instead of the center of its cell.
+fn finish(mut path: Path, to: (i32, i32, i32), z: i32) -> Path {
+    path.pop();
+    path.push((to.0, to.1, z));
+    path
+}
+
+/// A* from `start` to `end`, returns the cells walked through, `start` excluded, and how many cells were
+/// looked at.
+fn search(geodata: &Geodata, start: Node, start_layer: Layer, end: Node) -> (Option<Vec<Node>>, usize) {
+    let mut visits = vec![Visit { node: start, layer: start_layer, cost: 0, parent: None }];
+    let mut best: HashMap<Node, usize> = HashMap::from([(start, 0)]);
+    let mut open = BinaryHeap::from([Reverse((estimate(start, end), 0usize))]);
+    let mut closed = 0;
+
+    while let Some(Reverse((_, index))) = open.pop() {
+        let (node, layer, cost) = (visits[index].node, visits[index].layer, visits[index].cost);
+        // A cheaper way to this node was found after this entry was queued.
+        if best.get(&node) != Some(&index) {
+            continue;
+        }
+
+        if (node.x, node.y) == (end.x, end.y) && (node.z - end.z).abs() <= geodata::MAX_CLIMB {
+            let mut cells = Vec::new();
+            let mut current = Some(index);
+            while let Some(visit) = current.map(|index| &visits[index]) {
+                cells.push(visit.node);
+                current = visit.parent;
+            }
+            cells.pop();
+            cells.reverse();
+            return (Some(cells), closed);
+        }
+
+        closed += 1;
+        if closed >= MAX_NODES {
+            break;
+        }
+
+        for direction in NEIGHBOURS {
+            let next_layer = match neighbour(geodata, node, layer, direction) {
+                Some(next_layer) => next_layer,
+                None => continue,
+            };
+            let next = Node { x: node.x + direction.0, y: node.y + direction.1, z: next_layer.height };
+            let next_cost = cost + if direction.0 == 0 || direction.1 == 0 { STRAIGHT_COST } else { DIAGONAL_COST };
+            if best.get(&next).is_some_and(|known| visits[*known].cost <= next_cost) {
+                continue;
+            }
+
+            visits.push(Visit { node: next, layer: next_layer, cost: next_cost, parent: Some(index) });
+            best.insert(next, visits.len() - 1);
+            open.push(Reverse((next_cost + estimate(next, end), visits.len() - 1)));
+        }
+    }
+    (None, closed)
+}
+
+/// Turns the cells of a path into world waypoints, skipping every cell that can be walked past in a straight
+/// line so the client gets as few segments as possible.
+fn smooth(geodata: &Geodata, from: (i32, i32, i32), cells: Vec<Node>) -> Path {
+    let points: Vec<(i32, i32, i32)> = cells.iter()
+        .map(|node| {
+            let (x, y) = geodata::world_of(node.x, node.y);
+            (x, y, node.z)
+        })
+        .collect();
+
+    let mut path = Vec::new();
+    let mut current = from;
+    let mut index = 0;
+    while index < points.len() {
+        // Farthest point still in a straight walkable line, the next cell always is.
+        let mut farthest = index;
+        while farthest + 1 < points.len() && geodata.can_move_to(current, points[farthest + 1]) {
+            farthest += 1;
+        }
+        current = points[farthest];
+        path.push(current);
+        index = farthest + 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameserver::geodata::synthetic::*;
+    use crate::gameserver::geodata::{ALL, EAST, NORTH, SOUTH, WEST};
+
+    /// Wall along block column 12 from the top of the region down to block row 11, so going from one side to
+    /// the other means walking around its end at y 1536.
+    fn long_wall() -> Geodata {
+        let wall = (0..12).map(|block_y| (block(12, block_y), wall_block())).collect::<Vec<_>>();
+        geodata(&wall)
+    }
+
+    /// Walls around the block at 40,40, nothing gets in or out of it.
+    fn closed_room() -> Geodata {
+        let room = complex_block(GROUND, |cell_x, cell_y| {
+            let mut nswe = ALL;
+            if cell_x == 0 {
+                nswe &= !WEST;
+            }
+            if cell_x == 7 {
+                nswe &= !EAST;
+            }
+            if cell_y == 0 {
+                nswe &= !NORTH;
+            }
+            if cell_y == 7 {
+                nswe &= !SOUTH;
+            }
+            nswe
+        });
+        let mut blocks = vec![(block(40, 40), room)];
+        // The cells around the room can't step into it either.
+        blocks.push((block(39, 40), complex_block(GROUND, |cell_x, _| if cell_x == 7 { ALL & !EAST } else { ALL })));
+        blocks.push((block(41, 40), complex_block(GROUND, |cell_x, _| if cell_x == 0 { ALL & !WEST } else { ALL })));
+        blocks.push((block(40, 39), complex_block(GROUND, |_, cell_y| if cell_y == 7 { ALL & !SOUTH } else { ALL })));
+        blocks.push((block(40, 41), complex_block(GROUND, |_, cell_y| if cell_y == 0 { ALL & !NORTH } else { ALL })));
+        geodata(&blocks)
+    }
+
+    fn walkable(geodata: &Geodata, from: (i32, i32, i32), path: &Path) -> bool {
+        let mut current = from;
+        for point in path {
+            if !geodata.can_move_to(current, *point) {
+                return false;
+            }
+            current = *point;
+        }
+        true
+    }
+
+    #[test]
+    fn straight_line_on_open_ground() {
+        let geodata = geodata(&[]);
+        let path = Pathfinder::new().find_path(&geodata, (100, 100, 0), (900, 500, 0)).unwrap();
+        assert_eq!(path, vec![(900, 500, 0)]);
+    }
+
+    #[test]
+    fn goes_around_walls() {
+        let geodata = long_wall();
+        let (from, to) = ((1500, 1000, 0), (1700, 1000, 0));
+        assert!(!geodata.can_move_to(from, to));
+
+        let path = Pathfinder::new().find_path(&geodata, from, to).unwrap();
+        assert_eq!(path.last(), Some(&to));
+        assert!(path.iter().any(|point| point.1 >= 1536));
+        assert!(walkable(&geodata, from, &path));
+    }
+
+    #[test]
+    fn unreachable_target() {
+        let geodata = closed_room();
+        let target = (40 * 128 + 64, 40 * 128 + 64, 0);
+        assert_eq!(Pathfinder::new().find_path(&geodata, (4000, 4000, 0), target), None);
+
+        let start = geodata::cell_of(4000, 4000);
+        let end = geodata::cell_of(target.0, target.1);
+        let layer = geodata.nearest_layer(start.0, start.1, 0).unwrap();
+        let (path, closed) = search(&geodata, Node { x: start.0, y: start.1, z: 0 }, layer, Node { x: end.0, y: end.1, z: 0 });
+        assert!(path.is_none());
+        assert!(closed <= MAX_NODES);
+    }
+
+    #[test]
+    fn cached_paths_end_on_the_target() {
+        let geodata = long_wall();
+        let pathfinder = Pathfinder::new();
+        let first = pathfinder.find_path(&geodata, (1500, 1000, 0), (1700, 1000, 0)).unwrap();
+        let second = pathfinder.find_path(&geodata, (1501, 1001, 0), (1702, 1003, 0)).unwrap();
+        assert_eq!(pathfinder.cache().0.len(), 1);
+        assert_eq!(first[..first.len() - 1], second[..second.len() - 1]);
+        assert_eq!(second.last(), Some(&(1702, 1003, 0)));
+    }
+
+    /// Uncached searches around the long wall stay well within the node limit.
+    #[test]
+    fn searches_around_a_wall_stay_within_budget() {
+        let geodata = long_wall();
+        for run in 0..10 {
+            let from = geodata::cell_of(1500, 200 + run * 50);
+            let to = geodata::cell_of(1700, 200 + run * 50);
+            let layer = geodata.nearest_layer(from.0, from.1, 0).unwrap();
+            let (path, closed) = search(&geodata, Node { x: from.0, y: from.1, z: 0 }, layer, Node { x: to.0, y: to.1, z: 0 });
+            assert!(path.is_some());
+            assert!(closed < MAX_NODES / 2, "{} cells searched", closed);
+        }
+    }
+}
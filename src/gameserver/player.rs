@@ -0,0 +1,299 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Instant;
+
+use crate::database::characters::Character;
+use crate::database::clans::Clan;
+use crate::gameserver::combat::CombatState;
+use crate::gameserver::datapack::classes::ClassTemplate;
+use crate::gameserver::datapack::items::{ItemRegistry, WeaponStats};
+use crate::gameserver::html::Dialog;
+use crate::gameserver::inventory::Inventory;
+use crate::gameserver::models::{self, Sender};
+use crate::gameserver::movement::Movement;
+use crate::gameserver::skills::{Cast, Effect};
+use crate::gameserver::stats::{self, Calculator, Env, Source, Stat, Stats};
+use crate::gameserver::store::{StoreKind, StoreList};
+use crate::gameserver::trade::Trade;
+use crate::gameserver::warehouse::OpenWarehouse;
+
+/// What the worn items add to the stats of the class.
+#[derive(Default, Clone, Copy)]
+pub struct EquipmentStats {
+    /// The weapon replaces the bare hands attack of the class.
+    pub weapon: Option<WeaponStats>,
+    pub p_def: u32,
+    pub m_def: u32,
+    /// P.Def of the shield, only added to the hits it blocks.
+    pub shield: Option<u32>,
+}
+
+/// A character while it is in the world.
+pub struct Player {
+    pub character: Character,
+    pub inventory: Inventory,
+    pub equipment: EquipmentStats,
+    pub sender: Sender,
+    /// Objects this player currently sees.
+    pub known: HashSet<u32>,
+    /// Items on the ground this player currently sees.
+    pub known_items: HashSet<u32>,
+    /// Stat functions of the buffs, passives and items of the player.
+    pub calculator: Calculator,
+    pub stats: Stats,
+    /// Collision radius and height.
+    pub collision: (f64, f64),
+    pub running: bool,
+    pub sitting: bool,
+    pub movement: Option<Movement>,
+    /// Characters this player ignores, by object id with their names.
+    pub blocked: HashMap<u32, String>,
+    /// Refuses every whisper, not only the ones of blocked characters.
+    pub message_refusal: bool,
+    /// Unix time in milliseconds at which the chat ban ends, 0 for a ban that never ends.
+    pub chat_ban: Option<i64>,
+    /// When the player last spoke on each chat channel, for flood control.
+    pub last_chat: HashMap<u32, Instant>,
+    pub clan: Option<Clan>,
+    /// `CP_*` bits of what the player may do in its clan, the leader may do everything.
+    pub clan_privileges: u32,
+    /// Private warehouse, loaded the first time the player opens it.
+    pub warehouse: Option<Inventory>,
+    /// Warehouse window a keeper opened for the player, the only one it can move items to or from.
+    pub open_warehouse: Option<OpenWarehouse>,
+    pub trade: Option<Trade>,
+    /// Player asking this one to trade, with when the request lapses.
+    pub trade_request: Option<(u32, Instant)>,
+    pub sell_list: StoreList,
+    pub buy_list: StoreList,
+    pub manufacture_list: StoreList,
+    /// Private store the player sits in, if any.
+    pub store: Option<StoreKind>,
+    /// Left in the world with its store open after its client went away.
+    pub offline: bool,
+    /// Object the player last clicked on.
+    pub target: Option<u32>,
+    /// Last NPC dialog shown, whose bypasses the player may send.
+    pub dialog: Option<Dialog>,
+    /// NPC whose shop window the player has open.
+    pub merchant: Option<u32>,
+    /// Teleported, waiting for the client to finish loading and send Appearing.
+    pub teleporting: bool,
+    pub combat: CombatState,
+    /// Object id of the weapon a soulshot is loaded in, spent by its next swing.
+    pub soulshot: Option<u32>,
+    /// Levels of the skills the player knows, by skill id.
+    pub skills: BTreeMap<u32, u32>,
+    /// Unix time in milliseconds each skill used lately can be used again at, by skill id.
+    pub reuse: HashMap<u32, i64>,
+    pub cast: Option<Cast>,
+    pub effects: Vec<Effect>,
+    /// Item on the ground the player walks to, picked up once in reach.
+    pub pickup: Option<u32>,
+}
+
+impl Player {
+    pub fn new(character: Character, template: &ClassTemplate, inventory: Inventory, sender: Sender) -> Player {
+        let collision = template.collision[character.sex.min(1) as usize];
+        Player {
+            character,
+            inventory,
+            equipment: EquipmentStats::default(),
+            sender,
+            known: HashSet::new(),
+            known_items: HashSet::new(),
+            calculator: Calculator::default(),
+            stats: Stats::default(),
+            collision,
+            running: true,
+            sitting: false,
+            movement: None,
+            blocked: HashMap::new(),
+            message_refusal: false,
+            chat_ban: None,
+            last_chat: HashMap::new(),
+            clan: None,
+            clan_privileges: 0,
+            warehouse: None,
+            open_warehouse: None,
+            trade: None,
+            trade_request: None,
+            sell_list: StoreList::default(),
+            buy_list: StoreList::default(),
+            manufacture_list: StoreList::default(),
+            store: None,
+            offline: false,
+            target: None,
+            dialog: None,
+            merchant: None,
+            teleporting: false,
+            combat: CombatState::default(),
+            soulshot: None,
+            skills: BTreeMap::new(),
+            reuse: HashMap::new(),
+            cast: None,
+            effects: Vec::new(),
+            pickup: None,
+        }
+    }
+
+    pub fn is_gm(&self) -> bool {
+        self.character.access_level > 0
+    }
+
+    /// Whether this player ignores what `speaker` says. GMs can't be ignored.
+    pub fn ignores(&self, speaker: &Player) -> bool {
+        !speaker.is_gm() && self.blocked.contains_key(&speaker.obj_id())
+    }
+
+    pub fn is_chat_banned(&self, now: i64) -> bool {
+        match self.chat_ban {
+            Some(expires) => expires == 0 || expires > now,
+            None => false,
+        }
+    }
+
+    pub fn has_clan_privilege(&self, privilege: u32) -> bool {
+        match &self.clan {
+            Some(clan) => clan.leader_id == self.obj_id() || self.clan_privileges & privilege == privilege,
+            None => false,
+        }
+    }
+
+    /// Recomputes what the worn items give, and the stats with it. Every equipment change goes through here.
+    pub fn refresh_equipment(&mut self, templates: &ItemRegistry, class: &ClassTemplate) {
+        let mut equipment = EquipmentStats::default();
+        self.calculator.remove_items();
+        for item in self.inventory.equipped() {
+            let template = match templates.get(item.item_id) {
+                Some(template) => template,
+                None => continue,
+            };
+            for func in &template.stats {
+                self.calculator.add(func.to_func(Source::Item(item.object_id)));
+            }
+            if template.weapon.is_some() {
+                equipment.weapon = template.weapon;
+            }
+            match template.armor {
+                Some(armor) if item.loc_data == models::PAPERDOLL_LHAND => equipment.shield = Some(armor.p_def),
+                Some(armor) => {
+                    equipment.p_def += armor.p_def;
+                    equipment.m_def += armor.m_def;
+                },
+                None => {},
+            }
+        }
+        self.equipment = equipment;
+        self.refresh_stats(class);
+    }
+
+    /// Recomputes the stats from the class, level and equipment through the stat functions, giving the ones
+    /// that changed. Max HP, MP and CP are kept on the character, which is stored with them.
+    pub fn refresh_stats(&mut self, class: &ClassTemplate) -> Vec<Stat> {
+        let character = &self.character;
+        let env = Env {
+            weapon: self.equipment.weapon.map(|weapon| weapon.weapon_type),
+            hp_ratio: if character.max_hp > 0.0 { character.cur_hp / character.max_hp } else { 1.0 },
+        };
+        let stats = self.calculator.compute(&stats::player_bases(self, class), &env);
+        let changed = stats.changed(&self.stats);
+        self.stats = stats;
+
+        let character = &mut self.character;
+        character.max_hp = self.stats.get(Stat::MaxHp);
+        character.max_mp = self.stats.get(Stat::MaxMp);
+        character.max_cp = self.stats.get(Stat::MaxCp);
+        character.cur_hp = character.cur_hp.min(character.max_hp);
+        character.cur_mp = character.cur_mp.min(character.max_mp);
+        character.cur_cp = character.cur_cp.min(character.max_cp);
+        changed
+    }
+
+    /// Object id of the weapon in hand.
+    pub fn weapon_object(&self) -> Option<u32> {
+        self.inventory.equipped_in(models::PAPERDOLL_RHAND)
+            .or_else(|| self.inventory.equipped_in(models::PAPERDOLL_LRHAND))
+            .map(|item| item.object_id)
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.character.cur_hp <= 0.0
+    }
+
+    pub fn obj_id(&self) -> u32 {
+        self.character.obj_id
+    }
+
+    pub fn position(&self) -> (i32, i32, i32) {
+        (self.character.x, self.character.y, self.character.z)
+    }
+
+    /// Units per second at the current move type.
+    pub fn move_speed(&self) -> f64 {
+        self.stats.get(if self.running { Stat::RunSpeed } else { Stat::WalkSpeed })
+    }
+
+    pub fn send(&self, packet: Vec<u8>) {
+        self.sender.send(packet);
+    }
+}
+
+/// Players for the tests of the modules that deal with them.
+#[cfg(test)]
+pub mod synthetic {
+    use super::*;
+    use crate::database::items::LOC_INVENTORY;
+    use crate::gameserver::datapack::registry::{self, Datapack};
+    use crate::gameserver::inventory;
+
+    /// The datapack shipped with the server.
+    pub fn datapack() -> Datapack {
+        registry::load("data").unwrap()
+    }
+
+    pub fn character(obj_id: u32, name: &str, x: i32, y: i32) -> Character {
+        Character {
+            obj_id,
+            account_name: name.to_lowercase(),
+            char_name: name.to_string(),
+            level: 1,
+            max_hp: 100.0,
+            cur_hp: 100.0,
+            max_mp: 50.0,
+            cur_mp: 50.0,
+            max_cp: 50.0,
+            cur_cp: 50.0,
+            face: 0,
+            hair_style: 0,
+            hair_color: 0,
+            sex: 0,
+            heading: 0,
+            x,
+            y,
+            z: 0,
+            exp: 0,
+            sp: 0,
+            karma: 0,
+            pvp_kills: 0,
+            pk_kills: 0,
+            title: String::new(),
+            access_level: 0,
+            clan_id: 0,
+            race: 0,
+            class_id: 0,
+            base_class: 0,
+            delete_time: 0,
+            last_access: 0,
+            online: 1,
+            online_time: 0,
+        }
+    }
+
+    /// A human fighter standing at `(x, y)` with an empty inventory and no client.
+    pub fn player(datapack: &Datapack, obj_id: u32, name: &str, x: i32, y: i32) -> Player {
+        let template = datapack.classes.get(0).unwrap();
+        let inventory = Inventory::new(obj_id, LOC_INVENTORY, Vec::new(), inventory::max_slots(0),
+            inventory::max_load(template.stats.con), &datapack.items);
+        Player::new(character(obj_id, name, x, y), template, inventory, Sender::detached())
+    }
+}
\ No newline at end of file
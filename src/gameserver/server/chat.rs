@@ -0,0 +1,11 @@
+use crate::packet::packet::Buffer;
+
+pub fn creature_say(obj_id: u32, channel: u32, name: &str, text: &str) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x4a);
+    buffer.write_uint32(obj_id);
+    buffer.write_uint32(channel);
+    buffer.write_string(name);
+    buffer.write_string(text);
+    buffer.buffer
+}
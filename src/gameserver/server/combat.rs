@@ -0,0 +1,104 @@
+use crate::gameserver::combat::Hit;
+use crate::packet::packet::Buffer;
+
+const HIT_SOULSHOT: u8 = 0x10;
+const HIT_CRITICAL: u8 = 0x20;
+const HIT_SHIELD: u8 = 0x40;
+const HIT_MISS: u8 = 0x80;
+
+fn hit_flags(hit: &Hit) -> u8 {
+    let mut flags = 0;
+    if let Some(grade) = hit.soulshot {
+        // The grade picks the shot animation.
+        flags |= HIT_SOULSHOT | grade as u8;
+    }
+    if hit.critical {
+        flags |= HIT_CRITICAL;
+    }
+    if hit.shield {
+        flags |= HIT_SHIELD;
+    }
+    if hit.miss {
+        flags |= HIT_MISS;
+    }
+    flags
+}
+
+/// A swing with every hit it makes, the first one in front.
+pub fn attack(obj_id: u32, (x, y, z): (i32, i32, i32), hits: &[Hit]) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x05);
+    buffer.write_uint32(obj_id);
+    let (first, others) = match hits.split_first() {
+        Some(split) => split,
+        None => return Vec::new(),
+    };
+    buffer.write_uint32(first.target);
+    buffer.write_uint32(first.damage);
+    buffer.write_uint8(hit_flags(first));
+    buffer.write_int32(x);
+    buffer.write_int32(y);
+    buffer.write_int32(z);
+    buffer.write_uint16(others.len() as u16);
+    for hit in others {
+        buffer.write_uint32(hit.target);
+        buffer.write_uint32(hit.damage);
+        buffer.write_uint8(hit_flags(hit));
+    }
+    buffer.buffer
+}
+
+/// Draws the weapon, the creature stays in its combat stance until AutoAttackStop.
+pub fn auto_attack_start(obj_id: u32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x2b);
+    buffer.write_uint32(obj_id);
+    buffer.buffer
+}
+
+pub fn auto_attack_stop(obj_id: u32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x2c);
+    buffer.write_uint32(obj_id);
+    buffer.buffer
+}
+
+/// Death of a creature. For the player itself it opens the window to go back to town, a sweepable corpse shows
+/// it can be swept.
+pub fn die(obj_id: u32, sweepable: bool) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x06);
+    buffer.write_uint32(obj_id);
+    buffer.write_uint32(0x01); // to village
+    buffer.write_uint32(0x00); // to clan hall
+    buffer.write_uint32(0x00); // to castle
+    buffer.write_uint32(0x00); // to siege headquarters
+    buffer.write_uint32(sweepable as u32);
+    buffer.write_uint32(0x00); // fixed resurrection
+    buffer.buffer
+}
+
+/// A dead creature standing up again.
+pub fn revive(obj_id: u32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x07);
+    buffer.write_uint32(obj_id);
+    buffer.buffer
+}
+
+/// Animation of a skill, soulshots show theirs with it.
+pub fn magic_skill_use(obj_id: u32, target_id: u32, skill_id: u32, level: u32, hit_time: u32, reuse_delay: u32, (x, y, z): (i32, i32, i32)) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x48);
+    buffer.write_uint32(obj_id);
+    buffer.write_uint32(target_id);
+    buffer.write_uint32(skill_id);
+    buffer.write_uint32(level);
+    buffer.write_uint32(hit_time);
+    buffer.write_uint32(reuse_delay);
+    buffer.write_int32(x);
+    buffer.write_int32(y);
+    buffer.write_int32(z);
+    buffer.write_uint16(0x00);
+    buffer.buffer
+}
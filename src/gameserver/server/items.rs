@@ -0,0 +1,142 @@
+use crate::database::items::{self, Item, ItemChange};
+use crate::gameserver::datapack::items::ItemRegistry;
+use crate::gameserver::drops::GroundItem;
+use crate::gameserver::inventory::Inventory;
+use crate::packet::packet::Buffer;
+
+const UPDATE_ADDED: u16 = 1;
+const UPDATE_MODIFIED: u16 = 2;
+const UPDATE_REMOVED: u16 = 3;
+
+fn write_item(buffer: &mut Buffer, item: &Item, templates: &ItemRegistry) {
+    let (type1, type2, body_part) = match templates.get(item.item_id) {
+        Some(template) => (template.type1(), template.type2(), template.body_part),
+        None => (4, 5, 0),
+    };
+    buffer.write_uint16(type1);
+    buffer.write_uint32(item.object_id);
+    buffer.write_uint32(item.item_id);
+    buffer.write_uint32(item.count as u32);
+    buffer.write_uint16(type2);
+    buffer.write_uint16(0x00); // custom type 1
+    buffer.write_uint16((item.loc == items::LOC_PAPERDOLL) as u16);
+    buffer.write_uint32(body_part);
+    buffer.write_uint16(item.enchant_level as u16);
+    buffer.write_uint16(0x00); // custom type 2
+    buffer.write_uint32(0x00); // augmentation
+    buffer.write_int32(-1); // shadow item mana
+}
+
+/// Whole content of an inventory. `show_window` opens the inventory window on the client.
+pub fn item_list(inventory: &Inventory, templates: &ItemRegistry, show_window: bool) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x1b);
+    buffer.write_uint16(show_window as u16);
+    buffer.write_uint16(inventory.slots_used() as u16);
+    for item in inventory.items() {
+        write_item(&mut buffer, item, templates);
+    }
+    buffer.buffer
+}
+
+pub fn inventory_update(changes: &[ItemChange], templates: &ItemRegistry) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x27);
+    buffer.write_uint16(changes.len() as u16);
+    for change in changes {
+        let (kind, item) = match change {
+            ItemChange::Added(item) => (UPDATE_ADDED, item),
+            ItemChange::Modified(item) => (UPDATE_MODIFIED, item),
+            ItemChange::Removed(item) => (UPDATE_REMOVED, item),
+        };
+        buffer.write_uint16(kind);
+        write_item(&mut buffer, item, templates);
+    }
+    buffer.buffer
+}
+
+
+fn write_warehouse_item(buffer: &mut Buffer, item: &Item, templates: &ItemRegistry) {
+    let (type1, type2, body_part) = match templates.get(item.item_id) {
+        Some(template) => (template.type1(), template.type2(), template.body_part),
+        None => (4, 5, 0),
+    };
+    buffer.write_uint16(type1);
+    buffer.write_uint32(item.object_id);
+    buffer.write_uint32(item.item_id);
+    buffer.write_uint32(item.count as u32);
+    buffer.write_uint16(type2);
+    buffer.write_uint16(0x00); // custom type 1
+    buffer.write_uint32(body_part);
+    buffer.write_uint16(item.enchant_level as u16);
+    buffer.write_uint16(0x00); // custom type 2
+    buffer.write_uint16(0x00);
+    buffer.write_uint32(item.object_id);
+    buffer.write_uint64(0x00); // augmentation
+}
+
+fn warehouse_list<'a>(id: u8, warehouse_type: u16, adena: u64, items: impl Iterator<Item = &'a Item>, templates: &ItemRegistry) -> Vec<u8> {
+    let items: Vec<&Item> = items.collect();
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(id);
+    buffer.write_uint16(warehouse_type);
+    buffer.write_uint32(adena as u32);
+    buffer.write_uint16(items.len() as u16);
+    for item in items {
+        write_warehouse_item(&mut buffer, item, templates);
+    }
+    buffer.buffer
+}
+
+/// Items a player can put in a warehouse, `adena` is what the player carries.
+pub fn warehouse_deposit_list<'a>(warehouse_type: u16, adena: u64, items: impl Iterator<Item = &'a Item>, templates: &ItemRegistry) -> Vec<u8> {
+    warehouse_list(0x41, warehouse_type, adena, items, templates)
+}
+
+/// Items a player can take out of a warehouse, `adena` is what the player carries.
+pub fn warehouse_withdrawal_list<'a>(warehouse_type: u16, adena: u64, items: impl Iterator<Item = &'a Item>, templates: &ItemRegistry) -> Vec<u8> {
+    warehouse_list(0x42, warehouse_type, adena, items, templates)
+}
+
+fn write_ground_item(buffer: &mut Buffer, item: &GroundItem) {
+    let (x, y, z) = item.position;
+    buffer.write_uint32(item.obj_id);
+    buffer.write_uint32(item.item_id);
+    buffer.write_int32(x);
+    buffer.write_int32(y);
+    buffer.write_int32(z);
+    buffer.write_uint32(item.stackable as u32);
+    buffer.write_uint32(item.count as u32);
+}
+
+/// An item lying on the ground, for players coming near it.
+pub fn spawn_item(item: &GroundItem) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x0b);
+    write_ground_item(&mut buffer, item);
+    buffer.write_uint32(0x00);
+    buffer.buffer
+}
+
+/// An item falling from `dropper_id` to the ground.
+pub fn drop_item(dropper_id: u32, item: &GroundItem) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x0c);
+    buffer.write_uint32(dropper_id);
+    write_ground_item(&mut buffer, item);
+    buffer.write_uint32(0x01);
+    buffer.buffer
+}
+
+/// A player picking an item up from the ground.
+pub fn get_item(obj_id: u32, item: &GroundItem) -> Vec<u8> {
+    let (x, y, z) = item.position;
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x0d);
+    buffer.write_uint32(obj_id);
+    buffer.write_uint32(item.obj_id);
+    buffer.write_int32(x);
+    buffer.write_int32(y);
+    buffer.write_int32(z);
+    buffer.buffer
+}
\ No newline at end of file
@@ -0,0 +1,187 @@
+use crate::database::characters::Character;
+use crate::database::items::Item;
+use crate::gameserver::models::{self, paperdoll_view};
+use crate::gameserver::datapack::classes::ClassTemplate;
+use crate::packet::packet::Buffer;
+
+pub const CREATE_FAILED: u32 = 0x00;
+pub const CREATE_TOO_MANY_CHARACTERS: u32 = 0x01;
+pub const CREATE_NAME_ALREADY_EXISTS: u32 = 0x02;
+pub const CREATE_INVALID_NAME: u32 = 0x03;
+
+pub const DELETE_FAILED: u32 = 0x01;
+pub const DELETE_CLAN_MEMBER: u32 = 0x02;
+
+/// Paperdoll order used by CharSelectInfo, which differs from the one used in game.
+const SELECT_PAPERDOLL_ORDER: [i32; 16] = [
+    models::PAPERDOLL_FACE, models::PAPERDOLL_REAR, models::PAPERDOLL_LEAR, models::PAPERDOLL_NECK,
+    models::PAPERDOLL_RFINGER, models::PAPERDOLL_LFINGER, models::PAPERDOLL_HEAD, models::PAPERDOLL_RHAND,
+    models::PAPERDOLL_LHAND, models::PAPERDOLL_GLOVES, models::PAPERDOLL_CHEST, models::PAPERDOLL_LEGS,
+    models::PAPERDOLL_FEET, models::PAPERDOLL_BACK, models::PAPERDOLL_LRHAND, models::PAPERDOLL_HAIR,
+];
+
+pub fn key_packet(key: &[u8], accepted: bool) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x00);
+    buffer.write_uint8(accepted as u8);
+    buffer.write(key.to_vec());
+    buffer.write_uint32(0x01);
+    buffer.write_uint32(0x01);
+    buffer.buffer
+}
+
+/// Character list of an account, each one with the items it wears. `active` is the slot selected by default.
+pub fn char_select_info(account_name: &str, session_id: u32, characters: &[(Character, Vec<Item>)], active: Option<usize>, now: i64) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x13);
+    buffer.write_uint32(characters.len() as u32);
+
+    for (slot, (character, paperdoll)) in characters.iter().enumerate() {
+        buffer.write_string(&character.char_name);
+        buffer.write_uint32(character.obj_id);
+        buffer.write_string(account_name);
+        buffer.write_uint32(session_id);
+        buffer.write_uint32(character.clan_id);
+        buffer.write_uint32(0x00);
+        buffer.write_uint32(character.sex as u32);
+        buffer.write_uint32(character.race as u32);
+        buffer.write_uint32(character.base_class as u32);
+        buffer.write_uint32(0x01);
+        buffer.write_int32(character.x);
+        buffer.write_int32(character.y);
+        buffer.write_int32(character.z);
+        buffer.write_float64(character.cur_hp);
+        buffer.write_float64(character.cur_mp);
+        buffer.write_uint32(character.sp);
+        buffer.write_uint64(character.exp);
+        buffer.write_uint32(character.level as u32);
+        buffer.write_uint32(character.karma);
+        for _ in 0..9 {
+            buffer.write_uint32(0x00);
+        }
+
+        let view = paperdoll_view(paperdoll);
+        for slot in SELECT_PAPERDOLL_ORDER {
+            buffer.write_uint32(view[slot as usize].0);
+        }
+        for slot in SELECT_PAPERDOLL_ORDER {
+            buffer.write_uint32(view[slot as usize].1);
+        }
+
+        buffer.write_uint32(character.hair_style as u32);
+        buffer.write_uint32(character.hair_color as u32);
+        buffer.write_uint32(character.face as u32);
+        buffer.write_float64(character.max_hp);
+        buffer.write_float64(character.max_mp);
+
+        // Seconds left before a pending deletion, the client greys out the character when this is not 0.
+        let delete_seconds = if character.delete_time > 0 { ((character.delete_time - now) / 1000).max(1) } else { 0 };
+        buffer.write_uint32(delete_seconds as u32);
+        buffer.write_uint32(character.class_id as u32);
+        buffer.write_uint32((active == Some(slot)) as u32);
+        buffer.write_uint8(0x00);
+        buffer.write_uint32(0x00);
+    }
+
+    buffer.buffer
+}
+
+pub fn char_templates(templates: &[&ClassTemplate]) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x17);
+    buffer.write_uint32(templates.len() as u32);
+    for template in templates {
+        buffer.write_uint32(template.race as u32);
+        buffer.write_uint32(template.class_id as u32);
+        for stat in [template.stats.str, template.stats.dex, template.stats.con, template.stats.int, template.stats.wit, template.stats.men] {
+            buffer.write_uint32(0x46);
+            buffer.write_uint32(stat);
+            buffer.write_uint32(0x0a);
+        }
+    }
+    buffer.buffer
+}
+
+pub fn char_create_ok() -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x19);
+    buffer.write_uint32(0x01);
+    buffer.buffer
+}
+
+pub fn char_create_fail(reason: u32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x1a);
+    buffer.write_uint32(reason);
+    buffer.buffer
+}
+
+pub fn char_delete_ok() -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x23);
+    buffer.buffer
+}
+
+pub fn char_delete_fail(reason: u32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x24);
+    buffer.write_uint32(reason);
+    buffer.buffer
+}
+
+pub fn char_selected(character: &Character, template: &ClassTemplate, session_id: u32, game_time: u32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x15);
+    buffer.write_string(&character.char_name);
+    buffer.write_uint32(character.obj_id);
+    buffer.write_string(&character.title);
+    buffer.write_uint32(session_id);
+    buffer.write_uint32(character.clan_id);
+    buffer.write_uint32(0x00);
+    buffer.write_uint32(character.sex as u32);
+    buffer.write_uint32(character.race as u32);
+    buffer.write_uint32(character.class_id as u32);
+    buffer.write_uint32(0x01);
+    buffer.write_int32(character.x);
+    buffer.write_int32(character.y);
+    buffer.write_int32(character.z);
+    buffer.write_float64(character.cur_hp);
+    buffer.write_float64(character.cur_mp);
+    buffer.write_uint32(character.sp);
+    buffer.write_uint64(character.exp);
+    buffer.write_uint32(character.level as u32);
+    buffer.write_uint32(character.karma);
+    buffer.write_uint32(0x00);
+    for stat in [template.stats.int, template.stats.str, template.stats.con, template.stats.men, template.stats.dex, template.stats.wit] {
+        buffer.write_uint32(stat);
+    }
+    for _ in 0..32 {
+        buffer.write_uint32(0x00);
+    }
+    buffer.write_uint32(game_time);
+    buffer.write_uint32(0x00);
+    buffer.write_uint32(character.class_id as u32);
+    for _ in 0..16 {
+        buffer.write_uint32(0x00);
+    }
+    buffer.buffer
+}
+
+pub fn log_out_ok() -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x7e);
+    buffer.buffer
+}
+
+pub fn restart_response() -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x5f);
+    buffer.write_uint32(0x01);
+    buffer.buffer
+}
+
+pub fn action_failed() -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x25);
+    buffer.buffer
+}
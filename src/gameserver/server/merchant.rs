@@ -0,0 +1,101 @@
+use crate::database::items::Item;
+use crate::gameserver::datapack::items::{ItemRegistry, ItemTemplate};
+use crate::gameserver::datapack::multisell::MultisellEntry;
+use crate::packet::packet::Buffer;
+
+/// Entries of a multisell page.
+pub const MULTISELL_PAGE_SIZE: usize = 40;
+
+/// Items a merchant sells: templates with what is left in stock, `None` when it never runs out, and prices.
+pub fn buy_list(list_id: u32, adena: u64, items: &[(&ItemTemplate, Option<u64>, u64)]) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x11);
+    buffer.write_uint32(adena as u32);
+    buffer.write_uint32(list_id);
+    buffer.write_uint16(items.len() as u16);
+    for (template, stock, price) in items {
+        buffer.write_uint16(template.type1());
+        buffer.write_uint32(template.item_id); // object id, the list has no items of its own
+        buffer.write_uint32(template.item_id);
+        buffer.write_uint32(stock.unwrap_or(0) as u32);
+        buffer.write_uint16(template.type2());
+        buffer.write_uint16(0x00); // custom type 1
+        if template.type1() != 4 {
+            buffer.write_uint32(template.body_part);
+            buffer.write_uint16(0x00); // enchant level
+            buffer.write_uint16(0x00); // custom type 2
+            buffer.write_uint16(0x00);
+        }
+        buffer.write_uint32(*price as u32);
+    }
+    buffer.buffer
+}
+
+/// Items a player can sell to a merchant, with what the merchant pays for each.
+pub fn sell_list(adena: u64, items: &[(&Item, u64)], templates: &ItemRegistry) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x10);
+    buffer.write_uint32(adena as u32);
+    buffer.write_uint32(0x00); // list id
+    buffer.write_uint16(items.len() as u16);
+    for (item, price) in items {
+        let (type1, type2, body_part) = match templates.get(item.item_id) {
+            Some(template) => (template.type1(), template.type2(), template.body_part),
+            None => (4, 5, 0),
+        };
+        buffer.write_uint16(type1);
+        buffer.write_uint32(item.object_id);
+        buffer.write_uint32(item.item_id);
+        buffer.write_uint32(item.count as u32);
+        buffer.write_uint16(type2);
+        buffer.write_uint16(0x00); // custom type 1
+        buffer.write_uint32(body_part);
+        buffer.write_uint16(item.enchant_level as u16);
+        buffer.write_uint16(0x00); // custom type 2
+        buffer.write_uint16(0x00);
+        buffer.write_uint32(*price as u32);
+    }
+    buffer.buffer
+}
+
+/// One page of a multisell list. Entries come with the id the client chooses them by and the enchant level
+/// their equipment keeps.
+pub fn multisell_list(list_id: u32, page: usize, pages: usize, entries: &[(u32, &MultisellEntry, u32)], templates: &ItemRegistry) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0xd0);
+    buffer.write_uint32(list_id);
+    buffer.write_uint32(page as u32 + 1);
+    buffer.write_uint32((page + 1 == pages) as u32);
+    buffer.write_uint32(MULTISELL_PAGE_SIZE as u32);
+    buffer.write_uint32(entries.len() as u32);
+    for (entry_id, entry, enchant_level) in entries {
+        buffer.write_uint32(*entry_id);
+        buffer.write_uint32(0x00);
+        buffer.write_uint32(0x00);
+        buffer.write_uint8(0x01);
+        buffer.write_uint16(entry.products.len() as u16);
+        buffer.write_uint16(entry.ingredients.len() as u16);
+        for product in &entry.products {
+            let template = templates.get(product.item);
+            let enchant = if template.is_some_and(|template| template.is_equipable()) { *enchant_level } else { 0 };
+            buffer.write_uint16(product.item as u16);
+            buffer.write_uint32(template.map_or(0, |template| template.body_part));
+            buffer.write_uint16(template.map_or(5, |template| template.type2()));
+            buffer.write_uint32(product.count as u32);
+            buffer.write_uint16(enchant as u16);
+            buffer.write_uint32(0x00); // augmentation
+            buffer.write_uint32(0x00); // mana
+        }
+        for ingredient in &entry.ingredients {
+            let template = templates.get(ingredient.item);
+            let enchant = if template.is_some_and(|template| template.is_equipable()) { *enchant_level } else { 0 };
+            buffer.write_uint16(ingredient.item as u16);
+            buffer.write_uint16(template.map_or(5, |template| template.type2()));
+            buffer.write_uint32(ingredient.count as u32);
+            buffer.write_uint16(enchant as u16);
+            buffer.write_uint32(0x00); // augmentation
+            buffer.write_uint32(0x00); // mana
+        }
+    }
+    buffer.buffer
+}
@@ -0,0 +1,12 @@
+pub mod lobby;
+pub mod world;
+pub mod movement;
+pub mod system_message;
+pub mod chat;
+pub mod items;
+pub mod trade;
+pub mod store;
+pub mod npc;
+pub mod merchant;
+pub mod combat;
+pub mod skills;
\ No newline at end of file
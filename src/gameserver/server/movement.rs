@@ -0,0 +1,48 @@
+use crate::packet::packet::Buffer;
+
+pub fn move_to_location(obj_id: u32, destination: (i32, i32, i32), origin: (i32, i32, i32)) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x01);
+    buffer.write_uint32(obj_id);
+    buffer.write_int32(destination.0);
+    buffer.write_int32(destination.1);
+    buffer.write_int32(destination.2);
+    buffer.write_int32(origin.0);
+    buffer.write_int32(origin.1);
+    buffer.write_int32(origin.2);
+    buffer.buffer
+}
+
+pub fn stop_move(obj_id: u32, x: i32, y: i32, z: i32, heading: i32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x47);
+    buffer.write_uint32(obj_id);
+    buffer.write_int32(x);
+    buffer.write_int32(y);
+    buffer.write_int32(z);
+    buffer.write_int32(heading);
+    buffer.buffer
+}
+
+/// Puts an object back where the server has it, used to correct a client that drifted away.
+pub fn validate_location(obj_id: u32, x: i32, y: i32, z: i32, heading: i32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x61);
+    buffer.write_uint32(obj_id);
+    buffer.write_int32(x);
+    buffer.write_int32(y);
+    buffer.write_int32(z);
+    buffer.write_int32(heading);
+    buffer.buffer
+}
+
+/// Shows the loading screen and moves the object there, the client answers with Appearing once it is loaded.
+pub fn teleport_to_location(obj_id: u32, x: i32, y: i32, z: i32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x28);
+    buffer.write_uint32(obj_id);
+    buffer.write_int32(x);
+    buffer.write_int32(y);
+    buffer.write_int32(z);
+    buffer.buffer
+}
@@ -0,0 +1,11 @@
+use crate::packet::packet::Buffer;
+
+/// Dialog window of an NPC.
+pub fn npc_html_message(npc_obj_id: u32, html: &str) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x0f);
+    buffer.write_uint32(npc_obj_id);
+    buffer.write_string(html);
+    buffer.write_uint32(0x00); // item id
+    buffer.buffer
+}
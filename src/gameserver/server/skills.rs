@@ -0,0 +1,59 @@
+use crate::packet::packet::Buffer;
+
+/// Skills of the player as id, level and whether it is passive.
+pub fn skill_list(skills: &[(u32, u32, bool)]) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x58);
+    buffer.write_uint32(skills.len() as u32);
+    for (skill_id, level, passive) in skills {
+        buffer.write_uint32(*passive as u32);
+        buffer.write_uint32(*level);
+        buffer.write_uint32(*skill_id);
+        buffer.write_uint8(0x00); // disabled
+    }
+    buffer.buffer
+}
+
+/// A cast ending, with the creatures the skill lands on.
+pub fn magic_skill_launched(obj_id: u32, skill_id: u32, level: u32, targets: &[u32]) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x76);
+    buffer.write_uint32(obj_id);
+    buffer.write_uint32(skill_id);
+    buffer.write_uint32(level);
+    buffer.write_uint32(targets.len() as u32);
+    for target in targets {
+        buffer.write_uint32(*target);
+    }
+    buffer.buffer
+}
+
+pub fn magic_skill_canceld(obj_id: u32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x49);
+    buffer.write_uint32(obj_id);
+    buffer.buffer
+}
+
+/// Blue bar filling up over a cast, in milliseconds.
+pub fn setup_gauge(time: u32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x6d);
+    buffer.write_uint32(0x00); // blue
+    buffer.write_uint32(time);
+    buffer.write_uint32(time);
+    buffer.buffer
+}
+
+/// Icons of the effects on the player as skill id, level and seconds left.
+pub fn abnormal_status_update(effects: &[(u32, u32, u32)]) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x7f);
+    buffer.write_uint16(effects.len() as u16);
+    for (skill_id, level, seconds) in effects {
+        buffer.write_uint32(*skill_id);
+        buffer.write_uint16(*level as u16);
+        buffer.write_uint32(*seconds);
+    }
+    buffer.buffer
+}
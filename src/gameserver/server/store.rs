@@ -0,0 +1,201 @@
+use crate::database::items::{self, Item};
+use crate::gameserver::datapack::items::{ItemRegistry, ADENA};
+use crate::gameserver::player::Player;
+use crate::packet::packet::Buffer;
+
+/// Type, body part and reference price of an item.
+fn template_info(templates: &ItemRegistry, item_id: u32) -> (u16, u32, u32) {
+    match templates.get(item_id) {
+        Some(template) => (template.type2(), template.body_part, template.price as u32),
+        None => (5, 0, 0),
+    }
+}
+
+fn write_sell_item(buffer: &mut Buffer, item: &Item, count: u64, templates: &ItemRegistry) {
+    let (type2, body_part, _) = template_info(templates, item.item_id);
+    buffer.write_uint32(type2 as u32);
+    buffer.write_uint32(item.object_id);
+    buffer.write_uint32(item.item_id);
+    buffer.write_uint32(count as u32);
+    buffer.write_uint16(0x00);
+    buffer.write_uint16(item.enchant_level as u16);
+    buffer.write_uint16(0x00);
+    buffer.write_uint32(body_part);
+}
+
+/// Items of the sell list of a player that are still in its inventory, with the count and price they are
+/// sold at.
+fn listed(player: &Player) -> impl Iterator<Item = (&Item, u64, u64)> {
+    player.sell_list.items.iter()
+        .filter_map(|listed| player.inventory.get(listed.id).map(|item| (item, listed.count, listed.price)))
+}
+
+/// What a player sees while setting up its sell store: what it can sell, then what it already listed.
+pub fn private_store_manage_list_sell(player: &Player, templates: &ItemRegistry) -> Vec<u8> {
+    let available: Vec<&Item> = player.inventory.items()
+        .filter(|item| item.loc != items::LOC_PAPERDOLL && item.item_id != ADENA)
+        .filter(|item| !player.sell_list.items.iter().any(|listed| listed.id == item.object_id))
+        .collect();
+    let listed: Vec<(&Item, u64, u64)> = listed(player).collect();
+
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x9a);
+    buffer.write_uint32(player.obj_id());
+    buffer.write_uint32(player.sell_list.package as u32);
+    buffer.write_uint32(player.inventory.adena() as u32);
+    buffer.write_uint32(available.len() as u32);
+    for item in available {
+        write_sell_item(&mut buffer, item, item.count, templates);
+        buffer.write_uint32(template_info(templates, item.item_id).2);
+    }
+    buffer.write_uint32(listed.len() as u32);
+    for (item, count, price) in listed {
+        write_sell_item(&mut buffer, item, count, templates);
+        buffer.write_uint32(price as u32);
+        buffer.write_uint32(template_info(templates, item.item_id).2);
+    }
+    buffer.buffer
+}
+
+/// What a customer sees of a sell store.
+pub fn private_store_list_sell(store: &Player, customer_adena: u64, templates: &ItemRegistry) -> Vec<u8> {
+    let listed: Vec<(&Item, u64, u64)> = listed(store).collect();
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x9b);
+    buffer.write_uint32(store.obj_id());
+    buffer.write_uint32(store.sell_list.package as u32);
+    buffer.write_uint32(customer_adena as u32);
+    buffer.write_uint32(listed.len() as u32);
+    for (item, count, price) in listed {
+        write_sell_item(&mut buffer, item, count, templates);
+        buffer.write_uint32(price as u32);
+        buffer.write_uint32(template_info(templates, item.item_id).2);
+    }
+    buffer.buffer
+}
+
+fn write_buy_item(buffer: &mut Buffer, item_id: u32, count: u64, templates: &ItemRegistry) {
+    let (type2, body_part, reference_price) = template_info(templates, item_id);
+    buffer.write_uint32(item_id);
+    buffer.write_uint16(0x00);
+    buffer.write_uint32(count as u32);
+    buffer.write_uint32(reference_price);
+    buffer.write_uint16(0x00);
+    buffer.write_uint32(body_part);
+    buffer.write_uint16(type2);
+}
+
+/// What a player sees while setting up its buy store: the items it has to pick from, then what it already
+/// asks for.
+pub fn private_store_manage_list_buy(player: &Player, templates: &ItemRegistry) -> Vec<u8> {
+    let available: Vec<&Item> = player.inventory.items().filter(|item| item.item_id != ADENA).collect();
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0xb7);
+    buffer.write_uint32(player.obj_id());
+    buffer.write_uint32(player.inventory.adena() as u32);
+    buffer.write_uint32(available.len() as u32);
+    for item in available {
+        write_buy_item(&mut buffer, item.item_id, item.count, templates);
+    }
+    buffer.write_uint32(player.buy_list.items.len() as u32);
+    for wanted in &player.buy_list.items {
+        write_buy_item(&mut buffer, wanted.id, wanted.count, templates);
+        buffer.write_uint32(wanted.price as u32);
+        buffer.write_uint32(template_info(templates, wanted.id).2);
+    }
+    buffer.buffer
+}
+
+/// What a customer sees of a buy store: the items it has that the store asks for.
+pub fn private_store_list_buy(store: &Player, customer: &Player, templates: &ItemRegistry) -> Vec<u8> {
+    let offers: Vec<(&Item, u64, u64)> = store.buy_list.items.iter()
+        .flat_map(|wanted| {
+            customer.inventory.items()
+                .filter(move |item| item.item_id == wanted.id && item.loc != items::LOC_PAPERDOLL)
+                .map(move |item| (item, wanted.count, wanted.price))
+        })
+        .collect();
+
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0xb8);
+    buffer.write_uint32(store.obj_id());
+    buffer.write_uint32(customer.inventory.adena() as u32);
+    buffer.write_uint32(offers.len() as u32);
+    for (item, wanted, price) in offers {
+        let (type2, body_part, reference_price) = template_info(templates, item.item_id);
+        buffer.write_uint32(item.object_id);
+        buffer.write_uint32(item.item_id);
+        buffer.write_uint16(item.enchant_level as u16);
+        buffer.write_uint32(item.count as u32);
+        buffer.write_uint32(reference_price);
+        buffer.write_uint16(0x00);
+        buffer.write_uint32(body_part);
+        buffer.write_uint16(type2);
+        buffer.write_uint32(price as u32);
+        buffer.write_uint32(wanted as u32);
+    }
+    buffer.buffer
+}
+
+pub fn private_store_msg_sell(obj_id: u32, message: &str) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x9c);
+    buffer.write_uint32(obj_id);
+    buffer.write_string(message);
+    buffer.buffer
+}
+
+pub fn private_store_msg_buy(obj_id: u32, message: &str) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0xb9);
+    buffer.write_uint32(obj_id);
+    buffer.write_string(message);
+    buffer.buffer
+}
+
+/// What a player sees while setting up its manufacture store: the recipes of its dwarven recipe book, then the
+/// ones it already listed with their fee.
+pub fn recipe_shop_manage_list(player: &Player, recipes: &[u32]) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0xd8);
+    buffer.write_uint32(player.obj_id());
+    buffer.write_uint32(player.inventory.adena() as u32);
+    // Dwarven recipe book.
+    buffer.write_uint32(0x00);
+    buffer.write_uint32(recipes.len() as u32);
+    for (index, recipe) in recipes.iter().enumerate() {
+        buffer.write_uint32(*recipe);
+        buffer.write_uint32(index as u32 + 1);
+    }
+    write_recipes(&mut buffer, player);
+    buffer.buffer
+}
+
+/// What a customer sees of a manufacture store, with the MP the crafter has left for it.
+pub fn recipe_shop_sell_list(store: &Player, customer_adena: u64) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0xd9);
+    buffer.write_uint32(store.obj_id());
+    buffer.write_uint32(store.character.cur_mp as u32);
+    buffer.write_uint32(store.character.max_mp as u32);
+    buffer.write_uint32(customer_adena as u32);
+    write_recipes(&mut buffer, store);
+    buffer.buffer
+}
+
+fn write_recipes(buffer: &mut Buffer, store: &Player) {
+    buffer.write_uint32(store.manufacture_list.items.len() as u32);
+    for listed in &store.manufacture_list.items {
+        buffer.write_uint32(listed.id);
+        buffer.write_uint32(0x00);
+        buffer.write_uint32(listed.price as u32);
+    }
+}
+
+pub fn recipe_shop_msg(obj_id: u32, message: &str) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0xdb);
+    buffer.write_uint32(obj_id);
+    buffer.write_string(message);
+    buffer.buffer
+}
\ No newline at end of file
@@ -0,0 +1,110 @@
+use crate::packet::packet::Buffer;
+
+pub const S1_IS_NOT_ONLINE: u32 = 3;
+pub const TARGET_TOO_FAR: u32 = 22;
+pub const NOT_ENOUGH_HP: u32 = 23;
+pub const NOT_ENOUGH_MP: u32 = 24;
+pub const YOU_DID_S1_DMG: u32 = 35;
+pub const S1_GAVE_YOU_S2_DMG: u32 = 36;
+pub const AVOIDED_S1S_ATTACK: u32 = 42;
+pub const MISSED_TARGET: u32 = 43;
+pub const CRITICAL_HIT: u32 = 44;
+pub const USE_S1: u32 = 46;
+pub const S1_PREPARED_FOR_REUSE: u32 = 48;
+pub const S1_EQUIPPED: u32 = 49;
+pub const EARNED_S1_ADENA: u32 = 52;
+pub const EARNED_S2_S1_S: u32 = 53;
+pub const EARNED_ITEM_S1: u32 = 54;
+pub const FAILED_TO_PICKUP_S1_ADENA: u32 = 55;
+pub const FAILED_TO_PICKUP_S1: u32 = 56;
+pub const EFFECT_S1_DISAPPEARED: u32 = 92;
+pub const YOU_EARNED_S1_EXP_AND_S2_SP: u32 = 95;
+pub const YOU_INCREASED_YOUR_LEVEL: u32 = 96;
+pub const CANT_LOGOUT_WHILE_FIGHTING: u32 = 101;
+pub const CANT_RESTART_WHILE_FIGHTING: u32 = 102;
+pub const YOU_FEEL_S1_EFFECT: u32 = 110;
+pub const SHIELD_DEFENCE_SUCCESSFULL: u32 = 111;
+pub const NOT_ENOUGH_ARROWS: u32 = 112;
+pub const S1_CANNOT_BE_USED: u32 = 113;
+pub const REQUEST_S1_FOR_TRADE: u32 = 118;
+pub const S1_DENIED_TRADE_REQUEST: u32 = 119;
+pub const BEGIN_TRADE_WITH_S1: u32 = 120;
+pub const S1_CONFIRMED_TRADE: u32 = 121;
+pub const TRADE_SUCCESSFUL: u32 = 123;
+pub const S1_CANCELED_TRADE: u32 = 124;
+pub const SLOTS_FULL: u32 = 129;
+pub const TARGET_IS_INCORRECT: u32 = 144;
+pub const TARGET_IS_NOT_FOUND_IN_THE_GAME: u32 = 145;
+pub const S1_IS_BUSY_TRY_LATER: u32 = 153;
+pub const THE_PERSON_IS_IN_MESSAGE_REFUSAL_MODE: u32 = 176;
+pub const MESSAGE_REFUSAL_MODE: u32 = 177;
+pub const MESSAGE_ACCEPTANCE_MODE: u32 = 178;
+pub const CANT_SEE_TARGET: u32 = 181;
+pub const CHATTING_IS_CURRENTLY_PROHIBITED: u32 = 243;
+pub const YOU_NOT_ENOUGH_ADENA: u32 = 279;
+pub const S2_S1_DISAPPEARED: u32 = 301;
+pub const SOULSHOTS_GRADE_MISMATCH: u32 = 337;
+pub const NOT_ENOUGH_SOULSHOTS: u32 = 338;
+pub const CANNOT_USE_SOULSHOTS: u32 = 339;
+pub const ENABLED_SOULSHOT: u32 = 342;
+pub const SWEEPER_FAILED_TARGET_NOT_SPOILED: u32 = 343;
+pub const NOT_ENOUGH_ITEMS: u32 = 351;
+pub const ALREADY_SPOILED: u32 = 357;
+pub const S1_DISARMED: u32 = 417;
+pub const WEIGHT_LIMIT_EXCEEDED: u32 = 422;
+pub const SPOIL_SUCCESS: u32 = 612;
+/// Shows its only parameter as is.
+pub const S1: u32 = 614;
+pub const S1_ADENA_DISAPPEARED: u32 = 672;
+pub const S1_WAS_ADDED_TO_YOUR_IGNORE_LIST: u32 = 617;
+pub const S1_WAS_REMOVED_FROM_YOUR_IGNORE_LIST: u32 = 618;
+pub const S1_HP_RESTORED: u32 = 1066;
+pub const CANNOT_EQUIP_ITEM_DUE_TO_BAD_CONDITION: u32 = 1518;
+
+pub enum Param {
+    Text(String),
+    Number(u32),
+    /// Item id, shown as the item name.
+    Item(u32),
+    /// Skill id and level, shown as the skill name.
+    Skill(u32, u32),
+}
+
+pub fn system_message(id: u32, params: &[Param]) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x64);
+    buffer.write_uint32(id);
+    buffer.write_uint32(params.len() as u32);
+    for param in params {
+        match param {
+            Param::Text(text) => {
+                buffer.write_uint32(0x00);
+                buffer.write_string(text);
+            },
+            Param::Number(number) => {
+                buffer.write_uint32(0x01);
+                buffer.write_uint32(*number);
+            },
+            Param::Item(item_id) => {
+                buffer.write_uint32(0x03);
+                buffer.write_uint32(*item_id);
+            },
+            Param::Skill(skill_id, level) => {
+                buffer.write_uint32(0x04);
+                buffer.write_uint32(*skill_id);
+                buffer.write_uint32(*level);
+            },
+        }
+    }
+    buffer.buffer
+}
+
+/// Message with a single text parameter, such as the name of a character.
+pub fn with_text(id: u32, text: &str) -> Vec<u8> {
+    system_message(id, &[Param::Text(text.to_string())])
+}
+
+/// Free text shown in the system message window.
+pub fn text(message: &str) -> Vec<u8> {
+    with_text(S1, message)
+}
@@ -0,0 +1,79 @@
+use crate::database::items::Item;
+use crate::gameserver::datapack::items::ItemRegistry;
+use crate::packet::packet::Buffer;
+
+fn write_trade_item(buffer: &mut Buffer, item: &Item, count: u64, templates: &ItemRegistry) {
+    let (type1, type2, body_part) = match templates.get(item.item_id) {
+        Some(template) => (template.type1(), template.type2(), template.body_part),
+        None => (4, 5, 0),
+    };
+    buffer.write_uint16(type1);
+    buffer.write_uint32(item.object_id);
+    buffer.write_uint32(item.item_id);
+    buffer.write_uint32(count as u32);
+    buffer.write_uint16(type2);
+    buffer.write_uint16(0x00); // custom type 1
+    buffer.write_uint32(body_part);
+    buffer.write_uint16(item.enchant_level as u16);
+    buffer.write_uint16(0x00); // custom type 2
+    buffer.write_uint16(0x00);
+}
+
+/// Asks a player to trade with `requester_id`.
+pub fn send_trade_request(requester_id: u32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x5e);
+    buffer.write_uint32(requester_id);
+    buffer.buffer
+}
+
+/// Opens the trade window with the items the player can offer.
+pub fn trade_start<'a>(partner_id: u32, items: impl Iterator<Item = &'a Item>, templates: &ItemRegistry) -> Vec<u8> {
+    let items: Vec<&Item> = items.collect();
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x1e);
+    buffer.write_uint32(partner_id);
+    buffer.write_uint16(items.len() as u16);
+    for item in items {
+        write_trade_item(&mut buffer, item, item.count, templates);
+    }
+    buffer.buffer
+}
+
+fn trade_add(id: u8, item: &Item, count: u64, templates: &ItemRegistry) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(id);
+    buffer.write_uint16(1);
+    write_trade_item(&mut buffer, item, count, templates);
+    buffer.buffer
+}
+
+/// `count` of an item the player put in the trade.
+pub fn trade_own_add(item: &Item, count: u64, templates: &ItemRegistry) -> Vec<u8> {
+    trade_add(0x20, item, count, templates)
+}
+
+/// `count` of an item the partner put in the trade.
+pub fn trade_other_add(item: &Item, count: u64, templates: &ItemRegistry) -> Vec<u8> {
+    trade_add(0x21, item, count, templates)
+}
+
+/// Closes the trade window, `success` when the items were exchanged.
+pub fn trade_done(success: bool) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x22);
+    buffer.write_uint32(success as u32);
+    buffer.buffer
+}
+
+pub fn trade_press_own_ok() -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x75);
+    buffer.buffer
+}
+
+pub fn trade_press_other_ok() -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x7c);
+    buffer.buffer
+}
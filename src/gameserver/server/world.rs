@@ -0,0 +1,350 @@
+use crate::gameserver::datapack::classes::ClassTemplate;
+use crate::gameserver::models::{self, paperdoll_view};
+use crate::gameserver::npc::Npc;
+use crate::gameserver::player::Player;
+use crate::gameserver::stats::{Stat, Stats};
+use crate::packet::packet::Buffer;
+
+pub const STATUS_EXP: u32 = 0x02;
+pub const STATUS_CUR_HP: u32 = 0x09;
+pub const STATUS_MAX_HP: u32 = 0x0a;
+pub const STATUS_CUR_MP: u32 = 0x0b;
+pub const STATUS_MAX_MP: u32 = 0x0c;
+pub const STATUS_SP: u32 = 0x0d;
+pub const STATUS_CUR_LOAD: u32 = 0x0e;
+pub const STATUS_MAX_LOAD: u32 = 0x0f;
+pub const STATUS_P_ATK: u32 = 0x11;
+pub const STATUS_ATK_SPD: u32 = 0x12;
+pub const STATUS_P_DEF: u32 = 0x13;
+pub const STATUS_EVASION: u32 = 0x14;
+pub const STATUS_ACCURACY: u32 = 0x15;
+pub const STATUS_CRITICAL: u32 = 0x16;
+pub const STATUS_M_ATK: u32 = 0x17;
+pub const STATUS_CAST_SPD: u32 = 0x18;
+pub const STATUS_M_DEF: u32 = 0x19;
+pub const STATUS_KARMA: u32 = 0x1b;
+pub const STATUS_CUR_CP: u32 = 0x21;
+pub const STATUS_MAX_CP: u32 = 0x22;
+
+/// Paperdoll slots shown to other players by CharInfo, in packet order.
+const CHAR_INFO_PAPERDOLL: [i32; 12] = [
+    models::PAPERDOLL_UNDER, models::PAPERDOLL_HEAD, models::PAPERDOLL_RHAND, models::PAPERDOLL_LHAND,
+    models::PAPERDOLL_GLOVES, models::PAPERDOLL_CHEST, models::PAPERDOLL_LEGS, models::PAPERDOLL_FEET,
+    models::PAPERDOLL_BACK, models::PAPERDOLL_LRHAND, models::PAPERDOLL_HAIR, models::PAPERDOLL_FACE,
+];
+
+/// Run and walk speeds on land, in water and flying.
+fn write_speeds(buffer: &mut Buffer, stats: &Stats) {
+    let (run, walk) = (stats.get(Stat::RunSpeed) as u32, stats.get(Stat::WalkSpeed) as u32);
+    buffer.write_uint32(run);
+    buffer.write_uint32(walk);
+    buffer.write_uint32(run); // swim
+    buffer.write_uint32(walk);
+    buffer.write_uint32(0x00); // fly
+    buffer.write_uint32(0x00);
+    buffer.write_uint32(run);
+    buffer.write_uint32(walk);
+}
+
+pub fn user_info(player: &Player, template: &ClassTemplate) -> Vec<u8> {
+    let character = &player.character;
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x04);
+    buffer.write_int32(character.x);
+    buffer.write_int32(character.y);
+    buffer.write_int32(character.z);
+    buffer.write_int32(character.heading);
+    buffer.write_uint32(character.obj_id);
+    buffer.write_string(&character.char_name);
+    buffer.write_uint32(character.race as u32);
+    buffer.write_uint32(character.sex as u32);
+    buffer.write_uint32(character.base_class as u32);
+    buffer.write_uint32(character.level as u32);
+    buffer.write_uint64(character.exp);
+    for stat in [template.stats.str, template.stats.dex, template.stats.con, template.stats.int, template.stats.wit, template.stats.men] {
+        buffer.write_uint32(stat);
+    }
+    buffer.write_uint32(character.max_hp as u32);
+    buffer.write_uint32(character.cur_hp as u32);
+    buffer.write_uint32(character.max_mp as u32);
+    buffer.write_uint32(character.cur_mp as u32);
+    buffer.write_uint32(character.sp);
+    buffer.write_uint32(player.inventory.weight() as u32);
+    buffer.write_uint32(player.inventory.max_weight as u32);
+    buffer.write_uint32(0x28);
+
+    let view = paperdoll_view(player.inventory.equipped());
+    for (object_id, _) in view {
+        buffer.write_uint32(object_id);
+    }
+    for (_, item_id) in view {
+        buffer.write_uint32(item_id);
+    }
+    // Augmentation data, not supported yet.
+    for _ in 0..14 {
+        buffer.write_uint16(0x00);
+    }
+    buffer.write_uint32(0x00);
+    for _ in 0..12 {
+        buffer.write_uint16(0x00);
+    }
+    buffer.write_uint32(0x00);
+    for _ in 0..4 {
+        buffer.write_uint16(0x00);
+    }
+
+    let stats = &player.stats;
+    buffer.write_uint32(stats.get(Stat::PAtk) as u32);
+    buffer.write_uint32(stats.get(Stat::PAtkSpd) as u32);
+    buffer.write_uint32(stats.get(Stat::PDef) as u32);
+    buffer.write_uint32(stats.get(Stat::Evasion) as u32);
+    buffer.write_uint32(stats.get(Stat::Accuracy) as u32);
+    buffer.write_uint32(stats.get(Stat::Critical) as u32);
+    buffer.write_uint32(stats.get(Stat::MAtk) as u32);
+    buffer.write_uint32(stats.get(Stat::MAtkSpd) as u32);
+    buffer.write_uint32(stats.get(Stat::PAtkSpd) as u32);
+    buffer.write_uint32(stats.get(Stat::MDef) as u32);
+    buffer.write_uint32(0x00); // pvp flag
+    buffer.write_uint32(character.karma);
+
+    write_speeds(&mut buffer, &player.stats);
+    buffer.write_float64(1.0); // move multiplier
+    buffer.write_float64(1.0); // attack speed multiplier
+
+    let (radius, height) = player.collision;
+    buffer.write_float64(radius);
+    buffer.write_float64(height);
+    buffer.write_uint32(character.hair_style as u32);
+    buffer.write_uint32(character.hair_color as u32);
+    buffer.write_uint32(character.face as u32);
+    buffer.write_uint32((character.access_level > 0) as u32);
+    buffer.write_string(&character.title);
+
+    buffer.write_uint32(character.clan_id);
+    buffer.write_uint32(0x00); // clan crest
+    buffer.write_uint32(0x00); // ally
+    buffer.write_uint32(0x00); // ally crest
+    buffer.write_uint32(0x00); // relation
+    buffer.write_uint8(0x00); // mount
+    buffer.write_uint8(player.store.map_or(0, |kind| kind.store_type()));
+    buffer.write_uint8(0x00); // dwarven craft
+    buffer.write_uint32(character.pk_kills);
+    buffer.write_uint32(character.pvp_kills);
+    buffer.write_uint16(0x00); // cubics
+    buffer.write_uint8(0x00);
+    buffer.write_uint32(0x00); // abnormal effects
+    buffer.write_uint8(0x00);
+    buffer.write_uint32(0x00); // clan privileges
+    buffer.write_uint16(0x00); // recommendations left
+    buffer.write_uint16(0x00); // recommendations received
+    buffer.write_uint32(0x00); // mount npc
+    buffer.write_uint16(player.inventory.max_slots as u16);
+    buffer.write_uint32(character.class_id as u32);
+    buffer.write_uint32(0x00);
+    buffer.write_uint32(character.max_cp as u32);
+    buffer.write_uint32(character.cur_cp as u32);
+    buffer.write_uint8(0x00); // enchant effect
+    buffer.write_uint8(0x00); // team
+    buffer.write_uint32(0x00); // large clan crest
+    buffer.write_uint8(0x00); // noble
+    buffer.write_uint8(0x00); // hero
+    buffer.write_uint8(0x00); // fishing
+    buffer.write_int32(0);
+    buffer.write_int32(0);
+    buffer.write_int32(0);
+    buffer.write_uint32(0xffffff); // name color
+    buffer.write_uint8(player.running as u8);
+    buffer.write_uint32(0x00); // pledge class
+    buffer.write_uint32(0x00);
+    buffer.write_uint32(0xffff77); // title color
+    buffer.write_uint32(0x00); // cursed weapon
+    buffer.buffer
+}
+
+/// Appearance of another player.
+pub fn char_info(player: &Player) -> Vec<u8> {
+    let character = &player.character;
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x03);
+    buffer.write_int32(character.x);
+    buffer.write_int32(character.y);
+    buffer.write_int32(character.z);
+    buffer.write_int32(character.heading);
+    buffer.write_uint32(character.obj_id);
+    buffer.write_string(&character.char_name);
+    buffer.write_uint32(character.race as u32);
+    buffer.write_uint32(character.sex as u32);
+    buffer.write_uint32(character.base_class as u32);
+
+    let view = paperdoll_view(player.inventory.equipped());
+    for slot in CHAR_INFO_PAPERDOLL {
+        buffer.write_uint32(view[slot as usize].1);
+    }
+    // Augmentation data, not supported yet.
+    for _ in 0..12 {
+        buffer.write_uint16(0x00);
+    }
+    buffer.write_uint32(0x00);
+    for _ in 0..12 {
+        buffer.write_uint16(0x00);
+    }
+    buffer.write_uint32(0x00);
+    for _ in 0..4 {
+        buffer.write_uint16(0x00);
+    }
+
+    buffer.write_uint32(0x00); // pvp flag
+    buffer.write_uint32(character.karma);
+    buffer.write_uint32(player.stats.get(Stat::MAtkSpd) as u32);
+    buffer.write_uint32(player.stats.get(Stat::PAtkSpd) as u32);
+    buffer.write_uint32(0x00); // pvp flag
+    buffer.write_uint32(character.karma);
+
+    write_speeds(&mut buffer, &player.stats);
+    buffer.write_float64(1.0); // move multiplier
+    buffer.write_float64(1.0); // attack speed multiplier
+
+    let (radius, height) = player.collision;
+    buffer.write_float64(radius);
+    buffer.write_float64(height);
+    buffer.write_uint32(character.hair_style as u32);
+    buffer.write_uint32(character.hair_color as u32);
+    buffer.write_uint32(character.face as u32);
+    buffer.write_string(&character.title);
+
+    buffer.write_uint32(character.clan_id);
+    buffer.write_uint32(0x00); // clan crest
+    buffer.write_uint32(0x00); // ally
+    buffer.write_uint32(0x00); // ally crest
+    buffer.write_uint32(0x00);
+    buffer.write_uint8(!player.sitting as u8);
+    buffer.write_uint8(player.running as u8);
+    buffer.write_uint8(player.combat.in_combat() as u8);
+    buffer.write_uint8(player.is_dead() as u8);
+    buffer.write_uint8(0x00); // invisible
+    buffer.write_uint8(0x00); // mount
+    buffer.write_uint8(player.store.map_or(0, |kind| kind.store_type()));
+    buffer.write_uint16(0x00); // cubics
+    buffer.write_uint8(0x00); // looking for party
+    buffer.write_uint32(0x00); // abnormal effects
+    buffer.write_uint8(0x00); // recommendations left
+    buffer.write_uint16(0x00); // recommendations received
+    buffer.write_uint32(character.class_id as u32);
+    buffer.write_uint32(character.max_cp as u32);
+    buffer.write_uint32(character.cur_cp as u32);
+    buffer.write_uint8(0x00); // enchant effect
+    buffer.write_uint8(0x00); // team
+    buffer.write_uint32(0x00); // large clan crest
+    buffer.write_uint8(0x00); // noble
+    buffer.write_uint8(0x00); // hero
+    buffer.write_uint8(0x00); // fishing
+    buffer.write_int32(0);
+    buffer.write_int32(0);
+    buffer.write_int32(0);
+    buffer.write_uint32(0xffffff); // name color
+    buffer.write_int32(character.heading);
+    buffer.write_uint32(0x00); // pledge class
+    buffer.write_uint32(0x00); // pledge type
+    buffer.write_uint32(0xffff77); // title color
+    buffer.write_uint32(0x00); // cursed weapon
+    buffer.buffer
+}
+
+pub fn npc_info(npc: &Npc) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x16);
+    buffer.write_uint32(npc.obj_id);
+    buffer.write_uint32(npc.npc_id + 1000000);
+    buffer.write_uint32(npc.attackable as u32);
+    buffer.write_int32(npc.x);
+    buffer.write_int32(npc.y);
+    buffer.write_int32(npc.z);
+    buffer.write_int32(npc.heading);
+    buffer.write_uint32(0x00);
+    buffer.write_uint32(npc.stats.get(Stat::MAtkSpd) as u32);
+    buffer.write_uint32(npc.stats.get(Stat::PAtkSpd) as u32);
+    write_speeds(&mut buffer, &npc.stats);
+    buffer.write_float64(1.0); // move multiplier
+    buffer.write_float64(1.0); // attack speed multiplier
+
+    let (radius, height) = npc.collision;
+    buffer.write_float64(radius);
+    buffer.write_float64(height);
+    buffer.write_uint32(0x00); // right hand
+    buffer.write_uint32(0x00);
+    buffer.write_uint32(0x00); // left hand
+    buffer.write_uint8(0x01); // name above
+    buffer.write_uint8(npc.running as u8);
+    buffer.write_uint8(npc.combat.in_combat() as u8);
+    buffer.write_uint8(npc.is_dead() as u8);
+    buffer.write_uint8(0x00); // summoned
+    buffer.write_string(&npc.name);
+    buffer.write_string(&npc.title);
+    buffer.write_uint32(0x00);
+    buffer.write_uint32(0x00);
+    buffer.write_uint32(0x00); // karma
+    buffer.write_uint32(0x00); // abnormal effects
+    buffer.write_uint32(0x00); // clan
+    buffer.write_uint32(0x00); // clan crest
+    buffer.write_uint32(0x00); // ally
+    buffer.write_uint32(0x00); // ally crest
+    buffer.write_uint8(0x00); // flying
+    buffer.write_uint8(0x00); // team
+    buffer.write_float64(radius);
+    buffer.write_float64(height);
+    buffer.write_uint32(0x00); // enchant effect
+    buffer.write_uint32(0x00); // flying
+    buffer.buffer
+}
+
+pub fn delete_object(obj_id: u32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x12);
+    buffer.write_uint32(obj_id);
+    buffer.write_uint32(0x00);
+    buffer.buffer
+}
+
+/// New values of some stats of a creature, as attribute and value pairs.
+pub fn status_update(obj_id: u32, attributes: &[(u32, u32)]) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x0e);
+    buffer.write_uint32(obj_id);
+    buffer.write_uint32(attributes.len() as u32);
+    for (attribute, value) in attributes {
+        buffer.write_uint32(*attribute);
+        buffer.write_uint32(*value);
+    }
+    buffer.buffer
+}
+
+/// Sitting down or standing up.
+pub fn change_wait_type(player: &Player) -> Vec<u8> {
+    let (x, y, z) = player.position();
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x2f);
+    buffer.write_uint32(player.obj_id());
+    buffer.write_uint32(!player.sitting as u32);
+    buffer.write_int32(x);
+    buffer.write_int32(y);
+    buffer.write_int32(z);
+    buffer.buffer
+}
+
+/// Target picked by the player, `color` tells how its level compares for attackable targets.
+pub fn my_target_selected(obj_id: u32, color: u16) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0xa6);
+    buffer.write_uint32(obj_id);
+    buffer.write_uint16(color);
+    buffer.buffer
+}
+
+/// Animation such as a greeting, or the light of a level up.
+pub fn social_action(obj_id: u32, action: u32) -> Vec<u8> {
+    let mut buffer = Buffer::new();
+    buffer.write_uint8(0x2d);
+    buffer.write_uint32(obj_id);
+    buffer.write_uint32(action);
+    buffer.buffer
+}
\ No newline at end of file
@@ -0,0 +1,552 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::gameserver::ai;
+use crate::gameserver::client::skills as request;
+use crate::gameserver::combat;
+use crate::gameserver::datapack::registry::Datapack;
+use crate::gameserver::datapack::skills::{SkillKind, SkillTemplate, TargetType};
+use crate::gameserver::death;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::inventory;
+use crate::gameserver::lobby::now_millis;
+use crate::gameserver::models::Client;
+use crate::gameserver::movement;
+use crate::gameserver::player::Player;
+use crate::gameserver::server::combat as combat_response;
+use crate::gameserver::server::lobby::action_failed;
+use crate::gameserver::server::skills as response;
+use crate::gameserver::server::system_message::{self, Param};
+use crate::gameserver::server::world as world_response;
+use crate::gameserver::stats::{self, Source, Stat};
+use crate::gameserver::world::{World, WorldObject};
+
+/// How often casts are finished and effects wear off.
+const CAST_TICK: Duration = Duration::from_millis(100);
+/// Casting speed the hit times of the datapack are given at.
+const BASE_CAST_SPEED: f64 = 333.0;
+
+/// A skill being cast, it lands on its targets once the hit time is over.
+pub struct Cast {
+    pub skill_id: u32,
+    pub level: u32,
+    pub target: u32,
+    pub done_at: Instant,
+}
+
+/// A buff or debuff on a creature.
+#[derive(Clone, Debug)]
+pub struct Effect {
+    pub skill_id: u32,
+    pub level: u32,
+    pub abnormal: String,
+    pub order: u32,
+    pub ends_at: Instant,
+}
+
+/// What becomes of a new effect among those already on a creature.
+#[derive(PartialEq, Eq, Debug)]
+pub enum Stacking {
+    Add,
+    /// It takes the place of the effect at this index, of the same skill or abnormal type and no higher order.
+    Replace(usize),
+    /// A stronger effect of the same abnormal type stays.
+    Refused,
+}
+
+pub fn stacking(effects: &[Effect], skill_id: u32, abnormal: &str, order: u32) -> Stacking {
+    match effects.iter().position(|effect| effect.skill_id == skill_id || effect.abnormal == abnormal) {
+        Some(index) if effects[index].order > order => Stacking::Refused,
+        Some(index) => Stacking::Replace(index),
+        None => Stacking::Add,
+    }
+}
+
+/// Gives a player the skills of its class at its level, and puts on the stat functions of the passive ones. The
+/// caller refreshes the stats.
+pub fn learn(datapack: &Datapack, player: &mut Player) {
+    player.skills = datapack.classes.skills_at(player.character.class_id, player.character.level);
+    player.calculator.remove_passives();
+    for (skill_id, level) in &player.skills {
+        let skill = match datapack.skills.get(*skill_id, *level) {
+            Some(skill) if skill.kind == SkillKind::Passive => skill,
+            _ => continue,
+        };
+        for func in &skill.stats {
+            player.calculator.add(func.to_func(Source::Skill(*skill_id)));
+        }
+    }
+}
+
+pub fn skill_list(datapack: &Datapack, player: &Player) -> Vec<u8> {
+    let skills: Vec<(u32, u32, bool)> = player.skills.iter()
+        .map(|(skill_id, level)| {
+            let passive = datapack.skills.get(*skill_id, *level).is_some_and(|skill| skill.kind == SkillKind::Passive);
+            (*skill_id, *level, passive)
+        })
+        .collect();
+    response::skill_list(&skills)
+}
+
+/// Icons of the effects on a player, with the seconds they have left.
+pub fn show_effects(world: &World, obj_id: u32, now: Instant) {
+    if let Some(player) = world.player(obj_id) {
+        let effects: Vec<(u32, u32, u32)> = player.effects.iter()
+            .map(|effect| (effect.skill_id, effect.level, effect.ends_at.saturating_duration_since(now).as_secs() as u32))
+            .collect();
+        player.send(response::abnormal_status_update(&effects));
+    }
+}
+
+pub async fn request_skill_list(context: &Context, client: &mut Client) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let world = context.world();
+    match world.player(obj_id) {
+        Some(player) => {
+            player.send(skill_list(&context.datapack, player));
+            Ok(())
+        },
+        None => Err(format!("Player {} of {} is not in the world", obj_id, client.account_name)),
+    }
+}
+
+pub async fn request_magic_skill_use(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let request = request::new_magic_skill_use(data)?;
+    start_cast(context, &mut context.world(), obj_id, request.skill_id, request.ctrl)
+}
+
+fn refuse(player: &Player, message: u32, params: &[Param]) {
+    player.send(system_message::system_message(message, params));
+    player.send(action_failed());
+}
+
+/// Whether a skill reaches `other`: enemies for offensive skills, corpses of monsters for sweeps, players
+/// otherwise. Players are only fair game for offensive skills when forced.
+fn affects(skill: &SkillTemplate, other: &WorldObject, forced: bool) -> bool {
+    match other {
+        WorldObject::Npc(npc) if skill.kind == SkillKind::Sweep => npc.attackable && npc.is_dead(),
+        WorldObject::Player(_) if skill.kind == SkillKind::Sweep => false,
+        _ if other.is_dead() => false,
+        WorldObject::Npc(npc) => skill.kind.is_offensive() && npc.attackable,
+        WorldObject::Player(_) => !skill.kind.is_offensive() || forced,
+    }
+}
+
+fn collision_radius(object: &WorldObject) -> f64 {
+    match object {
+        WorldObject::Player(player) => player.collision.0,
+        WorldObject::Npc(npc) => npc.collision.0,
+    }
+}
+
+/// Whether a target is within the range of a skill, between the edges of both creatures.
+pub fn in_range(world: &World, obj_id: u32, target_id: u32, skill: &SkillTemplate) -> bool {
+    match (world.get(obj_id), world.get(target_id)) {
+        (Some(caster), Some(target)) => {
+            let reach = skill.range as f64 + collision_radius(caster) + collision_radius(target);
+            combat::distance_2d(caster.position(), target.position()) <= reach
+        },
+        _ => false,
+    }
+}
+
+/// Has a player cast a skill it knows. The skill is paid for up front and lands when the cast is over, the cast
+/// tick takes it from there.
+pub fn start_cast(context: &Context, world: &mut World, obj_id: u32, skill_id: u32, forced: bool) -> Result<(), String> {
+    let player = match world.player(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} is not in the world", obj_id)),
+    };
+    if player.is_dead() || player.sitting || player.teleporting || player.store.is_some() || player.cast.is_some() {
+        player.send(action_failed());
+        return Ok(());
+    }
+    let skill = match player.skills.get(&skill_id).and_then(|level| context.datapack.skills.get(skill_id, *level)) {
+        Some(skill) if skill.kind != SkillKind::Passive => skill,
+        _ => {
+            player.send(action_failed());
+            return Ok(());
+        }
+    };
+    if player.reuse.get(&skill_id).is_some_and(|ready_at| *ready_at > now_millis()) {
+        refuse(player, system_message::S1_PREPARED_FOR_REUSE, &[Param::Skill(skill_id, skill.level)]);
+        return Ok(());
+    }
+
+    let target_id = match skill.target {
+        TargetType::One | TargetType::Area => {
+            let target = player.target
+                .filter(|target_id| *target_id != obj_id && player.known.contains(target_id))
+                .and_then(|target_id| world.get(target_id))
+                .filter(|target| affects(skill, target, forced));
+            match target {
+                Some(target) => target.obj_id(),
+                None if !skill.kind.is_offensive() && skill.kind != SkillKind::Sweep => obj_id,
+                None => {
+                    refuse(player, system_message::TARGET_IS_INCORRECT, &[]);
+                    return Ok(());
+                }
+            }
+        },
+        TargetType::Myself | TargetType::Party | TargetType::Clan => obj_id,
+    };
+    let position = player.position();
+    if let Some(target) = world.get(target_id).filter(|_| target_id != obj_id) {
+        let to = target.position();
+        if !in_range(world, obj_id, target_id, skill) {
+            refuse(player, system_message::TARGET_TOO_FAR, &[]);
+            return Ok(());
+        }
+        if !context.geodata.can_see(position, to) {
+            refuse(player, system_message::CANT_SEE_TARGET, &[]);
+            return Ok(());
+        }
+    }
+
+    if skill.mp > player.character.cur_mp {
+        refuse(player, system_message::NOT_ENOUGH_MP, &[]);
+        return Ok(());
+    }
+    if skill.hp > 0.0 && skill.hp >= player.character.cur_hp {
+        refuse(player, system_message::NOT_ENOUGH_HP, &[]);
+        return Ok(());
+    }
+    if skill.item.is_some_and(|(item_id, count)| player.inventory.count_of(item_id) < count) {
+        refuse(player, system_message::NOT_ENOUGH_ITEMS, &[]);
+        return Ok(());
+    }
+
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Ok(()),
+    };
+    if let Some((item_id, count)) = skill.item {
+        let changes = match player.inventory.remove_by_item_id(&context.datapack.items, &context.ids, item_id, count) {
+            Ok(changes) => changes,
+            Err(e) => return Err(format!("Can't take {} of item {} from {}: {}", count, item_id, player.character.char_name, e)),
+        };
+        inventory::commit(context, player, changes);
+        player.send(system_message::system_message(system_message::S2_S1_DISAPPEARED,
+            &[Param::Item(item_id), Param::Number(count.min(u32::MAX as u64) as u32)]));
+    }
+    player.character.cur_mp -= skill.mp;
+    player.character.cur_hp -= skill.hp;
+    player.send(world_response::status_update(obj_id, &[
+        (world_response::STATUS_CUR_HP, player.character.cur_hp as u32),
+        (world_response::STATUS_CUR_MP, player.character.cur_mp as u32),
+    ]));
+    if !skill.reuse.is_zero() {
+        player.reuse.insert(skill_id, now_millis() + skill.reuse.as_millis() as i64);
+    }
+    player.send(system_message::system_message(system_message::USE_S1, &[Param::Skill(skill_id, skill.level)]));
+    world.start_recovering(obj_id);
+
+    let hit_time = begin_cast(world, obj_id, skill, target_id, Instant::now());
+    if let Some(player) = world.player(obj_id) {
+        player.send(response::setup_gauge(hit_time.as_millis() as u32));
+    }
+    Ok(())
+}
+
+/// Starts the cast of a skill already paid for: the caster stops, turns to its target and casts for the hit time
+/// its casting speed gives, which is returned.
+pub fn begin_cast(world: &mut World, obj_id: u32, skill: &SkillTemplate, target_id: u32, now: Instant) -> Duration {
+    let (position, target_position) = match (world.get(obj_id), world.get(target_id)) {
+        (Some(caster), Some(target)) => (caster.position(), target.position()),
+        _ => return Duration::ZERO,
+    };
+    combat::stop_attack(world, obj_id);
+    if world.get_mut(obj_id).is_some_and(|caster| caster.movement_mut().is_some()) {
+        movement::stop(world, obj_id);
+    }
+    let caster = match world.get_mut(obj_id) {
+        Some(caster) => caster,
+        None => return Duration::ZERO,
+    };
+    let hit_time = skill.hit_time.mul_f64(BASE_CAST_SPEED / caster.stats().get(Stat::MAtkSpd).max(1.0));
+    if target_id != obj_id {
+        caster.set_heading(movement::heading_to((position.0, position.1), (target_position.0, target_position.1)));
+    }
+    *caster.cast_mut() = Some(Cast { skill_id: skill.skill_id, level: skill.level, target: target_id, done_at: now + hit_time });
+    world.start_casting(obj_id);
+    world.broadcast_with_self(obj_id, &combat_response::magic_skill_use(obj_id, target_id, skill.skill_id, skill.level,
+        hit_time.as_millis() as u32, skill.reuse.as_millis() as u32, position));
+    hit_time
+}
+
+/// Stops the cast of a creature, the skill is lost along with what was paid for it.
+pub fn cancel(world: &mut World, obj_id: u32) {
+    let cast = world.get_mut(obj_id).and_then(|object| object.cast_mut().take());
+    world.stop_casting(obj_id);
+    if cast.is_some() {
+        world.broadcast_with_self(obj_id, &response::magic_skill_canceld(obj_id));
+    }
+}
+
+/// Finishes casts and ends effects for as long as the server runs.
+pub async fn run(context: Arc<Context>) {
+    let mut interval = tokio::time::interval(CAST_TICK);
+    loop {
+        interval.tick().await;
+        update(&context, &mut context.world(), Instant::now());
+    }
+}
+
+fn update(context: &Context, world: &mut World, now: Instant) {
+    for obj_id in world.casting() {
+        finish(context, world, obj_id, now);
+    }
+    for (obj_id, skill_id) in world.due_effect_ends(now) {
+        end_effect(context, world, obj_id, skill_id, now);
+    }
+}
+
+fn finish(context: &Context, world: &mut World, obj_id: u32, now: Instant) {
+    if world.get_mut(obj_id).is_some_and(|caster| caster.cast_mut().as_ref().is_some_and(|cast| cast.done_at > now)) {
+        return;
+    }
+    let cast = world.get_mut(obj_id).and_then(|caster| caster.cast_mut().take());
+    world.stop_casting(obj_id);
+    let (cast, skill) = match cast.and_then(|cast| context.datapack.skills.get(cast.skill_id, cast.level).map(|skill| (cast, skill))) {
+        Some(found) => found,
+        None => return,
+    };
+    let targets = targets(world, obj_id, cast.target, skill);
+    if targets.is_empty() {
+        world.broadcast_with_self(obj_id, &response::magic_skill_canceld(obj_id));
+        return;
+    }
+    world.broadcast_with_self(obj_id, &response::magic_skill_launched(obj_id, skill.skill_id, skill.level, &targets));
+    if skill.kind.is_offensive() {
+        combat::enter_combat(world, obj_id, now);
+    }
+    for target_id in targets {
+        land(context, world, obj_id, skill, target_id, now);
+    }
+}
+
+/// Creatures a finished cast lands on. The target of the cast comes first, an empty list means it went away or
+/// can't be hit anymore.
+fn targets(world: &World, obj_id: u32, target_id: u32, skill: &SkillTemplate) -> Vec<u32> {
+    let caster = match world.get(obj_id) {
+        Some(caster) if !caster.is_dead() => caster,
+        _ => return Vec::new(),
+    };
+    let target = match world.get(target_id) {
+        Some(target) if target_id == obj_id || (caster.known().contains(&target_id) && affects(skill, target, true)) => target,
+        _ => return Vec::new(),
+    };
+    let mut targets = vec![target_id];
+    let around = |position: (i32, i32, i32)| combat::distance_2d(target.position(), position) <= skill.radius as f64;
+    match skill.target {
+        TargetType::Myself | TargetType::One => {},
+        // Parties come later, see `TargetType::Party`.
+        TargetType::Party => {},
+        TargetType::Area => targets.extend(caster.known().iter()
+            .filter(|other| **other != target_id)
+            .filter(|other| world.get(**other).is_some_and(|other| affects(skill, other, false) && around(other.position())))),
+        TargetType::Clan => {
+            let clan_id = world.player(obj_id).map_or(0, |player| player.character.clan_id);
+            if clan_id != 0 {
+                targets.extend(caster.known().iter()
+                    .filter(|other| world.player(**other).is_some_and(|other| {
+                        other.character.clan_id == clan_id && !other.is_dead() && around(other.position())
+                    })));
+            }
+        },
+    }
+    targets
+}
+
+/// Damage of an offensive skill before any modifier.
+pub fn magic_damage(power: f64, m_atk: f64, m_def: f64) -> f64 {
+    91.0 * power * m_atk.sqrt() / m_def.max(1.0)
+}
+
+fn land(context: &Context, world: &mut World, caster_id: u32, skill: &SkillTemplate, target_id: u32, now: Instant) {
+    match skill.kind {
+        SkillKind::Damage => {
+            let (m_atk, name) = match world.get(caster_id) {
+                Some(caster @ WorldObject::Player(player)) => (player.stats.get(Stat::MAtk), caster.name().to_string()),
+                Some(caster @ WorldObject::Npc(npc)) => (npc.stats.get(Stat::MAtk), caster.name().to_string()),
+                None => return,
+            };
+            let m_def = match world.get(target_id) {
+                Some(WorldObject::Player(player)) => player.stats.get(Stat::MDef),
+                Some(WorldObject::Npc(npc)) => npc.stats.get(Stat::MDef),
+                None => return,
+            };
+            let damage = magic_damage(skill.power, m_atk, m_def).max(1.0) as u32;
+            if let Some(caster) = world.player(caster_id) {
+                caster.send(system_message::system_message(system_message::YOU_DID_S1_DMG, &[Param::Number(damage)]));
+            }
+            if let Some(target) = world.player(target_id) {
+                target.send(system_message::system_message(system_message::S1_GAVE_YOU_S2_DMG,
+                    &[Param::Text(name), Param::Number(damage)]));
+            }
+            combat::enter_combat(world, target_id, now);
+            combat::hurt(context, world, caster_id, target_id, damage, now);
+        },
+        SkillKind::Heal => {
+            let healed = match world.get_mut(target_id) {
+                Some(WorldObject::Player(player)) => {
+                    let before = player.character.cur_hp;
+                    player.character.cur_hp = (before + skill.power).min(player.character.max_hp);
+                    player.character.cur_hp - before
+                },
+                Some(WorldObject::Npc(npc)) => {
+                    let before = npc.cur_hp;
+                    npc.cur_hp = (before + skill.power).min(npc.stats.get(Stat::MaxHp));
+                    npc.cur_hp - before
+                },
+                None => return,
+            };
+            combat::show_hp(world, target_id);
+            if let Some(target) = world.player(target_id) {
+                target.send(system_message::system_message(system_message::S1_HP_RESTORED, &[Param::Number(healed as u32)]));
+            }
+            // Functions may depend on HP.
+            stats::refresh(context, world, target_id);
+        },
+        SkillKind::Buff | SkillKind::Debuff => {
+            add_effect(context, world, target_id, skill, now);
+            if skill.kind == SkillKind::Debuff {
+                combat::enter_combat(world, target_id, now);
+                ai::notify_attacked(context, world, target_id, caster_id, 1.0);
+            }
+        },
+        SkillKind::Spoil => {
+            let spoiled = match world.npc_mut(target_id) {
+                Some(npc) if npc.spoiler.is_none() => {
+                    npc.spoiler = Some(caster_id);
+                    true
+                },
+                Some(_) => false,
+                None => return,
+            };
+            if let Some(caster) = world.player(caster_id) {
+                let message = if spoiled { system_message::SPOIL_SUCCESS } else { system_message::ALREADY_SPOILED };
+                caster.send(system_message::system_message(message, &[]));
+            }
+            combat::enter_combat(world, target_id, now);
+            ai::notify_attacked(context, world, target_id, caster_id, 1.0);
+        },
+        SkillKind::Sweep => death::sweep(context, world, caster_id, target_id),
+        SkillKind::Passive => {},
+    }
+}
+
+/// Puts the effect of a buff or debuff on a creature, unless a stronger one of its abnormal type is there.
+pub fn add_effect(context: &Context, world: &mut World, obj_id: u32, skill: &SkillTemplate, now: Instant) {
+    let template = match skill.effect.as_ref() {
+        Some(template) => template,
+        None => return,
+    };
+    let object = match world.get_mut(obj_id) {
+        Some(object) => object,
+        None => return,
+    };
+    match stacking(object.effects(), skill.skill_id, &template.abnormal, template.order) {
+        Stacking::Refused => return,
+        Stacking::Replace(index) => {
+            let replaced = object.effects_mut().remove(index);
+            object.calculator_mut().remove(Source::Effect(replaced.skill_id));
+        },
+        Stacking::Add => {},
+    }
+    let ends_at = now + template.duration();
+    object.effects_mut().push(Effect {
+        skill_id: skill.skill_id,
+        level: skill.level,
+        abnormal: template.abnormal.clone(),
+        order: template.order,
+        ends_at,
+    });
+    for func in &template.stats {
+        object.calculator_mut().add(func.to_func(Source::Effect(skill.skill_id)));
+    }
+    world.schedule_effect_end(ends_at, obj_id, skill.skill_id);
+    stats::refresh(context, world, obj_id);
+    show_effects(world, obj_id, now);
+    if let Some(player) = world.player(obj_id) {
+        player.send(system_message::system_message(system_message::YOU_FEEL_S1_EFFECT, &[Param::Skill(skill.skill_id, skill.level)]));
+    }
+}
+
+/// Takes off an effect whose time is up. One replaced since by a newer effect of the same skill is left alone.
+fn end_effect(context: &Context, world: &mut World, obj_id: u32, skill_id: u32, now: Instant) {
+    let object = match world.get_mut(obj_id) {
+        Some(object) => object,
+        None => return,
+    };
+    let index = match object.effects().iter().position(|effect| effect.skill_id == skill_id && effect.ends_at <= now) {
+        Some(index) => index,
+        None => return,
+    };
+    let effect = object.effects_mut().remove(index);
+    object.calculator_mut().remove(Source::Effect(skill_id));
+    stats::refresh(context, world, obj_id);
+    show_effects(world, obj_id, now);
+    if let Some(player) = world.player(obj_id) {
+        player.send(system_message::system_message(system_message::EFFECT_S1_DISAPPEARED, &[Param::Skill(skill_id, effect.level)]));
+    }
+}
+
+/// Takes every effect off a creature.
+pub fn clear_effects(context: &Context, world: &mut World, obj_id: u32) {
+    let effects = match world.get_mut(obj_id) {
+        Some(object) => std::mem::take(object.effects_mut()),
+        None => return,
+    };
+    if effects.is_empty() {
+        return;
+    }
+    if let Some(object) = world.get_mut(obj_id) {
+        for effect in &effects {
+            object.calculator_mut().remove(Source::Effect(effect.skill_id));
+        }
+    }
+    stats::refresh(context, world, obj_id);
+    show_effects(world, obj_id, Instant::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameserver::player::synthetic::{datapack, player};
+
+    fn effect(skill_id: u32, abnormal: &str, order: u32) -> Effect {
+        Effect { skill_id, level: 1, abnormal: abnormal.to_string(), order, ends_at: Instant::now() }
+    }
+
+    #[test]
+    fn stronger_effects_replace_weaker_ones() {
+        let effects = vec![effect(1068, "pa_up", 2), effect(1040, "pd_up", 1)];
+        assert_eq!(stacking(&effects, 1007, "pa_up", 1), Stacking::Refused);
+        assert_eq!(stacking(&effects, 1007, "pa_up", 3), Stacking::Replace(0));
+        assert_eq!(stacking(&effects, 1040, "pd_up", 1), Stacking::Replace(1));
+        assert_eq!(stacking(&effects, 1204, "speed_up", 1), Stacking::Add);
+    }
+
+    #[test]
+    fn party_skills_only_reach_their_caster() {
+        let datapack = datapack();
+        let chant = datapack.skills.get(1007, 1).unwrap();
+        assert_eq!(chant.target, TargetType::Party);
+        let mut world = World::new();
+        for (obj_id, name) in [(1, "Caster"), (2, "Clanmate")] {
+            let mut player = player(&datapack, obj_id, name, 100, 100);
+            player.character.clan_id = 7;
+            world.add(WorldObject::Player(Box::new(player))).unwrap();
+        }
+        assert_eq!(targets(&world, 1, 1, chant), vec![1]);
+    }
+
+    #[test]
+    fn magic_damage_grows_with_power_and_m_atk() {
+        assert!(magic_damage(24.0, 100.0, 60.0) > magic_damage(12.0, 100.0, 60.0));
+        assert!(magic_damage(12.0, 400.0, 60.0) > magic_damage(12.0, 100.0, 60.0));
+        assert_eq!(magic_damage(10.0, 100.0, 91.0), 100.0);
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::gameserver::datapack::spawns::SpawnLocation;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::npc::Npc;
+use crate::gameserver::world::{World, WorldObject};
+
+const RESPAWN_TICK: Duration = Duration::from_secs(1);
+/// How long the corpse of an NPC stays before it goes away.
+pub const DECAY_TIME: Duration = Duration::from_millis(8500);
+
+/// Puts one NPC of a spawn in the world, returning its object id.
+pub fn spawn_npc(context: &Context, world: &mut World, index: usize) -> Result<u32, String> {
+    let spawn = match context.datapack.spawns.get(index) {
+        Some(spawn) => spawn,
+        None => return Err(format!("No spawn {}", index)),
+    };
+    let template = match context.datapack.npcs.get(spawn.npc_id) {
+        Some(template) => template,
+        None => return Err(format!("No template for npc {} of spawn {}", spawn.npc_id, index)),
+    };
+
+    let ((x, y, z), heading) = spawn.place(&mut rand::thread_rng());
+    // Random points are taken at the top of the territory, the NPC stands on the ground below.
+    let z = match &spawn.location {
+        SpawnLocation::Territory(territory) => context.geodata.height(x, y, z).clamp(territory.min_z, territory.max_z),
+        SpawnLocation::Point { .. } => z,
+    };
+    let obj_id = context.ids.next_id()?;
+    world.add(WorldObject::Npc(Box::new(Npc::new(obj_id, template, (x, y, z), heading, Some(index)))))?;
+    Ok(obj_id)
+}
+
+/// Fills the world with every NPC of the spawn table.
+pub fn spawn_all(context: &Context) {
+    let mut world = context.world();
+    let mut spawned = 0;
+    for (index, spawn) in context.datapack.spawns.iter() {
+        for _ in 0..spawn.count {
+            match spawn_npc(context, &mut world, index) {
+                Ok(_) => spawned += 1,
+                Err(e) => warn!("Couldn't spawn npc {}: {}", spawn.npc_id, e),
+            }
+        }
+    }
+    info!("Spawned {} npcs", spawned);
+}
+
+/// Takes an NPC out of the world. One placed by a spawn comes back after the respawn delay of the spawn.
+pub fn despawn(context: &Context, world: &mut World, obj_id: u32) {
+    let index = match world.npc(obj_id) {
+        Some(npc) => npc.spawn,
+        None => return,
+    };
+    world.remove(obj_id);
+    context.ids.release(obj_id);
+    if let Some((index, spawn)) = index.and_then(|index| context.datapack.spawns.get(index).map(|spawn| (index, spawn))) {
+        world.schedule_respawn(index, Instant::now() + spawn.respawn_delay(&mut rand::thread_rng()));
+    }
+}
+
+/// Clears the corpses that stayed long enough and brings back the NPCs whose respawn delay is over.
+pub async fn run(context: Arc<Context>) {
+    let mut interval = tokio::time::interval(RESPAWN_TICK);
+    loop {
+        interval.tick().await;
+        let mut world = context.world();
+        let now = Instant::now();
+        for obj_id in world.due_decays(now) {
+            // The id may have gone to another NPC since, only a corpse is taken away.
+            if world.npc(obj_id).is_some_and(|npc| npc.is_dead()) {
+                despawn(&context, &mut world, obj_id);
+            }
+        }
+        for index in world.due_respawns(now) {
+            if let Err(e) = spawn_npc(&context, &mut world, index) {
+                warn!("Couldn't respawn: {}", e);
+            }
+        }
+    }
+}
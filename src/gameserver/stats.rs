@@ -0,0 +1,498 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::gameserver::combat;
+use crate::gameserver::datapack::classes::ClassTemplate;
+use crate::gameserver::datapack::items::WeaponType;
+use crate::gameserver::datapack::npcs::NpcTemplate;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::npc::Npc;
+use crate::gameserver::player::Player;
+use crate::gameserver::server::world as world_response;
+use crate::gameserver::world::{World, WorldObject};
+
+/// How often HP, MP and CP come back, the regen stats are what comes back each time.
+const REGEN_TICK: Duration = Duration::from_secs(3);
+/// Critical rate out of 1000 without a weapon, and of NPCs.
+const BASE_CRITICAL: f64 = 40.0;
+/// Dexterity NPCs aim and dodge with, the datapack has none for them.
+const NPC_DEX: f64 = 30.0;
+
+/// A derived stat of a creature.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Stat {
+    MaxHp,
+    MaxMp,
+    MaxCp,
+    PAtk,
+    MAtk,
+    PDef,
+    MDef,
+    PAtkSpd,
+    MAtkSpd,
+    RunSpeed,
+    WalkSpeed,
+    Accuracy,
+    Evasion,
+    /// Critical rate out of 1000.
+    Critical,
+    HpRegen,
+    MpRegen,
+    CpRegen,
+}
+
+impl Stat {
+    pub const ALL: [Stat; 17] = [
+        Stat::MaxHp, Stat::MaxMp, Stat::MaxCp, Stat::PAtk, Stat::MAtk, Stat::PDef, Stat::MDef, Stat::PAtkSpd,
+        Stat::MAtkSpd, Stat::RunSpeed, Stat::WalkSpeed, Stat::Accuracy, Stat::Evasion, Stat::Critical,
+        Stat::HpRegen, Stat::MpRegen, Stat::CpRegen,
+    ];
+
+    /// StatusUpdate attribute carrying the stat, `None` for the ones only UserInfo shows or none at all.
+    fn status_attribute(self) -> Option<u32> {
+        match self {
+            Stat::MaxHp => Some(world_response::STATUS_MAX_HP),
+            Stat::MaxMp => Some(world_response::STATUS_MAX_MP),
+            Stat::MaxCp => Some(world_response::STATUS_MAX_CP),
+            Stat::PAtk => Some(world_response::STATUS_P_ATK),
+            Stat::MAtk => Some(world_response::STATUS_M_ATK),
+            Stat::PDef => Some(world_response::STATUS_P_DEF),
+            Stat::MDef => Some(world_response::STATUS_M_DEF),
+            Stat::PAtkSpd => Some(world_response::STATUS_ATK_SPD),
+            Stat::MAtkSpd => Some(world_response::STATUS_CAST_SPD),
+            Stat::Accuracy => Some(world_response::STATUS_ACCURACY),
+            Stat::Evasion => Some(world_response::STATUS_EVASION),
+            Stat::Critical => Some(world_response::STATUS_CRITICAL),
+            Stat::RunSpeed | Stat::WalkSpeed | Stat::HpRegen | Stat::MpRegen | Stat::CpRegen => None,
+        }
+    }
+
+    /// Whether others see the stat change, which takes resending the whole appearance.
+    fn changes_look(self) -> bool {
+        matches!(self, Stat::RunSpeed | Stat::WalkSpeed | Stat::PAtkSpd | Stat::MAtkSpd)
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Set,
+    Add,
+    Mul,
+}
+
+impl Op {
+    /// Order of a function whose data gives none: values are set first, then multiplied, then added to.
+    pub fn default_order(self) -> u8 {
+        match self {
+            Op::Set => 0x08,
+            Op::Mul => 0x30,
+            Op::Add => 0x40,
+        }
+    }
+}
+
+/// When a function applies, it is skipped otherwise.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// Holding a weapon of this type.
+    Weapon(WeaponType),
+    /// HP below this percent of max HP.
+    HpBelow(f64),
+}
+
+impl Condition {
+    fn holds(&self, env: &Env) -> bool {
+        match *self {
+            Condition::Weapon(weapon) => env.weapon == Some(weapon),
+            Condition::HpBelow(percent) => env.hp_ratio * 100.0 < percent,
+        }
+    }
+}
+
+/// What put a function on a creature, and takes it off again.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Source {
+    /// Item by object id.
+    Item(u32),
+    /// Passive skill by skill id.
+    Skill(u32),
+    /// Buff or debuff by skill id.
+    Effect(u32),
+}
+
+/// One step of the computation of a stat.
+#[derive(Clone, Copy, Debug)]
+pub struct Func {
+    pub stat: Stat,
+    pub op: Op,
+    pub value: f64,
+    /// Functions run from the lowest order up.
+    pub order: u8,
+    pub condition: Option<Condition>,
+    pub source: Source,
+}
+
+/// A function as the datapack gives it, put on creatures by items, passive skills and effects.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FuncTemplate {
+    pub stat: Stat,
+    pub op: Op,
+    pub value: f64,
+    pub order: Option<u8>,
+    pub condition: Option<Condition>,
+}
+
+impl FuncTemplate {
+    pub fn to_func(self, source: Source) -> Func {
+        Func {
+            stat: self.stat,
+            op: self.op,
+            value: self.value,
+            order: self.order.unwrap_or_else(|| self.op.default_order()),
+            condition: self.condition,
+            source,
+        }
+    }
+}
+
+/// State of a creature the conditions look at.
+pub struct Env {
+    pub weapon: Option<WeaponType>,
+    /// Current HP over max HP.
+    pub hp_ratio: f64,
+}
+
+/// Stat functions of a creature, kept in the order they run.
+#[derive(Default)]
+pub struct Calculator {
+    funcs: Vec<Func>,
+}
+
+impl Calculator {
+    /// Adds a function after those of the same order, which run in the order they were added.
+    pub fn add(&mut self, func: Func) {
+        let at = self.funcs.partition_point(|other| other.order <= func.order);
+        self.funcs.insert(at, func);
+    }
+
+    /// Takes off every function `source` put on.
+    pub fn remove(&mut self, source: Source) {
+        self.funcs.retain(|func| func.source != source);
+    }
+
+    /// Takes off the functions of every item, for the equipment to put its own back.
+    pub fn remove_items(&mut self) {
+        self.funcs.retain(|func| !matches!(func.source, Source::Item(_)));
+    }
+
+    /// Takes off the functions of every passive skill, for the skills known to put their own back.
+    pub fn remove_passives(&mut self) {
+        self.funcs.retain(|func| !matches!(func.source, Source::Skill(_)));
+    }
+
+    /// Runs the functions of `stat` that apply on its base value.
+    pub fn calc(&self, stat: Stat, base: f64, env: &Env) -> f64 {
+        self.funcs.iter()
+            .filter(|func| func.stat == stat && func.condition.is_none_or(|condition| condition.holds(env)))
+            .fold(base, |value, func| match func.op {
+                Op::Set => func.value,
+                Op::Add => value + func.value,
+                Op::Mul => value * func.value,
+            })
+            .max(0.0)
+    }
+
+    /// Every stat from its base value.
+    pub fn compute(&self, bases: &Stats, env: &Env) -> Stats {
+        let values = Stat::ALL.into_iter().map(|stat| (stat, self.calc(stat, bases.get(stat), env))).collect();
+        Stats { values }
+    }
+}
+
+/// Values of the stats of a creature.
+#[derive(Default, Clone)]
+pub struct Stats {
+    values: BTreeMap<Stat, f64>,
+}
+
+impl Stats {
+    pub fn get(&self, stat: Stat) -> f64 {
+        self.values.get(&stat).copied().unwrap_or(0.0)
+    }
+
+    /// Stats whose value differs from `old` as the client sees them, rounded down.
+    pub fn changed(&self, old: &Stats) -> Vec<Stat> {
+        Stat::ALL.into_iter().filter(|stat| self.get(*stat) as u32 != old.get(*stat) as u32).collect()
+    }
+}
+
+// Bonuses of the base stats, after the curves of the official tables.
+fn str_bonus(str: f64) -> f64 {
+    1.036f64.powf(str - 34.845)
+}
+
+fn int_bonus(int: f64) -> f64 {
+    1.020f64.powf(int - 31.375)
+}
+
+fn dex_bonus(dex: f64) -> f64 {
+    1.009f64.powf(dex - 19.360)
+}
+
+fn wit_bonus(wit: f64) -> f64 {
+    1.050f64.powf(wit - 20.000)
+}
+
+fn con_bonus(con: f64) -> f64 {
+    1.030f64.powf(con - 27.632)
+}
+
+fn men_bonus(men: f64) -> f64 {
+    1.010f64.powf(men - 0.060)
+}
+
+fn level_mod(level: u8) -> f64 {
+    (level as f64 + 89.0) / 100.0
+}
+
+/// Accuracy and evasion given by dexterity and level.
+fn aim(dex: f64, level: u8) -> f64 {
+    dex.sqrt() * 6.0 + level as f64
+}
+
+/// HP, MP and CP regained every regen tick before any bonus.
+fn regen_bases(level: u8) -> (f64, f64, f64) {
+    let level = level as f64;
+    if level <= 10.0 {
+        (1.5 + level / 10.0, 0.9, 5.0 + level / 10.0)
+    } else {
+        (1.4 + level / 10.0, 0.9 + 0.03 * (level - 10.0), 4.0 + level / 10.0)
+    }
+}
+
+/// Stats of a player before its stat functions: the class at its level with what it wears.
+pub fn player_bases(player: &Player, class: &ClassTemplate) -> Stats {
+    let level = player.character.level;
+    let base = &class.stats;
+    let (str, dex, con, int, wit, men) =
+        (base.str as f64, base.dex as f64, base.con as f64, base.int as f64, base.wit as f64, base.men as f64);
+    let level_mod = level_mod(level);
+    let weapon = player.equipment.weapon;
+    let p_atk = weapon.map_or(class.combat.p_atk, |weapon| weapon.p_atk) as f64;
+    let m_atk = weapon.map_or(class.combat.m_atk, |weapon| weapon.m_atk) as f64;
+    let p_atk_spd = weapon.map_or(class.combat.p_atk_spd, |weapon| weapon.atk_speed) as f64;
+    let critical = weapon.map_or(BASE_CRITICAL, |weapon| weapon.critical as f64 * 10.0);
+    let (hp_regen, mp_regen, cp_regen) = regen_bases(level);
+
+    let values = [
+        (Stat::MaxHp, class.max_hp(level) * con_bonus(con)),
+        (Stat::MaxMp, class.max_mp(level) * men_bonus(men)),
+        (Stat::MaxCp, class.max_cp(level) * con_bonus(con)),
+        (Stat::PAtk, p_atk * str_bonus(str) * level_mod),
+        (Stat::MAtk, m_atk * (int_bonus(int) * level_mod).powi(2)),
+        (Stat::PDef, (class.combat.p_def + player.equipment.p_def) as f64 * level_mod),
+        (Stat::MDef, (class.combat.m_def + player.equipment.m_def) as f64 * men_bonus(men) * level_mod),
+        (Stat::PAtkSpd, p_atk_spd * dex_bonus(dex)),
+        (Stat::MAtkSpd, class.combat.m_atk_spd as f64 * wit_bonus(wit)),
+        (Stat::RunSpeed, class.run_speed as f64 * dex_bonus(dex)),
+        (Stat::WalkSpeed, class.walk_speed as f64 * dex_bonus(dex)),
+        (Stat::Accuracy, aim(dex, level)),
+        (Stat::Evasion, aim(dex, level)),
+        (Stat::Critical, critical * dex_bonus(dex)),
+        (Stat::HpRegen, hp_regen * level_mod * con_bonus(con)),
+        (Stat::MpRegen, mp_regen * level_mod * men_bonus(men)),
+        (Stat::CpRegen, cp_regen * level_mod * con_bonus(con)),
+    ];
+    Stats { values: values.into_iter().collect() }
+}
+
+/// Stats of an NPC before its stat functions, the template already gives them at its level.
+pub fn npc_bases(npc: &Npc, template: &NpcTemplate) -> Stats {
+    let stats = &template.stats;
+    let (hp_regen, mp_regen, _) = regen_bases(npc.level);
+    let level_mod = level_mod(npc.level);
+    let values = [
+        (Stat::MaxHp, stats.hp),
+        (Stat::MaxMp, stats.mp),
+        (Stat::PAtk, stats.p_atk as f64),
+        (Stat::MAtk, stats.m_atk as f64),
+        (Stat::PDef, stats.p_def as f64),
+        (Stat::MDef, stats.m_def as f64),
+        (Stat::PAtkSpd, stats.p_atk_spd as f64),
+        (Stat::MAtkSpd, stats.m_atk_spd as f64),
+        (Stat::RunSpeed, stats.run_speed as f64),
+        (Stat::WalkSpeed, stats.walk_speed as f64),
+        (Stat::Accuracy, aim(NPC_DEX, npc.level)),
+        (Stat::Evasion, aim(NPC_DEX, npc.level)),
+        (Stat::Critical, BASE_CRITICAL),
+        (Stat::HpRegen, hp_regen * level_mod),
+        (Stat::MpRegen, mp_regen * level_mod),
+    ];
+    Stats { values: values.into_iter().collect() }
+}
+
+/// Recomputes the stats of a creature after something they depend on changed, and shows the ones that did.
+/// Speeds change its look for everyone, other stats only go to the player itself.
+pub fn refresh(context: &Context, world: &mut World, obj_id: u32) {
+    let changed = match world.get_mut(obj_id) {
+        Some(WorldObject::Player(player)) => match context.datapack.classes.get(player.character.class_id) {
+            Some(class) => player.refresh_stats(class),
+            None => return,
+        },
+        Some(WorldObject::Npc(npc)) => match context.datapack.npcs.get(npc.npc_id) {
+            Some(template) => npc.refresh_stats(template),
+            None => return,
+        },
+        None => return,
+    };
+    if changed.is_empty() {
+        return;
+    }
+    if changed.contains(&Stat::MaxHp) || changed.contains(&Stat::MaxMp) || changed.contains(&Stat::MaxCp) {
+        world.start_recovering(obj_id);
+    }
+    if changed.contains(&Stat::MaxHp) {
+        combat::show_hp(world, obj_id);
+    }
+
+    if changed.iter().any(|stat| stat.changes_look()) {
+        if let Some(player) = world.player(obj_id) {
+            if let Some(class) = context.datapack.classes.get(player.character.class_id) {
+                player.send(world_response::user_info(player, class));
+            }
+        }
+        world.broadcast_info(obj_id);
+        return;
+    }
+    if let Some(player) = world.player(obj_id) {
+        let attributes: Vec<(u32, u32)> = changed.iter()
+            .filter_map(|stat| stat.status_attribute().map(|attribute| (attribute, player.stats.get(*stat) as u32)))
+            .collect();
+        if !attributes.is_empty() {
+            player.send(world_response::status_update(obj_id, &attributes));
+        }
+    }
+}
+
+/// Gives HP, MP and CP back to every creature missing some for as long as the server runs.
+pub async fn run(context: Arc<Context>) {
+    let mut interval = tokio::time::interval(REGEN_TICK);
+    loop {
+        interval.tick().await;
+        regenerate(&context, &mut context.world());
+    }
+}
+
+/// How much of its regen a player gets: more sitting or standing still, less running.
+fn regen_rate(player: &Player) -> f64 {
+    if player.sitting {
+        1.5
+    } else if player.movement.is_none() {
+        1.1
+    } else if player.running {
+        0.7
+    } else {
+        1.0
+    }
+}
+
+fn regenerate(context: &Context, world: &mut World) {
+    let recovering: HashSet<u32> = world.recovering().into_iter().collect();
+    for obj_id in recovering {
+        let full = match world.get_mut(obj_id) {
+            Some(WorldObject::Player(player)) if !player.is_dead() => regenerate_player(player),
+            Some(WorldObject::Npc(npc)) if !npc.is_dead() => regenerate_npc(npc),
+            _ => {
+                world.stop_recovering(obj_id);
+                continue;
+            }
+        };
+        // Functions may depend on HP.
+        refresh(context, world, obj_id);
+        combat::show_hp(world, obj_id);
+        if full {
+            world.stop_recovering(obj_id);
+        }
+    }
+}
+
+/// Gives a player its regen and shows its MP and CP, returns whether it is full again. HP is shown to its
+/// targeters too, by the caller.
+fn regenerate_player(player: &mut Player) -> bool {
+    let rate = regen_rate(player);
+    let character = &mut player.character;
+    character.cur_hp = (character.cur_hp + player.stats.get(Stat::HpRegen) * rate).min(character.max_hp);
+    character.cur_mp = (character.cur_mp + player.stats.get(Stat::MpRegen) * rate).min(character.max_mp);
+    character.cur_cp = (character.cur_cp + player.stats.get(Stat::CpRegen) * rate).min(character.max_cp);
+    let full = character.cur_hp >= character.max_hp && character.cur_mp >= character.max_mp && character.cur_cp >= character.max_cp;
+    let packet = world_response::status_update(character.obj_id, &[
+        (world_response::STATUS_CUR_MP, character.cur_mp as u32),
+        (world_response::STATUS_CUR_CP, character.cur_cp as u32),
+    ]);
+    player.send(packet);
+    full
+}
+
+fn regenerate_npc(npc: &mut Npc) -> bool {
+    let (max_hp, max_mp) = (npc.stats.get(Stat::MaxHp), npc.stats.get(Stat::MaxMp));
+    npc.cur_hp = (npc.cur_hp + npc.stats.get(Stat::HpRegen)).min(max_hp);
+    npc.cur_mp = (npc.cur_mp + npc.stats.get(Stat::MpRegen)).min(max_mp);
+    npc.cur_hp >= max_hp && npc.cur_mp >= max_mp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn func(stat: Stat, op: Op, value: f64, condition: Option<Condition>, source: Source) -> Func {
+        Func { stat, op, value, order: op.default_order(), condition, source }
+    }
+
+    #[test]
+    fn functions_run_in_order_when_they_apply() {
+        let env = Env { weapon: Some(WeaponType::Bow), hp_ratio: 0.5 };
+        let mut calculator = Calculator::default();
+        calculator.add(func(Stat::PAtk, Op::Add, 10.0, None, Source::Effect(1)));
+        calculator.add(func(Stat::PAtk, Op::Mul, 2.0, None, Source::Skill(2)));
+        assert_eq!(calculator.calc(Stat::PAtk, 100.0, &env), 210.0);
+
+        calculator.add(func(Stat::PAtk, Op::Mul, 1.5, Some(Condition::Weapon(WeaponType::Sword)), Source::Item(3)));
+        calculator.add(func(Stat::PAtk, Op::Add, 5.0, Some(Condition::HpBelow(60.0)), Source::Item(4)));
+        assert_eq!(calculator.calc(Stat::PAtk, 100.0, &env), 215.0);
+        assert_eq!(calculator.calc(Stat::PDef, 100.0, &env), 100.0);
+
+        calculator.add(func(Stat::PAtk, Op::Set, 50.0, None, Source::Item(5)));
+        assert_eq!(calculator.calc(Stat::PAtk, 100.0, &env), 115.0);
+        calculator.remove_items();
+        calculator.remove(Source::Effect(1));
+        assert_eq!(calculator.calc(Stat::PAtk, 100.0, &env), 200.0);
+        calculator.remove_passives();
+        assert_eq!(calculator.calc(Stat::PAtk, 100.0, &env), 100.0);
+    }
+
+    #[test]
+    fn only_visible_changes_are_reported() {
+        let calculator = Calculator::default();
+        let env = Env { weapon: None, hp_ratio: 1.0 };
+        let bases = Stats { values: [(Stat::PAtk, 100.2), (Stat::HpRegen, 3.0)].into_iter().collect() };
+        let old = calculator.compute(&bases, &env);
+
+        let bases = Stats { values: [(Stat::PAtk, 100.9), (Stat::HpRegen, 3.5), (Stat::RunSpeed, 120.0)].into_iter().collect() };
+        assert_eq!(calculator.compute(&bases, &env).changed(&old), vec![Stat::RunSpeed]);
+    }
+
+    #[test]
+    fn base_stat_bonuses_follow_the_tables() {
+        assert!((str_bonus(40.0) - 1.20).abs() < 0.01);
+        assert!((dex_bonus(30.0) - 1.10).abs() < 0.01);
+        assert!((con_bonus(43.0) - 1.58).abs() < 0.01);
+        assert_eq!(level_mod(11), 1.0);
+    }
+}
@@ -0,0 +1,903 @@
+use std::collections::HashSet;
+
+use log::{info, warn};
+
+use crate::database::characters;
+use crate::database::items;
+use crate::database::private_stores::{self, StoredItem, StoredStore};
+use crate::gameserver::client::store as request;
+use crate::gameserver::datapack::items::{ItemRegistry, ADENA};
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::inventory::{self, Inventory, InventoryError, DWARF, MAX_COUNT};
+use crate::gameserver::lobby::now_millis;
+use crate::gameserver::models::{Client, ClientState, Sender};
+use crate::gameserver::player::Player;
+use crate::gameserver::server::lobby::action_failed;
+use crate::gameserver::server::store as response;
+use crate::gameserver::server::system_message;
+use crate::gameserver::server::world as world_response;
+use crate::gameserver::trade;
+use crate::gameserver::world::{World, WorldObject};
+
+/// Longest store title the client lets players type.
+const MAX_MESSAGE_LENGTH: usize = 29;
+/// Distance a customer can use a store from.
+const STORE_DISTANCE: f64 = 150.0;
+/// Recipes a player can list in a manufacture store. There is no recipe book yet, so none.
+const KNOWN_RECIPES: &[u32] = &[];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StoreKind {
+    Sell,
+    /// Sells everything listed to a single customer at once.
+    PackageSell,
+    Buy,
+    /// Crafts the recipes listed, for a fee, from the materials of the customer.
+    Manufacture,
+}
+
+impl StoreKind {
+    /// Store type as the client knows it.
+    pub fn store_type(self) -> u8 {
+        match self {
+            StoreKind::Sell => 1,
+            StoreKind::Buy => 3,
+            StoreKind::Manufacture => 5,
+            StoreKind::PackageSell => 8,
+        }
+    }
+
+    fn from_store_type(store_type: u8) -> Option<StoreKind> {
+        match store_type {
+            1 => Some(StoreKind::Sell),
+            3 => Some(StoreKind::Buy),
+            8 => Some(StoreKind::PackageSell),
+            _ => None,
+        }
+    }
+
+    fn sells(self) -> bool {
+        matches!(self, StoreKind::Sell | StoreKind::PackageSell)
+    }
+}
+
+#[derive(Clone)]
+pub struct StoreItem {
+    /// Object id of an item sold, item id of an item bought, or recipe id of a recipe made.
+    pub id: u32,
+    /// Always 1 for a recipe, made once per request.
+    pub count: u64,
+    /// Adena for each.
+    pub price: u64,
+}
+
+/// What a player sells or buys. Kept while the store is closed so it opens again the way it was.
+#[derive(Default)]
+pub struct StoreList {
+    pub items: Vec<StoreItem>,
+    pub message: String,
+    /// Sell lists only, everything goes to the same customer.
+    pub package: bool,
+}
+
+fn store_slots(context: &Context, player: &Player) -> usize {
+    let conf = &context.conf.stores;
+    if player.character.race == DWARF { conf.dwarf_slots } else { conf.slots }
+}
+
+/// Title of the store of a player, for the players around.
+pub fn message(player: &Player) -> Option<Vec<u8>> {
+    match player.store {
+        Some(StoreKind::Buy) => Some(response::private_store_msg_buy(player.obj_id(), &player.buy_list.message)),
+        Some(StoreKind::Manufacture) => Some(response::recipe_shop_msg(player.obj_id(), &player.manufacture_list.message)),
+        Some(_) => Some(response::private_store_msg_sell(player.obj_id(), &player.sell_list.message)),
+        None => None,
+    }
+}
+
+/// The store of a player as it is written for an offline store. Manufacture stores are not kept offline.
+fn stored(player: &Player) -> Option<StoredStore> {
+    let kind = player.store?;
+    let list = match kind {
+        StoreKind::Sell | StoreKind::PackageSell => &player.sell_list,
+        StoreKind::Buy => &player.buy_list,
+        StoreKind::Manufacture => return None,
+    };
+    Some(StoredStore {
+        obj_id: player.obj_id(),
+        store_type: kind.store_type(),
+        message: list.message.clone(),
+        items: list.items.iter().map(|item| StoredItem { item: item.id, count: item.count, price: item.price }).collect(),
+    })
+}
+
+/// Shows a player sitting down or standing up for its store, along with what the store changed in its look.
+fn show_store_state(context: &Context, world: &World, obj_id: u32) {
+    let player = match world.player(obj_id) {
+        Some(player) => player,
+        None => return,
+    };
+    world.broadcast_with_self(obj_id, &world_response::change_wait_type(player));
+    if let Some(class) = context.datapack.classes.get(player.character.class_id) {
+        player.send(world_response::user_info(player, class));
+    }
+    world.broadcast_info(obj_id);
+}
+
+fn open_store(context: &Context, world: &mut World, obj_id: u32, kind: StoreKind) {
+    if let Some(player) = world.player_mut(obj_id) {
+        player.store = Some(kind);
+        player.sitting = true;
+    }
+    show_store_state(context, world, obj_id);
+}
+
+fn close_store(context: &Context, world: &mut World, obj_id: u32) {
+    match world.player_mut(obj_id) {
+        Some(player) if player.store.is_some() => {
+            player.store = None;
+            player.sitting = false;
+        },
+        _ => return,
+    }
+    show_store_state(context, world, obj_id);
+}
+
+/// Checks a player can set up a store, telling it why not otherwise.
+fn can_set_up(player: &Player) -> bool {
+    if player.trade.is_some() {
+        player.send(system_message::text("You can't open a store while trading."));
+        return false;
+    }
+    true
+}
+
+fn player_or_err(world: &World, obj_id: u32) -> Result<&Player, String> {
+    match world.player(obj_id) {
+        Some(player) => Ok(player),
+        None => Err(format!("Player {} is not in the world", obj_id)),
+    }
+}
+
+/// Opens the window to set up a sell store, closing the store while it is set up.
+pub async fn manage_sell(context: &Context, client: &mut Client) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let mut world = context.world();
+    if !can_set_up(player_or_err(&world, obj_id)?) {
+        return Ok(());
+    }
+    close_store(context, &mut world, obj_id);
+    let player = player_or_err(&world, obj_id)?;
+    player.send(response::private_store_manage_list_sell(player, &context.datapack.items));
+    Ok(())
+}
+
+/// Opens the window to set up a buy store, closing the store while it is set up.
+pub async fn manage_buy(context: &Context, client: &mut Client) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let mut world = context.world();
+    if !can_set_up(player_or_err(&world, obj_id)?) {
+        return Ok(());
+    }
+    close_store(context, &mut world, obj_id);
+    let player = player_or_err(&world, obj_id)?;
+    player.send(response::private_store_manage_list_buy(player, &context.datapack.items));
+    Ok(())
+}
+
+/// Opens the window to set up a manufacture store with the recipes of the dwarven recipe book, closing the store
+/// while it is set up.
+pub async fn manage_manufacture(context: &Context, client: &mut Client) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let mut world = context.world();
+    if !can_set_up(player_or_err(&world, obj_id)?) {
+        return Ok(());
+    }
+    close_store(context, &mut world, obj_id);
+    let player = player_or_err(&world, obj_id)?;
+    player.send(response::recipe_shop_manage_list(player, KNOWN_RECIPES));
+    Ok(())
+}
+
+pub async fn quit(context: &Context, client: &mut Client) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    close_store(context, &mut context.world(), obj_id);
+    Ok(())
+}
+
+/// Checks the items of a sell list: distinct items of the inventory that are not worn or adena, with the count
+/// there is of them and a total price that fits in the adena a player can hold.
+fn check_sell_list(inventory: &Inventory, items: &[StoreItem]) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for listed in items {
+        let item = match inventory.get(listed.id) {
+            Some(item) if seen.insert(listed.id) => item,
+            _ => return Err(format!("item {} is missing or listed twice", listed.id)),
+        };
+        if item.loc == items::LOC_PAPERDOLL || item.item_id == ADENA {
+            return Err(format!("item {} can't be sold", listed.id));
+        }
+        if listed.count == 0 || listed.count > item.count {
+            return Err(format!("listed {} of item {} which has {}", listed.count, listed.id, item.count));
+        }
+        if listed.count * listed.price > MAX_COUNT {
+            return Err(format!("item {} is sold for more adena than can be held", listed.id));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the recipes of a manufacture list: distinct recipes of the recipe book, for a fee that fits in the
+/// adena a player can hold.
+fn check_manufacture_list(known: &[u32], recipes: &[StoreItem]) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for listed in recipes {
+        if !known.contains(&listed.id) || !seen.insert(listed.id) {
+            return Err(format!("recipe {} is unknown or listed twice", listed.id));
+        }
+        if listed.price > MAX_COUNT {
+            return Err(format!("recipe {} is made for more adena than can be held", listed.id));
+        }
+    }
+    Ok(())
+}
+
+pub async fn set_list_sell(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let list = request::new_set_private_store_list_sell(data)?;
+
+    let mut world = context.world();
+    let player = player_or_err(&world, obj_id)?;
+    if !can_set_up(player) {
+        return Ok(());
+    }
+    if list.items.len() > store_slots(context, player) {
+        player.send(system_message::text("You have listed more items than your store can hold."));
+        return Ok(());
+    }
+    if let Err(e) = check_sell_list(&player.inventory, &list.items) {
+        return Err(format!("{} sent a bad sell list: {}", player.character.char_name, e));
+    }
+
+    let empty = list.items.is_empty();
+    let kind = if list.package { StoreKind::PackageSell } else { StoreKind::Sell };
+    if let Some(player) = world.player_mut(obj_id) {
+        player.sell_list.items = list.items;
+        player.sell_list.package = list.package;
+    }
+    if empty {
+        close_store(context, &mut world, obj_id);
+    } else {
+        open_store(context, &mut world, obj_id, kind);
+    }
+    Ok(())
+}
+
+pub async fn set_list_buy(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let items = request::new_set_private_store_list_buy(data)?;
+
+    let mut world = context.world();
+    let player = player_or_err(&world, obj_id)?;
+    if !can_set_up(player) {
+        return Ok(());
+    }
+    if items.len() > store_slots(context, player) {
+        player.send(system_message::text("You have listed more items than your store can hold."));
+        return Ok(());
+    }
+    let mut seen = HashSet::new();
+    let mut total = 0;
+    for wanted in &items {
+        let valid = seen.insert(wanted.id)
+            && wanted.id != ADENA
+            && context.datapack.items.get(wanted.id).is_some()
+            && wanted.count > 0
+            && wanted.count * wanted.price <= MAX_COUNT;
+        if !valid {
+            return Err(format!("{} sent a bad buy list entry for item {}", player.character.char_name, wanted.id));
+        }
+        total += wanted.count * wanted.price;
+    }
+    if total > player.inventory.adena() {
+        player.send(system_message::system_message(system_message::YOU_NOT_ENOUGH_ADENA, &[]));
+        return Ok(());
+    }
+
+    let empty = items.is_empty();
+    if let Some(player) = world.player_mut(obj_id) {
+        player.buy_list.items = items;
+    }
+    if empty {
+        close_store(context, &mut world, obj_id);
+    } else {
+        open_store(context, &mut world, obj_id, StoreKind::Buy);
+    }
+    Ok(())
+}
+
+pub async fn set_list_manufacture(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let recipes = request::new_set_recipe_shop_list(data)?;
+
+    let mut world = context.world();
+    let player = player_or_err(&world, obj_id)?;
+    if !can_set_up(player) {
+        return Ok(());
+    }
+    if recipes.len() > store_slots(context, player) {
+        player.send(system_message::text("You have listed more recipes than your store can hold."));
+        return Ok(());
+    }
+    if let Err(e) = check_manufacture_list(KNOWN_RECIPES, &recipes) {
+        return Err(format!("{} sent a bad manufacture list: {}", player.character.char_name, e));
+    }
+
+    let empty = recipes.is_empty();
+    if let Some(player) = world.player_mut(obj_id) {
+        player.manufacture_list.items = recipes;
+    }
+    if empty {
+        close_store(context, &mut world, obj_id);
+    } else {
+        open_store(context, &mut world, obj_id, StoreKind::Manufacture);
+    }
+    Ok(())
+}
+
+fn set_message(context: &Context, obj_id: u32, message: String, sell: bool) -> Result<(), String> {
+    if message.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(format!("Store title of {} characters from player {}", message.chars().count(), obj_id));
+    }
+    let mut world = context.world();
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} is not in the world", obj_id)),
+    };
+    if sell {
+        player.sell_list.message = message;
+    } else {
+        player.buy_list.message = message;
+    }
+    // The title of an open store changes for everyone around right away.
+    if let Some(packet) = message_of_open(player, sell) {
+        world.broadcast_with_self(obj_id, &packet);
+    }
+    Ok(())
+}
+
+fn message_of_open(player: &Player, sell: bool) -> Option<Vec<u8>> {
+    match player.store {
+        Some(kind) if kind != StoreKind::Manufacture && kind.sells() == sell => message(player),
+        _ => None,
+    }
+}
+
+pub async fn set_message_sell(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    set_message(context, obj_id, request::new_set_private_store_msg(data)?, true)
+}
+
+pub async fn set_message_buy(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    set_message(context, obj_id, request::new_set_private_store_msg(data)?, false)
+}
+
+pub async fn set_message_manufacture(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let message = request::new_set_private_store_msg(data)?;
+    if message.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(format!("Store title of {} characters from player {}", message.chars().count(), obj_id));
+    }
+    let mut world = context.world();
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} is not in the world", obj_id)),
+    };
+    player.manufacture_list.message = message;
+    if player.store == Some(StoreKind::Manufacture) {
+        let packet = response::recipe_shop_msg(obj_id, &player.manufacture_list.message);
+        world.broadcast_with_self(obj_id, &packet);
+    }
+    Ok(())
+}
+
+fn in_reach(customer: &Player, store: &Player) -> bool {
+    let (x, y, _) = customer.position();
+    let (store_x, store_y, _) = store.position();
+    ((store_x - x) as f64).hypot((store_y - y) as f64) <= STORE_DISTANCE
+}
+
+/// Shows a customer what a store sells or buys.
+pub fn show_store(context: &Context, world: &World, customer_id: u32, store_id: u32) {
+    let (customer, store) = match (world.player(customer_id), world.player(store_id)) {
+        (Some(customer), Some(store)) if customer_id != store_id => (customer, store),
+        _ => return,
+    };
+    if !in_reach(customer, store) {
+        customer.send(system_message::system_message(system_message::TARGET_TOO_FAR, &[]));
+        customer.send(action_failed());
+        return;
+    }
+    let templates = &context.datapack.items;
+    match store.store {
+        Some(StoreKind::Buy) => customer.send(response::private_store_list_buy(store, customer, templates)),
+        Some(StoreKind::Manufacture) => customer.send(response::recipe_shop_sell_list(store, customer.inventory.adena())),
+        Some(_) => customer.send(response::private_store_list_sell(store, customer.inventory.adena(), templates)),
+        None => customer.send(action_failed()),
+    }
+}
+
+/// Sum of what a request pays, or `None` when it doesn't match the list: items not listed, listed at another
+/// price, asked more than once or more than listed. `key` finds the list entry of a requested item.
+fn price_of(list: &[StoreItem], requested: &[(u32, u64, u64)]) -> Option<u64> {
+    let mut seen = HashSet::new();
+    let mut total = 0;
+    for &(key, count, price) in requested {
+        let listed = list.iter().find(|listed| listed.id == key)?;
+        if !seen.insert(key) || count == 0 || count > listed.count || price != listed.price {
+            return None;
+        }
+        // Counts and prices are read as u32, so a product fits, and stopping past the limit keeps the sum in too.
+        total += count * price;
+        if total > MAX_COUNT {
+            return None;
+        }
+    }
+    Some(total)
+}
+
+/// Whether a request takes all of every item listed, the only way to buy from a package store.
+fn is_whole(list: &[StoreItem], requested: &[(u32, u64, u64)]) -> bool {
+    requested.len() == list.len() && list.iter().all(|listed| requested.iter().any(|(id, count, _)| *id == listed.id && *count == listed.count))
+}
+
+/// Takes what was traded off a store list, dropping the entries with nothing left.
+fn take_from_list(list: &mut StoreList, traded: &[(u32, u64)]) {
+    for &(key, count) in traded {
+        if let Some(listed) = list.items.iter_mut().find(|listed| listed.id == key) {
+            listed.count -= count.min(listed.count);
+        }
+    }
+    list.items.retain(|listed| listed.count > 0);
+}
+
+fn adena_stack(inventory: &Inventory) -> Option<u32> {
+    inventory.items().find(|item| item.item_id == ADENA && item.loc != items::LOC_PAPERDOLL).map(|item| item.object_id)
+}
+
+/// What a sale did to the store, which is closed or saved once the players are no longer borrowed.
+enum AfterSale {
+    Open,
+    Empty,
+    Offline(StoredStore),
+    OfflineEmpty,
+}
+
+fn after_sale(store: &Player, list: &StoreList) -> AfterSale {
+    match (store.offline, list.items.is_empty()) {
+        (false, false) => AfterSale::Open,
+        (false, true) => AfterSale::Empty,
+        (true, true) => AfterSale::OfflineEmpty,
+        (true, false) => match stored(store) {
+            Some(stored) => AfterSale::Offline(stored),
+            None => AfterSale::OfflineEmpty,
+        },
+    }
+}
+
+async fn finish_sale(context: &Context, store_id: u32, after: AfterSale) -> Result<(), String> {
+    match after {
+        AfterSale::Open => Ok(()),
+        AfterSale::Empty => {
+            close_store(context, &mut context.world(), store_id);
+            Ok(())
+        },
+        AfterSale::Offline(stored) => {
+            context.item_writer.save_store(stored);
+            Ok(())
+        },
+        AfterSale::OfflineEmpty => end_offline(context, store_id).await,
+    }
+}
+
+/// Tells a customer why the items didn't change hands.
+fn refuse(customer: &Player, e: InventoryError, customer_receives: bool) {
+    let packet = match e {
+        InventoryError::SlotsFull if customer_receives => system_message::system_message(system_message::SLOTS_FULL, &[]),
+        InventoryError::TooHeavy if customer_receives => system_message::system_message(system_message::WEIGHT_LIMIT_EXCEEDED, &[]),
+        InventoryError::SlotsFull | InventoryError::TooHeavy => system_message::text("The store can't hold these items."),
+        e => system_message::text(&format!("The purchase failed: {}.", e)),
+    };
+    customer.send(packet);
+}
+
+/// Buys from a sell store. The prices and counts sent have to match the store list, which may have changed
+/// since the customer looked at it.
+pub async fn buy(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let purchase = request::new_request_private_store_buy(data)?;
+    let templates = &context.datapack.items;
+
+    let after = {
+        let mut world = context.world();
+        let (customer, store) = match world.two_players_mut(obj_id, purchase.store_id) {
+            Some(players) => players,
+            None => return Ok(()),
+        };
+        let kind = match store.store {
+            Some(kind) if kind.sells() => kind,
+            _ => {
+                customer.send(action_failed());
+                return Ok(());
+            }
+        };
+        if !in_reach(customer, store) {
+            customer.send(system_message::system_message(system_message::TARGET_TOO_FAR, &[]));
+            return Ok(());
+        }
+        if customer.trade.is_some() || customer.store.is_some() || purchase.items.is_empty() {
+            customer.send(action_failed());
+            return Ok(());
+        }
+
+        let requested: Vec<(u32, u64, u64)> = purchase.items.iter().map(|item| (item.id, item.count, item.price)).collect();
+        let total = match price_of(&store.sell_list.items, &requested) {
+            Some(total) if kind != StoreKind::PackageSell || is_whole(&store.sell_list.items, &requested) => total,
+            _ => {
+                customer.send(system_message::text("The store has changed, look at it again."));
+                return Ok(());
+            }
+        };
+        if customer.inventory.adena() < total {
+            customer.send(system_message::system_message(system_message::YOU_NOT_ENOUGH_ADENA, &[]));
+            return Ok(());
+        }
+
+        let goods: Vec<(u32, u64)> = requested.iter().map(|(id, count, _)| (*id, *count)).collect();
+        let payment: Vec<(u32, u64)> = match adena_stack(&customer.inventory) {
+            Some(adena) if total > 0 => vec![(adena, total)],
+            _ => Vec::new(),
+        };
+        let exchange = match inventory::exchange(&mut store.inventory, &goods, &mut customer.inventory, &payment, templates, &context.ids) {
+            Ok(exchange) => exchange,
+            Err(e) => {
+                refuse(customer, e, true);
+                return Ok(());
+            }
+        };
+
+        context.item_writer.write(exchange.writes);
+        inventory::show(context, store, &exchange.first);
+        inventory::show(context, customer, &exchange.second);
+        info!("{} bought {} items from the store of {} for {} adena", customer.character.char_name, goods.len(), store.character.char_name, total);
+        store.send(system_message::text(&format!("{} bought from your store for {} adena.", customer.character.char_name, total)));
+        take_from_list(&mut store.sell_list, &goods);
+        after_sale(store, &store.sell_list)
+    };
+    finish_sale(context, purchase.store_id, after).await
+}
+
+/// Sells to a buy store, from the inventory of the customer.
+pub async fn sell(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let sale = request::new_request_private_store_sell(data)?;
+    let templates = &context.datapack.items;
+
+    let after = {
+        let mut world = context.world();
+        let (customer, store) = match world.two_players_mut(obj_id, sale.store_id) {
+            Some(players) => players,
+            None => return Ok(()),
+        };
+        if store.store != Some(StoreKind::Buy) {
+            customer.send(action_failed());
+            return Ok(());
+        }
+        if !in_reach(customer, store) {
+            customer.send(system_message::system_message(system_message::TARGET_TOO_FAR, &[]));
+            return Ok(());
+        }
+        if customer.trade.is_some() || customer.store.is_some() || sale.items.is_empty() {
+            customer.send(action_failed());
+            return Ok(());
+        }
+
+        let mut seen = HashSet::new();
+        for sold in &sale.items {
+            let valid = match customer.inventory.get(sold.object_id) {
+                Some(item) => item.item_id == sold.item_id && item.loc != items::LOC_PAPERDOLL && sold.count <= item.count,
+                None => false,
+            };
+            if !valid || !seen.insert(sold.object_id) {
+                return Err(format!("{} sold item {} it can't sell", customer.character.char_name, sold.object_id));
+            }
+        }
+        // Several stacks of the same item can be sold against one entry of the list.
+        let mut by_item: Vec<(u32, u64, u64)> = Vec::new();
+        for sold in &sale.items {
+            match by_item.iter_mut().find(|(item_id, _, _)| *item_id == sold.item_id) {
+                Some((_, count, price)) if *price == sold.price => *count += sold.count,
+                Some(_) => {
+                    customer.send(system_message::text("The store has changed, look at it again."));
+                    return Ok(());
+                },
+                None => by_item.push((sold.item_id, sold.count, sold.price)),
+            }
+        }
+        let total = match price_of(&store.buy_list.items, &by_item) {
+            Some(total) => total,
+            None => {
+                customer.send(system_message::text("The store has changed, look at it again."));
+                return Ok(());
+            }
+        };
+        if store.inventory.adena() < total {
+            customer.send(system_message::text("The store can't pay for these items."));
+            return Ok(());
+        }
+
+        let goods: Vec<(u32, u64)> = sale.items.iter().map(|sold| (sold.object_id, sold.count)).collect();
+        let payment: Vec<(u32, u64)> = match adena_stack(&store.inventory) {
+            Some(adena) if total > 0 => vec![(adena, total)],
+            _ => Vec::new(),
+        };
+        let exchange = match inventory::exchange(&mut customer.inventory, &goods, &mut store.inventory, &payment, templates, &context.ids) {
+            Ok(exchange) => exchange,
+            Err(InventoryError::SlotsFull | InventoryError::TooHeavy) => {
+                customer.send(system_message::text("The store can't hold these items."));
+                return Ok(());
+            },
+            Err(e) => {
+                refuse(customer, e, true);
+                return Ok(());
+            }
+        };
+
+        context.item_writer.write(exchange.writes);
+        inventory::show(context, customer, &exchange.first);
+        inventory::show(context, store, &exchange.second);
+        info!("{} sold {} items to the store of {} for {} adena", customer.character.char_name, goods.len(), store.character.char_name, total);
+        store.send(system_message::text(&format!("{} sold to your store for {} adena.", customer.character.char_name, total)));
+        let bought: Vec<(u32, u64)> = by_item.iter().map(|(item_id, count, _)| (*item_id, *count)).collect();
+        take_from_list(&mut store.buy_list, &bought);
+        after_sale(store, &store.buy_list)
+    };
+    finish_sale(context, sale.store_id, after).await
+}
+
+/// Leaves the player of a client in the world with its store open, when offline stores are enabled and it has
+/// one open. Nothing controls the player afterwards, the client leaves it behind. Returns whether it stayed.
+pub fn stay_offline(context: &Context, client: &mut Client) -> bool {
+    let obj_id = match client.obj_id {
+        Some(obj_id) if context.conf.stores.offline && client.state == ClientState::InGame => obj_id,
+        _ => return false,
+    };
+
+    let mut world = context.world();
+    trade::cancel(&mut world, obj_id);
+    let (player, stored) = match world.player_mut(obj_id) {
+        Some(player) => match stored(player) {
+            Some(stored) => (player, stored),
+            None => return false,
+        },
+        None => return false,
+    };
+    player.offline = true;
+    player.sender = Sender::detached();
+    player.target = None;
+    player.dialog = None;
+    player.merchant = None;
+    player.open_warehouse = None;
+    player.trade_request = None;
+    context.item_writer.save_store(stored);
+    info!("{} stays in the world with its store open", player.character.char_name);
+
+    client.obj_id = None;
+    client.state = ClientState::Authed;
+    true
+}
+
+/// Takes a player left offline with its store out of the world, once the store is empty or the owner logs in
+/// again.
+pub async fn end_offline(context: &Context, obj_id: u32) -> Result<(), String> {
+    let player = {
+        let mut world = context.world();
+        if !world.player(obj_id).is_some_and(|player| player.offline) {
+            return Ok(());
+        }
+        match world.remove(obj_id) {
+            Some(WorldObject::Player(player)) => player,
+            _ => return Ok(()),
+        }
+    };
+
+    context.item_writer.remove_store(obj_id);
+    context.item_writer.flush().await;
+    if context.item_writer.has_failed(obj_id) {
+        return Err(format!("Items of {} couldn't be saved, not saving the character either", player.character.char_name));
+    }
+    let mut character = player.character;
+    characters::update_on_logout(&context.database, &mut character, now_millis()).await?;
+    info!("Offline store of {} closed", character.char_name);
+    Ok(())
+}
+
+/// What of a stored list goes back up. Only what is still there is sold, the inventory may have changed since,
+/// and only items that still exist are bought.
+fn restored_items(kind: StoreKind, inventory: &Inventory, templates: &ItemRegistry, stored: &[StoredItem]) -> Vec<StoreItem> {
+    stored.iter()
+        .filter_map(|stored| {
+            let count = if kind.sells() {
+                match inventory.get(stored.item) {
+                    Some(item) if item.loc != items::LOC_PAPERDOLL => stored.count.min(item.count),
+                    _ => 0,
+                }
+            } else if templates.get(stored.item).is_some() {
+                stored.count
+            } else {
+                0
+            };
+            if count > 0 { Some(StoreItem { id: stored.item, count, price: stored.price }) } else { None }
+        })
+        .collect()
+}
+
+/// Puts the players left offline with their store open back in the world, with what of their stores is still
+/// in their inventories. Stores are dropped when offline stores are disabled.
+pub async fn restore_offline(context: &Context) -> Result<(), String> {
+    let stores = private_stores::load_all(&context.database).await?;
+    let mut restored = 0;
+    for stored in stores {
+        let obj_id = stored.obj_id;
+        let kind = match StoreKind::from_store_type(stored.store_type) {
+            Some(kind) if context.conf.stores.offline => kind,
+            _ => {
+                private_stores::remove(&context.database, obj_id).await?;
+                continue;
+            }
+        };
+        let mut character = match characters::load(&context.database, obj_id).await? {
+            Some(character) => character,
+            None => {
+                private_stores::remove(&context.database, obj_id).await?;
+                continue;
+            }
+        };
+        let template = match context.datapack.classes.get(character.class_id) {
+            Some(template) => template,
+            None => {
+                warn!("No template for class {} of {}, its offline store is not restored", character.class_id, character.char_name);
+                continue;
+            }
+        };
+        let inventory = Inventory::new(obj_id, items::LOC_INVENTORY, items::load_inventory(&context.database, obj_id).await?,
+            inventory::max_slots(character.race), inventory::max_load(template.stats.con), &context.datapack.items);
+
+        let items = restored_items(kind, &inventory, &context.datapack.items, &stored.items);
+        if items.is_empty() {
+            private_stores::remove(&context.database, obj_id).await?;
+            continue;
+        }
+
+        characters::update_on_login(&context.database, &mut character, now_millis()).await?;
+        let mut player = Player::new(character, template, inventory, Sender::detached());
+        player.refresh_equipment(&context.datapack.items, template);
+        let list = if kind.sells() { &mut player.sell_list } else { &mut player.buy_list };
+        list.items = items;
+        list.message = stored.message;
+        list.package = kind == StoreKind::PackageSell;
+        player.store = Some(kind);
+        player.sitting = true;
+        player.offline = true;
+        if let Err(e) = context.world().add(WorldObject::Player(Box::new(player))) {
+            warn!("Offline store of {} not restored: {}", obj_id, e);
+            continue;
+        }
+        restored += 1;
+    }
+    info!("Restored {} offline stores", restored);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameserver::idfactory::IdFactory;
+    use crate::gameserver::models::PAPERDOLL_RHAND;
+    use crate::gameserver::player::synthetic::datapack;
+
+    const SWORD: u32 = 1;
+    const ARROWS: u32 = 17;
+
+    fn listed(items: &[(u32, u64, u64)]) -> Vec<StoreItem> {
+        items.iter().map(|&(id, count, price)| StoreItem { id, count, price }).collect()
+    }
+
+    fn entries(items: &[StoreItem]) -> Vec<(u32, u64, u64)> {
+        items.iter().map(|item| (item.id, item.count, item.price)).collect()
+    }
+
+    /// An inventory with adena, a stack of arrows, a sword and a worn sword, giving their object ids in that order.
+    fn inventory(templates: &ItemRegistry, ids: &IdFactory) -> (Inventory, [u32; 4]) {
+        let mut inventory = Inventory::new(1, items::LOC_INVENTORY, Vec::new(), 80, u64::MAX, templates);
+        let mut object_ids = [0; 4];
+        for (index, (item_id, count)) in [(ADENA, 1000), (ARROWS, 100), (SWORD, 1), (SWORD, 1)].into_iter().enumerate() {
+            object_ids[index] = inventory.add(templates, ids, item_id, count).unwrap()[0].item().object_id;
+        }
+        inventory.set_slot(object_ids[3], Some(PAPERDOLL_RHAND));
+        (inventory, object_ids)
+    }
+
+    #[test]
+    fn requests_have_to_match_the_list() {
+        let list = listed(&[(10, 5, 100), (11, 1, 2000)]);
+        assert_eq!(price_of(&list, &[(10, 5, 100), (11, 1, 2000)]), Some(2500));
+        assert_eq!(price_of(&list, &[(10, 2, 100)]), Some(200));
+        assert_eq!(price_of(&list, &[]), Some(0));
+        assert_eq!(price_of(&list, &[(10, 2, 99)]), None);
+        assert_eq!(price_of(&list, &[(12, 1, 100)]), None);
+        assert_eq!(price_of(&list, &[(10, 2, 100), (10, 2, 100)]), None);
+        assert_eq!(price_of(&list, &[(10, 0, 100)]), None);
+        assert_eq!(price_of(&list, &[(10, 6, 100)]), None);
+
+        let list = listed(&[(10, 2, MAX_COUNT), (11, 1, MAX_COUNT)]);
+        assert_eq!(price_of(&list, &[(10, 1, MAX_COUNT)]), Some(MAX_COUNT));
+        assert_eq!(price_of(&list, &[(10, 2, MAX_COUNT)]), None);
+        assert_eq!(price_of(&list, &[(10, 1, MAX_COUNT), (11, 1, MAX_COUNT)]), None);
+    }
+
+    #[test]
+    fn package_stores_sell_everything_at_once() {
+        let list = listed(&[(10, 5, 100), (11, 1, 2000)]);
+        assert!(is_whole(&list, &[(11, 1, 2000), (10, 5, 100)]));
+        assert!(!is_whole(&list, &[(10, 5, 100)]));
+        assert!(!is_whole(&list, &[(10, 4, 100), (11, 1, 2000)]));
+        assert!(!is_whole(&list, &[(10, 5, 100), (10, 5, 100)]));
+    }
+
+    #[test]
+    fn sell_lists_only_take_what_can_be_sold() {
+        let datapack = datapack();
+        let ids = IdFactory::new(Vec::new());
+        let (inventory, [adena, arrows, sword, worn]) = inventory(&datapack.items, &ids);
+
+        assert!(check_sell_list(&inventory, &listed(&[(arrows, 100, 5), (sword, 1, 1000)])).is_ok());
+        assert!(check_sell_list(&inventory, &listed(&[(adena, 10, 1)])).is_err());
+        assert!(check_sell_list(&inventory, &listed(&[(worn, 1, 1000)])).is_err());
+        assert!(check_sell_list(&inventory, &listed(&[(arrows, 101, 5)])).is_err());
+        assert!(check_sell_list(&inventory, &listed(&[(arrows, 0, 5)])).is_err());
+        assert!(check_sell_list(&inventory, &listed(&[(arrows, 10, 5), (arrows, 10, 5)])).is_err());
+        assert!(check_sell_list(&inventory, &listed(&[(sword + 100, 1, 5)])).is_err());
+        assert!(check_sell_list(&inventory, &listed(&[(arrows, 100, MAX_COUNT)])).is_err());
+    }
+
+    #[test]
+    fn restored_stores_keep_what_is_still_there() {
+        let datapack = datapack();
+        let ids = IdFactory::new(Vec::new());
+        let (inventory, [_, arrows, sword, worn]) = inventory(&datapack.items, &ids);
+        let stored = |items: &[(u32, u64, u64)]| -> Vec<StoredItem> {
+            items.iter().map(|&(item, count, price)| StoredItem { item, count, price }).collect()
+        };
+
+        let sold = stored(&[(arrows, 150, 5), (sword, 1, 1000), (worn, 1, 1000), (sword + 100, 1, 5)]);
+        let restored = restored_items(StoreKind::Sell, &inventory, &datapack.items, &sold);
+        assert_eq!(entries(&restored), vec![(arrows, 100, 5), (sword, 1, 1000)]);
+
+        let bought = stored(&[(ARROWS, 500, 1), (999_999, 1, 1)]);
+        let restored = restored_items(StoreKind::Buy, &inventory, &datapack.items, &bought);
+        assert_eq!(entries(&restored), vec![(ARROWS, 500, 1)]);
+    }
+
+    #[test]
+    fn manufacture_lists_only_take_known_recipes() {
+        let known = [100, 101];
+        assert!(check_manufacture_list(&known, &listed(&[(100, 1, 500), (101, 1, 0)])).is_ok());
+        assert!(check_manufacture_list(&known, &listed(&[(102, 1, 500)])).is_err());
+        assert!(check_manufacture_list(&known, &listed(&[(100, 1, 500), (100, 1, 600)])).is_err());
+        assert!(check_manufacture_list(&known, &listed(&[(100, 1, MAX_COUNT + 1)])).is_err());
+        // Nothing can be listed without a recipe book.
+        assert!(check_manufacture_list(KNOWN_RECIPES, &listed(&[(100, 1, 500)])).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,129 @@
+use log::info;
+
+use crate::gameserver::combat;
+use crate::gameserver::datapack::items::ADENA;
+use crate::gameserver::datapack::teleports::Teleport;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::inventory;
+use crate::gameserver::models::Client;
+use crate::gameserver::server::lobby::action_failed;
+use crate::gameserver::server::movement as response;
+use crate::gameserver::server::system_message::{self, Param};
+use crate::gameserver::server::world as world_response;
+use crate::gameserver::skills;
+use crate::gameserver::trade;
+use crate::gameserver::world::World;
+
+pub const SCROLL_OF_ESCAPE: u32 = 736;
+
+/// Takes an object anywhere in the world at once. Everything around it forgets it on the way out, a player stays
+/// hidden until its client has loaded the new surroundings and sent Appearing.
+pub fn teleport(world: &mut World, obj_id: u32, (x, y, z): (i32, i32, i32)) {
+    if world.get(obj_id).is_none() {
+        return;
+    }
+    trade::cancel(world, obj_id);
+    combat::stop_attack(world, obj_id);
+    skills::cancel(world, obj_id);
+    world.stop_moving(obj_id);
+    world.broadcast_with_self(obj_id, &response::teleport_to_location(obj_id, x, y, z));
+    world.clear_known(obj_id);
+    world.relocate(obj_id, x, y, z);
+
+    match world.player_mut(obj_id) {
+        Some(player) => {
+            player.teleporting = true;
+            player.target = None;
+            player.dialog = None;
+            player.merchant = None;
+        },
+        None => world.refresh_known(obj_id),
+    }
+}
+
+/// The client finished loading after a teleport, the player is shown to its new surroundings.
+pub async fn appearing(context: &Context, client: &mut Client) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+
+    let mut world = context.world();
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} of {} is not in the world", obj_id, client.account_name)),
+    };
+    if !player.teleporting {
+        return Ok(());
+    }
+    player.teleporting = false;
+    if let Some(class) = context.datapack.classes.get(player.character.class_id) {
+        player.send(world_response::user_info(player, class));
+    }
+    world.refresh_known(obj_id);
+    Ok(())
+}
+
+/// Sends a player to a gatekeeper destination once it has paid for it, unless it is in combat.
+pub fn travel(context: &Context, world: &mut World, obj_id: u32, destination: &Teleport) -> Result<(), String> {
+    if combat::in_combat(world, obj_id) {
+        if let Some(player) = world.player(obj_id) {
+            player.send(system_message::text("You can't teleport while in combat."));
+            player.send(action_failed());
+        }
+        return Ok(());
+    }
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Ok(()),
+    };
+    if destination.price > 0 {
+        if player.inventory.count_of(destination.currency) < destination.price {
+            let id = if destination.currency == ADENA { system_message::YOU_NOT_ENOUGH_ADENA } else { system_message::NOT_ENOUGH_ITEMS };
+            player.send(system_message::system_message(id, &[]));
+            player.send(action_failed());
+            return Ok(());
+        }
+        let changes = match player.inventory.remove_by_item_id(&context.datapack.items, &context.ids, destination.currency, destination.price) {
+            Ok(changes) => changes,
+            Err(e) => return Err(format!("Can't take {} of item {} from {}: {}", destination.price, destination.currency, player.character.char_name, e)),
+        };
+        inventory::commit(context, player, changes);
+        player.send(disappeared(destination.currency, destination.price));
+    }
+    info!("{} teleported to {} ({})", player.character.char_name, destination.name, destination.id);
+    teleport(world, obj_id, destination.position);
+    Ok(())
+}
+
+/// Reads a Scroll of Escape, which takes the player back to the nearest town.
+pub fn escape(context: &Context, world: &mut World, obj_id: u32, object_id: u32) -> Result<(), String> {
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Ok(()),
+    };
+    let (x, y, _) = player.position();
+    let town = match context.datapack.teleports.nearest_town(x, y) {
+        Some(town) => town,
+        None => return Err("No town to escape to".to_string()),
+    };
+    if player.trade.is_some() || player.store.is_some() || player.teleporting || player.combat.in_combat() {
+        player.send(system_message::system_message(system_message::S1_CANNOT_BE_USED, &[Param::Item(SCROLL_OF_ESCAPE)]));
+        return Ok(());
+    }
+
+    let changes = match player.inventory.remove(&context.datapack.items, &context.ids, object_id, 1) {
+        Ok(changes) => changes,
+        Err(e) => return Err(format!("Can't take a Scroll of Escape from {}: {}", player.character.char_name, e)),
+    };
+    inventory::commit(context, player, changes);
+    player.send(disappeared(SCROLL_OF_ESCAPE, 1));
+    teleport(world, obj_id, town.position);
+    Ok(())
+}
+
+/// What a player is told about the adena or items it paid with.
+fn disappeared(item_id: u32, count: u64) -> Vec<u8> {
+    let count = Param::Number(count.min(u32::MAX as u64) as u32);
+    match item_id {
+        ADENA => system_message::system_message(system_message::S1_ADENA_DISAPPEARED, &[count]),
+        item_id => system_message::system_message(system_message::S2_S1_DISAPPEARED, &[Param::Item(item_id), count]),
+    }
+}
@@ -0,0 +1,345 @@
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::database::items;
+use crate::gameserver::client::trade as request;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::inventory;
+use crate::gameserver::models::Client;
+use crate::gameserver::player::Player;
+use crate::gameserver::server::system_message;
+use crate::gameserver::server::trade as response;
+use crate::gameserver::world::World;
+
+/// Both players have to stay this close from the request until the items change hands.
+const TRADE_DISTANCE: f64 = 150.0;
+/// Time a player has to answer a trade request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One side of a trade in progress.
+pub struct Trade {
+    pub partner: u32,
+    /// Object ids and counts offered, each item once.
+    pub items: Vec<(u32, u64)>,
+    pub confirmed: bool,
+}
+
+impl Trade {
+    fn new(partner: u32) -> Trade {
+        Trade { partner, items: Vec::new(), confirmed: false }
+    }
+
+    fn offered(&self, object_id: u32) -> u64 {
+        self.items.iter().filter(|(offered, _)| *offered == object_id).map(|(_, count)| count).sum()
+    }
+
+    fn offer(&mut self, object_id: u32, count: u64) {
+        match self.items.iter_mut().find(|(offered, _)| *offered == object_id) {
+            Some((_, offered)) => *offered += count,
+            None => self.items.push((object_id, count)),
+        }
+    }
+}
+
+/// Why a player can't start trading, `None` when it can.
+fn refusal(player: &Player) -> Option<&'static str> {
+    if player.trade.is_some() {
+        Some("You are already trading.")
+    } else if player.store.is_some() {
+        Some("You can't trade while your private store is open.")
+    } else if player.combat.in_combat() {
+        Some("You can't trade while fighting.")
+    } else {
+        None
+    }
+}
+
+/// Whether a player is doing something that keeps it from trading.
+fn is_busy(player: &Player, now: Instant) -> bool {
+    refusal(player).is_some() || player.trade_request.is_some_and(|(_, expires)| expires > now)
+}
+
+fn in_range(player: &Player, other: &Player) -> bool {
+    let (x, y, _) = player.position();
+    let (other_x, other_y, _) = other.position();
+    ((other_x - x) as f64).hypot((other_y - y) as f64) <= TRADE_DISTANCE
+}
+
+fn player_or_err(world: &World, obj_id: u32) -> Result<&Player, String> {
+    match world.player(obj_id) {
+        Some(player) => Ok(player),
+        None => Err(format!("Player {} is not in the world", obj_id)),
+    }
+}
+
+pub async fn trade_request(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let target_id = request::new_trade_request(data)?;
+    let now = Instant::now();
+
+    let mut world = context.world();
+    let player = player_or_err(&world, obj_id)?;
+    let target = match world.player(target_id) {
+        Some(target) if target_id != obj_id && player.known.contains(&target_id) => target,
+        _ => {
+            player.send(system_message::system_message(system_message::TARGET_IS_INCORRECT, &[]));
+            return Ok(());
+        }
+    };
+    if let Some(refusal) = refusal(player) {
+        player.send(system_message::text(refusal));
+        return Ok(());
+    }
+    if !in_range(player, target) {
+        player.send(system_message::system_message(system_message::TARGET_TOO_FAR, &[]));
+        return Ok(());
+    }
+    // A player that ignores the requester looks busy, the requester doesn't learn about it.
+    if is_busy(target, now) || target.ignores(player) {
+        player.send(system_message::with_text(system_message::S1_IS_BUSY_TRY_LATER, &target.character.char_name));
+        return Ok(());
+    }
+
+    player.send(system_message::with_text(system_message::REQUEST_S1_FOR_TRADE, &target.character.char_name));
+    target.send(response::send_trade_request(obj_id));
+    if let Some(target) = world.player_mut(target_id) {
+        target.trade_request = Some((obj_id, now + REQUEST_TIMEOUT));
+    }
+    Ok(())
+}
+
+pub async fn answer_trade_request(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let accepted = request::new_answer_trade_request(data)?;
+    let now = Instant::now();
+
+    let mut world = context.world();
+    let requester_id = match world.player_mut(obj_id).and_then(|player| player.trade_request.take()) {
+        Some((requester_id, expires)) if expires > now => requester_id,
+        Some(_) => {
+            player_or_err(&world, obj_id)?.send(system_message::text("The trade request has expired."));
+            return Ok(());
+        },
+        None => return Ok(()),
+    };
+
+    let player = player_or_err(&world, obj_id)?;
+    let requester = match world.player(requester_id) {
+        Some(requester) => requester,
+        None => {
+            player.send(system_message::system_message(system_message::TARGET_IS_NOT_FOUND_IN_THE_GAME, &[]));
+            return Ok(());
+        }
+    };
+    if !accepted {
+        requester.send(system_message::with_text(system_message::S1_DENIED_TRADE_REQUEST, &player.character.char_name));
+        return Ok(());
+    }
+    // Either side may have started something else while the request waited.
+    if let Some(refusal) = refusal(player) {
+        player.send(system_message::text(refusal));
+        return Ok(());
+    }
+    if refusal(requester).is_some() {
+        player.send(system_message::with_text(system_message::S1_IS_BUSY_TRY_LATER, &requester.character.char_name));
+        return Ok(());
+    }
+    if !in_range(player, requester) {
+        player.send(system_message::system_message(system_message::TARGET_TOO_FAR, &[]));
+        return Ok(());
+    }
+
+    let templates = &context.datapack.items;
+    for (trader, partner) in [(player, requester), (requester, player)] {
+        let offerable = trader.inventory.items().filter(|item| item.loc != items::LOC_PAPERDOLL);
+        trader.send(response::trade_start(partner.obj_id(), offerable, templates));
+        trader.send(system_message::with_text(system_message::BEGIN_TRADE_WITH_S1, &partner.character.char_name));
+    }
+    if let Some((player, requester)) = world.two_players_mut(obj_id, requester_id) {
+        player.trade = Some(Trade::new(requester_id));
+        requester.trade = Some(Trade::new(obj_id));
+    }
+    Ok(())
+}
+
+pub async fn add_trade_item(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let add = request::new_add_trade_item(data)?;
+    let templates = &context.datapack.items;
+
+    let mut world = context.world();
+    let player = player_or_err(&world, obj_id)?;
+    let name = &player.character.char_name;
+    let trade = match &player.trade {
+        Some(trade) => trade,
+        None => return Err(format!("{} offered an item without trading", name)),
+    };
+    if trade.confirmed {
+        player.send(system_message::text("You can't change a trade you already confirmed."));
+        return Ok(());
+    }
+    let item = match player.inventory.get(add.object_id) {
+        Some(item) if item.loc != items::LOC_PAPERDOLL => item,
+        _ => return Err(format!("{} offered item {} it can't trade", name, add.object_id)),
+    };
+    if add.count == 0 || trade.offered(add.object_id) + add.count > item.count {
+        return Err(format!("{} offered {} of item {} which has {}", name, add.count, add.object_id, item.count));
+    }
+
+    let partner_id = trade.partner;
+    player.send(response::trade_own_add(item, add.count, templates));
+    let packet = response::trade_other_add(item, add.count, templates);
+    if let Some((player, partner)) = world.two_players_mut(obj_id, partner_id) {
+        if let Some(trade) = &mut player.trade {
+            trade.offer(add.object_id, add.count);
+        }
+        // The partner agreed to a different trade, it has to confirm again.
+        if let Some(trade) = &mut partner.trade {
+            trade.confirmed = false;
+        }
+        partner.send(packet);
+    }
+    Ok(())
+}
+
+pub async fn trade_done(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let confirmed = request::new_trade_done(data)?;
+
+    let mut world = context.world();
+    if !confirmed {
+        cancel(&mut world, obj_id);
+        return Ok(());
+    }
+
+    let partner_id = match world.player(obj_id).and_then(|player| player.trade.as_ref()) {
+        Some(trade) => trade.partner,
+        None => return Ok(()),
+    };
+    let (player, partner) = match world.two_players_mut(obj_id, partner_id) {
+        Some(players) => players,
+        None => {
+            cancel(&mut world, obj_id);
+            return Ok(());
+        }
+    };
+    if let Some(trade) = &mut player.trade {
+        trade.confirmed = true;
+    }
+    if !partner.trade.as_ref().is_some_and(|trade| trade.confirmed) {
+        player.send(response::trade_press_own_ok());
+        partner.send(response::trade_press_other_ok());
+        partner.send(system_message::with_text(system_message::S1_CONFIRMED_TRADE, &player.character.char_name));
+        return Ok(());
+    }
+
+    let (own_offer, partner_offer) = match (player.trade.take(), partner.trade.take()) {
+        (Some(own), Some(other)) => (own.items, other.items),
+        _ => return Ok(()),
+    };
+    let exchanged = if in_range(player, partner) {
+        inventory::exchange(&mut player.inventory, &own_offer, &mut partner.inventory, &partner_offer, &context.datapack.items, &context.ids)
+            .map_err(|e| e.to_string())
+    } else {
+        Err("the players are too far apart".to_string())
+    };
+
+    match exchanged {
+        Ok(exchange) => {
+            info!("{} and {} traded {} and {} items", player.character.char_name, partner.character.char_name, own_offer.len(), partner_offer.len());
+            context.item_writer.write(exchange.writes);
+            inventory::show(context, player, &exchange.first);
+            inventory::show(context, partner, &exchange.second);
+            for trader in [&*player, &*partner] {
+                trader.send(response::trade_done(true));
+                trader.send(system_message::system_message(system_message::TRADE_SUCCESSFUL, &[]));
+            }
+        },
+        Err(e) => {
+            info!("Trade between {} and {} failed: {}", player.character.char_name, partner.character.char_name, e);
+            for trader in [&*player, &*partner] {
+                trader.send(response::trade_done(false));
+                trader.send(system_message::text(&format!("The trade failed: {}.", e)));
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Ends the trade of a player without exchanging anything, the partner is told who cancelled it.
+pub fn cancel(world: &mut World, obj_id: u32) {
+    let partner_id = match world.player_mut(obj_id).and_then(|player| player.trade.take()) {
+        Some(trade) => trade.partner,
+        None => return,
+    };
+    let name = match world.player(obj_id) {
+        Some(player) => {
+            player.send(response::trade_done(false));
+            player.character.char_name.clone()
+        },
+        None => return,
+    };
+    if let Some(partner) = world.player_mut(partner_id) {
+        if partner.trade.as_ref().is_some_and(|trade| trade.partner == obj_id) {
+            partner.trade = None;
+            partner.send(response::trade_done(false));
+            partner.send(system_message::with_text(system_message::S1_CANCELED_TRADE, &name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameserver::datapack::items::ADENA;
+    use crate::gameserver::idfactory::IdFactory;
+    use crate::gameserver::inventory::InventoryError;
+    use crate::gameserver::player::synthetic::{datapack, player};
+    use crate::gameserver::store::StoreKind;
+
+    #[test]
+    fn players_busy_elsewhere_refuse_trades() {
+        let datapack = datapack();
+        let now = Instant::now();
+        let mut trader = player(&datapack, 1, "Trader", 0, 0);
+        assert!(refusal(&trader).is_none() && !is_busy(&trader, now));
+
+        trader.trade_request = Some((2, now + REQUEST_TIMEOUT));
+        assert!(refusal(&trader).is_none() && is_busy(&trader, now));
+        assert!(!is_busy(&trader, now + REQUEST_TIMEOUT));
+
+        trader.combat.stance_until = Some(now);
+        assert_eq!(refusal(&trader), Some("You can't trade while fighting."));
+        trader.store = Some(StoreKind::Sell);
+        assert_eq!(refusal(&trader), Some("You can't trade while your private store is open."));
+        trader.trade = Some(Trade::new(2));
+        assert_eq!(refusal(&trader), Some("You are already trading."));
+    }
+
+    #[test]
+    fn failed_trades_leave_both_inventories_alone() {
+        let datapack = datapack();
+        let templates = &datapack.items;
+        let ids = IdFactory::new(Vec::new());
+        let mut first = player(&datapack, 1, "First", 0, 0);
+        let mut second = player(&datapack, 2, "Second", 0, 0);
+        first.inventory.add(templates, &ids, 1, 1).unwrap();
+        second.inventory.add(templates, &ids, ADENA, 50).unwrap();
+        let sword = first.inventory.items().next().unwrap().object_id;
+        let adena = second.inventory.items().next().unwrap().object_id;
+        let contents = |player: &Player| {
+            let mut contents: Vec<_> = player.inventory.items().map(|item| (item.object_id, item.owner_id, item.count)).collect();
+            contents.sort();
+            (contents, player.inventory.weight())
+        };
+        let (first_before, second_before, used) = (contents(&first), contents(&second), ids.used());
+
+        // The sword moves first, then the adena spent since the offer are missing.
+        let exchanged = inventory::exchange(&mut first.inventory, &[(sword, 1)], &mut second.inventory, &[(adena, 100)], templates, &ids);
+        assert_eq!(exchanged.err(), Some(InventoryError::NotEnough));
+        assert_eq!(contents(&first), first_before);
+        assert_eq!(contents(&second), second_before);
+        assert_eq!(ids.used(), used);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,305 @@
+use std::collections::HashSet;
+
+use crate::database::clans::CP_CL_VIEW_WAREHOUSE;
+use crate::database::items::{self, ItemChange};
+use crate::gameserver::client::items as request;
+use crate::gameserver::datapack::items::ADENA;
+use crate::gameserver::gameserver::Context;
+use crate::gameserver::inventory::{self, Inventory, InventoryError, DWARF};
+use crate::gameserver::models::Client;
+use crate::gameserver::player::Player;
+use crate::gameserver::server::items as response;
+use crate::gameserver::server::system_message;
+use crate::gameserver::world::World;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WarehouseKind {
+    Private,
+    Clan,
+}
+
+impl WarehouseKind {
+    /// Warehouse type of the deposit and withdrawal lists.
+    fn packet_type(self) -> u16 {
+        match self {
+            WarehouseKind::Private => 1,
+            WarehouseKind::Clan => 2,
+        }
+    }
+
+    fn loc(self) -> &'static str {
+        match self {
+            WarehouseKind::Private => items::LOC_WAREHOUSE,
+            WarehouseKind::Clan => items::LOC_CLANWH,
+        }
+    }
+}
+
+/// A warehouse window a keeper opened for a player.
+#[derive(Clone, Copy)]
+pub struct OpenWarehouse {
+    pub kind: WarehouseKind,
+    /// The keeper, the player has to stay in reach of it until the items are moved.
+    pub npc_id: u32,
+    pub deposit: bool,
+}
+
+/// Why a player can't use the warehouse of its clan, `None` when it can.
+fn clan_refusal(player: &Player, withdraw: bool) -> Option<&'static str> {
+    match &player.clan {
+        None => Some("You are not a member of a clan."),
+        Some(clan) if clan.clan_level == 0 => Some("Only clans of level 1 or higher can use a warehouse."),
+        Some(_) if withdraw && !player.has_clan_privilege(CP_CL_VIEW_WAREHOUSE) => Some("You do not have the right to use the clan warehouse."),
+        Some(_) => None,
+    }
+}
+
+fn is_near_keeper(world: &World, player: &Player, npc_id: u32) -> bool {
+    let (x, y, _) = player.position();
+    world.npc(npc_id).is_some_and(|npc| npc.is_within_reach((x, y)))
+}
+
+fn warehouse_of<'a>(world: &'a World, player: &'a Player, kind: WarehouseKind) -> Option<&'a Inventory> {
+    match kind {
+        WarehouseKind::Private => player.warehouse.as_ref(),
+        WarehouseKind::Clan => world.clan_warehouse(player.character.clan_id),
+    }
+}
+
+/// The inventory of a player along with a warehouse it uses, to move items between them.
+fn inventories(world: &mut World, obj_id: u32, kind: WarehouseKind) -> Option<(&mut Inventory, &mut Inventory)> {
+    let clan_id = world.player(obj_id)?.character.clan_id;
+    match kind {
+        WarehouseKind::Private => {
+            let player = world.player_mut(obj_id)?;
+            match &mut player.warehouse {
+                Some(warehouse) => Some((&mut player.inventory, warehouse)),
+                None => None,
+            }
+        },
+        WarehouseKind::Clan => world.inventory_and_clan_warehouse(obj_id, clan_id),
+    }
+}
+
+/// Item ids and counts of what a request moves out of `from`. Every item has to be there, only once in the
+/// request, not worn, and with at least the count asked.
+fn moved_items(from: &Inventory, entries: &[(u32, u64)]) -> Result<Vec<(u32, u64)>, String> {
+    let mut seen = HashSet::new();
+    let mut moved = Vec::new();
+    for &(object_id, count) in entries {
+        let item = match from.get(object_id) {
+            Some(item) if seen.insert(object_id) => item,
+            _ => return Err(format!("item {} is missing or listed twice", object_id)),
+        };
+        if item.loc == items::LOC_PAPERDOLL {
+            return Err(format!("item {} is equipped", object_id));
+        }
+        if count == 0 || count > item.count {
+            return Err(format!("asked {} of item {} which has {}", count, object_id, item.count));
+        }
+        moved.push((item.item_id, count));
+    }
+    Ok(moved)
+}
+
+/// Opens the deposit or withdrawal window of a warehouse for a player talking to a keeper, loading the
+/// warehouse the first time.
+pub async fn open(context: &Context, obj_id: u32, npc_id: u32, kind: WarehouseKind, deposit: bool) -> Result<(), String> {
+    let (owner_id, loaded, slots) = {
+        let world = context.world();
+        let player = match world.player(obj_id) {
+            Some(player) => player,
+            None => return Err(format!("Player {} is not in the world", obj_id)),
+        };
+        if !is_near_keeper(&world, player, npc_id) {
+            return Err(format!("{} opened a warehouse away from keeper {}", player.character.char_name, npc_id));
+        }
+        if kind == WarehouseKind::Clan {
+            if let Some(refusal) = clan_refusal(player, !deposit) {
+                player.send(system_message::text(refusal));
+                return Ok(());
+            }
+        }
+        let conf = &context.conf.warehouse;
+        match kind {
+            WarehouseKind::Private => {
+                let slots = if player.character.race == DWARF { conf.dwarf_slots } else { conf.slots };
+                (obj_id, player.warehouse.is_some(), slots)
+            },
+            WarehouseKind::Clan => {
+                let clan_id = player.character.clan_id;
+                (clan_id, world.has_clan_warehouse(clan_id), conf.clan_slots)
+            },
+        }
+    };
+
+    let stored = if loaded { None } else { Some(items::load_warehouse(&context.database, owner_id, kind.loc()).await?) };
+
+    let mut world = context.world();
+    if let Some(stored) = stored {
+        let warehouse = Inventory::new(owner_id, kind.loc(), stored, slots, u64::MAX, &context.datapack.items);
+        match kind {
+            WarehouseKind::Private => {
+                if let Some(player) = world.player_mut(obj_id) {
+                    player.warehouse.get_or_insert(warehouse);
+                }
+            },
+            WarehouseKind::Clan => world.add_clan_warehouse(owner_id, warehouse),
+        }
+    }
+
+    let player = match world.player(obj_id) {
+        Some(player) => player,
+        None => return Ok(()),
+    };
+    let templates = &context.datapack.items;
+    let packet = if deposit {
+        let items = player.inventory.items().filter(|item| item.loc != items::LOC_PAPERDOLL);
+        response::warehouse_deposit_list(kind.packet_type(), player.inventory.adena(), items, templates)
+    } else {
+        match warehouse_of(&world, player, kind) {
+            Some(warehouse) => response::warehouse_withdrawal_list(kind.packet_type(), player.inventory.adena(), warehouse.items(), templates),
+            None => return Err(format!("Warehouse of {} is not loaded", owner_id)),
+        }
+    };
+    player.send(packet);
+    if let Some(player) = world.player_mut(obj_id) {
+        player.open_warehouse = Some(OpenWarehouse { kind, npc_id, deposit });
+    }
+    Ok(())
+}
+
+/// Closes the warehouse window of a player, which has to be the kind it moves items through, next to its keeper.
+fn close(world: &mut World, obj_id: u32, deposit: bool) -> Result<OpenWarehouse, String> {
+    let player = match world.player_mut(obj_id) {
+        Some(player) => player,
+        None => return Err(format!("Player {} is not in the world", obj_id)),
+    };
+    let open = match player.open_warehouse.take() {
+        Some(open) if open.deposit == deposit => open,
+        _ => return Err(format!("{} used a warehouse window it didn't open", player.character.char_name)),
+    };
+    match world.player(obj_id) {
+        Some(player) if !is_near_keeper(world, player, open.npc_id) => {
+            Err(format!("{} used a warehouse away from keeper {}", player.character.char_name, open.npc_id))
+        },
+        _ => Ok(open),
+    }
+}
+
+/// Puts items in the open warehouse, paying the deposit fee for each of them. Either all the items move and the
+/// fee is paid, or nothing changes.
+pub async fn deposit(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let entries = request::new_warehouse_items(data)?;
+    let templates = &context.datapack.items;
+
+    let mut world = context.world();
+    let open = close(&mut world, obj_id, true)?;
+    let player = match world.player(obj_id) {
+        Some(player) => player,
+        None => return Ok(()),
+    };
+    if open.kind == WarehouseKind::Clan {
+        if let Some(refusal) = clan_refusal(player, false) {
+            player.send(system_message::text(refusal));
+            return Ok(());
+        }
+    }
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let moved = match moved_items(&player.inventory, &entries) {
+        Ok(moved) => moved,
+        Err(e) => return Err(format!("{} sent a bad deposit: {}", player.character.char_name, e)),
+    };
+    let fee = context.conf.warehouse.deposit_fee * entries.len() as u64;
+    let adena: u64 = moved.iter().filter(|(item_id, _)| *item_id == ADENA).map(|(_, count)| count).sum();
+    if player.inventory.adena() < fee + adena {
+        player.send(system_message::system_message(system_message::YOU_NOT_ENOUGH_ADENA, &[]));
+        return Ok(());
+    }
+    let warehouse = match warehouse_of(&world, player, open.kind) {
+        Some(warehouse) => warehouse,
+        None => return Err(format!("{} deposited in a warehouse that is not loaded", player.character.char_name)),
+    };
+    if let Err(e) = warehouse.check_add_all(templates, &moved) {
+        let message = match e {
+            InventoryError::SlotsFull => "Your warehouse is full.".to_string(),
+            e => format!("The warehouse can't take these items: {}.", e),
+        };
+        player.send(system_message::text(&message));
+        return Ok(());
+    }
+
+    let (inventory, warehouse) = match inventories(&mut world, obj_id, open.kind) {
+        Some(inventories) => inventories,
+        None => return Ok(()),
+    };
+    match inventory::move_for_fee(inventory, warehouse, &entries, fee, templates, &context.ids) {
+        Ok(moved) => finish(context, &world, obj_id, &moved.first, moved.writes),
+        Err(e) => return Err(format!("Deposit of {} failed after the checks: {}", obj_id, e)),
+    }
+    Ok(())
+}
+
+/// Takes items out of the open warehouse, as long as they all fit in the inventory. Either all the items move or
+/// none does.
+pub async fn withdraw(context: &Context, client: &mut Client, data: Vec<u8>) -> Result<(), String> {
+    let obj_id = client.player_id()?;
+    let entries = request::new_warehouse_items(data)?;
+    let templates = &context.datapack.items;
+
+    let mut world = context.world();
+    let open = close(&mut world, obj_id, false)?;
+    let player = match world.player(obj_id) {
+        Some(player) => player,
+        None => return Ok(()),
+    };
+    if open.kind == WarehouseKind::Clan {
+        if let Some(refusal) = clan_refusal(player, true) {
+            player.send(system_message::text(refusal));
+            return Ok(());
+        }
+    }
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let warehouse = match warehouse_of(&world, player, open.kind) {
+        Some(warehouse) => warehouse,
+        None => return Err(format!("{} withdrew from a warehouse that is not loaded", player.character.char_name)),
+    };
+    let moved = match moved_items(warehouse, &entries) {
+        Ok(moved) => moved,
+        Err(e) => return Err(format!("{} sent a bad withdrawal: {}", player.character.char_name, e)),
+    };
+    if let Err(e) = player.inventory.check_add_all(templates, &moved) {
+        let message = match e {
+            InventoryError::SlotsFull => system_message::system_message(system_message::SLOTS_FULL, &[]),
+            InventoryError::TooHeavy => system_message::system_message(system_message::WEIGHT_LIMIT_EXCEEDED, &[]),
+            e => system_message::text(&format!("You can't carry these items: {}.", e)),
+        };
+        player.send(message);
+        return Ok(());
+    }
+
+    let (inventory, warehouse) = match inventories(&mut world, obj_id, open.kind) {
+        Some(inventories) => inventories,
+        None => return Ok(()),
+    };
+    match inventory::exchange(warehouse, &entries, inventory, &[], templates, &context.ids) {
+        Ok(moved) => finish(context, &world, obj_id, &moved.second, moved.writes),
+        Err(e) => return Err(format!("Withdrawal of {} failed after the checks: {}", obj_id, e)),
+    }
+    Ok(())
+}
+
+/// Writes both sides of a move in one batch, in the order the changes were made, and shows the player its side.
+fn finish(context: &Context, world: &World, obj_id: u32, own: &[ItemChange], writes: Vec<ItemChange>) {
+    context.item_writer.write(writes);
+    if let Some(player) = world.player(obj_id) {
+        inventory::show(context, player, own);
+    }
+}
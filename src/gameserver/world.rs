@@ -0,0 +1,849 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::time::Instant;
+
+use crate::gameserver::combat::CombatState;
+use crate::gameserver::datapack::buylists::Stock;
+use crate::gameserver::drops::GroundItem;
+use crate::gameserver::inventory::Inventory;
+use crate::gameserver::merchant::ShopStock;
+use crate::gameserver::models::Sender;
+use crate::gameserver::movement::{Movement, Route};
+use crate::gameserver::npc::Npc;
+use crate::gameserver::player::Player;
+use crate::gameserver::skills::{Cast, Effect};
+use crate::gameserver::stats::{Calculator, Stats};
+use crate::gameserver::server::items as item_packets;
+use crate::gameserver::server::world as packets;
+use crate::gameserver::store;
+
+/// Regions are 2048x2048 squares, an object sees everything in its own region and the 8 around it.
+const REGION_SHIFT: i32 = 11;
+
+pub type RegionId = (i32, i32);
+
+pub fn region_of(x: i32, y: i32) -> RegionId {
+    (x >> REGION_SHIFT, y >> REGION_SHIFT)
+}
+
+/// The region and the 8 regions around it.
+pub fn surrounding(region: RegionId) -> impl Iterator<Item = RegionId> {
+    (-1..=1).flat_map(move |dx| (-1..=1).map(move |dy| (region.0 + dx, region.1 + dy)))
+}
+
+pub enum WorldObject {
+    Player(Box<Player>),
+    Npc(Box<Npc>),
+}
+
+impl WorldObject {
+    pub fn obj_id(&self) -> u32 {
+        match self {
+            WorldObject::Player(player) => player.obj_id(),
+            WorldObject::Npc(npc) => npc.obj_id,
+        }
+    }
+
+    pub fn position(&self) -> (i32, i32, i32) {
+        match self {
+            WorldObject::Player(player) => player.position(),
+            WorldObject::Npc(npc) => npc.position(),
+        }
+    }
+
+    fn set_position(&mut self, x: i32, y: i32, z: i32) {
+        match self {
+            WorldObject::Player(player) => {
+                player.character.x = x;
+                player.character.y = y;
+                player.character.z = z;
+            },
+            WorldObject::Npc(npc) => {
+                npc.x = x;
+                npc.y = y;
+                npc.z = z;
+            },
+        }
+    }
+
+    pub fn heading(&self) -> i32 {
+        match self {
+            WorldObject::Player(player) => player.character.heading,
+            WorldObject::Npc(npc) => npc.heading,
+        }
+    }
+
+    pub fn set_heading(&mut self, heading: i32) {
+        match self {
+            WorldObject::Player(player) => player.character.heading = heading,
+            WorldObject::Npc(npc) => npc.heading = heading,
+        }
+    }
+
+    pub fn move_speed(&self) -> f64 {
+        match self {
+            WorldObject::Player(player) => player.move_speed(),
+            WorldObject::Npc(npc) => npc.move_speed(),
+        }
+    }
+
+    pub fn movement_mut(&mut self) -> &mut Option<Movement> {
+        match self {
+            WorldObject::Player(player) => &mut player.movement,
+            WorldObject::Npc(npc) => &mut npc.movement,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            WorldObject::Player(player) => &player.character.char_name,
+            WorldObject::Npc(npc) => &npc.name,
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        match self {
+            WorldObject::Player(player) => player.is_dead(),
+            WorldObject::Npc(npc) => npc.is_dead(),
+        }
+    }
+
+    pub fn combat(&self) -> &CombatState {
+        match self {
+            WorldObject::Player(player) => &player.combat,
+            WorldObject::Npc(npc) => &npc.combat,
+        }
+    }
+
+    pub fn combat_mut(&mut self) -> &mut CombatState {
+        match self {
+            WorldObject::Player(player) => &mut player.combat,
+            WorldObject::Npc(npc) => &mut npc.combat,
+        }
+    }
+
+    pub fn stats(&self) -> &Stats {
+        match self {
+            WorldObject::Player(player) => &player.stats,
+            WorldObject::Npc(npc) => &npc.stats,
+        }
+    }
+
+    pub fn calculator_mut(&mut self) -> &mut Calculator {
+        match self {
+            WorldObject::Player(player) => &mut player.calculator,
+            WorldObject::Npc(npc) => &mut npc.calculator,
+        }
+    }
+
+    pub fn cast_mut(&mut self) -> &mut Option<Cast> {
+        match self {
+            WorldObject::Player(player) => &mut player.cast,
+            WorldObject::Npc(npc) => &mut npc.cast,
+        }
+    }
+
+    pub fn effects(&self) -> &Vec<Effect> {
+        match self {
+            WorldObject::Player(player) => &player.effects,
+            WorldObject::Npc(npc) => &npc.effects,
+        }
+    }
+
+    pub fn effects_mut(&mut self) -> &mut Vec<Effect> {
+        match self {
+            WorldObject::Player(player) => &mut player.effects,
+            WorldObject::Npc(npc) => &mut npc.effects,
+        }
+    }
+
+    pub fn known(&self) -> &HashSet<u32> {
+        match self {
+            WorldObject::Player(player) => &player.known,
+            WorldObject::Npc(npc) => &npc.known,
+        }
+    }
+
+    fn known_mut(&mut self) -> &mut HashSet<u32> {
+        match self {
+            WorldObject::Player(player) => &mut player.known,
+            WorldObject::Npc(npc) => &mut npc.known,
+        }
+    }
+
+    /// Packets that make this object appear on the client of another player, a player in a store also shows
+    /// its title.
+    pub fn info(&self) -> Vec<Vec<u8>> {
+        match self {
+            WorldObject::Player(player) => [Some(packets::char_info(player)), store::message(player)].into_iter().flatten().collect(),
+            WorldObject::Npc(npc) => vec![packets::npc_info(npc)],
+        }
+    }
+
+    fn sender(&self) -> Option<&Sender> {
+        match self {
+            WorldObject::Player(player) => Some(&player.sender),
+            WorldObject::Npc(_) => None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Region {
+    pub objects: HashSet<u32>,
+    pub players: usize,
+    /// Items lying on the ground.
+    pub items: HashSet<u32>,
+}
+
+/// Every object currently in game, indexed by object id and by region. Known lists are kept symmetric: when
+/// an object sees another one, the other one sees it too.
+pub struct World {
+    objects: HashMap<u32, WorldObject>,
+    regions: HashMap<RegionId, Region>,
+    /// Objects with a movement in progress, advanced on every movement tick.
+    moving: HashSet<u32>,
+    /// Players by lowercase name.
+    names: HashMap<String, u32>,
+    /// Clan warehouses by clan id, loaded the first time a member opens one and shared by every member.
+    clan_warehouses: HashMap<u32, Inventory>,
+    /// Objects attacking or in their combat stance, updated on every attack tick.
+    fighting: HashSet<u32>,
+    /// Objects missing HP, MP or CP, given some back on every regen tick.
+    recovering: HashSet<u32>,
+    /// Objects casting a skill, checked on every cast tick.
+    casting: HashSet<u32>,
+    /// Effects by when they wear off, with the object they are on and their skill id.
+    effect_ends: BinaryHeap<Reverse<(Instant, u32, u32)>>,
+    /// Spawns owed an NPC, by when it comes back.
+    respawns: BinaryHeap<Reverse<(Instant, usize)>>,
+    /// Corpses of NPCs, by when they go away.
+    decays: BinaryHeap<Reverse<(Instant, u32)>>,
+    /// What merchants have left of their limited items, by buy list and item id.
+    stocks: HashMap<(u32, u32), ShopStock>,
+    /// Items on the ground by object id, only players see them.
+    items: HashMap<u32, GroundItem>,
+    /// Items on the ground by when they go away.
+    item_expiries: BinaryHeap<Reverse<(Instant, u32)>>,
+    /// Players walking to an item to pick it up, checked on every drops tick.
+    picking: HashSet<u32>,
+    /// Paths to search once the world is released, by the object that walks them.
+    routes: HashMap<u32, Route>,
+}
+
+impl World {
+    pub fn new() -> World {
+        World {
+            objects: HashMap::new(),
+            regions: HashMap::new(),
+            moving: HashSet::new(),
+            names: HashMap::new(),
+            clan_warehouses: HashMap::new(),
+            fighting: HashSet::new(),
+            recovering: HashSet::new(),
+            casting: HashSet::new(),
+            effect_ends: BinaryHeap::new(),
+            respawns: BinaryHeap::new(),
+            decays: BinaryHeap::new(),
+            stocks: HashMap::new(),
+            items: HashMap::new(),
+            item_expiries: BinaryHeap::new(),
+            picking: HashSet::new(),
+            routes: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, obj_id: u32) -> Option<&WorldObject> {
+        self.objects.get(&obj_id)
+    }
+
+    pub fn get_mut(&mut self, obj_id: u32) -> Option<&mut WorldObject> {
+        self.objects.get_mut(&obj_id)
+    }
+
+    pub fn player(&self, obj_id: u32) -> Option<&Player> {
+        match self.objects.get(&obj_id) {
+            Some(WorldObject::Player(player)) => Some(player),
+            _ => None,
+        }
+    }
+
+    pub fn player_mut(&mut self, obj_id: u32) -> Option<&mut Player> {
+        match self.objects.get_mut(&obj_id) {
+            Some(WorldObject::Player(player)) => Some(player),
+            _ => None,
+        }
+    }
+
+    /// Two different players at once, such as both sides of a trade.
+    pub fn two_players_mut(&mut self, first: u32, second: u32) -> Option<(&mut Player, &mut Player)> {
+        if first == second {
+            return None;
+        }
+        match self.objects.get_disjoint_mut([&first, &second]) {
+            [Some(WorldObject::Player(first)), Some(WorldObject::Player(second))] => Some((first, second)),
+            _ => None,
+        }
+    }
+
+    /// Player in game with this name, whatever its case.
+    pub fn player_by_name(&self, name: &str) -> Option<&Player> {
+        self.names.get(&name.to_lowercase()).and_then(|obj_id| self.player(*obj_id))
+    }
+
+    pub fn npc(&self, obj_id: u32) -> Option<&Npc> {
+        match self.objects.get(&obj_id) {
+            Some(WorldObject::Npc(npc)) => Some(npc),
+            _ => None,
+        }
+    }
+
+    pub fn npc_mut(&mut self, obj_id: u32) -> Option<&mut Npc> {
+        match self.objects.get_mut(&obj_id) {
+            Some(WorldObject::Npc(npc)) => Some(npc),
+            _ => None,
+        }
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = &Player> {
+        self.objects.values().filter_map(|object| match object {
+            WorldObject::Player(player) => Some(player.as_ref()),
+            _ => None,
+        })
+    }
+
+    pub fn has_clan_warehouse(&self, clan_id: u32) -> bool {
+        self.clan_warehouses.contains_key(&clan_id)
+    }
+
+    /// Keeps a freshly loaded clan warehouse, unless another member loaded it first.
+    pub fn add_clan_warehouse(&mut self, clan_id: u32, warehouse: Inventory) {
+        self.clan_warehouses.entry(clan_id).or_insert(warehouse);
+    }
+
+    pub fn clan_warehouse(&self, clan_id: u32) -> Option<&Inventory> {
+        self.clan_warehouses.get(&clan_id)
+    }
+
+    /// The inventory of a player along with the warehouse of a clan, to move items between them.
+    pub fn inventory_and_clan_warehouse(&mut self, obj_id: u32, clan_id: u32) -> Option<(&mut Inventory, &mut Inventory)> {
+        let player = match self.objects.get_mut(&obj_id) {
+            Some(WorldObject::Player(player)) => player,
+            _ => return None,
+        };
+        let warehouse = self.clan_warehouses.get_mut(&clan_id)?;
+        Some((&mut player.inventory, warehouse))
+    }
+
+    pub fn schedule_respawn(&mut self, spawn: usize, at: Instant) {
+        self.respawns.push(Reverse((at, spawn)));
+    }
+
+    /// Takes the spawns whose NPC is due back by `now`, once for each NPC.
+    pub fn due_respawns(&mut self, now: Instant) -> Vec<usize> {
+        let mut due = Vec::new();
+        while let Some(Reverse((at, spawn))) = self.respawns.peek().copied() {
+            if at > now {
+                break;
+            }
+            self.respawns.pop();
+            due.push(spawn);
+        }
+        due
+    }
+
+    pub fn schedule_decay(&mut self, obj_id: u32, at: Instant) {
+        self.decays.push(Reverse((at, obj_id)));
+    }
+
+    /// Takes the corpses due to go away by `now`.
+    pub fn due_decays(&mut self, now: Instant) -> Vec<u32> {
+        let mut due = Vec::new();
+        while let Some(Reverse((at, obj_id))) = self.decays.peek().copied() {
+            if at > now {
+                break;
+            }
+            self.decays.pop();
+            due.push(obj_id);
+        }
+        due
+    }
+
+    pub fn shop_stock(&mut self, list_id: u32, item_id: u32, stock: &Stock) -> &mut ShopStock {
+        self.stocks.entry((list_id, item_id)).or_insert_with(|| ShopStock::new(stock))
+    }
+
+    /// A region is active while a player is in it or next to it.
+    pub fn is_region_active(&self, region: RegionId) -> bool {
+        surrounding(region).any(|id| self.regions.get(&id).is_some_and(|region| region.players > 0))
+    }
+
+    /// NPCs in active regions, the only ones whose AI runs.
+    pub fn active_npcs(&self) -> Vec<u32> {
+        self.regions.iter()
+            .filter(|(id, _)| self.is_region_active(**id))
+            .flat_map(|(_, region)| region.objects.iter().copied())
+            .filter(|obj_id| matches!(self.objects.get(obj_id), Some(WorldObject::Npc(_))))
+            .collect()
+    }
+
+    fn visible_from(&self, region: RegionId) -> Vec<u32> {
+        surrounding(region)
+            .filter_map(|id| self.regions.get(&id))
+            .flat_map(|region| region.objects.iter().copied())
+            .collect()
+    }
+
+    fn index(&mut self, obj_id: u32, region: RegionId, is_player: bool) {
+        let entry = self.regions.entry(region).or_default();
+        entry.objects.insert(obj_id);
+        if is_player {
+            entry.players += 1;
+        }
+    }
+
+    fn unindex(&mut self, obj_id: u32, region: RegionId, is_player: bool) {
+        if let Some(entry) = self.regions.get_mut(&region) {
+            entry.objects.remove(&obj_id);
+            if is_player {
+                entry.players -= 1;
+            }
+            if entry.objects.is_empty() && entry.items.is_empty() {
+                self.regions.remove(&region);
+            }
+        }
+    }
+
+    /// Puts an object in the world and shows it to everything around, and everything around to it. An object
+    /// already in the world stays as it is.
+    pub fn add(&mut self, object: WorldObject) -> Result<(), String> {
+        let obj_id = object.obj_id();
+        if self.objects.contains_key(&obj_id) {
+            return Err(format!("Object {} is already in the world", obj_id));
+        }
+        let (x, y, _) = object.position();
+        let region = region_of(x, y);
+
+        self.index(obj_id, region, matches!(object, WorldObject::Player(_)));
+        if let WorldObject::Player(player) = &object {
+            self.names.insert(player.character.char_name.to_lowercase(), obj_id);
+        }
+        self.objects.insert(obj_id, object);
+
+        for other in self.visible_from(region) {
+            if other != obj_id {
+                self.see_each_other(obj_id, other);
+            }
+        }
+        self.refresh_known_items(obj_id);
+        Ok(())
+    }
+
+    /// Takes an object out of the world, everything that saw it gets a DeleteObject.
+    pub fn remove(&mut self, obj_id: u32) -> Option<WorldObject> {
+        let object = self.objects.get(&obj_id)?;
+        let (x, y, _) = object.position();
+        let is_player = matches!(object, WorldObject::Player(_));
+        let known: Vec<u32> = object.known().iter().copied().collect();
+
+        for other in known {
+            self.forget_each_other(obj_id, other);
+        }
+        self.unindex(obj_id, region_of(x, y), is_player);
+        self.moving.remove(&obj_id);
+        self.fighting.remove(&obj_id);
+        self.recovering.remove(&obj_id);
+        self.casting.remove(&obj_id);
+        self.picking.remove(&obj_id);
+        self.routes.remove(&obj_id);
+        if let Some(WorldObject::Player(player)) = self.objects.get(&obj_id) {
+            self.names.remove(&player.character.char_name.to_lowercase());
+        }
+        self.objects.remove(&obj_id)
+    }
+
+    /// Moves an object. Known lists only change when the object crosses into another region.
+    pub fn move_to(&mut self, obj_id: u32, x: i32, y: i32, z: i32) {
+        let (old_region, is_player) = match self.objects.get_mut(&obj_id) {
+            Some(object) => {
+                let (old_x, old_y, _) = object.position();
+                object.set_position(x, y, z);
+                (region_of(old_x, old_y), matches!(object, WorldObject::Player(_)))
+            },
+            None => return,
+        };
+
+        let new_region = region_of(x, y);
+        if old_region == new_region {
+            return;
+        }
+
+        self.unindex(obj_id, old_region, is_player);
+        self.index(obj_id, new_region, is_player);
+        self.refresh_known(obj_id);
+    }
+
+    /// Puts an object somewhere else without touching known lists, for teleports which clear them first and
+    /// refresh them once the object appears.
+    pub fn relocate(&mut self, obj_id: u32, x: i32, y: i32, z: i32) {
+        let (old_region, is_player) = match self.objects.get_mut(&obj_id) {
+            Some(object) => {
+                let (old_x, old_y, _) = object.position();
+                object.set_position(x, y, z);
+                (region_of(old_x, old_y), matches!(object, WorldObject::Player(_)))
+            },
+            None => return,
+        };
+        let new_region = region_of(x, y);
+        if old_region != new_region {
+            self.unindex(obj_id, old_region, is_player);
+            self.index(obj_id, new_region, is_player);
+        }
+    }
+
+    /// Replaces the movement of an object.
+    pub fn start_moving(&mut self, obj_id: u32, movement: Movement) {
+        if let Some(object) = self.objects.get_mut(&obj_id) {
+            *object.movement_mut() = Some(movement);
+            self.moving.insert(obj_id);
+        }
+    }
+
+    /// Ends the movement of an object where it is, returns whether it was moving.
+    pub fn stop_moving(&mut self, obj_id: u32) -> bool {
+        if let Some(object) = self.objects.get_mut(&obj_id) {
+            *object.movement_mut() = None;
+        }
+        self.moving.remove(&obj_id)
+    }
+
+    pub fn moving(&self) -> Vec<u32> {
+        self.moving.iter().copied().collect()
+    }
+
+    /// Asks for a path to be searched without the world held, replacing the one the object asked for before.
+    pub fn request_route(&mut self, obj_id: u32, route: Route) {
+        if self.objects.contains_key(&obj_id) {
+            self.routes.insert(obj_id, route);
+        }
+    }
+
+    pub fn take_routes(&mut self) -> Vec<(u32, Route)> {
+        self.routes.drain().collect()
+    }
+
+    /// Has the attack tick look after an object until it is out of combat.
+    pub fn start_fighting(&mut self, obj_id: u32) {
+        if self.objects.contains_key(&obj_id) {
+            self.fighting.insert(obj_id);
+        }
+    }
+
+    pub fn stop_fighting(&mut self, obj_id: u32) {
+        self.fighting.remove(&obj_id);
+    }
+
+    pub fn fighting(&self) -> Vec<u32> {
+        self.fighting.iter().copied().collect()
+    }
+
+    /// Has the regen tick look after an object until it is full again.
+    pub fn start_recovering(&mut self, obj_id: u32) {
+        if self.objects.contains_key(&obj_id) {
+            self.recovering.insert(obj_id);
+        }
+    }
+
+    pub fn stop_recovering(&mut self, obj_id: u32) {
+        self.recovering.remove(&obj_id);
+    }
+
+    pub fn recovering(&self) -> Vec<u32> {
+        self.recovering.iter().copied().collect()
+    }
+
+    /// Has the cast tick look after an object until its cast ends.
+    pub fn start_casting(&mut self, obj_id: u32) {
+        if self.objects.contains_key(&obj_id) {
+            self.casting.insert(obj_id);
+        }
+    }
+
+    pub fn stop_casting(&mut self, obj_id: u32) {
+        self.casting.remove(&obj_id);
+    }
+
+    pub fn casting(&self) -> Vec<u32> {
+        self.casting.iter().copied().collect()
+    }
+
+    pub fn schedule_effect_end(&mut self, at: Instant, obj_id: u32, skill_id: u32) {
+        self.effect_ends.push(Reverse((at, obj_id, skill_id)));
+    }
+
+    /// Effects due to wear off by `now` as object and skill id. The effect may have been replaced or removed
+    /// since, the caller checks it is still there and due.
+    pub fn due_effect_ends(&mut self, now: Instant) -> Vec<(u32, u32)> {
+        let mut due = Vec::new();
+        while let Some(Reverse((at, obj_id, skill_id))) = self.effect_ends.peek().copied() {
+            if at > now {
+                break;
+            }
+            self.effect_ends.pop();
+            due.push((obj_id, skill_id));
+        }
+        due
+    }
+
+    /// Rebuilds the known list of an object from its current region.
+    pub fn refresh_known(&mut self, obj_id: u32) {
+        let (x, y, _) = match self.objects.get(&obj_id) {
+            Some(object) => object.position(),
+            None => return,
+        };
+
+        let visible: HashSet<u32> = self.visible_from(region_of(x, y)).into_iter().filter(|other| *other != obj_id).collect();
+        let known: Vec<u32> = self.objects[&obj_id].known().iter().copied().collect();
+
+        for other in known.iter().filter(|other| !visible.contains(other)) {
+            self.forget_each_other(obj_id, *other);
+        }
+        for other in visible {
+            if !self.objects[&obj_id].known().contains(&other) {
+                self.see_each_other(obj_id, other);
+            }
+        }
+        self.refresh_known_items(obj_id);
+    }
+
+    /// Forgets everything an object sees, so the next `refresh_known` sends every object again.
+    pub fn clear_known(&mut self, obj_id: u32) {
+        let known: Vec<u32> = match self.objects.get(&obj_id) {
+            Some(object) => object.known().iter().copied().collect(),
+            None => return,
+        };
+        for other in known {
+            self.forget_each_other(obj_id, other);
+        }
+        if let Some(WorldObject::Player(player)) = self.objects.get_mut(&obj_id) {
+            for item_id in player.known_items.drain() {
+                player.sender.send(packets::delete_object(item_id));
+            }
+        }
+    }
+
+    /// Shows a player the items on the ground around it, and takes away the ones it left behind.
+    fn refresh_known_items(&mut self, obj_id: u32) {
+        let region = match self.objects.get(&obj_id) {
+            Some(WorldObject::Player(player)) if !player.teleporting => region_of(player.character.x, player.character.y),
+            _ => return,
+        };
+        let visible: HashSet<u32> = surrounding(region)
+            .filter_map(|id| self.regions.get(&id))
+            .flat_map(|region| region.items.iter().copied())
+            .collect();
+        if let Some(WorldObject::Player(player)) = self.objects.get_mut(&obj_id) {
+            for item_id in player.known_items.difference(&visible) {
+                player.sender.send(packets::delete_object(*item_id));
+            }
+            for item in visible.difference(&player.known_items).filter_map(|item_id| self.items.get(item_id)) {
+                player.sender.send(item_packets::spawn_item(item));
+            }
+            player.known_items = visible;
+        }
+    }
+
+    pub fn ground_item(&self, obj_id: u32) -> Option<&GroundItem> {
+        self.items.get(&obj_id)
+    }
+
+    /// Puts an item on the ground, the players around see it fall from `dropper_id`.
+    pub fn drop_item(&mut self, dropper_id: u32, item: GroundItem) {
+        let (obj_id, (x, y, _)) = (item.obj_id, item.position);
+        let region = region_of(x, y);
+        self.regions.entry(region).or_default().items.insert(obj_id);
+        self.item_expiries.push(Reverse((item.expires_at, obj_id)));
+        let packet = item_packets::drop_item(dropper_id, &item);
+        self.items.insert(obj_id, item);
+        for other in self.visible_from(region) {
+            if let Some(WorldObject::Player(player)) = self.objects.get_mut(&other) {
+                if !player.teleporting && player.known_items.insert(obj_id) {
+                    player.send(packet.clone());
+                }
+            }
+        }
+    }
+
+    /// Takes an item off the ground, every player that saw it gets a DeleteObject.
+    pub fn take_item(&mut self, obj_id: u32) -> Option<GroundItem> {
+        let item = self.items.remove(&obj_id)?;
+        let region = region_of(item.position.0, item.position.1);
+        for other in self.visible_from(region) {
+            if let Some(WorldObject::Player(player)) = self.objects.get_mut(&other) {
+                if player.known_items.remove(&obj_id) {
+                    player.send(packets::delete_object(obj_id));
+                }
+            }
+        }
+        if let Some(entry) = self.regions.get_mut(&region) {
+            entry.items.remove(&obj_id);
+            if entry.objects.is_empty() && entry.items.is_empty() {
+                self.regions.remove(&region);
+            }
+        }
+        Some(item)
+    }
+
+    /// Takes the items due to go away by `now`. The id may have gone to another item since, the caller checks
+    /// the item is still there and due.
+    pub fn due_item_expiries(&mut self, now: Instant) -> Vec<u32> {
+        let mut due = Vec::new();
+        while let Some(Reverse((at, obj_id))) = self.item_expiries.peek().copied() {
+            if at > now {
+                break;
+            }
+            self.item_expiries.pop();
+            due.push(obj_id);
+        }
+        due
+    }
+
+    /// Has the drops tick look after a player walking to an item until it picks it up or gives up.
+    pub fn start_picking(&mut self, obj_id: u32) {
+        if self.objects.contains_key(&obj_id) {
+            self.picking.insert(obj_id);
+        }
+    }
+
+    pub fn stop_picking(&mut self, obj_id: u32) {
+        self.picking.remove(&obj_id);
+    }
+
+    pub fn picking(&self) -> Vec<u32> {
+        self.picking.iter().copied().collect()
+    }
+
+    fn see_each_other(&mut self, a: u32, b: u32) {
+        self.see(a, b);
+        self.see(b, a);
+    }
+
+    fn see(&mut self, viewer: u32, target: u32) {
+        let info = match (self.objects.get(&viewer), self.objects.get(&target)) {
+            // A player still loading after a teleport neither sees nor is seen until it appears.
+            (Some(WorldObject::Player(player)), _) | (_, Some(WorldObject::Player(player))) if player.teleporting => return,
+            (Some(viewer), Some(target)) if !viewer.known().contains(&target.obj_id()) => {
+                viewer.sender().map(|_| target.info())
+            },
+            _ => return,
+        };
+
+        if let Some(viewer) = self.objects.get_mut(&viewer) {
+            viewer.known_mut().insert(target);
+            if let (Some(sender), Some(info)) = (viewer.sender(), info) {
+                for packet in info {
+                    sender.send(packet);
+                }
+            }
+        }
+    }
+
+    fn forget_each_other(&mut self, a: u32, b: u32) {
+        self.forget(a, b);
+        self.forget(b, a);
+    }
+
+    fn forget(&mut self, viewer: u32, target: u32) {
+        if let Some(viewer) = self.objects.get_mut(&viewer) {
+            if viewer.known_mut().remove(&target) {
+                if let Some(sender) = viewer.sender() {
+                    sender.send(packets::delete_object(target));
+                }
+            }
+        }
+    }
+
+    /// Sends a packet to every player that sees `obj_id`.
+    pub fn broadcast(&self, obj_id: u32, packet: &[u8]) {
+        if let Some(object) = self.objects.get(&obj_id) {
+            for other in object.known() {
+                if let Some(WorldObject::Player(player)) = self.objects.get(other) {
+                    player.send(packet.to_vec());
+                }
+            }
+        }
+    }
+
+    /// Like `broadcast`, also sending the packet to `obj_id` itself when it is a player.
+    pub fn broadcast_with_self(&self, obj_id: u32, packet: &[u8]) {
+        if let Some(player) = self.player(obj_id) {
+            player.send(packet.to_vec());
+        }
+        self.broadcast(obj_id, packet);
+    }
+
+    /// Sends the current appearance of an object to everything that sees it.
+    pub fn broadcast_info(&self, obj_id: u32) {
+        if let Some(object) = self.objects.get(&obj_id) {
+            for packet in object.info() {
+                self.broadcast(obj_id, &packet);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameserver::npc::synthetic::npc;
+
+    fn knows(world: &World, viewer: u32, target: u32) -> bool {
+        world.get(viewer).unwrap().known().contains(&target)
+    }
+
+    #[test]
+    fn objects_see_the_regions_around_theirs() {
+        let mut world = World::new();
+        world.add(npc(1, 100, 100)).unwrap();
+        world.add(npc(2, 3000, 100)).unwrap();
+        world.add(npc(3, 5000, 100)).unwrap();
+
+        assert!(knows(&world, 1, 2) && knows(&world, 2, 1));
+        assert!(knows(&world, 2, 3) && knows(&world, 3, 2));
+        assert!(!knows(&world, 1, 3) && !knows(&world, 3, 1));
+        assert!(world.add(npc(2, 0, 0)).is_err());
+        assert_eq!(world.regions[&(1, 0)].objects.len(), 1);
+    }
+
+    #[test]
+    fn crossing_regions_updates_known_lists() {
+        let mut world = World::new();
+        world.add(npc(1, 100, 100)).unwrap();
+        world.add(npc(2, 3000, 100)).unwrap();
+        world.add(npc(3, 5000, 100)).unwrap();
+
+        world.move_to(3, 7000, 100, 0);
+        assert!(!knows(&world, 2, 3) && !knows(&world, 3, 2));
+        world.move_to(1, 1000, 100, 0);
+        assert!(knows(&world, 1, 2));
+
+        world.move_to(2, 6500, 100, 0);
+        assert!(knows(&world, 2, 3) && knows(&world, 3, 2));
+        assert!(!knows(&world, 1, 2) && !knows(&world, 2, 1));
+        assert!(!world.regions.contains_key(&(1, 0)));
+        assert_eq!(world.regions[&(3, 0)].objects.len(), 2);
+    }
+
+    #[test]
+    fn removed_objects_are_forgotten() {
+        let mut world = World::new();
+        world.add(npc(1, 100, 100)).unwrap();
+        world.add(npc(2, 200, 100)).unwrap();
+
+        assert!(world.remove(2).is_some());
+        assert!(world.get(1).unwrap().known().is_empty());
+        assert_eq!(world.regions[&(0, 0)].objects.len(), 1);
+        world.remove(1);
+        assert!(world.regions.is_empty());
+    }
+}
\ No newline at end of file
@@ -3,50 +3,73 @@ mod loginserver;
 mod blowfish;
 mod config;
 mod packet;
+mod database;
 
 use log::info;
 use log::LevelFilter;
 use log4rs::append::console::ConsoleAppender;
-use log4rs::config::{Appender, Root};
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::trigger::time::{TimeTrigger, TimeTriggerConfig, TimeTriggerInterval};
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::config::{Appender, Logger, Root};
+use log4rs::encode::pattern::PatternEncoder;
 use log4rs::Config;
-use tokio::task;
-
+use crate::gameserver::gameserver::GameServer;
 use crate::loginserver::loginserver::LoginServer;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let stdout = ConsoleAppender::builder().build();
+    // Every chat message goes to its own file, started over each day with the older days kept next to it.
+    let chat_trigger = TimeTrigger::new(TimeTriggerConfig {
+        interval: TimeTriggerInterval::Day(1),
+        modulate: true,
+        max_random_delay: 0,
+    });
+    let chat_roller = FixedWindowRoller::builder().build("./log/chat.{}.log", 90).unwrap();
+    let chat = RollingFileAppender::builder()
+        .encoder(Box::new(PatternEncoder::new("{d(%Y-%m-%d %H:%M:%S)} {m}{n}")))
+        .build("./log/chat.log", Box::new(CompoundPolicy::new(Box::new(chat_trigger), Box::new(chat_roller))))
+        .unwrap();
     let config = Config::builder()
         .appender(Appender::builder().build("stdout", Box::new(stdout)))
+        .appender(Appender::builder().build("chat", Box::new(chat)))
+        .logger(Logger::builder().appender("chat").additive(false).build("chat", LevelFilter::Info))
         .build(Root::builder().appender("stdout").build(LevelFilter::Trace))
         .unwrap();
     let _handle = log4rs::init_config(config).unwrap();
 
+    let conf = match config::config::new_config() {
+        Ok(conf) => conf,
+        Err(e) => {
+            info!("Error reading config.toml: {}", e);
+            return Ok(());
+        }
+    };
+    info!("Starting Lineage ][ Server");
+    info!("Config loaded");
+
     let game_server_task = async {
-        info!("hello world from non blocking task");
+        match GameServer::new(conf.gameserver).await {
+            Ok(mut gs) => {
+                gs.start().await;
+            },
+            Err(e) => {
+                info!("Error starting Game Server: {}", e);
+            }
+        }
     };
 
     let login_server_task = async {
+        let login_server = LoginServer::new(conf.loginserver).await;
 
-        match config::config::new_config() {
-            Ok(conf) => {
-                info!("Starting Lineage ][ Server");
-                info!("Config loaded");
-    
-                let login_server = LoginServer::new(conf.loginserver).await;
-    
-                match login_server {
-                    Ok(mut lg) => {
-                        lg.start().await;
-                    },
-                    Err(e) => {
-                        info!("Error starting Login Server: {}", e);
-                    }
-                }
-            
+        match login_server {
+            Ok(mut lg) => {
+                lg.start().await;
             },
             Err(e) => {
-                info!("Error reading config.toml: {}", e);
+                info!("Error starting Login Server: {}", e);
             }
         }
     };
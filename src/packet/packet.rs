@@ -1,6 +1,3 @@
-use std::io::{self};
-use byteorder::{LittleEndian, WriteBytesExt};
-
 pub struct Buffer {
     pub buffer: Vec<u8>,
 }
@@ -10,44 +7,48 @@ impl Buffer {
         Buffer { buffer: Vec::new() }
     }
 
-    pub fn write(&mut self, value: Vec<u8>) -> io::Result<()> {
+    pub fn write(&mut self, value: Vec<u8>) {
         self.buffer.extend(value);
-        Ok(())
     }
 
-    pub fn write_usize(&mut self, value: usize) -> io::Result<()> {
-        self.buffer.write_u8(value.try_into().unwrap())?;
-        Ok(())
+    pub fn write_usize(&mut self, value: usize) {
+        self.buffer.push(value.try_into().unwrap());
+    }
+
+    pub fn write_uint64(&mut self, value: u64) {
+        self.buffer.extend(value.to_le_bytes());
     }
 
-    pub fn write_uint64(&mut self, value: u64) -> io::Result<()> {
-        self.buffer.write_u64::<LittleEndian>(value)?;
-        Ok(())
+    pub fn write_uint32(&mut self, value: u32) {
+        self.buffer.extend(value.to_le_bytes());
     }
 
-    pub fn write_uint32(&mut self, value: u32) -> io::Result<()> {
-        self.buffer.write_u32::<LittleEndian>(value)?;
-        Ok(())
+    pub fn write_int32(&mut self, value: i32) {
+        self.buffer.extend(value.to_le_bytes());
     }
 
-    pub fn write_uint16(&mut self, value: u16) -> io::Result<()> {
-        self.buffer.write_u16::<LittleEndian>(value)?;
-        Ok(())
+    pub fn write_uint16(&mut self, value: u16) {
+        self.buffer.extend(value.to_le_bytes());
     }
 
-    pub fn write_uint8(&mut self, value: u8) -> io::Result<()> {
+    pub fn write_uint8(&mut self, value: u8) {
         self.buffer.push(value);
-        Ok(())
     }
 
-    pub fn write_float64(&mut self, value: f64) -> io::Result<()> {
-        self.buffer.write_f64::<LittleEndian>(value)?;
-        Ok(())
+    pub fn write_float64(&mut self, value: f64) {
+        self.buffer.extend(value.to_le_bytes());
     }
 
-    pub fn write_float32(&mut self, value: f32) -> io::Result<()> {
-        self.buffer.write_f32::<LittleEndian>(value)?;
-        Ok(())
+    pub fn write_float32(&mut self, value: f32) {
+        self.buffer.extend(value.to_le_bytes());
+    }
+
+    /// Writes a null terminated UTF-16LE string, the format used by the client for all texts.
+    pub fn write_string(&mut self, value: &str) {
+        for unit in value.encode_utf16() {
+            self.buffer.extend(unit.to_le_bytes());
+        }
+        self.buffer.extend([0, 0]);
     }
 }
 
@@ -55,41 +56,61 @@ impl Buffer {
 
 pub struct PacketRead {
     buffer: Vec<u8>,
+    position: usize,
 }
 
 impl PacketRead {
     pub fn new(buffer: Vec<u8>) -> PacketRead {
-        PacketRead {buffer}
+        PacketRead { buffer, position: 0 }
+    }
+
+    /// Returns the next `size` bytes, failing instead of panicking when the packet is too short.
+    fn take(&mut self, size: usize) -> Result<&[u8], String> {
+        if self.buffer.len() - self.position < size {
+            return Err(format!("Packet too short: wanted {} more bytes, {} left", size, self.buffer.len() - self.position));
+        }
+        let bytes = &self.buffer[self.position..self.position + size];
+        self.position += size;
+        Ok(bytes)
     }
 
-    pub fn read_u64(&mut self) -> u64 {
-        let mut bytes = [0u8; 8];
-        bytes.copy_from_slice(&self.buffer[0..8]);
-        self.buffer.drain(0..8);
-        u64::from_le_bytes(bytes)
+    /// Bytes not read yet, some packets have optional trailing fields.
+    pub fn remaining(&self) -> usize {
+        self.buffer.len() - self.position
     }
 
-    pub fn read_u32(&mut self) -> u32 {
+    pub fn read_u32(&mut self) -> Result<u32, String> {
         let mut bytes = [0u8; 4];
-        bytes.copy_from_slice(&self.buffer[0..4]);
-        self.buffer.drain(0..4);
-        u32::from_le_bytes(bytes)
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
     }
 
-    pub fn read_u16(&mut self) -> u16 {
-        let mut bytes = [0u8; 2];
-        bytes.copy_from_slice(&self.buffer[0..2]);
-        self.buffer.drain(0..2);
-        u16::from_le_bytes(bytes)
+    pub fn read_i32(&mut self) -> Result<i32, String> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(i32::from_le_bytes(bytes))
     }
 
-    pub fn read_u8(&mut self) -> u8 {
-        let mut bytes = [0u8; 1];
-        bytes.copy_from_slice(&self.buffer[0..1]);
-        self.buffer.drain(0..1);
-        u8::from_le_bytes(bytes)
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        let mut bytes = [0u8; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(bytes))
     }
 
-    
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
 
-}
\ No newline at end of file
+    /// Reads a null terminated UTF-16LE string.
+    pub fn read_string(&mut self) -> Result<String, String> {
+        let mut units = Vec::new();
+        loop {
+            let unit = self.read_u16()?;
+            if unit == 0 {
+                break;
+            }
+            units.push(unit);
+        }
+        String::from_utf16(&units).map_err(|e| format!("Invalid string in packet: {}", e))
+    }
+}